@@ -0,0 +1,23 @@
+use crate::catalog;
+use crate::context::PromptVars;
+use std::env;
+use std::time::Duration;
+
+const DEFAULT_TEXT: &str = "🤔 Thinking about this one, back in a moment…";
+
+// Provenance trigger of acknowledgements, so a retried mention finds the one already
+// posted and answers under it instead of acknowledging twice.
+pub const TRIGGER: &str = "ack";
+
+// How long a reply may take to generate before the post gets an acknowledgement,
+// from ACK_AFTER_SECS. None (the default, or 0) never acknowledges.
+pub fn delay() -> Option<Duration> {
+    env::var("ACK_AFTER_SECS").ok().and_then(|v| v.parse::<f64>().ok()).filter(|s| *s > 0.0).map(Duration::from_secs_f64)
+}
+
+// The `ack` message in the user's language, ACK_TEXT or the default, with the prompt
+// variables plus {{user}} for a pk: mention of the user.
+pub fn render(vars: &PromptVars, user: &str, language: Option<&str>) -> String {
+    let template = catalog::message("ack", language, "ACK_TEXT", DEFAULT_TEXT);
+    vars.apply(&template.replace("\\n", "\n")).replace("{{user}}", &format!("pk:{}", user))
+}
@@ -0,0 +1,173 @@
+use crate::analytics::AnalyticsRow;
+use crate::context::format_utc;
+use crate::memory::now_millis;
+use crate::privacy;
+use crate::secrets;
+use anyhow::Result;
+use dotenv::dotenv;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::VecDeque;
+use std::env;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Events kept for `GET /activity`; a tail that falls further behind skips ahead.
+const FEED_EVENTS: usize = 1000;
+// Events printed when `tail` connects, before following new ones.
+const BACKLOG_EVENTS: usize = 20;
+
+// Live activity of the running bot, in memory only: every analytics row plus errors
+// that are otherwise only logged. Read by `tail` through the admin API.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Event {
+    pub seq: u64,
+    pub timestamp: i64,
+    pub event: String,
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub post_uri: Option<String>,
+    #[serde(default)]
+    pub correlation_id: Option<String>,
+    #[serde(default)]
+    pub data: Map<String, Value>,
+}
+
+// The events after `after`, and the sequence number to ask for next.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Page {
+    pub events: Vec<Event>,
+    pub next: u64,
+}
+
+struct Feed {
+    seq: u64,
+    events: VecDeque<Event>,
+}
+
+static FEED: Mutex<Feed> = Mutex::new(Feed { seq: 0, events: VecDeque::new() });
+
+fn push(event: &str, user: Option<String>, post_uri: Option<String>, correlation_id: Option<String>, data: Map<String, Value>) {
+    let Ok(mut feed) = FEED.lock() else { return };
+    feed.seq += 1;
+    let seq = feed.seq;
+    if feed.events.len() >= FEED_EVENTS {
+        feed.events.pop_front();
+    }
+    feed.events.push_back(Event { seq, timestamp: now_millis(), event: event.to_string(), user, post_uri, correlation_id, data });
+}
+
+pub fn row(row: &AnalyticsRow) {
+    push(&row.event, row.user.clone(), row.post_uri.clone(), row.correlation_id.clone(), row.data.clone());
+}
+
+// An error the bot logged and carried on from, with what it was doing.
+pub fn error(stage: &str, error: impl fmt::Display) {
+    let mut data = Map::new();
+    data.insert("stage".to_string(), stage.into());
+    data.insert("message".to_string(), secrets::scrub(&error.to_string()).into());
+    push("error", None, None, crate::correlation::current(), data);
+}
+
+pub fn since(after: u64) -> Page {
+    let Ok(feed) = FEED.lock() else { return Page { events: Vec::new(), next: after } };
+    // A sequence number from before a restart starts over
+    let after = if after > feed.seq { 0 } else { after };
+    Page { events: feed.events.iter().filter(|e| e.seq > after).cloned().collect(), next: feed.seq }
+}
+
+// Events shown without --all: what came in, what went out, and what went wrong.
+fn is_headline(event: &Event) -> bool {
+    matches!(event.event.as_str(), "mention" | "reply" | "error" | "channel_question" | "thread_follow_up" | "startup") || event.event.ends_with("_failed")
+}
+
+fn format(event: &Event) -> String {
+    let (_, time) = format_utc(event.timestamp);
+    let seconds = event.timestamp.div_euclid(1000).rem_euclid(60);
+    let mut line = format!("{}:{:02} {:<18}", time.trim_end_matches(" UTC"), seconds, event.event);
+    if let Some(user) = &event.user {
+        line.push_str(&format!(" {}", privacy::user(user)));
+    }
+    if let Some(uri) = &event.post_uri {
+        line.push_str(&format!(" {}", privacy::uri(uri)));
+    }
+    for (key, value) in &event.data {
+        let value = match value {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        line.push_str(&format!(" {}={}", key, value));
+    }
+    if let Some(id) = &event.correlation_id {
+        line.push_str(&format!(" [{}]", id));
+    }
+    line
+}
+
+// Where the running bot's admin API is, from ADMIN_API_ADDR; a wildcard bind address
+// is reached on localhost.
+fn admin_url() -> Option<String> {
+    let addr = env::var("ADMIN_API_ADDR").ok().filter(|a| !a.trim().is_empty())?;
+    let addr = addr.replace("0.0.0.0", "127.0.0.1").replace("[::]", "[::1]");
+    Some(format!("http://{}", addr))
+}
+
+// `tail [--all] [--url URL]`: follows the running bot's activity through its admin API,
+// printing mentions, replies and errors as they happen (--all prints every event).
+pub async fn run_tail(args: &[String]) -> Result<()> {
+    dotenv().ok();
+    let mut all = false;
+    let mut url = admin_url();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--all" => all = true,
+            "--url" => url = Some(args.next().ok_or_else(|| anyhow::anyhow!("--url needs a value"))?.trim_end_matches('/').to_string()),
+            other => return Err(anyhow::anyhow!("Unknown tail option: {}", other)),
+        }
+    }
+    let url = url.ok_or_else(|| anyhow::anyhow!("Set ADMIN_API_ADDR, or pass --url, to reach the running bot"))?;
+    let token = secrets::Secret::from_env("ADMIN_API_TOKEN");
+    let client = reqwest::Client::new();
+
+    log!("Following activity from {} (Ctrl-C to stop)", url);
+    let mut after = None;
+    let mut connected = true;
+    loop {
+        let mut request = client.get(format!("{}/activity", url)).query(&[("after", after.unwrap_or(0))]);
+        if let Some(token) = &token {
+            request = request.bearer_auth(token.expose());
+        }
+        let response = request.send().await;
+        if let Ok(response) = &response {
+            if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+                return Err(anyhow::anyhow!("The admin API refused the request, check ADMIN_API_TOKEN"));
+            }
+        }
+        match response.and_then(|r| r.error_for_status()) {
+            Ok(response) => {
+                let page: Page = response.json().await?;
+                if !connected {
+                    log!("Reconnected to {}", url);
+                    connected = true;
+                }
+                let shown: Vec<&Event> = page.events.iter().filter(|e| all || is_headline(e)).collect();
+                // On connecting, only the latest events
+                let skip = if after.is_none() { shown.len().saturating_sub(BACKLOG_EVENTS) } else { 0 };
+                for event in &shown[skip..] {
+                    println!("{}", format(event));
+                }
+                after = Some(page.next);
+            }
+            // The bot restarting or briefly unreachable shouldn't end the tail
+            Err(e) if connected => {
+                log!("Lost connection to {}: {}", url, e);
+                connected = false;
+            }
+            Err(_) => {}
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
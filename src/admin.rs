@@ -0,0 +1,279 @@
+use crate::activity;
+use crate::approval::{OperatorCommand, PendingView};
+use crate::backup;
+use crate::budget;
+use crate::channels;
+use crate::intake;
+use crate::jobs;
+use crate::keepalive;
+use crate::llm_queue;
+use crate::memory::now_millis;
+use crate::models;
+use crate::notifications;
+use crate::secrets::Secret;
+use crate::slo;
+use crate::storage;
+use crate::throttle;
+use crate::timings;
+use crate::watch;
+use anyhow::Result;
+use std::env;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::UnboundedSender;
+
+const MAX_REQUEST_BYTES: usize = 16 * 1024;
+
+// Minimal HTTP admin API and dashboard, enabled by setting ADMIN_API_ADDR.
+//
+//   GET  /                       HTML dashboard listing pending replies
+//   GET  /pending                pending replies as JSON
+//   GET  /status                 bot status (storage usage, unknown notifications, LLM rate limits) as JSON
+//   GET  /activity?after=<seq>   recent activity events after a sequence number, followed by `tail`
+//   POST /pending/<id>/approve   publish a pending reply
+//   POST /pending/<id>/deny      drop a pending reply
+//
+// If ADMIN_API_TOKEN is set, requests must send `Authorization: Bearer <token>`
+// or a `token=<token>` query parameter.
+pub struct AdminApi {
+    pending: PendingView,
+    commands: UnboundedSender<OperatorCommand>,
+    token: Option<Secret>,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    authorization: Option<String>,
+    form: bool,
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl AdminApi {
+    pub async fn spawn_from_env(pending: PendingView, commands: UnboundedSender<OperatorCommand>) -> Result<()> {
+        let Some(addr) = env::var("ADMIN_API_ADDR").ok().filter(|a| !a.is_empty()) else {
+            return Ok(());
+        };
+        let token = Secret::from_env("ADMIN_API_TOKEN");
+        if token.is_none() {
+            log!("Warning: ADMIN_API_TOKEN is not set, the admin API is unauthenticated");
+        }
+
+        let listener = TcpListener::bind(&addr).await?;
+        log!("Admin API listening on http://{}", addr);
+        let api = std::sync::Arc::new(AdminApi { pending, commands, token });
+        tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else { continue };
+                let api = api.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = api.serve(stream).await {
+                        log!("Admin API request failed: {}", e);
+                    }
+                });
+            }
+        });
+        Ok(())
+    }
+
+    async fn serve(&self, mut stream: TcpStream) -> Result<()> {
+        let request = read_request(&mut stream).await?;
+        let response = self.route(&request);
+        let mut head = format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+            response.status,
+            response.content_type,
+            response.body.len()
+        );
+        for (name, value) in &response.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+        stream.write_all(head.as_bytes()).await?;
+        stream.write_all(response.body.as_bytes()).await?;
+        Ok(())
+    }
+
+    fn route(&self, request: &Request) -> Response {
+        if !self.authorized(request) {
+            return Response::json("401 Unauthorized", r#"{"error":"unauthorized"}"#.to_string());
+        }
+
+        let segments: Vec<&str> = request.path.trim_matches('/').split('/').filter(|s| !s.is_empty()).collect();
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", []) => Response::html(self.dashboard()),
+            ("GET", ["pending"]) => {
+                let pending = self.pending.lock().map(|p| p.clone()).unwrap_or_default();
+                Response::json("200 OK", serde_json::to_string(&pending).unwrap_or_default())
+            }
+            ("GET", ["status"]) => {
+                let status = serde_json::json!({
+                    "pending": self.pending.lock().map(|p| p.len()).unwrap_or_default(),
+                    "storage": storage::usage(),
+                    "backup": backup::stats(),
+                    "pkarr": keepalive::stats(),
+                    "unknown_notifications": notifications::unknown_counts(),
+                    "llm_rate_limits": llm_queue::rate_limit_stats(),
+                    "llm_queue": llm_queue::queue_stats(),
+                    "throttle": throttle::stats(),
+                    "llm_budgets": budget::stats(),
+                    "generation_jobs": jobs::stats(),
+                    "retired_models": models::switched(),
+                    "stage_timings": timings::histograms(),
+                    "latency_slos": slo::status(),
+                    "backlog": self.backlog(),
+                    "nexus": intake::availability(),
+                    "watched_threads": watch::active(),
+                    "channels": channels::stats(),
+                });
+                Response::json("200 OK", status.to_string())
+            }
+            ("GET", ["activity"]) => {
+                let after = request.query.split('&').find_map(|pair| pair.strip_prefix("after=")).and_then(|v| v.parse().ok()).unwrap_or(0);
+                Response::json("200 OK", serde_json::to_string(&activity::since(after)).unwrap_or_default())
+            }
+            ("POST", ["pending", id, action]) => {
+                let command = match *action {
+                    "approve" => OperatorCommand::Approve(id.to_string()),
+                    "deny" => OperatorCommand::Deny(id.to_string()),
+                    _ => return Response::json("404 Not Found", r#"{"error":"not found"}"#.to_string()),
+                };
+                if self.commands.send(command).is_err() {
+                    return Response::json("503 Service Unavailable", r#"{"error":"bot is shutting down"}"#.to_string());
+                }
+                if request.form {
+                    // Came from the dashboard form: go back to the dashboard
+                    let location = match &self.token {
+                        Some(_) => format!("/?{}", request.query),
+                        None => "/".to_string(),
+                    };
+                    return Response::redirect(location);
+                }
+                Response::json("202 Accepted", r#"{"status":"queued"}"#.to_string())
+            }
+            _ => Response::json("404 Not Found", r#"{"error":"not found"}"#.to_string()),
+        }
+    }
+
+    // Gauges showing whether the bot keeps up: unhandled notifications, LLM requests
+    // waiting (including rate-limit retries) and replies awaiting approval.
+    fn backlog(&self) -> serde_json::Value {
+        let pending = self.pending.lock().map(|p| p.clone()).unwrap_or_default();
+        let oldest_approval_age_ms = pending.iter().map(|p| p.created_at).min().map(|oldest| (now_millis() - oldest).max(0));
+        serde_json::json!({
+            "notifications": notifications::backlog_stats(),
+            "llm_queue_depth": llm_queue::depth(),
+            "approvals_pending": pending.len(),
+            "oldest_approval_age_ms": oldest_approval_age_ms,
+        })
+    }
+
+    fn authorized(&self, request: &Request) -> bool {
+        let Some(token) = &self.token else {
+            return true;
+        };
+        let bearer = request.authorization.as_deref().and_then(|h| h.strip_prefix("Bearer "));
+        let query_token = request.query.split('&').find_map(|pair| pair.strip_prefix("token="));
+        bearer == Some(token.expose()) || query_token == Some(token.expose())
+    }
+
+    fn dashboard(&self) -> String {
+        let pending = self.pending.lock().map(|p| p.clone()).unwrap_or_default();
+        let query = match &self.token {
+            Some(token) => format!("?token={}", token.expose()),
+            None => String::new(),
+        };
+        let mut rows = String::new();
+        for reply in &pending {
+            rows.push_str(&format!(
+                "<tr><td>{id}</td><td>{to}<br><small>{parent}</small></td><td>{prompt}</td><td>{content}</td>\
+                 <td><form method=\"post\" action=\"/pending/{id}/approve{query}\"><button>Approve</button></form>\
+                 <form method=\"post\" action=\"/pending/{id}/deny{query}\"><button>Deny</button></form></td></tr>",
+                id = escape_html(&reply.id),
+                to = escape_html(&reply.draft.to_user),
+                parent = escape_html(&reply.draft.parent_uri),
+                prompt = escape_html(&reply.draft.prompt),
+                content = escape_html(&reply.draft.content),
+                query = query,
+            ));
+        }
+        if pending.is_empty() {
+            rows.push_str("<tr><td colspan=\"5\">No pending replies</td></tr>");
+        }
+        format!(
+            "<!doctype html><html><head><meta charset=\"utf-8\"><title>AI Rand - pending replies</title>\
+             <meta http-equiv=\"refresh\" content=\"10\"></head><body><p>Storage: {}</p><h1>Pending replies ({})</h1>\
+             <table border=\"1\" cellpadding=\"6\"><tr><th>ID</th><th>To</th><th>Post</th><th>Reply</th><th></th></tr>{}</table>\
+             </body></html>",
+            escape_html(&storage::format_usage(&storage::usage())),
+            pending.len(),
+            rows
+        )
+    }
+}
+
+impl Response {
+    fn json(status: &'static str, body: String) -> Response {
+        Response { status, content_type: "application/json", headers: Vec::new(), body }
+    }
+
+    fn html(body: String) -> Response {
+        Response { status: "200 OK", content_type: "text/html; charset=utf-8", headers: Vec::new(), body }
+    }
+
+    fn redirect(location: String) -> Response {
+        Response {
+            status: "303 See Other",
+            content_type: "text/plain",
+            headers: vec![("Location".to_string(), location)],
+            body: String::new(),
+        }
+    }
+}
+
+// Reads the request line and headers; request bodies are not used by any route.
+async fn read_request(stream: &mut TcpStream) -> Result<Request> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buffer.windows(4).any(|w| w == b"\r\n\r\n") {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        if buffer.len() > MAX_REQUEST_BYTES {
+            return Err(anyhow::anyhow!("Request headers too large"));
+        }
+    }
+
+    let text = String::from_utf8_lossy(&buffer);
+    let mut lines = text.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let target = request_line.next().unwrap_or("/");
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let mut authorization = None;
+    let mut form = false;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else { continue };
+        match name.trim().to_ascii_lowercase().as_str() {
+            "authorization" => authorization = Some(value.trim().to_string()),
+            "content-type" => form = value.contains("application/x-www-form-urlencoded"),
+            _ => {}
+        }
+    }
+
+    Ok(Request { method, path: path.to_string(), query: query.to_string(), authorization, form })
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
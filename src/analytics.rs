@@ -0,0 +1,116 @@
+use crate::activity;
+use crate::correlation;
+use crate::memory::now_millis;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::VecDeque;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+
+// Append-only JSON-lines log of what the bot decided and did, one row per event.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalyticsRow {
+    pub timestamp: i64,
+    pub event: String,
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub post_uri: Option<String>,
+    // Notification whose handling produced the row
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub data: Map<String, Value>,
+}
+
+impl AnalyticsRow {
+    pub fn new(event: &str, user: Option<&str>, post_uri: Option<&str>) -> AnalyticsRow {
+        AnalyticsRow {
+            timestamp: now_millis(),
+            event: event.to_string(),
+            user: user.map(String::from),
+            post_uri: post_uri.map(String::from),
+            correlation_id: correlation::current(),
+            data: Map::new(),
+        }
+    }
+
+    pub fn with(mut self, key: &str, value: impl Into<Value>) -> AnalyticsRow {
+        self.data.insert(key.to_string(), value.into());
+        self
+    }
+}
+
+fn analytics_path() -> String {
+    env::var("ANALYTICS_LOG").unwrap_or_else(|_| "analytics.jsonl".to_string())
+}
+
+// Rows recorded by this process while handling a notification, kept briefly so what
+// became of it can be read back without scanning the log.
+const RECENT_ROWS: usize = 500;
+static RECENT: Mutex<VecDeque<AnalyticsRow>> = Mutex::new(VecDeque::new());
+// Held while appending a row or rewriting the log, so a row recorded during a rewrite
+// isn't lost when the rewritten file replaces the log
+static LOG_LOCK: Mutex<()> = Mutex::new(());
+
+// Rows recorded recently under a correlation ID, oldest first.
+pub fn recent(correlation_id: &str) -> Vec<AnalyticsRow> {
+    let Ok(recent) = RECENT.lock() else { return Vec::new() };
+    recent.iter().filter(|row| row.correlation_id.as_deref() == Some(correlation_id)).cloned().collect()
+}
+
+// Analytics must never break reply handling, so failures are only logged.
+pub fn record(row: AnalyticsRow) {
+    activity::row(&row);
+    if row.correlation_id.is_some() {
+        if let Ok(mut recent) = RECENT.lock() {
+            if recent.len() >= RECENT_ROWS {
+                recent.pop_front();
+            }
+            recent.push_back(row.clone());
+        }
+    }
+    let _guard = LOG_LOCK.lock();
+    let result = (|| -> Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(analytics_path())?;
+        writeln!(file, "{}", serde_json::to_string(&row)?)?;
+        Ok(())
+    })();
+    if let Err(e) = result {
+        log!("Failed to record analytics: {}", e);
+    }
+}
+
+pub fn load_rows() -> Result<Vec<AnalyticsRow>> {
+    let content = match fs::read_to_string(analytics_path()) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect())
+}
+
+// Removes every row about a user and returns how many were dropped.
+pub fn forget_user(user: &str) -> Result<usize> {
+    if let Ok(mut recent) = RECENT.lock() {
+        recent.retain(|r| r.user.as_deref() != Some(user));
+    }
+    let _guard = LOG_LOCK.lock();
+    let rows = load_rows()?;
+    let kept: Vec<&AnalyticsRow> = rows.iter().filter(|r| r.user.as_deref() != Some(user)).collect();
+    let removed = rows.len() - kept.len();
+    if removed > 0 {
+        let mut content = String::new();
+        for row in kept {
+            content.push_str(&serde_json::to_string(row)?);
+            content.push('\n');
+        }
+        let tmp = format!("{}.tmp", analytics_path());
+        fs::write(&tmp, content)?;
+        fs::rename(&tmp, analytics_path())?;
+    }
+    Ok(removed)
+}
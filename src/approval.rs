@@ -0,0 +1,18 @@
+use crate::memory::PendingReply;
+use std::env;
+use std::sync::{Arc, Mutex};
+
+// Read-only view of the pending queue shared with the admin API.
+pub type PendingView = Arc<Mutex<Vec<PendingReply>>>;
+
+// Decisions sent by the operator from Telegram or the admin API.
+#[derive(Debug)]
+pub enum OperatorCommand {
+    Approve(String),
+    Deny(String),
+}
+
+// With SUPERVISED=true generated replies wait for operator approval before being published.
+pub fn is_supervised() -> bool {
+    env::var("SUPERVISED").map(|v| v == "true").unwrap_or(false)
+}
@@ -0,0 +1,143 @@
+use crate::gzip;
+use crate::memory::now_millis;
+use crate::replay::{self, Recorded};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// Name of the segment being written; rotated segments are named after the time
+// they were closed, so they sort oldest first.
+const ACTIVE_FILE: &str = "notifications.jsonl";
+
+static ARCHIVE_LOCK: Mutex<()> = Mutex::new(());
+
+// Archiving, enabled by pointing NOTIFICATION_ARCHIVE_DIR at a directory: every raw
+// Nexus notifications response is appended there before it is parsed, in the same
+// line format as a RECORD_TRAFFIC recording so a segment can be fed to `replay`.
+fn archive_dir() -> Option<PathBuf> {
+    env::var("NOTIFICATION_ARCHIVE_DIR").ok().filter(|p| !p.is_empty()).map(PathBuf::from)
+}
+
+// Size at which the active segment is closed and compressed, from
+// NOTIFICATION_ARCHIVE_MAX_BYTES.
+fn max_bytes() -> u64 {
+    env::var("NOTIFICATION_ARCHIVE_MAX_BYTES").ok().and_then(|v| v.parse().ok()).filter(|n| *n > 0).unwrap_or(10 * 1024 * 1024)
+}
+
+// Rotated segments kept, from NOTIFICATION_ARCHIVE_KEEP; older ones are deleted.
+fn keep() -> usize {
+    env::var("NOTIFICATION_ARCHIVE_KEEP").ok().and_then(|v| v.parse().ok()).unwrap_or(20)
+}
+
+pub fn record(body: &str) {
+    let Some(dir) = archive_dir() else { return };
+    let _guard = ARCHIVE_LOCK.lock();
+    if let Err(e) = append(&dir, body) {
+        log!("Failed to archive notifications to {}: {}", dir.display(), e);
+    }
+}
+
+fn append(dir: &Path, body: &str) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let active = dir.join(ACTIVE_FILE);
+    let line = serde_json::to_string(&Recorded::NexusNotifications { recorded_at: now_millis(), body: body.to_string() })?;
+    let mut file = OpenOptions::new().create(true).append(true).open(&active)?;
+    writeln!(file, "{}", line)?;
+    if file.metadata()?.len() >= max_bytes() {
+        drop(file);
+        rotate(dir, &active)?;
+    }
+    Ok(())
+}
+
+// Closes the active segment, compresses it in the background and drops the oldest
+// segments beyond the ones kept.
+fn rotate(dir: &Path, active: &Path) -> std::io::Result<()> {
+    let closed = dir.join(format!("notifications-{}.jsonl", now_millis()));
+    fs::rename(active, &closed)?;
+    log!("Rotated notification archive to {}", closed.display());
+    std::thread::spawn(move || compress(&closed));
+    prune(dir)
+}
+
+// Replaces the segment with `<segment>.gz`; a segment that can't be compressed stays as
+// plain JSON lines. Holds ARCHIVE_LOCK, so `forget_user` can't scrub the segment while
+// a copy of its old contents is being compressed.
+fn compress(path: &Path) {
+    let _guard = ARCHIVE_LOCK.lock();
+    let mut target = path.as_os_str().to_owned();
+    target.push(".gz");
+    let tmp = path.with_extension("gz.tmp");
+    let result = fs::read(path).and_then(|data| fs::write(&tmp, gzip::compress(&data))).and_then(|()| fs::rename(&tmp, &target)).and_then(|()| fs::remove_file(path));
+    if let Err(e) = result {
+        log!("Failed to compress {}, leaving it uncompressed: {}", path.display(), e);
+        let _ = fs::remove_file(&tmp);
+    }
+}
+
+// A finished segment: rotated, compressed or not, but not a leftover `.gz.tmp`.
+fn is_segment(name: &str) -> bool {
+    name.starts_with("notifications-") && (name.ends_with(".jsonl") || name.ends_with(".jsonl.gz"))
+}
+
+fn prune(dir: &Path) -> std::io::Result<()> {
+    let mut segments: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.file_name().and_then(|n| n.to_str()).is_some_and(is_segment))
+        .collect();
+    segments.sort();
+    let excess = segments.len().saturating_sub(keep());
+    for path in segments.into_iter().take(excess) {
+        fs::remove_file(&path)?;
+        log!("Deleted old notification archive {}", path.display());
+    }
+    Ok(())
+}
+
+// Removes `user`'s notifications from every segment, compressed or not, and returns how
+// many went.
+pub fn forget_user(user: &str) -> std::io::Result<usize> {
+    let Some(dir) = archive_dir() else { return Ok(0) };
+    let _guard = ARCHIVE_LOCK.lock();
+    let segments = match fs::read_dir(&dir) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok().map(|e| e.path())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    let mut removed = 0;
+    for path in segments {
+        let Some(name) = path.file_name().and_then(|n| n.to_str()).map(String::from) else { continue };
+        let compressed = name.ends_with(".jsonl.gz");
+        if !name.starts_with("notifications") || !(compressed || name.ends_with(".jsonl")) {
+            continue;
+        }
+        let data = fs::read(&path)?;
+        let data = if compressed { gzip::decompress(&data).map_err(std::io::Error::other)? } else { data };
+        let (kept, count) = replay::forget_user_in(&String::from_utf8_lossy(&data), user);
+        if count == 0 {
+            continue;
+        }
+        let kept = if compressed { gzip::compress(kept.as_bytes()) } else { kept.into_bytes() };
+        let tmp = dir.join(format!(".{}.tmp", name));
+        fs::write(&tmp, kept)?;
+        fs::rename(&tmp, &path)?;
+        removed += count;
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_finished_segments_are_pruned() {
+        assert!(is_segment("notifications-1700000000000.jsonl"));
+        assert!(is_segment("notifications-1700000000000.jsonl.gz"));
+        assert!(!is_segment("notifications-1700000000000.gz.tmp"));
+        assert!(!is_segment(".notifications-1700000000000.jsonl.gz.tmp"));
+        assert!(!is_segment(ACTIVE_FILE));
+    }
+}
@@ -0,0 +1,232 @@
+use crate::budget;
+use crate::error::{BotError, BotResult};
+use crate::homeserver::{get_cached, get_file_limited};
+use crate::http;
+use crate::llm::openai_url;
+use crate::llm_queue::{self, Priority};
+use crate::models;
+use crate::privacy;
+use crate::secrets::Secret;
+use base64::Engine;
+use pubky::Client;
+use pubky_app_specs::PubkyAppFile;
+use serde::Deserialize;
+use serde_json::json;
+use std::env;
+
+const NO_TEXT: &str = "NO_TEXT";
+const OCR_INSTRUCTION: &str = "If this image is a screenshot or photo containing readable text (error messages, code, config files, terminal output, documents), transcribe that text exactly, keeping line breaks. Reply only with the transcription. If there is no meaningful text, reply with NO_TEXT.";
+// Images are billed by size, so a rough flat estimate is enough for rate limiting
+const IMAGE_TOKENS: usize = 1000;
+const OCR_MAX_TOKENS: i32 = 1000;
+const TRANSCRIPTION_MODEL: &str = "whisper-1";
+// The transcription API rejects uploads over 25 MB
+const MAX_AUDIO_MB: usize = 25;
+const DEFAULT_MIME_TYPES: &str = "image/png,image/jpeg,image/gif,image/webp,audio/*";
+
+#[derive(Debug, Deserialize)]
+struct Transcription {
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponse {
+    choices: Vec<Choice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Choice {
+    message: Message,
+}
+
+#[derive(Debug, Deserialize)]
+struct Message {
+    content: String,
+}
+
+// Reading text out of attached images, enabled with OCR=true.
+pub fn is_ocr_enabled() -> bool {
+    env::var("OCR").map(|v| v == "true").unwrap_or(false)
+}
+
+// Transcribing audio attachments (voice notes), enabled with TRANSCRIBE_AUDIO=true.
+pub fn is_transcription_enabled() -> bool {
+    env::var("TRANSCRIBE_AUDIO").map(|v| v == "true").unwrap_or(false)
+}
+
+pub fn is_enabled() -> bool {
+    is_ocr_enabled() || is_transcription_enabled()
+}
+
+fn max_image_bytes() -> usize {
+    env::var("OCR_MAX_MB").ok().and_then(|v| v.parse::<usize>().ok()).unwrap_or(5) * 1024 * 1024
+}
+
+fn max_audio_bytes() -> usize {
+    env::var("AUDIO_MAX_MB").ok().and_then(|v| v.parse::<usize>().ok()).unwrap_or(MAX_AUDIO_MB).min(MAX_AUDIO_MB) * 1024 * 1024
+}
+
+// Content types of attachments that are downloaded at all, from ATTACHMENT_MIME_TYPES
+// (comma-separated, `type/*` matches a whole type).
+fn is_allowed_type(content_type: &str) -> bool {
+    let allowed = env::var("ATTACHMENT_MIME_TYPES").ok().filter(|v| !v.trim().is_empty()).unwrap_or_else(|| DEFAULT_MIME_TYPES.to_string());
+    let content_type = content_type.split(';').next().unwrap_or_default().trim().to_lowercase();
+    allowed.split(',').map(|t| t.trim().to_lowercase()).any(|t| match t.strip_suffix("/*") {
+        Some(prefix) => content_type.split_once('/').is_some_and(|(kind, _)| kind == prefix),
+        None => t == content_type,
+    })
+}
+
+// Loads an attachment's file record.
+async fn fetch_file(client: &Client, uri: &str) -> BotResult<Option<PubkyAppFile>> {
+    let Some(body) = get_cached(client, uri).await? else {
+        return Ok(None);
+    };
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| BotError::Validation(format!("Attachment {} is not a file record: {}", uri, e)))
+}
+
+// Downloads an attachment's blob, aborting once it grows past `max_bytes` whatever
+// size its file record claims.
+async fn fetch_blob(client: &Client, file: &PubkyAppFile, max_bytes: usize) -> BotResult<Vec<u8>> {
+    get_file_limited(client, &file.src, max_bytes).await?.ok_or_else(|| BotError::Homeserver(format!("Attachment blob {} not found", file.src)))
+}
+
+// Turns the post's attachments into text for the prompt: the text shown in images
+// (e.g. a screenshot of an error message) and transcripts of audio. Attachments
+// that yield nothing are skipped; None when nothing was found.
+pub async fn attachment_text(client: &Client, attachments: &[String]) -> Option<String> {
+    let mut sections = Vec::new();
+    for uri in attachments {
+        let file = match fetch_file(client, uri).await {
+            Ok(Some(file)) => file,
+            Ok(None) => continue,
+            Err(e) => {
+                log!("Failed to load attachment {}: {}", privacy::uri(uri), e);
+                continue;
+            }
+        };
+        if !is_allowed_type(&file.content_type) {
+            log!("Skipping attachment {}: {} is not an allowed type", file.name, file.content_type);
+            continue;
+        }
+        let section = if is_ocr_enabled() && file.content_type.starts_with("image/") {
+            image_text(client, &file).await.map(|text| text.map(|t| format!("[Text from the attached image]\n{}", t)))
+        } else if is_transcription_enabled() && file.content_type.starts_with("audio/") {
+            transcribe(client, &file).await.map(|text| text.map(|t| format!("[Transcript of the attached audio]\n{}", t)))
+        } else {
+            Ok(None)
+        };
+        match section {
+            Ok(Some(section)) => sections.push(section),
+            Ok(None) => {}
+            Err(e) => log!("Failed to read attachment {}: {}", privacy::uri(uri), e),
+        }
+    }
+    (!sections.is_empty()).then(|| sections.join("\n\n"))
+}
+
+async fn image_text(client: &Client, file: &PubkyAppFile) -> BotResult<Option<String>> {
+    if file.content_type == "image/svg+xml" {
+        return Ok(None);
+    }
+    if file.size > max_image_bytes() {
+        log!("Skipping OCR for {}: {} bytes is over the limit", file.name, file.size);
+        return Ok(None);
+    }
+    let bytes = fetch_blob(client, file, max_image_bytes()).await?;
+    log!("Running OCR on attachment {} ({})", file.name, file.content_type);
+
+    let data_url = format!("data:{};base64,{}", file.content_type, base64::engine::general_purpose::STANDARD.encode(&bytes));
+    let text = vision_completion(&data_url).await?;
+    let text = text.trim();
+    if text.is_empty() || text.contains(NO_TEXT) {
+        return Ok(None);
+    }
+    Ok(Some(text.to_string()))
+}
+
+async fn vision_completion(image_url: &str) -> BotResult<String> {
+    let api_key = Secret::from_env("OPENAI_API_KEY").ok_or_else(|| BotError::Validation("OPENAI_API_KEY not found in .env".to_string()))?;
+    budget::take(Priority::Mention, IMAGE_TOKENS + OCR_MAX_TOKENS as usize)?;
+    llm_queue::acquire(Priority::Mention, IMAGE_TOKENS + OCR_MAX_TOKENS as usize).await;
+    let model = models::resolve("vision");
+
+    let response = http::llm()
+        .post(openai_url("chat/completions"))
+        .header("Authorization", format!("Bearer {}", api_key.expose()))
+        .json(&json!({
+            "model": model,
+            "temperature": 0.0,
+            "max_tokens": OCR_MAX_TOKENS,
+            "messages": [{
+                "role": "user",
+                "content": [
+                    { "type": "text", "text": OCR_INSTRUCTION },
+                    { "type": "image_url", "image_url": { "url": image_url } },
+                ],
+            }],
+        }))
+        .send()
+        .await
+        .map_err(BotError::llm)?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        // The next image goes to the successor
+        if models::is_retired_error(status, &body) {
+            models::retire(&model);
+        }
+        return Err(BotError::Llm(format!("Vision request failed: {} {}", status, body)));
+    }
+    let chat_response = response.json::<ChatResponse>().await.map_err(BotError::llm)?;
+    chat_response
+        .choices
+        .into_iter()
+        .next()
+        .map(|choice| choice.message.content)
+        .ok_or_else(|| BotError::Llm("Vision response contained no choices".to_string()))
+}
+
+// Transcribes an audio attachment with the Whisper API.
+async fn transcribe(client: &Client, file: &PubkyAppFile) -> BotResult<Option<String>> {
+    if file.size > max_audio_bytes() {
+        log!("Skipping transcription of {}: {} bytes is over the limit", file.name, file.size);
+        return Ok(None);
+    }
+    let bytes = fetch_blob(client, file, max_audio_bytes()).await?;
+    log!("Transcribing attachment {} ({})", file.name, file.content_type);
+    let api_key = Secret::from_env("OPENAI_API_KEY").ok_or_else(|| BotError::Validation("OPENAI_API_KEY not found in .env".to_string()))?;
+
+    // Multipart form with the model name and the audio file
+    let boundary = format!("ai-rand-{}", crate::memory::now_millis());
+    let filename = file.name.replace(['"', '\r', '\n'], "_");
+    let mut body = format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"model\"\r\n\r\n{model}\r\n--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{name}\"\r\nContent-Type: {ct}\r\n\r\n",
+        b = boundary,
+        model = TRANSCRIPTION_MODEL,
+        name = filename,
+        ct = file.content_type,
+    )
+    .into_bytes();
+    body.extend_from_slice(&bytes);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+
+    let response = http::llm()
+        .post(openai_url("audio/transcriptions"))
+        .header("Authorization", format!("Bearer {}", api_key.expose()))
+        .header("Content-Type", format!("multipart/form-data; boundary={}", boundary))
+        .body(body)
+        .send()
+        .await
+        .map_err(BotError::llm)?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(BotError::Llm(format!("Transcription request failed: {} {}", status, body)));
+    }
+    let text = response.json::<Transcription>().await.map_err(BotError::llm)?.text;
+    let text = text.trim();
+    Ok((!text.is_empty()).then(|| text.to_string()))
+}
@@ -0,0 +1,275 @@
+use crate::error::{BotError, BotResult};
+use crate::homeserver;
+use crate::migrations;
+use crate::storage;
+use pubky::{Client, Keypair, PublicKey};
+use pubky_common::auth::AuthToken;
+use pubky_common::capabilities::Capability;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+// Secondary homeserver holding a copy of everything the bot writes, from
+// BACKUP_HOMESERVER. The bot's PKARR record keeps pointing at the primary, so readers
+// only see the backup's copy if the operator moves the bot there.
+struct Backup {
+    host: String,
+    user: String,
+}
+
+static BACKUP: OnceLock<Backup> = OnceLock::new();
+
+// Files whose copies differ between the homeservers: written to the backup while the
+// primary was unreachable, or not yet mirrored because the backup was. Kept in
+// BACKUP_STATE so a restart resumes the sync.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Outbox {
+    // Format version, see migrations::BACKUP_STATE
+    #[serde(default)]
+    schema_version: u32,
+    to_primary: BTreeSet<String>,
+    to_backup: BTreeSet<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupStats {
+    pub host: String,
+    pub pending_to_primary: usize,
+    pub pending_to_backup: usize,
+}
+
+fn configured_host() -> Option<String> {
+    env::var("BACKUP_HOMESERVER").ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+fn state_path() -> String {
+    env::var("BACKUP_STATE").unwrap_or_else(|_| "backup-state.json".to_string())
+}
+
+fn outbox() -> &'static Mutex<Outbox> {
+    static OUTBOX: OnceLock<Mutex<Outbox>> = OnceLock::new();
+    OUTBOX.get_or_init(|| {
+        let mut outbox: Outbox = migrations::upgrade_or_set_aside(Path::new(&state_path()), &migrations::BACKUP_STATE)
+            .and_then(|value| serde_json::from_value(value).map_err(|e| log!("Failed to read the backup sync state: {}", e)).ok())
+            .unwrap_or_default();
+        outbox.schema_version = migrations::BACKUP_STATE.version();
+        Mutex::new(outbox)
+    })
+}
+
+fn update(change: impl FnOnce(&mut Outbox)) {
+    let Ok(mut outbox) = outbox().lock() else { return };
+    change(&mut outbox);
+    let result = serde_json::to_string(&*outbox).map_err(anyhow::Error::from).and_then(|json| {
+        let tmp = format!("{}.tmp", state_path());
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, state_path())?;
+        Ok(())
+    });
+    if let Err(e) = result {
+        log!("Failed to save backup sync state: {}", e);
+    }
+}
+
+pub fn is_enabled() -> bool {
+    BACKUP.get().is_some()
+}
+
+// Opens a session on the backup homeserver, creating the bot's account there on first
+// use (with BACKUP_SIGNUP_TOKEN if the server requires one). Unlike `Client::signup`
+// this doesn't publish the backup in the bot's PKARR record.
+pub async fn connect(client: &Client, keypair: &Keypair) -> anyhow::Result<()> {
+    let Some(host) = configured_host() else {
+        return Ok(());
+    };
+    PublicKey::try_from(host.as_str()).map_err(|e| anyhow::anyhow!("BACKUP_HOMESERVER is not a homeserver public key: {}", e))?;
+    let user = keypair.public_key().to_string();
+    let token = || AuthToken::sign(keypair, vec![Capability::root()]).serialize();
+
+    let mut signup_url = format!("https://{}/signup", host);
+    if let Ok(signup_token) = env::var("BACKUP_SIGNUP_TOKEN") {
+        signup_url.push_str(&format!("?signup_token={}", signup_token));
+    }
+    let response = client.post(&signup_url).header("pubky-host", &user).body(token()).send().await?;
+    if !response.status().is_success() {
+        // Most likely the account already exists: sign in instead
+        let response = client.post(format!("https://{}/session", host)).header("pubky-host", &user).body(token()).send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("Could not sign in to the backup homeserver {}: {}", host, response.status()));
+        }
+    }
+    log!("Connected to backup homeserver {}", host);
+    let _ = BACKUP.set(Backup { host, user });
+    Ok(())
+}
+
+// The backup's URL for one of the bot's own pubky:// URIs; None for other users' files.
+fn backup_url(uri: &str) -> Option<String> {
+    let backup = BACKUP.get()?;
+    let path = uri.strip_prefix("pubky://")?.strip_prefix(backup.user.as_str())?;
+    path.starts_with('/').then(|| format!("https://{}{}", backup.host, path))
+}
+
+// Whether a request to the primary failed because the homeserver is down or
+// unreachable, rather than rejecting the request.
+pub fn primary_unreachable(sent: &Result<reqwest::Response, reqwest::Error>) -> bool {
+    match sent {
+        Ok(response) => response.status().is_server_error(),
+        Err(e) => e.is_connect() || e.is_timeout() || e.is_request(),
+    }
+}
+
+async fn backup_request(client: &Client, method: reqwest::Method, uri: &str, body: Option<Vec<u8>>) -> BotResult<reqwest::Response> {
+    let (Some(url), Some(backup)) = (backup_url(uri), BACKUP.get()) else {
+        return Err(BotError::Homeserver(format!("{} can't be stored on the backup homeserver", uri)));
+    };
+    let mut request = client.request(method, &url).header("pubky-host", &backup.user);
+    if let Some(body) = body {
+        request = request.body(body);
+    }
+    request.send().await.map_err(BotError::homeserver)
+}
+
+async fn put_backup(client: &Client, uri: &str, body: Vec<u8>) -> BotResult<()> {
+    let response = backup_request(client, reqwest::Method::PUT, uri, Some(body)).await?;
+    if !response.status().is_success() {
+        return Err(BotError::Homeserver(format!("Failed to write {} to the backup: {}", uri, response.status())));
+    }
+    Ok(())
+}
+
+async fn delete_backup(client: &Client, uri: &str) -> BotResult<()> {
+    let response = backup_request(client, reqwest::Method::DELETE, uri, None).await?;
+    let status = response.status();
+    if !status.is_success() && status != reqwest::StatusCode::NOT_FOUND {
+        return Err(BotError::Homeserver(format!("Failed to delete {} from the backup: {}", uri, status)));
+    }
+    Ok(())
+}
+
+// The backup's copy of one of the bot's files, or None if it has none.
+pub async fn get(client: &Client, uri: &str) -> BotResult<Option<Vec<u8>>> {
+    let response = backup_request(client, reqwest::Method::GET, uri, None).await?;
+    let status = response.status();
+    if status == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !status.is_success() {
+        return Err(BotError::Homeserver(format!("Fetching {} from the backup failed: {}", uri, status)));
+    }
+    Ok(Some(response.bytes().await.map_err(BotError::homeserver)?.to_vec()))
+}
+
+// Copies a write that reached the primary. A failure only queues the file for the
+// next sync, it never fails the write.
+pub async fn mirror_put(client: &Client, uri: &str, body: Vec<u8>) {
+    if backup_url(uri).is_none() {
+        return;
+    }
+    if let Err(e) = put_backup(client, uri, body).await {
+        log!("Backup homeserver: {}; will retry", e);
+        update(|outbox| {
+            outbox.to_backup.insert(uri.to_string());
+        });
+    }
+}
+
+pub async fn mirror_delete(client: &Client, uri: &str) {
+    if backup_url(uri).is_none() {
+        return;
+    }
+    if let Err(e) = delete_backup(client, uri).await {
+        log!("Backup homeserver: {}; will retry", e);
+        update(|outbox| {
+            outbox.to_backup.insert(uri.to_string());
+        });
+    }
+}
+
+// Writes to the backup instead of the unreachable primary, and queues the file to be
+// copied back once the primary recovers.
+pub async fn failover_put(client: &Client, uri: &str, body: Vec<u8>) -> BotResult<()> {
+    put_backup(client, uri, body).await?;
+    log!("Primary homeserver unreachable, wrote {} to the backup", uri);
+    update(|outbox| {
+        outbox.to_backup.remove(uri);
+        outbox.to_primary.insert(uri.to_string());
+    });
+    Ok(())
+}
+
+pub async fn failover_delete(client: &Client, uri: &str) -> BotResult<()> {
+    delete_backup(client, uri).await?;
+    log!("Primary homeserver unreachable, deleted {} from the backup", uri);
+    update(|outbox| {
+        outbox.to_backup.remove(uri);
+        outbox.to_primary.insert(uri.to_string());
+    });
+    Ok(())
+}
+
+// Queues files for a full copy to the backup, e.g. everything written before the
+// backup was configured.
+pub fn queue_for_backup(uris: impl IntoIterator<Item = String>) {
+    update(|outbox| outbox.to_backup.extend(uris.into_iter().filter(|uri| backup_url(uri).is_some())));
+}
+
+// Brings both homeservers back in line: files written to the backup during an outage
+// are copied to the primary, and writes the backup missed are copied to it. Each file
+// is copied as it is now, so a file deleted since is deleted on the other side too.
+// Stops at the first failure and leaves the rest for the next run.
+pub async fn sync(client: &Client) -> BotResult<usize> {
+    let Ok((to_primary, to_backup)) = outbox().lock().map(|o| (o.to_primary.clone(), o.to_backup.clone())) else {
+        return Ok(0);
+    };
+    let mut synced = 0;
+    for uri in to_primary {
+        let response = match get(client, &uri).await? {
+            Some(body) => {
+                storage::record_write(&uri, body.len() as u64);
+                client.put(&uri).body(body).send().await
+            }
+            None => {
+                storage::record_delete(&uri);
+                client.delete(&uri).send().await
+            }
+        };
+        let response = response.map_err(BotError::homeserver)?;
+        if !response.status().is_success() && response.status() != reqwest::StatusCode::NOT_FOUND {
+            return Err(BotError::Homeserver(format!("Copying {} back to the primary failed: {}", uri, response.status())));
+        }
+        homeserver::invalidate(&uri);
+        update(|outbox| {
+            outbox.to_primary.remove(&uri);
+        });
+        synced += 1;
+    }
+    for uri in to_backup {
+        // Files that also changed during an outage are settled above first
+        let response = client.get(&uri).send().await.map_err(BotError::homeserver)?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            delete_backup(client, &uri).await?;
+        } else if response.status().is_success() {
+            put_backup(client, &uri, response.bytes().await.map_err(BotError::homeserver)?.to_vec()).await?;
+        } else {
+            return Err(BotError::Homeserver(format!("Reading {} for the backup failed: {}", uri, response.status())));
+        }
+        update(|outbox| {
+            outbox.to_backup.remove(&uri);
+        });
+        synced += 1;
+    }
+    if synced > 0 {
+        log!("Backup homeserver sync copied {} file(s)", synced);
+    }
+    Ok(synced)
+}
+
+pub fn stats() -> Option<BackupStats> {
+    let backup = BACKUP.get()?;
+    let outbox = outbox().lock().ok()?;
+    Some(BackupStats { host: backup.host.clone(), pending_to_primary: outbox.to_primary.len(), pending_to_backup: outbox.to_backup.len() })
+}
@@ -0,0 +1,111 @@
+use crate::context::PromptVars;
+use crate::kb;
+use crate::llm::{generate_response, PromptContext};
+use crate::mock_llm;
+use crate::prompt;
+use anyhow::Result;
+use dotenv::dotenv;
+use std::env;
+use std::fs;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+const QUESTIONS: &[&str] = &[
+    "What is Pubky and how does it differ from other social networks?",
+    "How do I run my own homeserver?",
+    "What are tags used for?",
+    "Can I move my account to another homeserver?",
+];
+
+// `bench [--iterations N]`: times the knowledge base and reply pipeline against the
+// mock LLM, so the numbers reflect the bot's own code rather than the provider. Uses
+// the configured knowledge base, prompt and features, but a throwaway KB index and
+// analytics log.
+pub async fn run(args: &[String]) -> Result<()> {
+    let iterations = match args {
+        [] => 20,
+        [flag, n] if flag == "--iterations" => n.parse::<usize>().ok().filter(|n| *n > 0).ok_or_else(|| anyhow::anyhow!("--iterations needs a positive number"))?,
+        _ => return Err(anyhow::anyhow!("Usage: bench [--iterations N]")),
+    };
+    dotenv().ok();
+    let scratch = env::temp_dir().join(format!("ai-rand-bench-{}", std::process::id()));
+    fs::create_dir_all(&scratch)?;
+    let index_path = scratch.join("kb-index.json");
+    env::set_var("KB_INDEX", &index_path);
+    env::set_var("ANALYTICS_LOG", scratch.join("analytics.jsonl"));
+    env::set_var("KB_RETRIEVAL", "true");
+    // Only the mock answers: no rate limiting, no second provider
+    env::set_var("LLM_RPM", "0");
+    env::set_var("LLM_TPM", "0");
+    env::set_var("RACE_API_URL", "");
+    mock_llm::install().await?;
+    println!("Benchmarking {} iteration(s) per stage", iterations);
+
+    let mut results = Vec::new();
+    results.push(measure("KB indexing (cold)", iterations, |_| async {
+        let _ = fs::remove_file(&index_path);
+        kb::build_index(None).await?;
+        Ok(())
+    })
+    .await?);
+    // Leave a warm index behind for retrieval
+    kb::knowledge_for(QUESTIONS[0], None).await?;
+    results.push(measure("KB retrieval", iterations, |i| async move {
+        kb::knowledge_for(QUESTIONS[i % QUESTIONS.len()], None).await?;
+        Ok(())
+    })
+    .await?);
+    let knowledge = kb::knowledge_for(QUESTIONS[0], None).await?;
+    let knowledge = &knowledge;
+    results.push(measure("prompt build", iterations, |_| async move {
+        prompt::system_prompt(knowledge, &PromptVars::basic())?;
+        Ok(())
+    })
+    .await?);
+    results.push(measure("generation (end to end)", iterations, |i| async move {
+        generate_response(QUESTIONS[i % QUESTIONS.len()], &PromptContext::default()).await?;
+        Ok(())
+    })
+    .await?);
+
+    println!();
+    println!("{:<26} {:>10} {:>10} {:>10} {:>10}", "stage", "min", "median", "p95", "max");
+    for (name, timings) in &results {
+        println!(
+            "{:<26} {:>10} {:>10} {:>10} {:>10}",
+            name,
+            format_ms(timings[0]),
+            format_ms(percentile(timings, 50)),
+            format_ms(percentile(timings, 95)),
+            format_ms(timings[timings.len() - 1])
+        );
+    }
+    let _ = fs::remove_dir_all(&scratch);
+    Ok(())
+}
+
+// Runs `work` `iterations` times and returns the sorted timings.
+async fn measure<F, Fut>(name: &'static str, iterations: usize, work: F) -> Result<(&'static str, Vec<Duration>)>
+where
+    F: Fn(usize) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    println!("Measuring {}...", name);
+    let mut timings = Vec::with_capacity(iterations);
+    for i in 0..iterations {
+        let started = Instant::now();
+        work(i).await?;
+        timings.push(started.elapsed());
+    }
+    timings.sort();
+    Ok((name, timings))
+}
+
+fn percentile(sorted: &[Duration], p: usize) -> Duration {
+    let index = (sorted.len() * p).div_ceil(100).saturating_sub(1);
+    sorted[index.min(sorted.len() - 1)]
+}
+
+fn format_ms(duration: Duration) -> String {
+    format!("{:.2} ms", duration.as_secs_f64() * 1000.0)
+}
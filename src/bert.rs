@@ -0,0 +1,605 @@
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+// A BERT sentence-embedding model run in the bot process, for EMBEDDING_BACKEND=builtin.
+// Loads a sentence-transformers model directory as published on Hugging Face (e.g.
+// all-MiniLM-L6-v2): config.json, vocab.txt and model.safetensors, plus the optional
+// tokenizer_config.json, sentence_bert_config.json and 1_Pooling/config.json. A text is
+// tokenized with WordPiece, run through the encoder on the CPU and pooled into one
+// unit-length vector.
+
+// Longest word WordPiece splits; longer ones become [UNK], as in BERT
+const MAX_WORD_CHARS: usize = 100;
+
+#[derive(Debug, Deserialize)]
+struct Config {
+    hidden_size: usize,
+    num_hidden_layers: usize,
+    num_attention_heads: usize,
+    #[serde(default = "default_max_positions")]
+    max_position_embeddings: usize,
+    #[serde(default = "default_eps")]
+    layer_norm_eps: f32,
+    #[serde(default = "default_act")]
+    hidden_act: String,
+}
+
+fn default_max_positions() -> usize {
+    512
+}
+
+fn default_eps() -> f32 {
+    1e-12
+}
+
+fn default_act() -> String {
+    "gelu".to_string()
+}
+
+// A weight matrix stored as PyTorch does, one row per output: [out, in].
+struct Linear {
+    weight: Vec<f32>,
+    bias: Vec<f32>,
+    inputs: usize,
+}
+
+struct LayerNorm {
+    weight: Vec<f32>,
+    bias: Vec<f32>,
+}
+
+struct Layer {
+    query: Linear,
+    key: Linear,
+    value: Linear,
+    attention_output: Linear,
+    attention_norm: LayerNorm,
+    intermediate: Linear,
+    output: Linear,
+    output_norm: LayerNorm,
+}
+
+pub struct Bert {
+    tokenizer: WordPiece,
+    word_embeddings: Vec<f32>,
+    position_embeddings: Vec<f32>,
+    token_type_embeddings: Vec<f32>,
+    embedding_norm: LayerNorm,
+    layers: Vec<Layer>,
+    hidden: usize,
+    heads: usize,
+    eps: f32,
+    tanh_gelu: bool,
+    max_tokens: usize,
+    // [CLS] pooling instead of the mean of all tokens
+    cls_pooling: bool,
+}
+
+impl Bert {
+    pub fn load(dir: &Path) -> Result<Bert> {
+        let read_json = |name: &str| -> Result<Option<Value>> {
+            match fs::read_to_string(dir.join(name)) {
+                Ok(content) => Ok(Some(serde_json::from_str(&content).with_context(|| format!("{} in {}", name, dir.display()))?)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(anyhow!("Failed to read {} in {}: {}", name, dir.display(), e)),
+            }
+        };
+        let config: Config = serde_json::from_value(read_json("config.json")?.ok_or_else(|| anyhow!("No config.json in {}", dir.display()))?)?;
+        let lowercase = read_json("tokenizer_config.json")?.and_then(|c| c["do_lower_case"].as_bool()).unwrap_or(true);
+        let vocab = fs::read_to_string(dir.join("vocab.txt")).with_context(|| format!("No vocab.txt in {}", dir.display()))?;
+        let tokenizer = WordPiece::new(&vocab, lowercase)?;
+        let max_seq = read_json("sentence_bert_config.json")?.and_then(|c| c["max_seq_length"].as_u64()).map(|n| n as usize);
+        let cls_pooling = read_json("1_Pooling/config.json")?.and_then(|c| c["pooling_mode_cls_token"].as_bool()).unwrap_or(false);
+        let data = fs::read(dir.join("model.safetensors")).with_context(|| format!("No model.safetensors in {}", dir.display()))?;
+        Bert::from_parts(config, tokenizer, &SafeTensors::parse(&data)?, max_seq, cls_pooling)
+    }
+
+    fn from_parts(config: Config, tokenizer: WordPiece, tensors: &SafeTensors, max_seq: Option<usize>, cls_pooling: bool) -> Result<Bert> {
+        let hidden = config.hidden_size;
+        if config.num_attention_heads == 0 || !hidden.is_multiple_of(config.num_attention_heads) {
+            bail!("hidden_size {} isn't divisible by {} attention heads", hidden, config.num_attention_heads);
+        }
+        let tanh_gelu = match config.hidden_act.as_str() {
+            "gelu" => false,
+            "gelu_new" | "gelu_pytorch_tanh" => true,
+            other => bail!("Unsupported activation {}", other),
+        };
+        let norm = |name: &str| -> Result<LayerNorm> { Ok(LayerNorm { weight: tensors.get(&format!("{}.weight", name), &[hidden])?, bias: tensors.get(&format!("{}.bias", name), &[hidden])? }) };
+        let linear = |name: &str| -> Result<Linear> {
+            let shape = tensors.shape(&format!("{}.weight", name))?;
+            let [outputs, inputs] = shape[..] else { bail!("{}.weight isn't a matrix", name) };
+            Ok(Linear { weight: tensors.get(&format!("{}.weight", name), &[outputs, inputs])?, bias: tensors.get(&format!("{}.bias", name), &[outputs])?, inputs })
+        };
+        let mut layers = Vec::with_capacity(config.num_hidden_layers);
+        for i in 0..config.num_hidden_layers {
+            let prefix = format!("encoder.layer.{}", i);
+            layers.push(Layer {
+                query: linear(&format!("{}.attention.self.query", prefix))?,
+                key: linear(&format!("{}.attention.self.key", prefix))?,
+                value: linear(&format!("{}.attention.self.value", prefix))?,
+                attention_output: linear(&format!("{}.attention.output.dense", prefix))?,
+                attention_norm: norm(&format!("{}.attention.output.LayerNorm", prefix))?,
+                intermediate: linear(&format!("{}.intermediate.dense", prefix))?,
+                output: linear(&format!("{}.output.dense", prefix))?,
+                output_norm: norm(&format!("{}.output.LayerNorm", prefix))?,
+            });
+        }
+        let rows = |name: &str| -> Result<usize> { tensors.shape(name)?.first().copied().ok_or_else(|| anyhow!("{} is empty", name)) };
+        // Some checkpoints pad the embedding matrix past the vocabulary
+        let vocab_rows = rows("embeddings.word_embeddings.weight")?;
+        if tokenizer.vocab.values().any(|id| *id >= vocab_rows) {
+            bail!("vocab.txt has more tokens than the model's {} embeddings", vocab_rows);
+        }
+        let position_rows = rows("embeddings.position_embeddings.weight")?;
+        let positions = position_rows.min(config.max_position_embeddings);
+        Ok(Bert {
+            word_embeddings: tensors.get("embeddings.word_embeddings.weight", &[vocab_rows, hidden])?,
+            position_embeddings: tensors.get("embeddings.position_embeddings.weight", &[position_rows, hidden])?,
+            token_type_embeddings: tensors.get("embeddings.token_type_embeddings.weight", &[rows("embeddings.token_type_embeddings.weight")?, hidden])?,
+            embedding_norm: norm("embeddings.LayerNorm")?,
+            tokenizer,
+            layers,
+            hidden,
+            heads: config.num_attention_heads,
+            eps: config.layer_norm_eps,
+            tanh_gelu,
+            max_tokens: max_seq.unwrap_or(positions).min(positions),
+            cls_pooling,
+        })
+    }
+
+    // The text's embedding, normalized to unit length. Text past the model's sequence
+    // length is left out, as sentence-transformers does.
+    pub fn embed(&self, text: &str) -> Vec<f32> {
+        let ids = self.tokenizer.encode(text, self.max_tokens);
+        let (tokens, hidden) = (ids.len(), self.hidden);
+        let mut x = Vec::with_capacity(tokens * hidden);
+        for (position, id) in ids.iter().enumerate() {
+            let word = &self.word_embeddings[id * hidden..(id + 1) * hidden];
+            let position = &self.position_embeddings[position * hidden..(position + 1) * hidden];
+            x.extend(word.iter().zip(position).zip(&self.token_type_embeddings[..hidden]).map(|((w, p), t)| w + p + t));
+        }
+        layer_norm(&mut x, &self.embedding_norm, self.eps);
+        for layer in &self.layers {
+            x = self.layer(layer, x, tokens);
+        }
+
+        let mut pooled = if self.cls_pooling {
+            x[..hidden].to_vec()
+        } else {
+            let mut sum = vec![0.0; hidden];
+            for token in x.chunks(hidden) {
+                sum.iter_mut().zip(token).for_each(|(s, v)| *s += v);
+            }
+            sum.iter().map(|s| s / tokens as f32).collect()
+        };
+        let norm = pooled.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            pooled.iter_mut().for_each(|v| *v /= norm);
+        }
+        pooled
+    }
+
+    fn layer(&self, layer: &Layer, x: Vec<f32>, tokens: usize) -> Vec<f32> {
+        let hidden = self.hidden;
+        let head_size = hidden / self.heads;
+        let (q, k, v) = (layer.query.apply(&x), layer.key.apply(&x), layer.value.apply(&x));
+        let scale = 1.0 / (head_size as f32).sqrt();
+        let mut context = vec![0.0; tokens * hidden];
+        let mut scores = vec![0.0; tokens];
+        for head in 0..self.heads {
+            let offset = head * head_size;
+            for i in 0..tokens {
+                let qi = &q[i * hidden + offset..i * hidden + offset + head_size];
+                for (j, score) in scores.iter_mut().enumerate() {
+                    *score = dot(qi, &k[j * hidden + offset..j * hidden + offset + head_size]) * scale;
+                }
+                softmax(&mut scores);
+                let out = &mut context[i * hidden + offset..i * hidden + offset + head_size];
+                for (j, weight) in scores.iter().enumerate() {
+                    out.iter_mut().zip(&v[j * hidden + offset..j * hidden + offset + head_size]).for_each(|(o, v)| *o += weight * v);
+                }
+            }
+        }
+        let mut attended = layer.attention_output.apply(&context);
+        attended.iter_mut().zip(&x).for_each(|(a, x)| *a += x);
+        layer_norm(&mut attended, &layer.attention_norm, self.eps);
+
+        let mut intermediate = layer.intermediate.apply(&attended);
+        intermediate.iter_mut().for_each(|v| *v = if self.tanh_gelu { gelu_tanh(*v) } else { gelu(*v) });
+        let mut output = layer.output.apply(&intermediate);
+        output.iter_mut().zip(&attended).for_each(|(o, a)| *o += a);
+        layer_norm(&mut output, &layer.output_norm, self.eps);
+        output
+    }
+}
+
+impl Linear {
+    // `x` holds one row of `inputs` values per token. Tokens are split across threads,
+    // as this is nearly all of the work.
+    fn apply(&self, x: &[f32]) -> Vec<f32> {
+        let outputs = self.bias.len();
+        let tokens = x.len() / self.inputs;
+        let mut y = vec![0.0; tokens * outputs];
+        let threads = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1).min(tokens).max(1);
+        let per_thread = tokens.div_ceil(threads);
+        std::thread::scope(|scope| {
+            for (rows_in, rows_out) in x.chunks(per_thread * self.inputs).zip(y.chunks_mut(per_thread * outputs)) {
+                scope.spawn(move || {
+                    for (input, output) in rows_in.chunks(self.inputs).zip(rows_out.chunks_mut(outputs)) {
+                        for (o, (w, b)) in output.iter_mut().zip(self.weight.chunks(self.inputs).zip(&self.bias)) {
+                            *o = dot(input, w) + b;
+                        }
+                    }
+                });
+            }
+        });
+        y
+    }
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    // Eight running sums, which the compiler turns into SIMD
+    let mut sums = [0.0f32; 8];
+    let (a_chunks, b_chunks) = (a.chunks_exact(8), b.chunks_exact(8));
+    let tail: f32 = a_chunks.remainder().iter().zip(b_chunks.remainder()).map(|(x, y)| x * y).sum();
+    for (x, y) in a_chunks.zip(b_chunks) {
+        for i in 0..8 {
+            sums[i] += x[i] * y[i];
+        }
+    }
+    sums.iter().sum::<f32>() + tail
+}
+
+fn softmax(values: &mut [f32]) {
+    let max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let mut sum = 0.0;
+    for v in values.iter_mut() {
+        *v = (*v - max).exp();
+        sum += *v;
+    }
+    values.iter_mut().for_each(|v| *v /= sum);
+}
+
+fn layer_norm(x: &mut [f32], norm: &LayerNorm, eps: f32) {
+    let n = norm.weight.len();
+    for row in x.chunks_mut(n) {
+        let mean = row.iter().sum::<f32>() / n as f32;
+        let variance = row.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / n as f32;
+        let scale = 1.0 / (variance + eps).sqrt();
+        for ((v, w), b) in row.iter_mut().zip(&norm.weight).zip(&norm.bias) {
+            *v = (*v - mean) * scale * w + b;
+        }
+    }
+}
+
+fn gelu(x: f32) -> f32 {
+    0.5 * x * (1.0 + erf(x / std::f32::consts::SQRT_2))
+}
+
+fn gelu_tanh(x: f32) -> f32 {
+    0.5 * x * (1.0 + (0.797_884_6 * (x + 0.044_715 * x * x * x)).tanh())
+}
+
+// Abramowitz and Stegun 7.1.26, accurate to 1.5e-7, well below f32 noise here.
+fn erf(x: f32) -> f32 {
+    let t = 1.0 / (1.0 + 0.327_591_1 * x.abs() as f64);
+    let poly = t * (0.254_829_592 + t * (-0.284_496_736 + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    let y = 1.0 - poly * (-(x as f64) * x as f64).exp();
+    (if x < 0.0 { -y } else { y }) as f32
+}
+
+// BERT's tokenizer: text is cleaned, lowercased without accents (for uncased models),
+// split on whitespace and punctuation, and each word split into the longest vocabulary
+// pieces, continuations prefixed with `##`.
+struct WordPiece {
+    vocab: HashMap<String, usize>,
+    lowercase: bool,
+    unknown: usize,
+    cls: usize,
+    sep: usize,
+}
+
+impl WordPiece {
+    fn new(vocab: &str, lowercase: bool) -> Result<WordPiece> {
+        let vocab: HashMap<String, usize> = vocab.lines().enumerate().map(|(id, token)| (token.trim_end_matches('\r').to_string(), id)).collect();
+        let id = |token: &str| vocab.get(token).copied().ok_or_else(|| anyhow!("vocab.txt has no {}", token));
+        Ok(WordPiece { unknown: id("[UNK]")?, cls: id("[CLS]")?, sep: id("[SEP]")?, vocab, lowercase })
+    }
+
+    // Token IDs between [CLS] and [SEP], at most `max_tokens` in all.
+    fn encode(&self, text: &str, max_tokens: usize) -> Vec<usize> {
+        let mut ids = vec![self.cls];
+        let limit = max_tokens.max(2) - 1;
+        for word in self.words(text) {
+            for id in self.pieces(&word) {
+                if ids.len() == limit {
+                    ids.push(self.sep);
+                    return ids;
+                }
+                ids.push(id);
+            }
+        }
+        ids.push(self.sep);
+        ids
+    }
+
+    fn words(&self, text: &str) -> Vec<String> {
+        let mut words = Vec::new();
+        let mut word = String::new();
+        for c in text.chars() {
+            if c == '\0' || c == '\u{fffd}' || (c.is_control() && !c.is_whitespace()) {
+                continue;
+            }
+            if c.is_whitespace() || is_punctuation(c) || is_cjk(c) {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+                if !c.is_whitespace() {
+                    words.push(c.to_string());
+                }
+                continue;
+            }
+            word.push(c);
+        }
+        if !word.is_empty() {
+            words.push(word);
+        }
+        if self.lowercase {
+            words = words.into_iter().map(|w| w.to_lowercase().nfd().filter(|c| !is_combining_mark(*c)).collect()).collect();
+            words.retain(|w| !w.is_empty());
+        }
+        words
+    }
+
+    fn pieces(&self, word: &str) -> Vec<usize> {
+        let chars: Vec<char> = word.chars().collect();
+        if chars.len() > MAX_WORD_CHARS {
+            return vec![self.unknown];
+        }
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        while start < chars.len() {
+            let found = (start + 1..=chars.len()).rev().find_map(|end| {
+                let piece: String = chars[start..end].iter().collect();
+                let piece = if start > 0 { format!("##{}", piece) } else { piece };
+                self.vocab.get(&piece).map(|id| (end, *id))
+            });
+            let Some((end, id)) = found else { return vec![self.unknown] };
+            pieces.push(id);
+            start = end;
+        }
+        pieces
+    }
+}
+
+// BERT counts every non-alphanumeric ASCII symbol as punctuation, plus Unicode
+// punctuation, approximated here by its main blocks.
+fn is_punctuation(c: char) -> bool {
+    c.is_ascii_punctuation() || matches!(c as u32, 0xa1..=0xbf | 0x2000..=0x206f | 0x2e00..=0x2e7f | 0x3000..=0x303f | 0xfe30..=0xfe4f | 0xff01..=0xff0f | 0xff1a..=0xff20 | 0xff3b..=0xff40 | 0xff5b..=0xff65)
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32, 0x4e00..=0x9fff | 0x3400..=0x4dbf | 0x20000..=0x2a6df | 0x2a700..=0x2b73f | 0x2b740..=0x2b81f | 0x2b820..=0x2ceaf | 0xf900..=0xfaff | 0x2f800..=0x2fa1f)
+}
+
+// The tensors of a .safetensors file: a little-endian u64 header length, a JSON header
+// with each tensor's dtype, shape and byte range, then the data.
+struct SafeTensors<'a> {
+    tensors: HashMap<String, (String, Vec<usize>, &'a [u8])>,
+}
+
+impl<'a> SafeTensors<'a> {
+    fn parse(data: &'a [u8]) -> Result<SafeTensors<'a>> {
+        let header_len = data.get(..8).map(|b| u64::from_le_bytes(b.try_into().unwrap_or_default())).ok_or_else(|| anyhow!("Truncated safetensors file"))?;
+        // The length comes from the file, so it can't be trusted not to overflow
+        let header_end = usize::try_from(header_len).ok().and_then(|n| n.checked_add(8)).filter(|end| *end <= data.len()).ok_or_else(|| anyhow!("Truncated safetensors header"))?;
+        let header = &data[8..header_end];
+        let body = &data[header_end..];
+        let header: HashMap<String, Value> = serde_json::from_slice(header)?;
+        let mut tensors = HashMap::new();
+        for (name, info) in header {
+            if name == "__metadata__" {
+                continue;
+            }
+            let dtype = info["dtype"].as_str().unwrap_or_default().to_string();
+            let shape: Vec<usize> = serde_json::from_value(info["shape"].clone())?;
+            let offsets: [usize; 2] = serde_json::from_value(info["data_offsets"].clone())?;
+            let bytes = body.get(offsets[0]..offsets[1]).ok_or_else(|| anyhow!("Tensor {} is out of bounds", name))?;
+            // Checkpoints saved from BertModel directly prefix every name
+            let name = name.strip_prefix("bert.").unwrap_or(&name).to_string();
+            tensors.insert(name, (dtype, shape, bytes));
+        }
+        Ok(SafeTensors { tensors })
+    }
+
+    fn shape(&self, name: &str) -> Result<&[usize]> {
+        self.tensors.get(name).map(|(_, shape, _)| shape.as_slice()).ok_or_else(|| anyhow!("Model has no tensor {}", name))
+    }
+
+    // The tensor as f32, checked to have `shape`.
+    fn get(&self, name: &str, shape: &[usize]) -> Result<Vec<f32>> {
+        let (dtype, actual, bytes) = self.tensors.get(name).ok_or_else(|| anyhow!("Model has no tensor {}", name))?;
+        if actual != shape {
+            bail!("Tensor {} has shape {:?}, expected {:?}", name, actual, shape);
+        }
+        let values: Vec<f32> = match dtype.as_str() {
+            "F32" => bytes.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect(),
+            "F16" => bytes.chunks_exact(2).map(|b| f16_to_f32(u16::from_le_bytes([b[0], b[1]]))).collect(),
+            "BF16" => bytes.chunks_exact(2).map(|b| f32::from_bits((u16::from_le_bytes([b[0], b[1]]) as u32) << 16)).collect(),
+            other => bail!("Tensor {} has unsupported dtype {}", name, other),
+        };
+        if values.len() != shape.iter().product::<usize>() {
+            bail!("Tensor {} has {} values for shape {:?}", name, values.len(), shape);
+        }
+        Ok(values)
+    }
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = ((bits >> 15) as u32) << 31;
+    let exponent = ((bits >> 10) & 0x1f) as u32;
+    let mantissa = (bits & 0x3ff) as u32;
+    let bits = match (exponent, mantissa) {
+        (0, 0) => sign,
+        // Subnormal: scaled by hand
+        (0, m) => return f32::from_bits(sign) + (if sign != 0 { -1.0 } else { 1.0 }) * m as f32 * 2f32.powi(-24),
+        (0x1f, m) => sign | 0x7f80_0000 | (m << 13),
+        (e, m) => sign | ((e + 112) << 23) | (m << 13),
+    };
+    f32::from_bits(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VOCAB: &str = "[PAD]\n[UNK]\n[CLS]\n[SEP]\nhello\nworld\n!\nun\n##want\n##ed\n,\ncafe\n的";
+
+    fn tokenizer() -> WordPiece {
+        WordPiece::new(VOCAB, true).unwrap()
+    }
+
+    #[test]
+    fn wordpiece_splits_words_and_punctuation() {
+        let t = tokenizer();
+        assert_eq!(t.encode("Hello, world!", 512), vec![2, 4, 10, 5, 6, 3]);
+        assert_eq!(t.encode("unwanted", 512), vec![2, 7, 8, 9, 3]);
+        assert_eq!(t.encode("unwantedly", 512), vec![2, 1, 3]);
+        assert_eq!(t.encode("  Café\u{0}\t的 ", 512), vec![2, 11, 12, 3]);
+    }
+
+    #[test]
+    fn wordpiece_truncates_to_the_sequence_length() {
+        assert_eq!(tokenizer().encode("hello world hello world", 4), vec![2, 4, 5, 3]);
+    }
+
+    #[test]
+    fn math_helpers() {
+        assert!((erf(0.5) - 0.520_499_9).abs() < 1e-6);
+        assert!((erf(-2.0) + 0.995_322_3).abs() < 1e-6);
+        assert!((gelu(1.0) - 0.841_344_7).abs() < 1e-6);
+        let mut values = [1.0, 2.0, 3.0];
+        softmax(&mut values);
+        assert!((values.iter().sum::<f32>() - 1.0).abs() < 1e-6 && values[2] > values[1]);
+        let a: Vec<f32> = (0..19).map(|i| i as f32).collect();
+        assert_eq!(dot(&a, &a), (0..19).map(|i| (i * i) as f32).sum::<f32>());
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+        assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+    }
+
+    // A safetensors file of F32 tensors.
+    fn safetensors(tensors: &[(&str, Vec<usize>, Vec<f32>)]) -> Vec<u8> {
+        let mut header = serde_json::Map::new();
+        let mut body = Vec::new();
+        for (name, shape, values) in tensors {
+            let start = body.len();
+            body.extend(values.iter().flat_map(|v| v.to_le_bytes()));
+            header.insert(name.to_string(), serde_json::json!({ "dtype": "F32", "shape": shape, "data_offsets": [start, body.len()] }));
+        }
+        let header = serde_json::to_vec(&header).unwrap();
+        let mut data = (header.len() as u64).to_le_bytes().to_vec();
+        data.extend(header);
+        data.extend(body);
+        data
+    }
+
+    // Small varied weights, generated the same way by the reference script in `parity`.
+    fn values(n: usize, seed: usize) -> Vec<f32> {
+        (0..n).map(|i| (((i * 7 + seed * 13) % 17) as f32 - 8.0) / 20.0).collect()
+    }
+
+    // A two-layer model with hidden size 4 and 2 heads.
+    fn tiny_model() -> Vec<u8> {
+        let (hidden, vocab, intermediate) = (4, VOCAB.lines().count(), 8);
+        let mut tensors = vec![
+            ("bert.embeddings.word_embeddings.weight".to_string(), vec![vocab, hidden], values(vocab * hidden, 1)),
+            ("bert.embeddings.position_embeddings.weight".to_string(), vec![16, hidden], values(16 * hidden, 2)),
+            ("bert.embeddings.token_type_embeddings.weight".to_string(), vec![2, hidden], values(2 * hidden, 3)),
+        ];
+        let mut norm = |name: String, seed: usize| {
+            tensors.push((format!("{}.weight", name), vec![hidden], values(hidden, seed).iter().map(|v| 1.0 + v).collect()));
+            tensors.push((format!("{}.bias", name), vec![hidden], values(hidden, seed + 1)));
+        };
+        norm("embeddings.LayerNorm".to_string(), 20);
+        for layer in 0..2 {
+            norm(format!("encoder.layer.{}.attention.output.LayerNorm", layer), 22 + layer * 10);
+            norm(format!("encoder.layer.{}.output.LayerNorm", layer), 24 + layer * 10);
+        }
+        for layer in 0..2 {
+            for (seed, (name, outputs, inputs)) in [
+                ("attention.self.query", hidden, hidden),
+                ("attention.self.key", hidden, hidden),
+                ("attention.self.value", hidden, hidden),
+                ("attention.output.dense", hidden, hidden),
+                ("intermediate.dense", intermediate, hidden),
+                ("output.dense", hidden, intermediate),
+            ]
+            .into_iter()
+            .enumerate()
+            {
+                let seed = seed + layer * 30;
+                tensors.push((format!("encoder.layer.{}.{}.weight", layer, name), vec![outputs, inputs], values(outputs * inputs, seed + 4)));
+                tensors.push((format!("encoder.layer.{}.{}.bias", layer, name), vec![outputs], values(outputs, seed + 10)));
+            }
+        }
+        let borrowed: Vec<(&str, Vec<usize>, Vec<f32>)> = tensors.iter().map(|(n, s, v)| (n.as_str(), s.clone(), v.clone())).collect();
+        safetensors(&borrowed)
+    }
+
+    fn config() -> Config {
+        serde_json::from_value(serde_json::json!({ "hidden_size": 4, "num_hidden_layers": 2, "num_attention_heads": 2, "max_position_embeddings": 16 })).unwrap()
+    }
+
+    #[test]
+    fn tiny_model_embeds_to_unit_vectors() {
+        let data = tiny_model();
+        let model = Bert::from_parts(config(), tokenizer(), &SafeTensors::parse(&data).unwrap(), None, false).unwrap();
+        let a = model.embed("hello world");
+        let b = model.embed("hello world");
+        let c = model.embed("unwanted, cafe!");
+        assert_eq!(a.len(), 4);
+        assert_eq!(a, b);
+        assert!((a.iter().map(|v| v * v).sum::<f32>() - 1.0).abs() < 1e-5);
+        assert!(a.iter().zip(&c).any(|(x, y)| (x - y).abs() > 1e-3));
+        assert!(a.iter().all(|v| v.is_finite()));
+        // Longer than the 16 positions the model has
+        assert_eq!(model.embed(&"hello ".repeat(40)).len(), 4);
+    }
+
+    // Expected vectors from an independent float64 implementation of Hugging Face's
+    // BertModel plus sentence-transformers pooling, run on the same weights
+    #[test]
+    fn parity() {
+        let data = tiny_model();
+        let tensors = SafeTensors::parse(&data).unwrap();
+        let mean = Bert::from_parts(config(), tokenizer(), &tensors, None, false).unwrap();
+        let cls = Bert::from_parts(config(), tokenizer(), &tensors, None, true).unwrap();
+        let close = |actual: Vec<f32>, expected: [f32; 4]| {
+            assert!(actual.iter().zip(expected).all(|(a, e)| (a - e).abs() < 1e-4), "{:?} != {:?}", actual, expected);
+        };
+        close(mean.embed("hello world"), [-0.195162, 0.359949, -0.909438, -0.072599]);
+        close(cls.embed("hello world"), [-0.567738, 0.754965, -0.307196, 0.115465]);
+        close(mean.embed("unwanted, cafe!"), [0.339569, -0.615701, -0.710828, -0.018131]);
+        close(cls.embed("unwanted, cafe!"), [-0.68146, 0.723826, -0.02635, 0.104851]);
+    }
+
+    #[test]
+    fn rejects_mismatched_tensors() {
+        let data = safetensors(&[("embeddings.word_embeddings.weight", vec![3, 4], vec![0.0; 12])]);
+        let error = Bert::from_parts(config(), tokenizer(), &SafeTensors::parse(&data).unwrap(), None, false).err().unwrap();
+        assert!(error.to_string().contains("no tensor"), "{}", error);
+        assert!(SafeTensors::parse(&data[..20]).is_err());
+        let mut huge = u64::MAX.to_le_bytes().to_vec();
+        huge.extend(b"{}");
+        assert!(SafeTensors::parse(&huge).is_err());
+        assert!(SafeTensors::parse(&[1, 2, 3]).is_err());
+    }
+}
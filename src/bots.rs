@@ -0,0 +1,38 @@
+use crate::profiles::ProfileCache;
+use crate::privacy;
+use pubky::Client;
+use pubky_app_specs::{PubkyAppUser, PubkyAppUserLink};
+use std::env;
+
+// Profiles of automated accounts carry a link titled "bot" (the convention this bot
+// also follows), or a #bot hashtag in their bio.
+const MARKER_TITLE: &str = "bot";
+const MARKER_HASHTAG: &str = "#bot";
+
+// Bot-to-bot etiquette, on unless BOT_ETIQUETTE=false: another bot gets at most one
+// reply per thread and is never followed back, so two bots can't keep each other busy.
+pub fn is_enabled() -> bool {
+    env::var("BOT_ETIQUETTE").map(|v| v != "false").unwrap_or(true)
+}
+
+// The link published on the bot's own profile so peers can tell it is a bot.
+pub fn marker(bot: &str) -> PubkyAppUserLink {
+    PubkyAppUserLink { title: MARKER_TITLE.to_string(), url: format!("pubky://{}", bot) }
+}
+
+pub fn is_marked(user: &PubkyAppUser) -> bool {
+    let link = user.links.iter().flatten().any(|link| link.title.trim().eq_ignore_ascii_case(MARKER_TITLE));
+    let hashtag = user.bio.as_deref().is_some_and(|bio| bio.split_whitespace().any(|word| word.trim_end_matches(|c: char| c.is_ascii_punctuation()).eq_ignore_ascii_case(MARKER_HASHTAG)));
+    link || hashtag
+}
+
+// Whether `user`'s profile carries the bot marker. An unreadable profile counts as human.
+pub async fn is_bot(profiles: &mut ProfileCache, client: &Client, user: &str) -> bool {
+    match profiles.get(client, user).await {
+        Ok(profile) => profile.is_some_and(|p| p.bot),
+        Err(e) => {
+            log!("Failed to fetch profile of {}: {}", privacy::user(user), e);
+            false
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use crate::kb;
+use crate::leaderboard;
+use crate::llm::{chat_completion, reply_max_chars, ChatMessage, ChatRequest};
+use crate::llm_queue::{self, Priority};
+use crate::memory::{BroadcastDelivery, MemoryDb};
+use crate::models;
+use anyhow::Result;
+use std::env;
+
+pub const DAILY_TIP: &str = "daily-tip";
+pub const LEADERBOARD: &str = "leaderboard";
+
+const TIP_INSTRUCTION: &str = "Write today's tip for people using Pubky: one short, practical thing they may not know, based only on the knowledge base. Pick a different feature than the recent tips listed below. Reply only with the tip, starting with \"💡 Daily tip:\".";
+
+// Daily tip post generated from the knowledge base, enabled with DAILY_TIP=true.
+pub fn daily_tip_enabled() -> bool {
+    env::var("DAILY_TIP").map(|v| v == "true").unwrap_or(false)
+}
+
+// Broadcasts users can subscribe to with `!subscribe <topic>`: the scheduled posts
+// that are switched on.
+pub fn topics() -> Vec<&'static str> {
+    let mut topics = Vec::new();
+    if daily_tip_enabled() {
+        topics.push(DAILY_TIP);
+    }
+    if leaderboard::is_enabled() {
+        topics.push(LEADERBOARD);
+    }
+    topics
+}
+
+// Subscribers mentioned per post, from BROADCAST_BATCH_SIZE.
+fn batch_size() -> usize {
+    env::var("BROADCAST_BATCH_SIZE").ok().and_then(|v| v.parse().ok()).filter(|n| *n > 0).unwrap_or(10)
+}
+
+// Time between two batches, from BROADCAST_BATCH_INTERVAL_SECS, so a large subscriber
+// list is spread out instead of flooding the homeserver and Nexus.
+pub fn batch_interval_millis() -> i64 {
+    env::var("BROADCAST_BATCH_INTERVAL_SECS").ok().and_then(|v| v.parse::<i64>().ok()).unwrap_or(60).max(0) * 1000
+}
+
+// Generates the daily tip, avoiding the subjects of the last few.
+pub async fn build_daily_tip(recent: &[String]) -> Result<String> {
+    let knowledge = kb::knowledge_for("Pubky tips and lesser-known features", None).await?;
+    let max_chars = reply_max_chars();
+    let request = ChatRequest {
+        model: models::resolve("default"),
+        messages: vec![
+            ChatMessage {
+                role: "system".to_string(),
+                content: format!("{} Keep it under {} characters.\n\nKNOWLEDGE BASE:\n{}", TIP_INSTRUCTION, max_chars, knowledge),
+            },
+            ChatMessage { role: "user".to_string(), content: format!("Recent tips:\n{}", recent.join("\n")) },
+        ],
+        temperature: 0.9,
+        max_tokens: llm_queue::completion_tokens(max_chars, ""),
+    };
+    let tip = chat_completion(&request, Priority::Scheduled).await?;
+    Ok(tip.trim().chars().take(max_chars).collect())
+}
+
+// Queues mentions of the topic's subscribers under a published broadcast.
+pub fn queue_delivery(memory: &mut MemoryDb, topic: &str, post_uri: &str) {
+    let remaining: Vec<String> = memory.subscriptions.get(topic).map(|users| users.iter().cloned().collect()).unwrap_or_default();
+    if remaining.is_empty() {
+        return;
+    }
+    log!("Notifying {} subscriber(s) of {} in batches of {}", remaining.len(), topic, batch_size());
+    memory.broadcast_deliveries.push(BroadcastDelivery { topic: topic.to_string(), post_uri: post_uri.to_string(), remaining });
+}
+
+// The next batch to post: the broadcast it replies to, how many subscribers it
+// mentions and its text. It stays queued until `mark_delivered`, so a failed post is
+// retried.
+pub fn next_batch(memory: &MemoryDb) -> Option<(String, usize, String)> {
+    let delivery = memory.broadcast_deliveries.first()?;
+    let users = &delivery.remaining[..batch_size().min(delivery.remaining.len())];
+    let mentions = users.iter().map(|user| format!("pk:{}", user)).collect::<Vec<_>>().join(" ");
+    let text = format!("📬 New {} for {}\n\n(`!unsubscribe {}` to stop)", delivery.topic, mentions, delivery.topic);
+    Some((delivery.post_uri.clone(), users.len(), text))
+}
+
+// Drops the first `count` subscribers of the oldest delivery, and the delivery once
+// everyone was mentioned.
+pub fn mark_delivered(memory: &mut MemoryDb, count: usize) {
+    let Some(delivery) = memory.broadcast_deliveries.first_mut() else { return };
+    delivery.remaining.drain(..count.min(delivery.remaining.len()));
+    if delivery.remaining.is_empty() {
+        memory.broadcast_deliveries.remove(0);
+    }
+}
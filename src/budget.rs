@@ -0,0 +1,134 @@
+use crate::analytics::{self, AnalyticsRow};
+use crate::error::{BotError, BotResult};
+use crate::llm_queue::Priority;
+use crate::memory::now_millis;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const CATEGORIES: [Priority; 4] = [Priority::Mention, Priority::Engagement, Priority::Scheduled, Priority::Digest];
+
+// Token bucket of one trigger category: it holds up to an hour's worth of estimated
+// LLM tokens and refills continuously, so a burst can spend the hour at once but a
+// category never takes more than its share over time.
+struct Bucket {
+    per_hour: f64,
+    tokens: f64,
+    refilled_at: Instant,
+    spent: u64,
+    rejected: u64,
+}
+
+static BUCKETS: Mutex<BTreeMap<Priority, Bucket>> = Mutex::new(BTreeMap::new());
+
+// Estimated tokens per hour a category may spend, from LLM_BUDGET_<CATEGORY> (e.g.
+// LLM_BUDGET_SCHEDULED). None (the default, or 0) leaves it unlimited.
+fn per_hour(category: Priority) -> Option<f64> {
+    let var = format!("LLM_BUDGET_{}", category.name().to_uppercase());
+    env::var(var).ok().and_then(|v| v.parse::<u64>().ok()).filter(|n| *n > 0).map(|n| n as f64)
+}
+
+// Takes `tokens` from the category's bucket, or fails with BudgetExceeded when it
+// doesn't hold that many, so experimental features run dry on their own budget
+// instead of the one answering people. A single request larger than the whole hour
+// is let through on a full bucket.
+pub fn take(category: Priority, tokens: usize) -> BotResult<()> {
+    let Some(per_hour) = per_hour(category) else { return Ok(()) };
+    let mut buckets = BUCKETS.lock().map_err(|_| BotError::BudgetExceeded("budget lock poisoned".to_string()))?;
+    let now = Instant::now();
+    let bucket = buckets.entry(category).or_insert(Bucket { per_hour, tokens: per_hour, refilled_at: now, spent: 0, rejected: 0 });
+    // Picks up a changed limit without losing what was already spent
+    if bucket.per_hour != per_hour {
+        bucket.tokens = bucket.tokens.min(per_hour);
+        bucket.per_hour = per_hour;
+    }
+    let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+    bucket.tokens = (bucket.tokens + elapsed * per_hour / 3600.0).min(per_hour);
+    bucket.refilled_at = now;
+
+    let tokens = tokens as f64;
+    if bucket.tokens >= tokens || bucket.tokens >= per_hour {
+        bucket.tokens = (bucket.tokens - tokens).max(0.0);
+        bucket.spent += tokens as u64;
+        return Ok(());
+    }
+    bucket.rejected += 1;
+    let available = bucket.tokens as u64;
+    drop(buckets);
+    log!("LLM budget for {} exhausted: {} tokens needed, {} left", category.name(), tokens, available);
+    analytics::record(
+        AnalyticsRow::new("budget_exceeded", None, None)
+            .with("category", category.name())
+            .with("tokens", tokens as u64)
+            .with("available", available),
+    );
+    Err(BotError::BudgetExceeded(format!("the {} budget ({} tokens an hour) is used up", category.name(), per_hour as u64)))
+}
+
+// A category's budget and use since startup, for /status and the heartbeat.
+#[derive(Debug, Clone, Serialize)]
+pub struct BudgetStats {
+    pub per_hour: Option<u64>,
+    pub available: Option<u64>,
+    pub spent: u64,
+    pub rejected: u64,
+}
+
+pub fn stats() -> BTreeMap<&'static str, BudgetStats> {
+    let buckets = BUCKETS.lock().ok();
+    CATEGORIES
+        .iter()
+        .map(|category| {
+            let limit = per_hour(*category);
+            let bucket = buckets.as_ref().and_then(|b| b.get(category));
+            let available = limit.map(|limit| match bucket {
+                Some(bucket) => (bucket.tokens + bucket.refilled_at.elapsed().as_secs_f64() * limit / 3600.0).min(limit) as u64,
+                None => limit as u64,
+            });
+            let stats = BudgetStats {
+                per_hour: limit.map(|l| l as u64),
+                available,
+                spent: bucket.map_or(0, |b| b.spent),
+                rejected: bucket.map_or(0, |b| b.rejected),
+            };
+            (category.name(), stats)
+        })
+        .collect()
+}
+
+// A bucket as kept in the state snapshot, with wall-clock times since Instants don't
+// survive a restart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BucketSnapshot {
+    pub tokens: f64,
+    pub refilled_at: i64,
+    pub spent: u64,
+    pub rejected: u64,
+}
+
+pub fn snapshot() -> BTreeMap<String, BucketSnapshot> {
+    let now = now_millis();
+    let Ok(buckets) = BUCKETS.lock() else { return BTreeMap::new() };
+    buckets
+        .iter()
+        .map(|(category, bucket)| {
+            let refilled_at = now - bucket.refilled_at.elapsed().as_millis() as i64;
+            (category.name().to_string(), BucketSnapshot { tokens: bucket.tokens, refilled_at, spent: bucket.spent, rejected: bucket.rejected })
+        })
+        .collect()
+}
+
+// Puts back the buckets of a snapshot, so a restart doesn't refill every budget.
+// Categories that are no longer limited are dropped.
+pub fn restore(snapshot: &BTreeMap<String, BucketSnapshot>) {
+    let Ok(mut buckets) = BUCKETS.lock() else { return };
+    let now = Instant::now();
+    for category in CATEGORIES {
+        let (Some(per_hour), Some(saved)) = (per_hour(category), snapshot.get(category.name())) else { continue };
+        let age = Duration::from_millis((now_millis() - saved.refilled_at).max(0) as u64);
+        let refilled_at = now.checked_sub(age).unwrap_or(now);
+        buckets.insert(category, Bucket { per_hour, tokens: saved.tokens.min(per_hour), refilled_at, spent: saved.spent, rejected: saved.rejected });
+    }
+}
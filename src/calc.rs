@@ -0,0 +1,286 @@
+use crate::error::{BotError, BotResult};
+use std::env;
+
+// Limits that keep a hostile expression from tying up the bot
+const MAX_EXPRESSION_CHARS: usize = 500;
+const MAX_DEPTH: usize = 64;
+
+// Math evaluation tool, enabled with CALCULATOR=true. It only understands arithmetic,
+// so there is nothing to sandbox: no variables, loops, I/O or code execution.
+pub fn is_enabled() -> bool {
+    env::var("CALCULATOR").map(|v| v == "true").unwrap_or(false)
+}
+
+// Evaluates an arithmetic expression: numbers, + - * / % ^, parentheses, the constants
+// pi and e, and sqrt, abs, ln, log, log2, exp, sin, cos, tan, floor, ceil, round.
+pub fn evaluate(expression: &str) -> BotResult<String> {
+    if expression.chars().count() > MAX_EXPRESSION_CHARS {
+        return Err(BotError::Tool(format!("Expression is longer than {} characters", MAX_EXPRESSION_CHARS)));
+    }
+    let mut parser = Parser { chars: expression.chars().collect(), pos: 0, depth: 0 };
+    let value = parser.expression()?;
+    parser.skip_whitespace();
+    if parser.pos < parser.chars.len() {
+        return Err(BotError::Tool(format!("Unexpected \"{}\" at position {}", parser.chars[parser.pos], parser.pos + 1)));
+    }
+    if !value.is_finite() {
+        return Err(BotError::Tool("The result is not a finite number".to_string()));
+    }
+    Ok(format_number(value))
+}
+
+fn format_number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        format!("{}", value)
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    // Spaces separate tokens but can't split one, so "2 3" is an error rather than 23
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    // expression := term (('+' | '-') term)*
+    fn expression(&mut self) -> BotResult<f64> {
+        let mut value = self.term()?;
+        loop {
+            if self.eat('+') {
+                value += self.term()?;
+            } else if self.eat('-') {
+                value -= self.term()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    // term := unary (('*' | '/' | '%') unary)*
+    fn term(&mut self) -> BotResult<f64> {
+        let mut value = self.unary()?;
+        loop {
+            if self.eat('*') {
+                value *= self.unary()?;
+            } else if self.eat('/') {
+                let divisor = self.unary()?;
+                if divisor == 0.0 {
+                    return Err(BotError::Tool("Division by zero".to_string()));
+                }
+                value /= divisor;
+            } else if self.eat('%') {
+                let divisor = self.unary()?;
+                if divisor == 0.0 {
+                    return Err(BotError::Tool("Division by zero".to_string()));
+                }
+                value %= divisor;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    // unary := ('-' | '+') unary | power, so -2^2 is -(2^2)
+    fn unary(&mut self) -> BotResult<f64> {
+        if self.eat('-') {
+            self.enter()?;
+            let value = -self.unary()?;
+            self.depth -= 1;
+            return Ok(value);
+        }
+        if self.eat('+') {
+            self.enter()?;
+            let value = self.unary()?;
+            self.depth -= 1;
+            return Ok(value);
+        }
+        self.power()
+    }
+
+    // power := atom ('^' unary)?, right-associative
+    fn power(&mut self) -> BotResult<f64> {
+        let base = self.atom()?;
+        if self.eat('^') {
+            self.enter()?;
+            let exponent = self.unary()?;
+            self.depth -= 1;
+            return Ok(base.powf(exponent));
+        }
+        Ok(base)
+    }
+
+    // atom := number | constant | function '(' expression ')' | '(' expression ')'
+    fn atom(&mut self) -> BotResult<f64> {
+        self.skip_whitespace();
+        if self.eat('(') {
+            return self.parenthesized();
+        }
+        match self.peek() {
+            Some(c) if c.is_ascii_digit() || c == '.' => self.number(),
+            Some(c) if c.is_ascii_alphabetic() => {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_ascii_alphanumeric()) {
+                    self.pos += 1;
+                }
+                let name: String = self.chars[start..self.pos].iter().collect::<String>().to_lowercase();
+                match name.as_str() {
+                    "pi" => return Ok(std::f64::consts::PI),
+                    "e" => return Ok(std::f64::consts::E),
+                    _ => {}
+                }
+                if !self.eat('(') {
+                    return Err(BotError::Tool(format!("Unknown name \"{}\"", name)));
+                }
+                let arg = self.parenthesized()?;
+                apply_function(&name, arg)
+            }
+            Some(c) => Err(BotError::Tool(format!("Unexpected \"{}\" at position {}", c, self.pos + 1))),
+            None => Err(BotError::Tool("Unexpected end of expression".to_string())),
+        }
+    }
+
+    fn parenthesized(&mut self) -> BotResult<f64> {
+        self.enter()?;
+        let value = self.expression()?;
+        self.depth -= 1;
+        if !self.eat(')') {
+            return Err(BotError::Tool("Missing closing parenthesis".to_string()));
+        }
+        Ok(value)
+    }
+
+    fn number(&mut self) -> BotResult<f64> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+            self.pos += 1;
+        }
+        // Scientific notation, e.g. 1.5e-3
+        if self.peek().is_some_and(|c| c == 'e' || c == 'E')
+            && self.chars.get(self.pos + 1).is_some_and(|c| c.is_ascii_digit() || *c == '-' || *c == '+')
+        {
+            self.pos += 2;
+            while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                self.pos += 1;
+            }
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        text.parse().map_err(|_| BotError::Tool(format!("Invalid number \"{}\"", text)))
+    }
+
+    fn enter(&mut self) -> BotResult<()> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(BotError::Tool("Expression is nested too deeply".to_string()));
+        }
+        Ok(())
+    }
+}
+
+fn apply_function(name: &str, arg: f64) -> BotResult<f64> {
+    Ok(match name {
+        "sqrt" => arg.sqrt(),
+        "abs" => arg.abs(),
+        "ln" => arg.ln(),
+        "log" => arg.log10(),
+        "log2" => arg.log2(),
+        "exp" => arg.exp(),
+        "sin" => arg.sin(),
+        "cos" => arg.cos(),
+        "tan" => arg.tan(),
+        "floor" => arg.floor(),
+        "ceil" => arg.ceil(),
+        "round" => arg.round(),
+        _ => return Err(BotError::Tool(format!("Unknown function \"{}\"", name))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(expression: &str) -> String {
+        evaluate(expression).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        assert_eq!(evaluate("1 + 2 * 3").unwrap(), "7");
+        assert_eq!(evaluate("(1 + 2) * 3").unwrap(), "9");
+        assert_eq!(evaluate("-2^2").unwrap(), "-4");
+        assert_eq!(evaluate("(-2)^2").unwrap(), "4");
+        assert_eq!(evaluate("2^3^2").unwrap(), "512");
+        assert_eq!(evaluate("2^-1").unwrap(), "0.5");
+        assert_eq!(evaluate("10 - 4 - 3").unwrap(), "3");
+        assert_eq!(evaluate("7 % 4 * 2").unwrap(), "6");
+        assert_eq!(evaluate("--3").unwrap(), "3");
+    }
+
+    #[test]
+    fn numbers_constants_and_functions() {
+        assert_eq!(evaluate("1.5e-3").unwrap(), "0.0015");
+        assert_eq!(evaluate("2E3 + 1").unwrap(), "2001");
+        assert_eq!(evaluate(".5 * 4").unwrap(), "2");
+        assert_eq!(evaluate("sqrt(16) + abs(-2)").unwrap(), "6");
+        assert_eq!(evaluate("round(pi * 100)").unwrap(), "314");
+        assert_eq!(evaluate("ln(e)").unwrap(), "1");
+        assert_eq!(evaluate("log(1000)").unwrap(), "3");
+    }
+
+    #[test]
+    fn malformed_numbers() {
+        assert!(error("2e").contains("Unexpected \"e\""));
+        assert!(error("2e+").contains("Invalid number"));
+        assert!(error("1.2.3").contains("Invalid number"));
+    }
+
+    #[test]
+    fn division_and_modulo_by_zero() {
+        assert!(error("1 / 0").contains("Division by zero"));
+        assert!(error("1 % 0").contains("Division by zero"));
+        assert!(error("1 / (2 - 2)").contains("Division by zero"));
+        assert!(error("sqrt(-1)").contains("not a finite number"));
+        assert!(error("10^400").contains("not a finite number"));
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let nested = |depth: usize| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(evaluate(&nested(MAX_DEPTH)).unwrap(), "1");
+        assert!(error(&nested(MAX_DEPTH + 1)).contains("nested too deeply"));
+        assert!(error(&"-".repeat(MAX_DEPTH + 1)).contains("nested too deeply"));
+        assert!(error(&"1".repeat(MAX_EXPRESSION_CHARS + 1)).contains("longer than"));
+    }
+
+    #[test]
+    fn trailing_and_unknown_input() {
+        assert!(error("1 + 2)").contains("Unexpected \")\" at position 6"));
+        assert!(error("2 3").contains("Unexpected \"3\""));
+        assert!(error("(1 + 2").contains("Missing closing parenthesis"));
+        assert!(error("1 +").contains("Unexpected end"));
+        assert!(error("x + 1").contains("Unknown name \"x\""));
+        assert!(error("foo(1)").contains("Unknown function \"foo\""));
+        assert!(error("1; rm -rf /").contains("Unexpected \";\""));
+    }
+}
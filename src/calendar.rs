@@ -0,0 +1,306 @@
+use crate::analytics::{self, AnalyticsRow};
+use crate::cron::{civil_from_days, weekday, CronExpr};
+use crate::error::{BotError, BotResult};
+use crate::kb;
+use crate::llm::{chat_completion, reply_max_chars, ChatMessage, ChatRequest};
+use crate::llm_queue::{self, Priority};
+use crate::memory::now_millis;
+use crate::models;
+use crate::tz::Zone;
+use anyhow::Result;
+use dotenv::dotenv;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::sync::Mutex;
+
+// How often the calendar is checked for due posts.
+const CHECK_INTERVAL_MILLIS: i64 = 30_000;
+// Occurrences found due later than this (the bot was down) are skipped, not posted late.
+const MISSED_AFTER_MILLIS: i64 = 3_600_000;
+// How far ahead the next occurrence is searched, in days.
+const SEARCH_DAYS: i64 = 4 * 366;
+
+const POST_INSTRUCTION: &str = "You write a post for your own feed on Pubky, a decentralized social network. Follow the brief below, base any facts about Pubky only on the knowledge base and reply only with the post text.";
+
+static LAST_CHECK: Mutex<i64> = Mutex::new(0);
+static LAST_ERROR: Mutex<Option<String>> = Mutex::new(None);
+
+// The content calendar in CALENDAR_FILE.
+#[derive(Debug, Default, Deserialize)]
+pub struct Calendar {
+    // Named date lists entries can skip: "YYYY-MM-DD" for one day, "MM-DD" for every year
+    #[serde(default)]
+    pub holidays: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Entry {
+    pub name: String,
+    pub cron: String,
+    // IANA name or fixed offset; default UTC
+    #[serde(default)]
+    pub timezone: Option<String>,
+    // Posts are delayed by a random 0..=jitter_minutes, fixed per occurrence
+    #[serde(default)]
+    pub jitter_minutes: u32,
+    // Holiday lists to skip
+    #[serde(default)]
+    pub skip: Vec<String>,
+    // Extra dates to skip, in the same formats as the holiday lists
+    #[serde(default)]
+    pub skip_dates: Vec<String>,
+    // Posted as written
+    #[serde(default)]
+    pub text: Option<String>,
+    // Brief for a post generated from the knowledge base
+    #[serde(default)]
+    pub prompt: Option<String>,
+}
+
+// An entry with its cron expression and zone parsed.
+pub struct Schedule<'a> {
+    pub entry: &'a Entry,
+    cron: CronExpr,
+    pub zone: Zone,
+}
+
+// A post that is due now.
+#[derive(Debug)]
+pub struct DuePost {
+    pub name: String,
+    // Scheduled time (unix ms), before jitter
+    pub scheduled_at: i64,
+    pub text: Option<String>,
+    pub prompt: Option<String>,
+}
+
+fn calendar_path() -> String {
+    env::var("CALENDAR_FILE").unwrap_or_else(|_| "calendar.json".to_string())
+}
+
+pub fn is_enabled() -> bool {
+    fs::metadata(calendar_path()).is_ok()
+}
+
+pub fn load() -> BotResult<Calendar> {
+    let content = match fs::read_to_string(calendar_path()) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Calendar::default()),
+        Err(e) => return Err(BotError::Validation(format!("Failed to read {}: {}", calendar_path(), e))),
+    };
+    let calendar: Calendar = serde_json::from_str(&content).map_err(|e| BotError::Validation(format!("Invalid {}: {}", calendar_path(), e)))?;
+    for entry in &calendar.entries {
+        if entry.text.is_some() == entry.prompt.is_some() {
+            return Err(BotError::Validation(format!("Calendar entry {} needs either `text` or `prompt`", entry.name)));
+        }
+        if let Some(list) = entry.skip.iter().find(|list| !calendar.holidays.contains_key(*list)) {
+            return Err(BotError::Validation(format!("Calendar entry {} skips unknown holiday list {}", entry.name, list)));
+        }
+    }
+    Ok(calendar)
+}
+
+impl Calendar {
+    pub fn schedules(&self) -> BotResult<Vec<Schedule<'_>>> {
+        self.entries
+            .iter()
+            .map(|entry| {
+                let cron = CronExpr::parse(&entry.cron).map_err(|e| BotError::Validation(format!("Calendar entry {}: {}", entry.name, e)))?;
+                let zone = Zone::parse(entry.timezone.as_deref().unwrap_or("UTC"))?;
+                Ok(Schedule { entry, cron, zone })
+            })
+            .collect()
+    }
+
+    // Whether an occurrence falls on one of the entry's skipped dates, in its zone.
+    pub fn is_skipped(&self, schedule: &Schedule, at: i64) -> bool {
+        let (year, month, day) = local_date(&schedule.zone, at);
+        let full = format!("{:04}-{:02}-{:02}", year, month, day);
+        let yearly = format!("{:02}-{:02}", month, day);
+        let lists = schedule.entry.skip.iter().filter_map(|list| self.holidays.get(list));
+        std::iter::once(&schedule.entry.skip_dates).chain(lists).flatten().any(|date| *date == full || *date == yearly)
+    }
+}
+
+impl Schedule<'_> {
+    // The first occurrence strictly after `after` (unix ms), in unix ms.
+    pub fn next_after(&self, after: i64) -> Option<i64> {
+        let local_day = (after / 1000 + self.zone.offset_at(after / 1000)).div_euclid(86_400);
+        // Start a day early so a zone change around `after` can't skip an occurrence
+        for day in local_day - 1..local_day + SEARCH_DAYS {
+            let (_, month, date) = civil_from_days(day);
+            if !self.cron.matches_day(month, date, weekday(day)) {
+                continue;
+            }
+            for (hour, minute) in self.cron.times() {
+                let local = day * 86_400 + (hour * 3600 + minute * 60) as i64;
+                if let Some(utc) = self.zone.to_utc(local) {
+                    if utc * 1000 > after {
+                        return Some(utc * 1000);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    // Delay of an occurrence within the entry's jitter, stable across checks and restarts.
+    pub fn jitter(&self, scheduled_at: i64) -> i64 {
+        let window = self.entry.jitter_minutes as u64 * 60_000;
+        if window == 0 {
+            return 0;
+        }
+        let hash = Sha256::digest(format!("{}:{}", self.entry.name, scheduled_at).as_bytes());
+        (u64::from_be_bytes(hash[..8].try_into().unwrap_or_default()) % (window + 1)) as i64
+    }
+}
+
+fn local_date(zone: &Zone, at: i64) -> (i64, u32, u32) {
+    civil_from_days((at / 1000 + zone.offset_at(at / 1000)).div_euclid(86_400))
+}
+
+// "YYYY-MM-DD HH:MM" of a unix ms time in a zone.
+pub fn format_time(zone: &Zone, at: i64) -> String {
+    let local = at / 1000 + zone.offset_at(at / 1000);
+    let (year, month, day) = civil_from_days(local.div_euclid(86_400));
+    let seconds = local.rem_euclid(86_400);
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, seconds / 3600, seconds % 3600 / 60)
+}
+
+// Posts due now, at most every CHECK_INTERVAL_MILLIS. `runs` holds the scheduled time
+// of each entry's last handled occurrence and is updated here, before anything is
+// posted, so a failing post isn't retried on every check. A new entry starts counting
+// from now; occurrences on skipped dates, or missed by more than an hour while the bot
+// was down, are recorded as handled without posting.
+pub fn due(runs: &mut BTreeMap<String, i64>) -> Vec<DuePost> {
+    let now = now_millis();
+    {
+        let Ok(mut last_check) = LAST_CHECK.lock() else { return Vec::new() };
+        if now - *last_check < CHECK_INTERVAL_MILLIS {
+            return Vec::new();
+        }
+        *last_check = now;
+    }
+    let loaded = load();
+    let (calendar, schedules) = match loaded.as_ref().map_err(BotError::to_string).and_then(|c| Ok((c, c.schedules().map_err(|e| e.to_string())?))) {
+        Ok(loaded) => loaded,
+        Err(message) => {
+            // Logged once per distinct error rather than on every check
+            if let Ok(mut last) = LAST_ERROR.lock() {
+                if last.as_deref() != Some(message.as_str()) {
+                    log!("Content calendar not loaded: {}", message);
+                    *last = Some(message);
+                }
+            }
+            return Vec::new();
+        }
+    };
+
+    let mut posts = Vec::new();
+    for schedule in &schedules {
+        let name = &schedule.entry.name;
+        let Some(last) = runs.get(name).copied() else {
+            runs.insert(name.clone(), now);
+            continue;
+        };
+        let Some(scheduled_at) = schedule.next_after(last) else { continue };
+        let due_at = scheduled_at + schedule.jitter(scheduled_at);
+        if due_at > now {
+            continue;
+        }
+        if now - due_at > MISSED_AFTER_MILLIS {
+            log!("Calendar entry {} missed its {} post, skipping to the next one", name, format_time(&schedule.zone, scheduled_at));
+            runs.insert(name.clone(), now);
+            continue;
+        }
+        runs.insert(name.clone(), scheduled_at);
+        if calendar.is_skipped(schedule, scheduled_at) {
+            log!("Calendar entry {} skipped on {} (holiday)", name, format_time(&schedule.zone, scheduled_at));
+            analytics::record(AnalyticsRow::new("calendar_skipped", None, None).with("entry", name.as_str()));
+            continue;
+        }
+        posts.push(DuePost { name: name.clone(), scheduled_at, text: schedule.entry.text.clone(), prompt: schedule.entry.prompt.clone() });
+    }
+    posts
+}
+
+// The post text for a due entry: its text, or one generated from its brief.
+pub async fn build_post(post: &DuePost) -> Result<String> {
+    if let Some(text) = &post.text {
+        return Ok(text.clone());
+    }
+    let brief = post.prompt.as_deref().unwrap_or_default();
+    let knowledge = kb::knowledge_for(brief, None).await?;
+    let max_chars = reply_max_chars();
+    let request = ChatRequest {
+        model: models::resolve("default"),
+        messages: vec![
+            ChatMessage {
+                role: "system".to_string(),
+                content: format!("{} Keep it under {} characters.\n\nKNOWLEDGE BASE:\n{}", POST_INSTRUCTION, max_chars, knowledge),
+            },
+            ChatMessage { role: "user".to_string(), content: brief.to_string() },
+        ],
+        temperature: 0.8,
+        max_tokens: llm_queue::completion_tokens(max_chars, brief),
+    };
+    let text = chat_completion(&request, Priority::Scheduled).await?;
+    Ok(text.trim().chars().take(max_chars).collect())
+}
+
+// `schedule list` prints every calendar entry with its next post; `schedule next [N]`
+// prints the next N posts (default 10) across entries, including skipped ones.
+pub async fn run(args: &[String]) -> Result<()> {
+    dotenv().ok();
+    let calendar = load()?;
+    let schedules = calendar.schedules()?;
+    let now = now_millis();
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["list"] | [] => {
+            if schedules.is_empty() {
+                println!("No entries in {}", calendar_path());
+            }
+            for schedule in &schedules {
+                let entry = schedule.entry;
+                let next = match schedule.next_after(now) {
+                    Some(at) => describe(&calendar, schedule, at),
+                    None => "never".to_string(),
+                };
+                let kind = if entry.text.is_some() { "text" } else { "generated" };
+                println!("{} [{}] `{}` {} (jitter {} min): next {}", entry.name, kind, entry.cron, entry.timezone.as_deref().unwrap_or("UTC"), entry.jitter_minutes, next);
+            }
+        }
+        ["next"] | ["next", _] => {
+            let count: usize = match args.get(1) {
+                Some(n) => n.parse().map_err(|_| anyhow::anyhow!("Usage: schedule next [N]"))?,
+                None => 10,
+            };
+            // Repeatedly take the earliest upcoming occurrence across entries
+            let mut cursors: HashMap<usize, i64> = (0..schedules.len()).filter_map(|i| Some((i, schedules[i].next_after(now)?))).collect();
+            for _ in 0..count {
+                let Some((&i, &at)) = cursors.iter().min_by_key(|(_, at)| **at) else { break };
+                println!("{}  {}", describe(&calendar, &schedules[i], at), schedules[i].entry.name);
+                match schedules[i].next_after(at) {
+                    Some(next) => cursors.insert(i, next),
+                    None => cursors.remove(&i),
+                };
+            }
+        }
+        _ => return Err(anyhow::anyhow!("Usage: schedule list | schedule next [N]")),
+    }
+    Ok(())
+}
+
+fn describe(calendar: &Calendar, schedule: &Schedule, at: i64) -> String {
+    let posts_at = at + schedule.jitter(at);
+    let mut text = format!("{} {} ({} UTC)", format_time(&schedule.zone, posts_at), schedule.entry.timezone.as_deref().unwrap_or("UTC"), format_time(&Zone::Fixed(0), posts_at));
+    if calendar.is_skipped(schedule, at) {
+        text.push_str(" [skipped: holiday]");
+    }
+    text
+}
@@ -0,0 +1,71 @@
+use crate::catalog;
+use crate::context::PromptVars;
+use std::env;
+
+// {{commands}} is the command list, generated from the command registry
+const DEFAULT_HELP: &str = "👋 I'm {{bot_name}}, an AI assistant for Pubky. Mention me with a question and I'll answer. Commands:\n{{commands}}";
+const DEFAULT_PING: &str = "🏓 Pong! {{bot_name}} is up and answering mentions ({{date}} {{time}} UTC).";
+const DEFAULT_GREETING: &str = "Hi {{user_name}}! 👋 Ask me anything about Pubky and I'll do my best to help.";
+const DEFAULT_FOLLOW: &str = "Thanks for the follow, {{user}}! 🙌 Mention me anytime you have a question about Pubky.";
+const DEFAULT_DEFLECTION: &str = "I'm here to answer questions about Pubky, so I'll pass on that one. 🙂 What would you like to know?";
+
+// Words a post may consist of, besides mentions and punctuation, to count as a greeting.
+const GREETING_WORDS: &[&str] = &[
+    "hi", "hello", "hey", "heya", "hiya", "yo", "gm", "gn", "good", "morning", "afternoon", "evening", "there", "hola", "buenas", "oi", "olá",
+    "ola", "bom", "dia", "bonjour", "salut", "hallo", "moin", "ciao",
+];
+// Longer posts are answered normally even if they only greet
+const MAX_GREETING_WORDS: usize = 4;
+
+// Interactions answered from a template instead of the model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Canned {
+    Help,
+    Ping,
+    Greeting,
+    Follow,
+    Deflection,
+}
+
+impl Canned {
+    pub fn name(self) -> &'static str {
+        match self {
+            Canned::Help => "help",
+            Canned::Ping => "ping",
+            Canned::Greeting => "greeting",
+            Canned::Follow => "follow",
+            Canned::Deflection => "deflection",
+        }
+    }
+
+    // Which canned responses are on, from CANNED_RESPONSES (e.g. "help,ping,greeting").
+    pub fn is_enabled(self) -> bool {
+        env::var("CANNED_RESPONSES").is_ok_and(|v| v.split(',').any(|name| name.trim().eq_ignore_ascii_case(self.name())))
+    }
+
+    // Renders the template in the user's language (see catalog.rs), CANNED_<NAME> or
+    // the default, with the prompt variables plus {{user}} for a pk: mention of the user.
+    pub fn render(self, vars: &PromptVars, user: &str, language: Option<&str>) -> String {
+        let variable = format!("CANNED_{}", self.name().to_uppercase());
+        let default = match self {
+            Canned::Help => DEFAULT_HELP,
+            Canned::Ping => DEFAULT_PING,
+            Canned::Greeting => DEFAULT_GREETING,
+            Canned::Follow => DEFAULT_FOLLOW,
+            Canned::Deflection => DEFAULT_DEFLECTION,
+        };
+        let template = catalog::message(self.name(), language, &variable, default);
+        vars.apply(&template.replace("\\n", "\n")).replace("{{user}}", &format!("pk:{}", user))
+    }
+}
+
+// Whether a post does nothing but greet, e.g. "gm pk:abc!" or "Olá 👋".
+pub fn is_greeting(content: &str) -> bool {
+    let words: Vec<String> = content
+        .split_whitespace()
+        .filter(|word| !word.starts_with("pk:") && !word.starts_with('@'))
+        .map(|word| word.chars().filter(|c| c.is_alphanumeric()).collect::<String>().to_lowercase())
+        .filter(|word| !word.is_empty())
+        .collect();
+    !words.is_empty() && words.len() <= MAX_GREETING_WORDS && words.iter().all(|word| GREETING_WORDS.contains(&word.as_str()))
+}
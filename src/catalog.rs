@@ -0,0 +1,89 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+// Catalogs loaded from MESSAGE_CATALOG_DIR, by locale, and the modification times
+// they were loaded at, so an edited, added or removed file is picked up at runtime.
+#[derive(Default)]
+struct Loaded {
+    dir: PathBuf,
+    stamps: Vec<(PathBuf, SystemTime)>,
+    catalogs: HashMap<String, HashMap<String, String>>,
+}
+
+static LOADED: Mutex<Option<Loaded>> = Mutex::new(None);
+
+// Directory of the message catalogs, one `<locale>.json` per language (`pt.json`,
+// `pt-br.json`, ...) holding an object of message key -> template. From
+// MESSAGE_CATALOG_DIR, default `messages`; a missing directory means no catalogs.
+fn dir() -> PathBuf {
+    PathBuf::from(env::var("MESSAGE_CATALOG_DIR").ok().filter(|d| !d.is_empty()).unwrap_or_else(|| "messages".to_string()))
+}
+
+// The catalog files and their modification times, sorted by path.
+fn stamps(dir: &PathBuf) -> Vec<(PathBuf, SystemTime)> {
+    let Ok(entries) = fs::read_dir(dir) else { return Vec::new() };
+    let mut stamps: Vec<(PathBuf, SystemTime)> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|path| {
+            let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+            Some((path, modified))
+        })
+        .collect();
+    stamps.sort();
+    stamps
+}
+
+fn load(dir: PathBuf, stamps: Vec<(PathBuf, SystemTime)>) -> Loaded {
+    let mut catalogs = HashMap::new();
+    for (path, _) in &stamps {
+        let Some(locale) = path.file_stem().and_then(|s| s.to_str()).map(|s| s.to_lowercase()) else { continue };
+        let parsed = fs::read_to_string(path).map_err(|e| e.to_string()).and_then(|text| serde_json::from_str::<HashMap<String, Value>>(&text).map_err(|e| e.to_string()));
+        match parsed {
+            Ok(messages) => {
+                let messages = messages.into_iter().filter_map(|(key, value)| Some((key, value.as_str()?.to_string()))).collect();
+                catalogs.insert(locale, messages);
+            }
+            Err(e) => log!("Ignoring message catalog {}: {}", path.display(), e),
+        }
+    }
+    if !catalogs.is_empty() {
+        let mut locales: Vec<&String> = catalogs.keys().collect();
+        locales.sort();
+        log!("Loaded message catalogs: {}", locales.iter().map(|l| l.as_str()).collect::<Vec<_>>().join(", "));
+    }
+    Loaded { dir, stamps, catalogs }
+}
+
+// The template for `key` in the catalog of `locale` ("pt-BR" falls back to "pt"),
+// reloading the catalogs first when their files changed.
+fn lookup(key: &str, locale: &str) -> Option<String> {
+    let dir = dir();
+    let current = stamps(&dir);
+    let mut loaded = LOADED.lock().ok()?;
+    if loaded.as_ref().is_none_or(|l| l.dir != dir || l.stamps != current) {
+        *loaded = Some(load(dir, current));
+    }
+    let catalogs = &loaded.as_ref()?.catalogs;
+    let locale = locale.to_lowercase().replace('_', "-");
+    let base = locale.split('-').next().unwrap_or_default().to_string();
+    [locale, base].iter().find_map(|l| catalogs.get(l)?.get(key)).filter(|t| !t.trim().is_empty()).cloned()
+}
+
+// A system-authored message in the user's language: the `key` template of their
+// locale's catalog, then the `variable` setting, then the English catalog, then the
+// built-in English `default`.
+pub fn message(key: &str, language: Option<&str>, variable: &str, default: &str) -> String {
+    if let Some(template) = language.filter(|l| !l.eq_ignore_ascii_case("en")).and_then(|l| lookup(key, l)) {
+        return template;
+    }
+    if let Some(template) = env::var(variable).ok().filter(|t| !t.trim().is_empty()) {
+        return template;
+    }
+    lookup(key, "en").unwrap_or_else(|| default.to_string())
+}
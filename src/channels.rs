@@ -0,0 +1,226 @@
+use crate::error::{BotError, BotResult};
+use crate::homeserver::get_file;
+use crate::http;
+use crate::memory::now_millis;
+use crate::privacy;
+use pubky::Client;
+use pubky_app_specs::PubkyAppFeed;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::env;
+use std::sync::Mutex;
+
+const HOUR_MILLIS: i64 = 3_600_000;
+
+// A tag or feed the bot takes part in: it answers questions posted there even when
+// they don't mention it.
+#[derive(Debug, Clone)]
+pub struct Channel {
+    // Names the channel's CHANNEL_PERSONA_<KEY> and CHANNEL_MAX_PER_HOUR_<KEY> settings
+    pub key: String,
+    source: Source,
+}
+
+#[derive(Debug, Clone)]
+enum Source {
+    Tag(String),
+    // pubky://<user>/pub/pubky.app/feeds/<id>, followed through its tags
+    Feed(String),
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ChannelState {
+    // Posts indexed before this are never answered
+    since: i64,
+    seen: HashSet<String>,
+    // When questions were taken, for the hourly cap
+    answered: VecDeque<i64>,
+    // Read again after a restart, in case the feed changed
+    #[serde(skip)]
+    feed_tags: Option<Vec<String>>,
+}
+
+static STATE: Mutex<Option<HashMap<String, ChannelState>>> = Mutex::new(None);
+static LAST_POLL: Mutex<i64> = Mutex::new(0);
+
+// A question posted in a channel.
+#[derive(Debug, Clone)]
+pub struct Question {
+    pub channel: String,
+    pub author: String,
+    pub post_uri: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelStats {
+    pub channel: String,
+    pub answered_last_hour: usize,
+    pub max_per_hour: usize,
+}
+
+fn key_for(name: &str) -> String {
+    name.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' }).collect()
+}
+
+// The channels in CHANNELS: comma-separated tags (`pubky`, `#rust-help`) and feed URIs.
+pub fn channels() -> Vec<Channel> {
+    env::var("CHANNELS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            if entry.starts_with("pubky://") {
+                let id = entry.rsplit('/').next().unwrap_or(entry);
+                Channel { key: key_for(id), source: Source::Feed(entry.to_string()) }
+            } else {
+                let tag = entry.trim_start_matches('#').to_lowercase();
+                Channel { key: key_for(&tag), source: Source::Tag(tag) }
+            }
+        })
+        .collect()
+}
+
+pub fn is_enabled() -> bool {
+    !channels().is_empty()
+}
+
+// Persona replies in the channel are written with, from CHANNEL_PERSONA_<KEY>.
+pub fn persona(channel: &str) -> Option<String> {
+    env::var(format!("CHANNEL_PERSONA_{}", channel)).ok().filter(|p| !p.trim().is_empty())
+}
+
+// Questions answered per hour at most in the channel, from CHANNEL_MAX_PER_HOUR_<KEY>.
+fn max_per_hour(channel: &str) -> usize {
+    env::var(format!("CHANNEL_MAX_PER_HOUR_{}", channel)).ok().and_then(|v| v.parse().ok()).unwrap_or(5)
+}
+
+fn interval_millis() -> i64 {
+    env::var("CHANNEL_POLL_INTERVAL_SECS").ok().and_then(|v| v.parse::<i64>().ok()).unwrap_or(60).max(1) * 1000
+}
+
+// Posts that read as a question: a question mark outside links.
+fn is_question(content: &str) -> bool {
+    content.split_whitespace().filter(|word| !word.contains("://")).any(|word| word.contains('?'))
+}
+
+// New questions in every channel since the bot started, at most every
+// CHANNEL_POLL_INTERVAL_SECS. Posts by the bot or mentioning it are left to the
+// mention path. Questions over a channel's hourly cap are skipped, not delayed.
+pub async fn poll(client: &Client, bot_key: &str) -> BotResult<Vec<Question>> {
+    let now = now_millis();
+    {
+        let Ok(mut last_poll) = LAST_POLL.lock() else { return Ok(Vec::new()) };
+        if now - *last_poll < interval_millis() {
+            return Ok(Vec::new());
+        }
+        *last_poll = now;
+    }
+    let nexus_url = env::var("NEXT_PUBLIC_NEXUS").map_err(|_| BotError::Validation("NEXT_PUBLIC_NEXUS not found in .env".to_string()))?;
+
+    let mut questions = Vec::new();
+    for channel in channels() {
+        let tags = match &channel.source {
+            Source::Tag(tag) => vec![tag.clone()],
+            Source::Feed(uri) => match feed_tags(client, &channel.key, uri).await {
+                Ok(tags) => tags,
+                Err(e) => {
+                    log!("Failed to read channel feed {}: {}", uri, e);
+                    continue;
+                }
+            },
+        };
+        if tags.is_empty() {
+            continue;
+        }
+        let url = format!("{}/v0/stream/posts?source=all&sorting=timeline&tags={}&limit=20", nexus_url, tags.join(","));
+        let response = http::nexus().get(&url).send().await.map_err(BotError::nexus)?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            continue;
+        }
+        let posts: Vec<Value> = response.error_for_status().map_err(BotError::nexus)?.json().await.map_err(BotError::nexus)?;
+
+        let Ok(mut state) = STATE.lock() else { continue };
+        let state = state.get_or_insert_with(HashMap::new).entry(channel.key.clone()).or_insert_with(|| ChannelState { since: now, ..Default::default() });
+        state.answered.retain(|at| now - at < HOUR_MILLIS);
+        let cap = max_per_hour(&channel.key);
+        // Oldest first, so a capped burst answers the earliest questions
+        for details in posts.iter().rev().map(|post| &post["details"]) {
+            let (Some(author), Some(uri), Some(content)) = (details["author"].as_str(), details["uri"].as_str(), details["content"].as_str()) else {
+                continue;
+            };
+            if details["indexed_at"].as_i64().unwrap_or(0) < state.since || !state.seen.insert(uri.to_string()) {
+                continue;
+            }
+            if author == bot_key || content.contains(bot_key) || !is_question(content) {
+                continue;
+            }
+            if state.answered.len() >= cap {
+                log!("Channel {} reached {} answers this hour, skipping {}", channel.key, cap, privacy::uri(uri));
+                continue;
+            }
+            state.answered.push_back(now);
+            questions.push(Question { channel: channel.key.clone(), author: author.to_string(), post_uri: uri.to_string() });
+        }
+    }
+    Ok(questions)
+}
+
+// Tags of a feed, read once and then kept for the process lifetime.
+async fn feed_tags(client: &Client, key: &str, uri: &str) -> BotResult<Vec<String>> {
+    if let Some(tags) = STATE.lock().ok().and_then(|state| state.as_ref()?.get(key)?.feed_tags.clone()) {
+        return Ok(tags);
+    }
+    let body = get_file(client, uri).await?.ok_or_else(|| BotError::Validation(format!("Feed {} not found", uri)))?;
+    let feed: PubkyAppFeed = serde_json::from_slice(&body).map_err(|e| BotError::Validation(format!("Invalid feed {}: {}", uri, e)))?;
+    let tags = feed.feed.tags.unwrap_or_default();
+    if tags.is_empty() {
+        log!("Channel feed {} has no tags; only tag feeds can be followed", uri);
+    }
+    if let Ok(mut state) = STATE.lock() {
+        state.get_or_insert_with(HashMap::new).entry(key.to_string()).or_insert_with(|| ChannelState { since: now_millis(), ..Default::default() }).feed_tags = Some(tags.clone());
+    }
+    Ok(tags)
+}
+
+// What each channel has seen and answered, for the state snapshot.
+pub fn snapshot() -> HashMap<String, ChannelState> {
+    let now = now_millis();
+    let Ok(state) = STATE.lock() else { return HashMap::new() };
+    state
+        .iter()
+        .flatten()
+        .map(|(key, channel)| {
+            let answered = channel.answered.iter().copied().filter(|at| now - at < HOUR_MILLIS).collect();
+            (key.clone(), ChannelState { since: channel.since, seen: channel.seen.clone(), answered, feed_tags: None })
+        })
+        .collect()
+}
+
+// Restores the channels of a snapshot that are still configured, so their hourly caps
+// and already seen posts carry over a restart.
+pub fn restore(saved: HashMap<String, ChannelState>) {
+    let keys: HashSet<String> = channels().into_iter().map(|c| c.key).collect();
+    if let Ok(mut state) = STATE.lock() {
+        state.get_or_insert_with(HashMap::new).extend(saved.into_iter().filter(|(key, _)| keys.contains(key)));
+    }
+}
+
+// Answers per channel in the last hour, for /status.
+pub fn stats() -> Vec<ChannelStats> {
+    let now = now_millis();
+    let state = STATE.lock();
+    channels()
+        .into_iter()
+        .map(|channel| ChannelStats {
+            answered_last_hour: state
+                .as_ref()
+                .ok()
+                .and_then(|state| state.as_ref()?.get(&channel.key).map(|s| s.answered.iter().filter(|at| now - **at < HOUR_MILLIS).count()))
+                .unwrap_or(0),
+            max_per_hour: max_per_hour(&channel.key),
+            channel: channel.key,
+        })
+        .collect()
+}
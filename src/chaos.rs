@@ -0,0 +1,70 @@
+use std::env;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// Fault injection for verifying retries, backoff, queues and failover under controlled
+// failure. The fault points are compiled in only with `--features chaos`; in a normal
+// build every one of them is a constant no-op, whatever the environment says.
+
+static RNG: AtomicU64 = AtomicU64::new(0);
+
+fn percent(var: &str) -> u64 {
+    if !cfg!(feature = "chaos") {
+        return 0;
+    }
+    env::var(var).ok().and_then(|v| v.parse::<u64>().ok()).unwrap_or(0).min(100)
+}
+
+// Whether a fault with the given probability fires, from a xorshift generator seeded
+// with CHAOS_SEED (or the clock), so a run can be reproduced.
+fn fires(percent: u64) -> bool {
+    if percent == 0 {
+        return false;
+    }
+    let mut state = RNG.load(Ordering::Relaxed);
+    if state == 0 {
+        state = env::var("CHAOS_SEED").ok().and_then(|v| v.parse().ok()).filter(|s| *s != 0).unwrap_or_else(|| crate::memory::now_millis() as u64 | 1);
+    }
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    RNG.store(state, Ordering::Relaxed);
+    state % 100 < percent
+}
+
+// Drops a Nexus notifications response, from CHAOS_NEXUS_DROP_PERCENT.
+pub fn drop_nexus_response() -> bool {
+    fires(percent("CHAOS_NEXUS_DROP_PERCENT"))
+}
+
+// Extra latency before each LLM request, from CHAOS_LLM_DELAY_MS, applied to
+// CHAOS_LLM_DELAY_PERCENT of requests (default all).
+pub fn llm_delay() -> Option<Duration> {
+    if !cfg!(feature = "chaos") {
+        return None;
+    }
+    let delay = env::var("CHAOS_LLM_DELAY_MS").ok().and_then(|v| v.parse::<u64>().ok()).filter(|ms| *ms > 0)?;
+    let share = env::var("CHAOS_LLM_DELAY_PERCENT").ok().map_or(100, |_| percent("CHAOS_LLM_DELAY_PERCENT"));
+    fires(share).then(|| Duration::from_millis(delay))
+}
+
+// Fails a homeserver PUT before it is sent, from CHAOS_HOMESERVER_PUT_FAIL_PERCENT.
+pub fn fail_homeserver_put() -> bool {
+    fires(percent("CHAOS_HOMESERVER_PUT_FAIL_PERCENT"))
+}
+
+// Logs the faults in force at startup, so a chaos build is never mistaken for a normal one.
+pub fn announce() {
+    if !cfg!(feature = "chaos") {
+        return;
+    }
+    let faults: Vec<String> = ["CHAOS_NEXUS_DROP_PERCENT", "CHAOS_LLM_DELAY_MS", "CHAOS_LLM_DELAY_PERCENT", "CHAOS_HOMESERVER_PUT_FAIL_PERCENT", "CHAOS_SEED"]
+        .iter()
+        .filter_map(|var| env::var(var).ok().map(|v| format!("{}={}", var, v)))
+        .collect();
+    if faults.is_empty() {
+        log!("Chaos build: no faults configured");
+    } else {
+        log!("⚠️ Chaos build: injecting faults ({})", faults.join(", "));
+    }
+}
@@ -0,0 +1,156 @@
+use crate::context::PromptVars;
+use crate::http;
+use crate::kb;
+use crate::llm::openai_url;
+use crate::prompt;
+use crate::retention::RetentionPolicy;
+use crate::secrets::{self, Secret};
+use crate::telegram::TelegramBridge;
+use crate::topics;
+use anyhow::Result;
+use dotenv::dotenv;
+use pubky::Keypair;
+use std::env;
+use std::fs;
+use std::time::Duration;
+
+const REQUIRED_VARS: &[&str] = &["BOT_SECRET_KEY", "BOT_PUBLIC_KEY", "OPENAI_API_KEY", "NEXT_PUBLIC_NEXUS"];
+const PING_TIMEOUT: Duration = Duration::from_secs(15);
+
+// Tally of the checks, printed as they run.
+#[derive(Default)]
+struct Report {
+    failed: usize,
+}
+
+impl Report {
+    fn pass(&mut self, name: &str, detail: &str) {
+        println!("✅ {}: {}", name, detail);
+    }
+
+    fn fail(&mut self, name: &str, problem: &str) {
+        self.failed += 1;
+        println!("❌ {}: {}", name, problem);
+    }
+}
+
+// `check`: validates the configuration and reachability of everything the bot needs
+// without starting it, for CI/CD gates before a deploy. Returns an error (non-zero
+// exit) when any check fails.
+pub async fn run() -> Result<()> {
+    if dotenv().is_err() {
+        println!("No .env file found, checking the process environment");
+    }
+    let mut report = Report::default();
+
+    let missing: Vec<&str> = REQUIRED_VARS.iter().copied().filter(|name| env::var(name).map_or(true, |v| v.trim().is_empty())).collect();
+    if missing.is_empty() {
+        report.pass("config", "required variables are set");
+    } else {
+        report.fail("config", &format!("{} not set; see .env-sample", missing.join(", ")));
+    }
+    check_optional_config(&mut report);
+
+    let keypair = match crate::load_or_create_keypair().await {
+        Ok(keypair) => {
+            report.pass("keypair", &format!("BOT_SECRET_KEY derives {}", keypair.public_key()));
+            Some(keypair)
+        }
+        Err(e) => {
+            report.fail("keypair", &format!("{}; BOT_SECRET_KEY must be the BIP39 mnemonic for BOT_PUBLIC_KEY", e));
+            None
+        }
+    };
+    match &keypair {
+        Some(keypair) => {
+            check_homeserver(&mut report, keypair).await;
+            check_nexus(&mut report, keypair).await;
+        }
+        None => report.fail("homeserver", "skipped, no valid keypair"),
+    }
+    check_llm(&mut report).await;
+    check_files(&mut report);
+
+    if report.failed > 0 {
+        return Err(anyhow::anyhow!("{} check(s) failed", report.failed));
+    }
+    println!("All checks passed");
+    Ok(())
+}
+
+// Settings that are optional but rejected at startup when malformed.
+fn check_optional_config(report: &mut Report) {
+    match RetentionPolicy::from_env() {
+        Ok(_) => report.pass("retention", "settings are valid"),
+        Err(e) => report.fail("retention", &e.to_string()),
+    }
+    match TelegramBridge::from_env() {
+        Ok(_) => report.pass("telegram", "settings are valid"),
+        Err(e) => report.fail("telegram", &e.to_string()),
+    }
+    let topics = topics::restricted_topics();
+    if !topics.is_empty() {
+        report.pass("topics", &format!("{} restricted topic(s)", topics.len()));
+    }
+}
+
+async fn check_homeserver(report: &mut Report, keypair: &Keypair) {
+    let client = match crate::build_client() {
+        Ok(client) => client,
+        Err(e) => return report.fail("homeserver", &format!("could not create the Pubky client: {}", e)),
+    };
+    match tokio::time::timeout(PING_TIMEOUT, client.signin(keypair)).await {
+        Ok(Ok(_)) => report.pass("homeserver", "signed in"),
+        Ok(Err(e)) => report.fail("homeserver", &format!("sign-in failed: {}; check that the key is signed up and TESTNET matches its network", e)),
+        Err(_) => report.fail("homeserver", "sign-in timed out"),
+    }
+}
+
+async fn check_nexus(report: &mut Report, keypair: &Keypair) {
+    let Ok(nexus_url) = env::var("NEXT_PUBLIC_NEXUS") else {
+        return report.fail("nexus", "skipped, NEXT_PUBLIC_NEXUS is not set");
+    };
+    let url = format!("{}/v0/user/{}/notifications?skip=0&limit=1", nexus_url, keypair.public_key());
+    match http::nexus().get(&url).timeout(PING_TIMEOUT).send().await {
+        Ok(response) if response.status().is_success() => report.pass("nexus", &format!("{} is reachable", nexus_url)),
+        Ok(response) => report.fail("nexus", &format!("{} answered {}; check NEXT_PUBLIC_NEXUS", nexus_url, response.status())),
+        Err(e) => report.fail("nexus", &format!("{} is unreachable: {}", nexus_url, secrets::scrub(&e.to_string()))),
+    }
+}
+
+async fn check_llm(report: &mut Report) {
+    let Some(api_key) = Secret::from_env("OPENAI_API_KEY") else {
+        return report.fail("llm", "skipped, OPENAI_API_KEY is not set");
+    };
+    let response = http::llm()
+        .get(openai_url("models"))
+        .header("Authorization", format!("Bearer {}", api_key.expose()))
+        .timeout(PING_TIMEOUT)
+        .send()
+        .await;
+    match response {
+        Ok(response) if response.status().is_success() => report.pass("llm", "OpenAI accepted the API key"),
+        Ok(response) if response.status() == reqwest::StatusCode::UNAUTHORIZED => report.fail("llm", "OpenAI rejected OPENAI_API_KEY"),
+        Ok(response) => report.fail("llm", &format!("OpenAI answered {}", response.status())),
+        Err(e) => report.fail("llm", &format!("OpenAI is unreachable: {}", secrets::scrub(&e.to_string()))),
+    }
+}
+
+fn check_files(report: &mut Report) {
+    let kb_path = kb::knowledge_base_path();
+    match fs::read_to_string(&kb_path) {
+        Ok(content) if content.trim().is_empty() => report.fail("knowledge base", &format!("{} is empty", kb_path)),
+        Ok(content) => report.pass("knowledge base", &format!("{} ({} bytes, version {})", kb_path, content.len(), kb::content_version(&content))),
+        Err(e) => report.fail("knowledge base", &format!("cannot read {}: {}; set KNOWLEDGE_BASE to the file's path", kb_path, e)),
+    }
+    if let Ok(path) = env::var("SYSTEM_PROMPT_FILE") {
+        if fs::metadata(&path).is_err() {
+            report.fail("system prompt", &format!("SYSTEM_PROMPT_FILE {} does not exist", path));
+            return;
+        }
+    }
+    match prompt::system_prompt("", &PromptVars::basic()) {
+        Ok(_) => report.pass("system prompt", "template and includes load"),
+        Err(e) => report.fail("system prompt", &e.to_string()),
+    }
+}
@@ -0,0 +1,59 @@
+use crate::kb::{self, KbSource};
+use std::env;
+
+// Blank line between the reply and its sources
+const SEPARATOR: &str = "\n\n";
+const MAX_REFERENCE_CHARS: usize = 60;
+
+// KB_CITATIONS=true lists the knowledge base chunks an answer was retrieved from under
+// it. Only with KB_RETRIEVAL, since otherwise the whole knowledge base is in the prompt.
+pub fn is_enabled() -> bool {
+    env::var("KB_CITATIONS").map(|v| v == "true").unwrap_or(false) && kb::is_retrieval_enabled()
+}
+
+fn max_sources() -> usize {
+    env::var("KB_CITATIONS_MAX").ok().and_then(|v| v.parse().ok()).unwrap_or(2)
+}
+
+// Chunks less similar to the question than KB_CITATIONS_MIN_SCORE were retrieved only
+// to fill KB_TOP_K and aren't cited.
+fn min_score() -> f32 {
+    env::var("KB_CITATIONS_MIN_SCORE").ok().and_then(|v| v.parse().ok()).unwrap_or(0.3)
+}
+
+// The "Sources: ..." line for the retrieved chunks, best match first, or None when
+// citations are off or no chunk is relevant enough.
+pub fn line(sources: &[KbSource]) -> Option<String> {
+    if !is_enabled() {
+        return None;
+    }
+    let mut references: Vec<String> = Vec::new();
+    for source in sources.iter().filter(|s| s.score >= min_score()) {
+        let reference = compact(&source.reference);
+        if !references.contains(&reference) {
+            references.push(reference);
+        }
+    }
+    references.truncate(max_sources());
+    (!references.is_empty()).then(|| format!("Sources: {}", references.join(" · ")))
+}
+
+fn compact(reference: &str) -> String {
+    if reference.chars().count() <= MAX_REFERENCE_CHARS {
+        return reference.to_string();
+    }
+    let cut: String = reference.chars().take(MAX_REFERENCE_CHARS - 1).collect();
+    format!("{}…", cut.trim_end())
+}
+
+// Characters the sources line takes, taken off the reply's own budget.
+pub fn reserved_chars(line: Option<&str>) -> usize {
+    line.map_or(0, |line| SEPARATOR.chars().count() + line.chars().count())
+}
+
+pub fn append(reply: String, line: Option<&str>) -> String {
+    match line {
+        Some(line) => format!("{}{}{}", reply.trim_end(), SEPARATOR, line),
+        None => reply,
+    }
+}
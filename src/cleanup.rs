@@ -0,0 +1,121 @@
+use crate::error::{BotError, BotResult};
+use crate::examples;
+use crate::homeserver::{delete_post, fetch_post, list_own_posts};
+use crate::memory::MemoryDb;
+use anyhow::Result;
+use pubky::{Client, Keypair};
+use pubky_timestamp::Timestamp;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Selects which of the bot's replies `delete-replies` should remove.
+#[derive(Debug, Default)]
+pub struct ReplyFilter {
+    pub since_micros: Option<u64>,
+    pub to_user: Option<String>,
+    pub uri: Option<String>,
+    pub dry_run: bool,
+}
+
+impl ReplyFilter {
+    // Parses `--since <6h|2d|unix-seconds> --to-user <pubky> --uri <reply uri> --dry-run`.
+    pub fn parse(args: &[String]) -> BotResult<ReplyFilter> {
+        let mut filter = ReplyFilter::default();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let mut value = |name: &str| {
+                iter.next().cloned().ok_or_else(|| BotError::Validation(format!("{} requires a value", name)))
+            };
+            match arg.as_str() {
+                "--since" => filter.since_micros = Some(parse_since(&value("--since")?)?),
+                "--to-user" => filter.to_user = Some(value("--to-user")?.trim_start_matches("pk:").to_string()),
+                "--uri" => filter.uri = Some(value("--uri")?),
+                "--dry-run" => filter.dry_run = true,
+                other => return Err(BotError::Validation(format!("Unknown delete-replies option: {}", other))),
+            }
+        }
+        if filter.since_micros.is_none() && filter.to_user.is_none() && filter.uri.is_none() {
+            return Err(BotError::Validation("delete-replies needs at least one of --since, --to-user or --uri".to_string()));
+        }
+        Ok(filter)
+    }
+}
+
+// Accepts a relative age ("30m", "6h", "2d") or an absolute unix timestamp in seconds.
+fn parse_since(value: &str) -> BotResult<u64> {
+    if let Ok(secs) = value.parse::<u64>() {
+        return Ok(secs * 1_000_000);
+    }
+    Ok(now_micros().saturating_sub(parse_age(value)? * 1_000_000))
+}
+
+// Parses a relative age such as "30m", "6h" or "2d" into seconds.
+pub fn parse_age(value: &str) -> BotResult<u64> {
+    let (number, unit) = value.split_at(value.len().saturating_sub(1));
+    let number: u64 = number.parse().map_err(|_| BotError::Validation(format!("Invalid age: {}", value)))?;
+    match unit {
+        "m" => Ok(number * 60),
+        "h" => Ok(number * 3600),
+        "d" => Ok(number * 86400),
+        _ => Err(BotError::Validation(format!("Invalid age unit in {} (use m, h or d)", value))),
+    }
+}
+
+pub fn now_micros() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_micros() as u64).unwrap_or(0)
+}
+
+pub fn post_micros(uri: &str) -> Option<u64> {
+    let id = uri.rsplit('/').next()?;
+    Timestamp::try_from(id.to_string()).ok().map(|t| t.as_u64())
+}
+
+// Finds the bot's replies matching the filter and deletes them (unless dry-run).
+// Returns the matching URIs.
+pub async fn delete_replies(client: &Client, keypair: &Keypair, memory: &mut MemoryDb, filter: &ReplyFilter) -> Result<Vec<String>> {
+    let own_prefix = format!("pubky://{}/", keypair.public_key());
+    let candidates = match &filter.uri {
+        Some(uri) if !uri.starts_with(&own_prefix) => {
+            return Err(anyhow::anyhow!("{} is not one of the bot's posts", uri));
+        }
+        Some(uri) => vec![uri.clone()],
+        None => list_own_posts(client, keypair).await?,
+    };
+    log!("Checking {} post(s) for matching replies", candidates.len());
+
+    let mut matched = Vec::new();
+    for uri in candidates {
+        if let Some(since) = filter.since_micros {
+            if post_micros(&uri).is_none_or(|t| t < since) {
+                continue;
+            }
+        }
+        let Some(post) = fetch_post(client, &uri).await? else {
+            continue;
+        };
+        let Some(parent) = post.parent else {
+            continue;
+        };
+        if let Some(user) = &filter.to_user {
+            if !parent.starts_with(&format!("pubky://{}/", user)) {
+                continue;
+            }
+        }
+        matched.push(uri);
+    }
+
+    for uri in &matched {
+        if filter.dry_run {
+            log!("Would delete: {}", uri);
+            continue;
+        }
+        delete_post(client, uri).await?;
+        memory.remove_reply(uri);
+        examples::remove(uri);
+        log!("Deleted: {}", uri);
+    }
+    if !filter.dry_run {
+        memory.save()?;
+    }
+
+    Ok(matched)
+}
@@ -0,0 +1,618 @@
+use crate::analytics::{self, AnalyticsRow};
+use crate::archive;
+use crate::broadcasts;
+use crate::canned::Canned;
+use crate::cleanup::{delete_replies, ReplyFilter};
+use crate::context;
+use crate::correlation;
+use crate::examples;
+use crate::faq;
+use crate::homeserver::{delete_post, fetch_post};
+use crate::jobs;
+use crate::kb;
+use crate::llm::{generate_response, reply_max_chars, ChatMessage, ChatRequest, PromptContext};
+use crate::llm_queue::Priority;
+use crate::memory::{now_millis, Draft, MemoryDb};
+use crate::models;
+use crate::notifications;
+use crate::permissions::{self, Access, Role};
+use crate::privacy;
+use crate::provenance;
+use crate::profiles::ProfileCache;
+use crate::receipts;
+use crate::replay;
+use crate::stats;
+use crate::structured;
+use crate::thread::{self, thread_history};
+use crate::watch;
+use anyhow::Result;
+use pubky::{Client, Keypair};
+use serde::Deserialize;
+use std::env;
+use std::collections::BTreeMap;
+
+const PREF_KEYS: [&str; 4] = ["lang", "tone", "length", "style"];
+const MAX_PREF_VALUE_LEN: usize = 32;
+const ELI5_INSTRUCTION: &str = "Explain the post below as if to a curious five-year-old, using simple words and a friendly analogy.";
+
+pub struct CommandContext<'a> {
+    pub client: &'a Client,
+    pub keypair: &'a Keypair,
+    pub author: &'a str,
+    pub post_uri: &'a str,
+    pub trigger: Trigger,
+    pub memory: &'a mut MemoryDb,
+    pub profiles: &'a mut ProfileCache,
+}
+
+// What caused a command to run: a `!command` in a mention, or a tag label listed in TAG_TRIGGERS.
+#[derive(Debug, Clone, Copy)]
+pub enum Trigger {
+    Mention,
+    Tag,
+}
+
+pub enum CommandReply {
+    Text(String),
+    // Generate a reply to `prompt` with an extra system instruction.
+    Generate { prompt: String, instruction: String },
+    // A new version of one of the bot's replies, to go through the same hooks, shadow
+    // check and approval as any reply before it overwrites the old one.
+    Regenerated(Box<Draft>),
+}
+
+#[derive(Debug)]
+pub struct Command {
+    pub name: String,
+    pub args: String,
+}
+
+// Finds the first `!command` token in a post, e.g. "pk:abc !prefs lang=pt".
+// Everything after the command on the same line is treated as its arguments.
+pub fn parse_command(content: &str) -> Option<Command> {
+    for line in content.lines() {
+        let mut words = line.split_whitespace();
+        while let Some(word) = words.next() {
+            if let Some(name) = word.strip_prefix('!') {
+                if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+                    let args = words.collect::<Vec<_>>().join(" ");
+                    return Some(Command { name: name.to_lowercase(), args });
+                }
+            }
+        }
+    }
+    None
+}
+
+// Looks up the command configured for a tag label in TAG_TRIGGERS,
+// e.g. `TAG_TRIGGERS=explain:eli5;translate-pt:translate pt`.
+pub fn tag_trigger(label: &str) -> Option<Command> {
+    let triggers = env::var("TAG_TRIGGERS").ok()?;
+    triggers.split(';').find_map(|entry| {
+        let (tag, command) = entry.split_once(':')?;
+        if tag.trim() != label {
+            return None;
+        }
+        let mut words = command.split_whitespace();
+        let name = words.next()?.trim_start_matches('!').to_lowercase();
+        Some(Command { name, args: words.collect::<Vec<_>>().join(" ") })
+    })
+}
+
+// What a command does, for the generated `!help`. Every command handled in
+// `handle_command` has an entry here, so the help can't drift from what the bot does.
+pub struct CommandInfo {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub description: &'static str,
+    pub owner_only: bool,
+    // Whether the command is on in this configuration
+    pub enabled: fn() -> bool,
+}
+
+fn always() -> bool {
+    true
+}
+
+fn has_broadcasts() -> bool {
+    !broadcasts::topics().is_empty()
+}
+
+fn canned_ping() -> bool {
+    Canned::Ping.is_enabled()
+}
+
+pub const COMMANDS: &[CommandInfo] = &[
+    CommandInfo { name: "help", usage: "!help [command]", description: "list my commands, or explain one", owner_only: false, enabled: always },
+    CommandInfo { name: "ping", usage: "!ping", description: "check that I'm up", owner_only: false, enabled: canned_ping },
+    CommandInfo { name: "prefs", usage: "!prefs [lang=pt tone=formal length=short style=...|clear]", description: "set how I answer you", owner_only: false, enabled: always },
+    CommandInfo { name: "quiz", usage: "!quiz [topic]", description: "a multiple-choice question about Pubky or the topic", owner_only: false, enabled: always },
+    CommandInfo { name: "eli5", usage: "!eli5", description: "explain the post you reply to in simple words", owner_only: false, enabled: always },
+    CommandInfo { name: "translate", usage: "!translate <lang>", description: "translate the post you reply to", owner_only: false, enabled: always },
+    CommandInfo { name: "regen", usage: "!regen", description: "in reply to one of my answers, write it again", owner_only: false, enabled: always },
+    CommandInfo { name: "stats", usage: "!stats", description: "mentions, replies and top topics", owner_only: false, enabled: always },
+    CommandInfo { name: "leaderboard", usage: "!leaderboard [optout|optin]", description: "leave or rejoin the weekly leaderboard", owner_only: false, enabled: always },
+    CommandInfo { name: "subscribe", usage: "!subscribe <topic>", description: "get mentioned under a scheduled broadcast", owner_only: false, enabled: has_broadcasts },
+    CommandInfo { name: "unsubscribe", usage: "!unsubscribe [topic]", description: "stop one broadcast, or all of them", owner_only: false, enabled: has_broadcasts },
+    CommandInfo { name: "quiet", usage: "!quiet [off]", description: "stop me replying in this thread, or let me back in", owner_only: false, enabled: always },
+    CommandInfo { name: "didyousee", usage: "!didyousee [post]", description: "what became of one of your posts", owner_only: false, enabled: always },
+    CommandInfo { name: "deletemydata", usage: "!deletemydata [replies]", description: "forget everything about you, and optionally delete my replies", owner_only: false, enabled: always },
+    CommandInfo { name: "delete-replies", usage: "!delete-replies [options]", description: "delete my replies, with the options of the CLI command", owner_only: true, enabled: always },
+    CommandInfo { name: "kb-version", usage: "!kb-version", description: "the knowledge base version in use", owner_only: true, enabled: always },
+];
+
+// Commands a user with `access` can run in this configuration.
+fn available(access: Access) -> impl Iterator<Item = &'static CommandInfo> {
+    COMMANDS.iter().filter(move |info| access.allows(permissions::role(info)) && (info.enabled)())
+}
+
+// The access of the command's author, looking up followers only when some command
+// is limited to them.
+async fn help_access(ctx: &CommandContext<'_>) -> Access {
+    let needed = if COMMANDS.iter().any(|info| permissions::role(info) == Role::Followers) { Role::Followers } else { Role::Everyone };
+    permissions::access(ctx.keypair, ctx.author, needed).await
+}
+
+// `!help` lists the commands (through CANNED_HELP's {{commands}}); `!help <command>`
+// explains one. Answered without the model.
+async fn handle_help(args: &str, ctx: &mut CommandContext<'_>) -> String {
+    let name = args.split_whitespace().next().map(|n| n.trim_start_matches('!').to_lowercase());
+    let access = help_access(ctx).await;
+    if let Some(name) = name {
+        return match available(access).find(|info| info.name == name) {
+            Some(info) => format!("{}: {}", info.usage, info.description),
+            None => format!(
+                "I don't have a !{} command. Try one of: {}",
+                name,
+                available(access).map(|info| format!("!{}", info.name)).collect::<Vec<_>>().join(", ")
+            ),
+        };
+    }
+    let commands = available(access).map(|info| format!("{}: {}", info.usage, info.description)).collect::<Vec<_>>().join("\n");
+    canned_reply(Canned::Help, ctx).await.replace("{{commands}}", &commands)
+}
+
+// Runs a command and returns the reply text, or None if the command is unknown
+// and the mention should go through normal generation instead.
+pub async fn handle_command(cmd: &Command, ctx: &mut CommandContext<'_>) -> Result<Option<CommandReply>> {
+    if let Some(info) = COMMANDS.iter().find(|info| info.name == cmd.name) {
+        let role = permissions::role(info);
+        if !permissions::access(ctx.keypair, ctx.author, role).await.allows(role) {
+            log!("!{} from {} needs the {} role", cmd.name, privacy::user(ctx.author), role.name());
+            analytics::record(AnalyticsRow::new("command_denied", Some(ctx.author), Some(ctx.post_uri)).with("command", cmd.name.as_str()).with("role", role.name()));
+            return Ok(match role {
+                // Owner commands stay hidden, the mention is answered normally
+                Role::Owner => None,
+                Role::Followers => Some(CommandReply::Text(format!("🔒 !{} is only for people who follow me. Follow me and try again!", cmd.name))),
+                _ => Some(CommandReply::Text(format!("🔒 Sorry, !{} is restricted to a list of approved users.", cmd.name))),
+            });
+        }
+    }
+    let text = match cmd.name.as_str() {
+        "help" => handle_help(&cmd.args, ctx).await,
+        "ping" if Canned::Ping.is_enabled() => canned_reply(Canned::Ping, ctx).await,
+        "prefs" => handle_prefs(&cmd.args, ctx.author, ctx.memory)?,
+        "deletemydata" => handle_delete_my_data(&cmd.args, ctx).await?,
+        "leaderboard" => handle_leaderboard(&cmd.args, ctx.author, ctx.memory)?,
+        "subscribe" => handle_subscribe(&cmd.args, ctx.author, ctx.memory)?,
+        "unsubscribe" => handle_unsubscribe(&cmd.args, ctx.author, ctx.memory)?,
+        "didyousee" => handle_did_you_see(&cmd.args, ctx.author, ctx.memory),
+        "quiet" => handle_quiet(&cmd.args, ctx).await?,
+        "stats" => stats::build_reply(&analytics::load_rows()?, now_millis()),
+        "delete-replies" => handle_delete_replies(&cmd.args, ctx).await?,
+        "kb-version" => handle_kb_version(ctx).await?,
+        "quiz" => handle_quiz(&cmd.args).await?,
+        "regen" => return handle_regen(ctx).await.map(Some),
+        "eli5" => return rewrite_target(ctx, ELI5_INSTRUCTION.to_string()).await.map(Some),
+        "translate" => {
+            let lang = cmd.args.split_whitespace().next().unwrap_or("en");
+            let instruction = format!("Translate the post into the language with code \"{}\". Reply only with the translation.", lang);
+            return rewrite_target(ctx, instruction).await.map(Some);
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(CommandReply::Text(text)))
+}
+
+async fn canned_reply(canned: Canned, ctx: &mut CommandContext<'_>) -> String {
+    let vars = context::build(ctx.client, ctx.keypair, ctx.profiles, ctx.author).await;
+    analytics::record(AnalyticsRow::new("canned", Some(ctx.author), Some(ctx.post_uri)).with("trigger", canned.name()));
+    canned.render(&vars, ctx.author, ctx.memory.language(ctx.author))
+}
+
+// Tells a user what became of one of their posts (or the latest one the bot saw).
+// Only the user's own posts are looked up.
+fn handle_did_you_see(args: &str, author: &str, memory: &MemoryDb) -> String {
+    let receipts = memory.receipts(author);
+    let Some(uri) = args.split_whitespace().next().map(receipts::normalize_uri) else {
+        return match receipts.last() {
+            Some(last) => format!("The latest of your posts I saw is {}. {}", last.post_uri, receipts::describe(last)),
+            None => "I haven't seen any of your posts yet.".to_string(),
+        };
+    };
+    match receipts.iter().find(|r| r.post_uri == uri) {
+        Some(receipt) => receipts::describe(receipt),
+        None => match receipts.last() {
+            Some(last) => format!("🤷 I haven't seen {} from you. The latest of your posts I saw is {}.", uri, last.post_uri),
+            None => format!("🤷 I haven't seen {} from you, or any of your posts yet.", uri),
+        },
+    }
+}
+
+// `!quiet` from anyone in a thread stops the bot replying anywhere in it, until
+// `!quiet off`. The thread is identified by its first post, so it stays muted however
+// it branches and across restarts.
+async fn handle_quiet(args: &str, ctx: &mut CommandContext<'_>) -> Result<String> {
+    let root = thread::root(ctx.client, ctx.post_uri).await?;
+    if args.trim().eq_ignore_ascii_case("off") {
+        if !ctx.memory.unmute_thread(&root) {
+            return Ok("I wasn't keeping quiet in this thread.".to_string());
+        }
+        ctx.memory.save()?;
+        log!("Thread {} unmuted by {}", privacy::uri(&root), privacy::user(ctx.author));
+        analytics::record(AnalyticsRow::new("thread_unmuted", Some(ctx.author), Some(&root)));
+        return Ok("👋 I'm back in this thread. Mention me if you need me.".to_string());
+    }
+    if !ctx.memory.mute_thread(&root) {
+        return Ok("I'm already keeping quiet in this thread.".to_string());
+    }
+    ctx.memory.save()?;
+    log!("Thread {} muted by {}", privacy::uri(&root), privacy::user(ctx.author));
+    analytics::record(AnalyticsRow::new("thread_muted", Some(ctx.author), Some(&root)));
+    Ok("🤫 Got it, I won't reply in this thread anymore. Anyone here can bring me back with `!quiet off`.".to_string())
+}
+
+fn handle_prefs(args: &str, author: &str, memory: &mut MemoryDb) -> Result<String> {
+    let args = args.trim();
+    if args.is_empty() {
+        return Ok(match memory.prefs(author) {
+            Some(prefs) => format!("Your preferences: {}", format_prefs(prefs)),
+            None => format!("You have no preferences set. Try `!prefs lang=pt tone=formal` (keys: {}).", PREF_KEYS.join(", ")),
+        });
+    }
+    if args == "clear" {
+        memory.clear_prefs(author);
+        memory.save()?;
+        return Ok("Your preferences have been cleared.".to_string());
+    }
+
+    let mut prefs = BTreeMap::new();
+    for pair in args.split_whitespace() {
+        let Some((key, value)) = pair.split_once('=') else {
+            return Ok(format!("I couldn't read \"{}\". Use key=value, e.g. `!prefs lang=pt tone=formal`.", pair));
+        };
+        let key = key.to_lowercase();
+        if !PREF_KEYS.contains(&key.as_str()) {
+            return Ok(format!("Unknown preference \"{}\". Supported keys: {}.", key, PREF_KEYS.join(", ")));
+        }
+        if value.is_empty()
+            || value.len() > MAX_PREF_VALUE_LEN
+            || !value.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+        {
+            return Ok(format!("Invalid value for \"{}\". Use a short word like `formal` or `pt`.", key));
+        }
+        prefs.insert(key, value.to_string());
+    }
+
+    memory.set_prefs(author, prefs);
+    memory.save()?;
+    let saved = memory.prefs(author).map(format_prefs).unwrap_or_default();
+    Ok(format!("Got it! I'll remember your preferences: {}", saved))
+}
+
+// `!leaderboard optout` keeps the user out of the weekly leaderboard post; `optin` undoes it.
+fn handle_leaderboard(args: &str, author: &str, memory: &mut MemoryDb) -> Result<String> {
+    let opt_out = match args.trim() {
+        "optout" | "opt-out" | "off" => true,
+        "optin" | "opt-in" | "on" => false,
+        _ => {
+            let status = if memory.leaderboard_opt_out(author) { "hidden from" } else { "included in" };
+            return Ok(format!("You are {} the weekly leaderboard. Use `!leaderboard optout` or `!leaderboard optin` to change it.", status));
+        }
+    };
+    memory.set_leaderboard_opt_out(author, opt_out);
+    memory.save()?;
+    Ok(if opt_out {
+        "Done, you won't appear in the weekly leaderboard.".to_string()
+    } else {
+        "Done, you can appear in the weekly leaderboard again.".to_string()
+    })
+}
+
+// `!subscribe <topic>` gets the user mentioned under each post of a scheduled broadcast
+// (see `broadcasts::topics`); without a topic it lists them.
+fn handle_subscribe(args: &str, author: &str, memory: &mut MemoryDb) -> Result<String> {
+    let topics = broadcasts::topics();
+    if topics.is_empty() {
+        return Ok("There are no broadcasts to subscribe to right now.".to_string());
+    }
+    let topic = args.trim().to_lowercase();
+    if !topics.contains(&topic.as_str()) {
+        let current = memory.subscriptions_of(author);
+        let status = if current.is_empty() { "You have no subscriptions.".to_string() } else { format!("You are subscribed to: {}.", current.join(", ")) };
+        return Ok(format!("Subscribe with `!subscribe <topic>`; available: {}. {}", topics.join(", "), status));
+    }
+    if !memory.subscribe(&topic, author) {
+        return Ok(format!("You are already subscribed to {}.", topic));
+    }
+    memory.save()?;
+    analytics::record(AnalyticsRow::new("subscribe", Some(author), None).with("topic", topic.as_str()));
+    Ok(format!("Subscribed! I'll mention you when the next {} is out. Use `!unsubscribe {}` to stop.", topic, topic))
+}
+
+// `!unsubscribe <topic>` stops one broadcast; without a topic, all of them.
+fn handle_unsubscribe(args: &str, author: &str, memory: &mut MemoryDb) -> Result<String> {
+    let topic = args.trim().to_lowercase();
+    let topics = if topic.is_empty() { memory.subscriptions_of(author) } else { vec![topic] };
+    let removed: Vec<String> = topics.into_iter().filter(|topic| memory.unsubscribe(topic, author)).collect();
+    if removed.is_empty() {
+        return Ok("You weren't subscribed to that.".to_string());
+    }
+    memory.save()?;
+    for topic in &removed {
+        analytics::record(AnalyticsRow::new("unsubscribe", Some(author), None).with("topic", topic.as_str()));
+    }
+    Ok(format!("Unsubscribed from {}.", removed.join(", ")))
+}
+
+// Analytics event recorded once `!deletemydata` has run.
+pub const DATA_DELETED_EVENT: &str = "data_deleted";
+
+// `!deletemydata` forgets the user; `!deletemydata replies` also removes the
+// bot's replies to them from the homeserver.
+async fn handle_delete_my_data(args: &str, ctx: &mut CommandContext<'_>) -> Result<String> {
+    let delete_replies = args.split_whitespace().any(|a| a == "replies");
+
+    let mut deleted = 0;
+    let mut failed = 0;
+    if delete_replies {
+        let uris: Vec<String> = ctx.memory.replies_to(ctx.author).iter().map(|r| r.uri.clone()).collect();
+        for uri in uris {
+            match delete_post(ctx.client, &uri).await {
+                Ok(_) => deleted += 1,
+                Err(e) => {
+                    log!("Failed to delete reply {}: {}", uri, e);
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    let reply_uris: Vec<String> = ctx.memory.replies_to(ctx.author).iter().map(|r| r.uri.clone()).collect();
+    for uri in &reply_uris {
+        examples::remove(uri);
+    }
+    let mut removed = ctx.memory.forget_user(ctx.author);
+    ctx.memory.save()?;
+    removed += provenance::forget_user(ctx.client, ctx.keypair, ctx.author, &reply_uris).await?;
+    removed += analytics::forget_user(ctx.author)?;
+    removed += faq::forget_user(ctx.author) + watch::forget_user(ctx.author);
+    removed += jobs::forget_user(ctx.author)?;
+    removed += notifications::forget_user(ctx.author)? + archive::forget_user(ctx.author)? + replay::forget_user(ctx.author)?;
+    log!("Deleted data for {}: {} records, {} replies", privacy::user(ctx.author), removed, deleted);
+    // Without the user or post, so it holds nothing about them; tells the notification
+    // loop not to store a receipt for this mention afterwards
+    analytics::record(AnalyticsRow::new(DATA_DELETED_EVENT, None, None).with("records", removed));
+
+    let mut reply = format!("Done! I removed {} stored record(s) about you, including your preferences, our conversation history, analytics, cached answers, the public records linking my replies to your posts, and the notifications of yours I had queued, archived or recorded.", removed);
+    if delete_replies {
+        reply.push_str(&format!(" I also deleted {} of my replies to you.", deleted));
+        if failed > 0 {
+            reply.push_str(&format!(" {} could not be deleted right now; try again later.", failed));
+        }
+    } else {
+        reply.push_str(" Use `!deletemydata replies` to also delete my replies to you.");
+    }
+    Ok(reply)
+}
+
+// Owner-only: same options as the `delete-replies` CLI command.
+async fn handle_delete_replies(args: &str, ctx: &mut CommandContext<'_>) -> Result<String> {
+    let args: Vec<String> = args.split_whitespace().map(String::from).collect();
+    let filter = match ReplyFilter::parse(&args) {
+        Ok(filter) => filter,
+        Err(e) => return Ok(format!("{}", e)),
+    };
+    let matched = delete_replies(ctx.client, ctx.keypair, ctx.memory, &filter).await?;
+    Ok(if filter.dry_run {
+        format!("{} of my replies match; nothing was deleted (dry run).", matched.len())
+    } else {
+        format!("Deleted {} of my replies.", matched.len())
+    })
+}
+
+// `!regen` posted as a reply to one of the bot's answers regenerates that answer
+// from the original prompt and overwrites it in place.
+async fn handle_regen(ctx: &mut CommandContext<'_>) -> Result<CommandReply> {
+    let parent = fetch_post(ctx.client, ctx.post_uri).await?.and_then(|p| p.parent);
+    let Some(reply_uri) = parent else {
+        return Ok(CommandReply::Text("Reply to one of my answers with `!regen` to regenerate it.".to_string()));
+    };
+    let Some(record) = ctx.memory.replies.iter().find(|r| r.uri == reply_uri).cloned() else {
+        return Ok(CommandReply::Text("I can only regenerate my own answers that I still remember.".to_string()));
+    };
+    if record.to_user != ctx.author && !is_owner(ctx.author) {
+        return Ok(CommandReply::Text("Only the person I answered can regenerate this reply.".to_string()));
+    }
+
+    log!("Regenerating reply {}", record.uri);
+    let vars = context::build(ctx.client, ctx.keypair, ctx.profiles, &record.to_user).await;
+    let history = match record.instruction {
+        None => thread_history(ctx.client, ctx.keypair, &record.parent_uri).await.unwrap_or_default(),
+        Some(_) => Vec::new(),
+    };
+    let context = PromptContext {
+        prefs: ctx.memory.prefs(&record.to_user),
+        instruction: record.instruction.as_deref(),
+        vars: Some(&vars),
+        history: &history,
+        language: ctx.memory.language(&record.to_user),
+        ..Default::default()
+    };
+    let content = generate_response(&record.prompt, &context).await?;
+    let kb_version = kb::version(kb::variant_for(&record.prompt, context.language).as_deref()).ok();
+    Ok(CommandReply::Regenerated(Box::new(Draft {
+        parent_uri: record.parent_uri,
+        to_user: record.to_user,
+        prompt: record.prompt,
+        instruction: record.instruction,
+        trigger_label: record.trigger_label,
+        kb_version,
+        content,
+        correlation_id: correlation::current(),
+        overwrite: Some(record.uri),
+    })))
+}
+
+// Reports the knowledge base version in use and, when the mention replies to one of the
+// bot's answers, the version that answer was generated with.
+async fn handle_kb_version(ctx: &mut CommandContext<'_>) -> Result<String> {
+    let mut reply = format!("Knowledge base {} is at version {}", kb::knowledge_base_path(), kb::version(None)?);
+    if let Some(age) = kb::age() {
+        let hours = age.as_secs() / 3600;
+        reply.push_str(&format!(", last updated {}d {}h ago", hours / 24, hours % 24));
+    }
+    reply.push('.');
+
+    let parent = fetch_post(ctx.client, ctx.post_uri).await?.and_then(|p| p.parent);
+    if let Some(record) = parent.and_then(|uri| ctx.memory.replies.iter().find(|r| r.uri == uri)) {
+        match &record.kb_version {
+            Some(version) => reply.push_str(&format!(" The answer you replied to was generated with version {}.", version)),
+            None => reply.push_str(" The answer you replied to has no recorded version."),
+        }
+    }
+    Ok(reply)
+}
+
+// Commands like !eli5 work on the tagged post, or on the post a mention replies to
+// (falling back to the mention itself).
+async fn rewrite_target(ctx: &mut CommandContext<'_>, instruction: String) -> Result<CommandReply> {
+    let post = fetch_post(ctx.client, ctx.post_uri).await?;
+    let target = match (ctx.trigger, post) {
+        (Trigger::Mention, Some(post)) => match post.parent {
+            Some(parent) => fetch_post(ctx.client, &parent).await?.map(|p| p.content),
+            None => Some(post.content),
+        },
+        (_, post) => post.map(|p| p.content),
+    };
+    let Some(prompt) = target.filter(|c| !c.trim().is_empty()) else {
+        return Ok(CommandReply::Text("I couldn't find a post to work on.".to_string()));
+    };
+    Ok(CommandReply::Generate { prompt, instruction })
+}
+
+#[derive(Debug, Deserialize)]
+struct Quiz {
+    question: String,
+    options: Vec<String>,
+    // Index of the correct option
+    answer: usize,
+    explanation: String,
+}
+
+// `!quiz [topic]` posts a multiple-choice question about the topic (Pubky by default),
+// grounded in the knowledge base.
+async fn handle_quiz(args: &str) -> Result<String> {
+    let topic = if args.trim().is_empty() { "Pubky" } else { args.trim() };
+    let knowledge = kb::knowledge_for(topic, None).await?;
+    // Sized so the whole quiz fits in one reply, frame included
+    let max_chars = reply_max_chars();
+    let (question_chars, option_chars, explanation_chars) = (max_chars / 4, max_chars / 10, max_chars / 5);
+    let request = ChatRequest {
+        model: models::resolve("default"),
+        messages: vec![
+            ChatMessage {
+                role: "system".to_string(),
+                content: format!(
+                    "Write one fun multiple-choice quiz question about the topic the user gives, with 3 or 4 short options and exactly one correct answer. Base it on the knowledge base when it covers the topic. Keep the question under {} characters, each option under {} and the explanation to one sentence under {}.\n\nKNOWLEDGE BASE:\n{}",
+                    question_chars, option_chars, explanation_chars, knowledge
+                ),
+            },
+            ChatMessage { role: "user".to_string(), content: topic.to_string() },
+        ],
+        temperature: 0.8,
+        max_tokens: 300,
+    };
+    let schema = serde_json::json!({
+        "type": "object",
+        "properties": {
+            "question": { "type": "string", "description": format!("At most {} characters", question_chars) },
+            "options": { "type": "array", "items": { "type": "string", "description": format!("At most {} characters", option_chars) } },
+            "answer": { "type": "integer", "description": "Zero-based index of the correct option" },
+            "explanation": { "type": "string", "description": format!("One sentence of at most {} characters", explanation_chars) },
+        },
+        "required": ["question", "options", "answer", "explanation"],
+        "additionalProperties": false,
+    });
+    let quiz: Quiz = structured::complete(&request, "quiz", schema, Priority::Mention).await?;
+    if !(2..=4).contains(&quiz.options.len()) || quiz.answer >= quiz.options.len() {
+        anyhow::bail!("Invalid quiz: {} options, answer {}", quiz.options.len(), quiz.answer);
+    }
+    format_quiz(&quiz, max_chars).ok_or_else(|| anyhow::anyhow!("Quiz doesn't fit in {} characters even without its explanation", max_chars))
+}
+
+// The quiz as a post of at most `max_chars` characters. The explanation is left out when
+// it doesn't fit, since the answer stands without it; a quiz that doesn't fit even then
+// is None, as a cut-off question or option would be unanswerable.
+fn format_quiz(quiz: &Quiz, max_chars: usize) -> Option<String> {
+    let letters = ['A', 'B', 'C', 'D'];
+    let options = quiz
+        .options
+        .iter()
+        .zip(letters)
+        .map(|(option, letter)| format!("{}) {}", letter, option))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let text = format!(
+        "🧠 Quiz: {}\n\n{}\n\nThink it over, answer below 👇\n.\n.\n.\nAnswer: {}) {}.",
+        quiz.question.trim(),
+        options,
+        letters[quiz.answer],
+        quiz.options[quiz.answer].trim_end_matches('.')
+    );
+    let explained = format!("{} {}", text, quiz.explanation.trim());
+    [explained, text].into_iter().find(|t| t.chars().count() <= max_chars)
+}
+
+pub fn is_owner(user: &str) -> bool {
+    env::var("BOT_OWNER").map(|owner| owner.trim_start_matches("pk:") == user).unwrap_or(false)
+}
+
+pub fn format_prefs(prefs: &BTreeMap<String, String>) -> String {
+    prefs.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quiz(explanation: &str) -> Quiz {
+        Quiz {
+            question: "What identifies a Pubky user?".to_string(),
+            options: vec!["An email".to_string(), "A public key".to_string(), "A phone number".to_string()],
+            answer: 1,
+            explanation: explanation.to_string(),
+        }
+    }
+
+    #[test]
+    fn quiz_keeps_the_explanation_when_it_fits() {
+        let text = format_quiz(&quiz("Keys are the identity."), 1000).unwrap();
+        assert!(text.starts_with("🧠 Quiz: What identifies a Pubky user?\n\nA) An email\nB) A public key\nC) A phone number\n"));
+        assert!(text.ends_with("Answer: B) A public key. Keys are the identity."));
+    }
+
+    #[test]
+    fn quiz_drops_the_explanation_before_cutting() {
+        let full = format_quiz(&quiz("Keys are the identity."), 1000).unwrap();
+        let limit = full.chars().count() - 1;
+        let text = format_quiz(&quiz("Keys are the identity."), limit).unwrap();
+        assert!(text.ends_with("Answer: B) A public key."));
+        assert!(text.chars().count() <= limit);
+    }
+
+    #[test]
+    fn quiz_that_cannot_fit_is_rejected() {
+        assert_eq!(format_quiz(&quiz(""), 40), None);
+    }
+}
@@ -0,0 +1,121 @@
+use crate::error::{BotError, BotResult};
+use crate::http;
+use crate::llm::reply_max_chars;
+use crate::memory::now_millis;
+use crate::profiles::ProfileCache;
+use pubky::{Client, Keypair};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::env;
+use std::sync::Mutex;
+use tokio::time::{Duration, Instant};
+
+const FOLLOWER_COUNT_TTL: Duration = Duration::from_secs(600);
+
+// Values for the {{variables}} in the system prompt template, resolved when a reply
+// is generated.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PromptVars {
+    pub date: String,
+    pub time: String,
+    pub bot_name: String,
+    pub follower_count: String,
+    pub user_name: String,
+    pub persona: String,
+    pub max_chars: String,
+}
+
+impl PromptVars {
+    // Variables that need no lookups: the current date/time, the persona and the
+    // reply length limit.
+    pub fn basic() -> PromptVars {
+        let (date, time) = format_utc(now_millis());
+        PromptVars {
+            date,
+            time,
+            persona: env::var("PERSONA").unwrap_or_else(|_| "default".to_string()),
+            max_chars: reply_max_chars().to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn apply(&self, template: &str) -> String {
+        template
+            .replace("{{date}}", &self.date)
+            .replace("{{time}}", &self.time)
+            .replace("{{bot_name}}", &self.bot_name)
+            .replace("{{follower_count}}", &self.follower_count)
+            .replace("{{user_name}}", &self.user_name)
+            .replace("{{persona}}", &self.persona)
+            .replace("{{max_chars}}", &self.max_chars)
+    }
+}
+
+// Resolves every prompt variable for a reply to `user`. Lookups that fail leave the
+// variable with a neutral fallback instead of failing the reply.
+pub async fn build(client: &Client, keypair: &Keypair, profiles: &mut ProfileCache, user: &str) -> PromptVars {
+    let mut vars = PromptVars::basic();
+    let bot_key = keypair.public_key().to_string();
+
+    vars.bot_name = match profiles.get(client, &bot_key).await {
+        Ok(Some(profile)) => profile.name,
+        _ => "AI Rand".to_string(),
+    };
+    vars.user_name = match profiles.get(client, user).await {
+        Ok(Some(profile)) if !profile.name.trim().is_empty() => profile.name.trim().to_string(),
+        _ => "there".to_string(),
+    };
+    vars.follower_count = match follower_count(&bot_key).await {
+        Ok(count) => count.to_string(),
+        Err(e) => {
+            log!("Failed to fetch follower count: {}", e);
+            "some".to_string()
+        }
+    };
+    vars
+}
+
+// The bot's follower count from Nexus, cached for a few minutes.
+pub async fn follower_count(bot_key: &str) -> BotResult<u64> {
+    static CACHE: Mutex<Option<(Instant, u64)>> = Mutex::new(None);
+    if let Some((fetched_at, count)) = *CACHE.lock().map_err(BotError::nexus)? {
+        if fetched_at.elapsed() < FOLLOWER_COUNT_TTL {
+            return Ok(count);
+        }
+    }
+
+    let nexus_url = env::var("NEXT_PUBLIC_NEXUS").map_err(|_| BotError::Validation("NEXT_PUBLIC_NEXUS not found in .env".to_string()))?;
+    let url = format!("{}/v0/user/{}/counts", nexus_url, bot_key);
+    let counts: Value = http::nexus()
+        .get(&url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(BotError::nexus)?
+        .json()
+        .await
+        .map_err(BotError::nexus)?;
+    let count = counts["followers"].as_u64().ok_or_else(|| BotError::Nexus("counts response has no followers field".to_string()))?;
+    *CACHE.lock().map_err(BotError::nexus)? = Some((Instant::now(), count));
+    Ok(count)
+}
+
+// ("YYYY-MM-DD", "HH:MM UTC") for a unix timestamp in milliseconds.
+pub fn format_utc(millis: i64) -> (String, String) {
+    let secs = millis.div_euclid(1000);
+    let (days, rem) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    // Days since 1970-01-01 to a civil date (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (
+        format!("{:04}-{:02}-{:02}", year, month, day),
+        format!("{:02}:{:02} UTC", rem / 3600, rem % 3600 / 60),
+    )
+}
@@ -0,0 +1,23 @@
+use sha2::{Digest, Sha256};
+use std::future::Future;
+
+tokio::task_local! {
+    static CORRELATION_ID: String;
+}
+
+// Short ID for a notification, derived from its content so a notification that is
+// fetched again after a failed batch keeps the same ID across the retry.
+pub fn for_notification(timestamp: i64, body: &serde_json::Value) -> String {
+    Sha256::digest(format!("{}\n{}", timestamp, body).as_bytes()).iter().take(6).map(|b| format!("{:02x}", b)).collect()
+}
+
+// Runs `work` with `id` as the current correlation ID. Log lines, analytics rows and
+// provenance records written meanwhile carry it, so one mention can be traced
+// across subsystems.
+pub async fn scope<F: Future>(id: String, work: F) -> F::Output {
+    CORRELATION_ID.scope(id, work).await
+}
+
+pub fn current() -> Option<String> {
+    CORRELATION_ID.try_with(|id| id.clone()).ok()
+}
@@ -0,0 +1,43 @@
+use crate::error::BotResult;
+use crate::llm::{chat_completion, ChatMessage, ChatRequest};
+use crate::llm_queue::{self, Priority};
+use crate::models;
+use crate::privacy;
+use std::env;
+
+const CRITIQUE_INSTRUCTION: &str = "You review a draft reply written by a social media assistant before it is published. Check it against the knowledge base and the assistant's rules below: fix statements the knowledge base contradicts or doesn't support when they are presented as facts about Pubky, and fix violations of the length, tone and language rules. Keep everything that is already correct and keep the draft's voice. Reply only with the final reply text, unchanged if nothing needs fixing.";
+
+// Self-critique pass before publishing, enabled with SELF_CRITIQUE=true.
+pub fn is_enabled() -> bool {
+    env::var("SELF_CRITIQUE").map(|v| v == "true").unwrap_or(false)
+}
+
+// Reviews `draft` (the answer to `question`) against the knowledge base chunks it was
+// generated with and the prompt rules, and returns the revised reply.
+pub async fn revise(question: &str, draft: &str, knowledge_base: &str, rules: &str, max_chars: usize, priority: Priority) -> BotResult<String> {
+    let system = format!(
+        "{}\n\nThe reply must stay under {} characters.\n\nASSISTANT RULES:\n{}\n\nKNOWLEDGE BASE:\n{}",
+        CRITIQUE_INSTRUCTION, max_chars, rules.trim(), knowledge_base
+    );
+    let request = ChatRequest {
+        model: models::resolve(&env::var("CRITIQUE_MODEL").unwrap_or_else(|_| "default".to_string())),
+        messages: vec![
+            ChatMessage { role: "system".to_string(), content: system },
+            ChatMessage {
+                role: "user".to_string(),
+                content: format!("POST:\n{}\n\nDRAFT REPLY:\n{}", question, draft),
+            },
+        ],
+        temperature: 0.2,
+        max_tokens: llm_queue::completion_tokens(max_chars, draft),
+    };
+    let revised = chat_completion(&request, priority).await?;
+    let revised = revised.trim();
+    if revised.is_empty() {
+        return Ok(draft.to_string());
+    }
+    if revised != draft.trim() {
+        log!("Self-critique revised the reply: {}", privacy::content(revised));
+    }
+    Ok(revised.to_string())
+}
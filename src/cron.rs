@@ -0,0 +1,197 @@
+use crate::error::{BotError, BotResult};
+
+const MONTHS: [&str; 12] = ["JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC"];
+const WEEKDAYS: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+// A five-field cron expression: minute, hour, day of month, month, day of week.
+// Fields take `*`, numbers, names (JAN-DEC, SUN-SAT), ranges (`1-5`), lists (`1,15`)
+// and steps (`*/15`, `9-17/2`); Sunday is 0 or 7. As in classic cron, when both the
+// day of month and the day of week are restricted, a day matching either one runs.
+#[derive(Debug, Clone)]
+pub struct CronExpr {
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days: Vec<bool>,
+    months: Vec<bool>,
+    weekdays: Vec<bool>,
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl CronExpr {
+    pub fn parse(expr: &str) -> BotResult<CronExpr> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(BotError::Validation(format!("Cron expression `{}` needs 5 fields", expr)));
+        };
+        let mut weekdays = parse_field(weekday, 0, 7, &WEEKDAYS)?;
+        // 7 is another name for Sunday
+        weekdays[0] |= weekdays[7];
+        weekdays.truncate(7);
+        Ok(CronExpr {
+            minutes: parse_field(minute, 0, 59, &[])?,
+            hours: parse_field(hour, 0, 23, &[])?,
+            days: parse_field(day, 1, 31, &[])?,
+            months: parse_field(month, 1, 12, &MONTHS)?,
+            weekdays,
+            days_restricted: day != "*",
+            weekdays_restricted: weekday != "*",
+        })
+    }
+
+    // Whether the expression runs on a date; `weekday` is 0 for Sunday.
+    pub fn matches_day(&self, month: u32, day: u32, weekday: u32) -> bool {
+        if !self.months[month as usize] {
+            return false;
+        }
+        let by_day = self.days[day as usize];
+        let by_weekday = self.weekdays[weekday as usize];
+        match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => by_day || by_weekday,
+            (true, false) => by_day,
+            (false, true) => by_weekday,
+            (false, false) => true,
+        }
+    }
+
+    // The (hour, minute) times of day the expression runs at, in order.
+    pub fn times(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        (0..24u32).filter(|h| self.hours[*h as usize]).flat_map(move |h| (0..60u32).filter(|m| self.minutes[*m as usize]).map(move |m| (h, m)))
+    }
+}
+
+// One field as a table indexed by value (from 0, so unused low slots stay false).
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> BotResult<Vec<bool>> {
+    let invalid = || BotError::Validation(format!("Invalid cron field `{}` (allowed {}-{})", field, min, max));
+    let value = |s: &str| -> BotResult<u32> {
+        if let Some(i) = names.iter().position(|name| name.eq_ignore_ascii_case(s)) {
+            return Ok(i as u32 + min);
+        }
+        s.parse::<u32>().ok().filter(|v| (min..=max).contains(v)).ok_or_else(invalid)
+    };
+    let mut allowed = vec![false; max as usize + 1];
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|s| *s > 0).ok_or_else(invalid)?),
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                // `5/15` runs from 5 to the end of the range
+                None if part.contains('/') => (value(range)?, max),
+                None => (value(range)?, value(range)?),
+            },
+        };
+        if start > end {
+            return Err(invalid());
+        }
+        for v in (start..=end).step_by(step as usize) {
+            allowed[v as usize] = true;
+        }
+    }
+    Ok(allowed)
+}
+
+// Days since 1970-01-01 of a proleptic Gregorian date.
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+// (year, month, day) of a day count since 1970-01-01.
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    (year_of_era + era * 400 + (month <= 2) as i64, month, day)
+}
+
+// Day of the week of a day count since 1970-01-01, 0 for Sunday.
+pub fn weekday(days: i64) -> u32 {
+    (days + 4).rem_euclid(7) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // (month, day, weekday) of a date.
+    fn date(year: i64, month: u32, day: u32) -> (u32, u32, u32) {
+        (month, day, weekday(days_from_civil(year, month, day)))
+    }
+
+    fn runs_on(expr: &CronExpr, (month, day, weekday): (u32, u32, u32)) -> bool {
+        expr.matches_day(month, day, weekday)
+    }
+
+    #[test]
+    fn day_of_month_or_day_of_week() {
+        // Both restricted: the 13th, or any Friday
+        let expr = CronExpr::parse("0 9 13 * FRI").unwrap();
+        assert!(runs_on(&expr, date(2026, 11, 13)));
+        assert!(runs_on(&expr, date(2026, 11, 6)));
+        assert!(runs_on(&expr, date(2026, 10, 13)));
+        assert!(!runs_on(&expr, date(2026, 10, 14)));
+    }
+
+    #[test]
+    fn only_one_day_field_restricted() {
+        let by_day = CronExpr::parse("0 9 13 * *").unwrap();
+        assert!(runs_on(&by_day, date(2026, 10, 13)));
+        assert!(!runs_on(&by_day, date(2026, 11, 6)));
+        let by_weekday = CronExpr::parse("0 9 * * MON-FRI").unwrap();
+        assert!(runs_on(&by_weekday, date(2026, 10, 16)));
+        assert!(!runs_on(&by_weekday, date(2026, 10, 17)));
+        let by_month = CronExpr::parse("0 9 * DEC *").unwrap();
+        assert!(runs_on(&by_month, date(2026, 12, 1)));
+        assert!(!runs_on(&by_month, date(2026, 11, 30)));
+    }
+
+    #[test]
+    fn sunday_is_zero_or_seven() {
+        for expr in ["0 9 * * 0", "0 9 * * 7", "0 9 * * SUN"] {
+            let expr = CronExpr::parse(expr).unwrap();
+            assert!(runs_on(&expr, date(2026, 10, 18)));
+            assert!(!runs_on(&expr, date(2026, 10, 19)));
+        }
+    }
+
+    #[test]
+    fn times_with_steps_lists_and_ranges() {
+        let expr = CronExpr::parse("*/20 9-11/2,23 * * *").unwrap();
+        assert_eq!(expr.times().collect::<Vec<_>>(), vec![(9, 0), (9, 20), (9, 40), (11, 0), (11, 20), (11, 40), (23, 0), (23, 20), (23, 40)]);
+        let expr = CronExpr::parse("5/30 0 * * *").unwrap();
+        assert_eq!(expr.times().collect::<Vec<_>>(), vec![(0, 5), (0, 35)]);
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        for expr in ["0 9 * *", "60 9 * * *", "0 24 * * *", "0 9 0 * *", "0 9 * 13 *", "0 9 * * 8", "0 9 5-1 * *", "*/0 9 * * *", "0 9 * * FUNDAY"] {
+            assert!(CronExpr::parse(expr).is_err(), "{}", expr);
+        }
+    }
+
+    #[test]
+    fn civil_dates_round_trip() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(weekday(0), 4);
+        for days in [-719_468, -1, 0, 11_016, 19_722, 20_742, 2_932_896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+        assert_eq!(civil_from_days(days_from_civil(2024, 2, 29)), (2024, 2, 29));
+        assert_eq!(civil_from_days(days_from_civil(2024, 2, 29) + 1), (2024, 3, 1));
+    }
+}
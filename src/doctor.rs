@@ -0,0 +1,109 @@
+use crate::homeserver::{delete_file, put_file};
+use crate::http;
+use crate::llm::{chat_completion, ChatMessage, ChatRequest};
+use crate::llm_queue::Priority;
+use crate::memory::now_millis;
+use crate::models;
+use crate::notifications;
+use anyhow::Result;
+use dotenv::dotenv;
+use pubky::{Client, Keypair};
+use std::env;
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::Instant;
+
+const STEP_TIMEOUT: Duration = Duration::from_secs(30);
+
+// `doctor`: live round trips against every service the bot talks to, with the latency
+// of each, to diagnose a running deployment. Unlike `check` it writes (and removes) a
+// probe file and spends a one-token model call. Returns an error when any step fails.
+pub async fn run() -> Result<()> {
+    dotenv().ok();
+    let client = crate::build_client()?;
+    let keypair = crate::load_or_create_keypair().await?;
+    let mut failed = 0;
+
+    failed += step("sign-in", async {
+        client.signin(&keypair).await?;
+        Ok("session established".to_string())
+    })
+    .await;
+    failed += step("homeserver write/read/delete", probe_homeserver(&client, &keypair)).await;
+    failed += step("notifications", fetch_notifications(&keypair)).await;
+    failed += step("llm", async {
+        let request = ChatRequest {
+            model: models::resolve("default"),
+            messages: vec![ChatMessage { role: "user".to_string(), content: "Reply with OK.".to_string() }],
+            temperature: 0.0,
+            max_tokens: 1,
+        };
+        let answer = chat_completion(&request, Priority::Mention).await?;
+        Ok(format!("model answered {:?}", answer.trim()))
+    })
+    .await;
+
+    if failed > 0 {
+        return Err(anyhow::anyhow!("{} diagnostic step(s) failed", failed));
+    }
+    println!("All diagnostics passed");
+    Ok(())
+}
+
+// Runs one timed step and prints its outcome; returns 1 when it failed.
+async fn step(name: &str, work: impl Future<Output = Result<String>>) -> usize {
+    let started = Instant::now();
+    let result = tokio::time::timeout(STEP_TIMEOUT, work).await;
+    let ms = started.elapsed().as_millis();
+    match result {
+        Ok(Ok(detail)) => {
+            println!("✅ {} ({} ms): {}", name, ms, detail);
+            0
+        }
+        Ok(Err(e)) => {
+            println!("❌ {} ({} ms): {}", name, ms, e);
+            1
+        }
+        Err(_) => {
+            println!("❌ {} (timed out after {:?})", name, STEP_TIMEOUT);
+            1
+        }
+    }
+}
+
+// Writes a small probe file, reads it back uncached and deletes it.
+async fn probe_homeserver(client: &Client, keypair: &Keypair) -> Result<String> {
+    let url = format!("pubky://{}/pub/ai-rand/doctor-probe", keypair.public_key());
+    let body = format!("ai-rand doctor probe {}", now_millis());
+
+    let started = Instant::now();
+    put_file(client, &url, body.clone().into_bytes()).await?;
+    let write_ms = started.elapsed().as_millis();
+
+    let started = Instant::now();
+    let response = client.get(&url).send().await?;
+    let read = response.bytes().await?;
+    let read_ms = started.elapsed().as_millis();
+    let matches = read.as_ref() == body.as_bytes();
+
+    let started = Instant::now();
+    delete_file(client, &url).await?;
+    let delete_ms = started.elapsed().as_millis();
+
+    if !matches {
+        return Err(anyhow::anyhow!("read back different content than was written"));
+    }
+    Ok(format!("write {} ms, read {} ms, delete {} ms", write_ms, read_ms, delete_ms))
+}
+
+async fn fetch_notifications(keypair: &Keypair) -> Result<String> {
+    let nexus_url = env::var("NEXT_PUBLIC_NEXUS").map_err(|_| anyhow::anyhow!("NEXT_PUBLIC_NEXUS not found in .env"))?;
+    let url = format!("{}/v0/user/{}/notifications?skip=0&limit=30", nexus_url, keypair.public_key());
+    let response = http::nexus().get(&url).send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(anyhow::anyhow!("Nexus answered {}", status));
+    }
+    let notifications = notifications::parse_batch(&response.text().await?)?;
+    Ok(format!("{} notification(s) in the first page", notifications.len()))
+}
@@ -0,0 +1,187 @@
+use crate::bert::Bert;
+use crate::error::{BotError, BotResult};
+use crate::http;
+use crate::llm::openai_url;
+use crate::secrets::Secret;
+use serde::Deserialize;
+use serde_json::json;
+use std::env;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+const OPENAI_MODEL: &str = "text-embedding-3-small";
+
+// Where embeddings are computed, from EMBEDDING_BACKEND.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    OpenAi,
+    // A local embedding server speaking Ollama's /api/embed, so retrieval works offline
+    Local,
+    // A sentence-transformer model run in the bot process itself, see src/bert.rs
+    Builtin,
+}
+
+fn backend() -> Backend {
+    match env::var("EMBEDDING_BACKEND").unwrap_or_default().trim().to_lowercase().as_str() {
+        "local" | "ollama" => Backend::Local,
+        "builtin" => Backend::Builtin,
+        _ => Backend::OpenAi,
+    }
+}
+
+// Sentence-embedding model on the local server, from EMBEDDING_LOCAL_MODEL.
+fn local_model() -> String {
+    env::var("EMBEDDING_LOCAL_MODEL").ok().filter(|m| !m.trim().is_empty()).unwrap_or_else(|| "all-minilm".to_string())
+}
+
+fn local_url() -> String {
+    env::var("EMBEDDING_LOCAL_URL").ok().filter(|u| !u.trim().is_empty()).unwrap_or_else(|| "http://localhost:11434".to_string())
+}
+
+// Directory of the built-in backend's model, from EMBEDDING_MODEL_DIR.
+fn model_dir() -> PathBuf {
+    PathBuf::from(env::var("EMBEDDING_MODEL_DIR").ok().filter(|d| !d.trim().is_empty()).unwrap_or_else(|| "models/all-MiniLM-L6-v2".to_string()))
+}
+
+// The embedding model in use, stored with every index and cache so vectors from
+// another model (or backend) are never compared with the query's and get rebuilt.
+pub fn model() -> String {
+    match backend() {
+        Backend::OpenAi => OPENAI_MODEL.to_string(),
+        Backend::Local => format!("local:{}", local_model()),
+        Backend::Builtin => format!("builtin:{}", model_dir().file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default()),
+    }
+}
+
+// OpenAI accepts up to 2048 inputs per request; the character cap keeps each
+// request well under the per-request token limit.
+const MAX_BATCH_INPUTS: usize = 2048;
+const MAX_BATCH_CHARS: usize = 600_000;
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LocalEmbeddingResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+fn batch_size() -> usize {
+    env::var("EMBEDDING_BATCH_SIZE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(256)
+        .clamp(1, MAX_BATCH_INPUTS)
+}
+
+// Embeds all texts, sending as few requests as the provider limits allow.
+// The result has one vector per input, in input order.
+pub async fn embed_batch(texts: &[String]) -> BotResult<Vec<Vec<f32>>> {
+    let max_inputs = batch_size();
+    let mut embeddings = Vec::with_capacity(texts.len());
+    let mut start = 0;
+    while start < texts.len() {
+        let mut end = start;
+        let mut chars = 0;
+        while end < texts.len() && end - start < max_inputs && (end == start || chars + texts[end].len() <= MAX_BATCH_CHARS) {
+            chars += texts[end].len();
+            end += 1;
+        }
+        log!("Embedding batch of {} text(s) ({} chars)", end - start, chars);
+        embeddings.extend(request_embeddings(&texts[start..end]).await?);
+        start = end;
+    }
+    Ok(embeddings)
+}
+
+pub async fn embed(text: &str) -> BotResult<Vec<f32>> {
+    let mut embeddings = request_embeddings(&[text.to_string()]).await?;
+    embeddings.pop().ok_or_else(|| BotError::Llm("Embedding response was empty".to_string()))
+}
+
+async fn request_embeddings(inputs: &[String]) -> BotResult<Vec<Vec<f32>>> {
+    match backend() {
+        Backend::Local => return request_local_embeddings(inputs).await,
+        Backend::Builtin => return builtin_embeddings(inputs).await,
+        Backend::OpenAi => {}
+    }
+    let api_key = Secret::from_env("OPENAI_API_KEY").ok_or_else(|| BotError::Validation("OPENAI_API_KEY not found in .env".to_string()))?;
+    let response = http::llm()
+        .post(openai_url("embeddings"))
+        .header("Authorization", format!("Bearer {}", api_key.expose()))
+        .json(&json!({ "model": OPENAI_MODEL, "input": inputs }))
+        .send()
+        .await
+        .map_err(BotError::llm)?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(BotError::Llm(format!("Embedding request failed: {} {}", status, body)));
+    }
+
+    let mut data = response.json::<EmbeddingResponse>().await.map_err(BotError::llm)?.data;
+    if data.len() != inputs.len() {
+        return Err(BotError::Llm(format!("Expected {} embeddings, got {}", inputs.len(), data.len())));
+    }
+    data.sort_by_key(|d| d.index);
+    Ok(data.into_iter().map(|d| d.embedding).collect())
+}
+
+async fn request_local_embeddings(inputs: &[String]) -> BotResult<Vec<Vec<f32>>> {
+    let url = format!("{}/api/embed", local_url().trim_end_matches('/'));
+    let response = http::llm().post(&url).json(&json!({ "model": local_model(), "input": inputs })).send().await.map_err(BotError::llm)?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(BotError::Llm(format!("Local embedding request to {} failed: {} {}", url, status, body)));
+    }
+    let embeddings = response.json::<LocalEmbeddingResponse>().await.map_err(BotError::llm)?.embeddings;
+    if embeddings.len() != inputs.len() {
+        return Err(BotError::Llm(format!("Expected {} embeddings, got {}", inputs.len(), embeddings.len())));
+    }
+    Ok(embeddings)
+}
+
+// Loaded on first use and kept for the life of the process.
+static BUILTIN: Mutex<Option<Arc<Bert>>> = Mutex::new(None);
+
+fn builtin_model() -> BotResult<Arc<Bert>> {
+    let mut model = BUILTIN.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(model) = model.as_ref() {
+        return Ok(model.clone());
+    }
+    let dir = model_dir();
+    log!("Loading embedding model from {}", dir.display());
+    let loaded = Arc::new(Bert::load(&dir).map_err(|e| BotError::Llm(format!("Failed to load embedding model: {:#}", e)))?);
+    *model = Some(loaded.clone());
+    Ok(loaded)
+}
+
+// The encoder is CPU-bound, so it runs off the async workers.
+async fn builtin_embeddings(inputs: &[String]) -> BotResult<Vec<Vec<f32>>> {
+    let inputs = inputs.to_vec();
+    tokio::task::spawn_blocking(move || {
+        let model = builtin_model()?;
+        Ok(inputs.iter().map(|text| model.embed(text)).collect())
+    })
+    .await
+    .map_err(BotError::llm)?
+}
+
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
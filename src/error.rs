@@ -0,0 +1,49 @@
+use crate::secrets::scrub;
+use std::fmt::Display;
+use thiserror::Error;
+
+// Error classes for the bot's building blocks (homeserver, Nexus, model calls),
+// so callers and retries can branch on what failed instead of matching strings.
+// Application code keeps using anyhow and converts with `?`. Messages are scrubbed of
+// credentials, since they often wrap HTTP errors and response bodies.
+#[derive(Debug, Error)]
+pub enum BotError {
+    #[error("homeserver error: {}", scrub(.0))]
+    Homeserver(String),
+    #[error("Nexus error: {}", scrub(.0))]
+    Nexus(String),
+    #[error("LLM error: {}", scrub(.0))]
+    Llm(String),
+    // A model tool (web search, lookups) failed
+    #[error("tool error: {}", scrub(.0))]
+    Tool(String),
+    #[error("invalid input: {}", scrub(.0))]
+    Validation(String),
+    // A trigger category spent its LLM budget
+    #[error("budget exceeded: {}", scrub(.0))]
+    BudgetExceeded(String),
+}
+
+pub type BotResult<T> = std::result::Result<T, BotError>;
+
+impl BotError {
+    pub fn homeserver(e: impl Display) -> BotError {
+        BotError::Homeserver(e.to_string())
+    }
+
+    pub fn nexus(e: impl Display) -> BotError {
+        BotError::Nexus(e.to_string())
+    }
+
+    pub fn llm(e: impl Display) -> BotError {
+        BotError::Llm(e.to_string())
+    }
+
+    pub fn tool(e: impl Display) -> BotError {
+        BotError::Tool(e.to_string())
+    }
+
+    pub fn validation(e: impl Display) -> BotError {
+        BotError::Validation(e.to_string())
+    }
+}
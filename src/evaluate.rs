@@ -0,0 +1,159 @@
+use crate::analytics::{self, AnalyticsRow};
+use crate::cleanup::parse_age;
+use crate::kb;
+use crate::llm::{reply_max_chars, ChatMessage, ChatRequest};
+use crate::llm_queue::Priority;
+use crate::memory::{now_millis, MemoryDb, ReplyRecord};
+use crate::models;
+use crate::structured;
+use anyhow::Result;
+use dotenv::dotenv;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
+
+const JUDGE_INSTRUCTION: &str = "You grade replies a social media assistant gave to posts about Pubky. Score each criterion from 1 (poor) to 5 (excellent):\n- accuracy: the reply's statements about Pubky agree with the knowledge base below and it doesn't present unsupported claims as facts\n- tone: friendly, respectful and helpful, without filler or condescension\n- length: as short as the question allows while still answering it, and within the character limit\nAdd one short sentence explaining the lowest score.";
+
+#[derive(Debug, Deserialize)]
+struct Scores {
+    accuracy: u8,
+    tone: u8,
+    length: u8,
+    notes: String,
+}
+
+struct Options {
+    since: Option<i64>,
+    limit: Option<usize>,
+    rescore: bool,
+}
+
+fn parse_args(args: &[String]) -> Result<Options> {
+    let usage = || anyhow::anyhow!("Usage: evaluate [--since <age, e.g. 7d>] [--limit N] [--rescore]");
+    let mut options = Options { since: None, limit: None, rescore: false };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--since" => options.since = Some(now_millis() - parse_age(args.next().ok_or_else(usage)?)? as i64 * 1000),
+            "--limit" => options.limit = Some(args.next().and_then(|n| n.parse().ok()).ok_or_else(usage)?),
+            "--rescore" => options.rescore = true,
+            _ => return Err(usage()),
+        }
+    }
+    Ok(options)
+}
+
+// `evaluate [--since 7d] [--limit N] [--rescore]`: has a judge model (EVALUATE_MODEL)
+// score the generated replies in the memory DB for accuracy against the knowledge base,
+// tone and length, and writes each score to the analytics log as `reply_score` with the
+// prompt and knowledge base versions the reply was generated with. Replies scored
+// before are skipped unless --rescore is given.
+pub async fn run(args: &[String]) -> Result<()> {
+    let options = parse_args(args)?;
+    dotenv().ok();
+    let memory = MemoryDb::load()?;
+    let rows = analytics::load_rows()?;
+    let scored: HashSet<&str> = rows
+        .iter()
+        .filter(|r| r.event == "reply_score")
+        .filter_map(|r| r.data.get("reply_uri").and_then(|v| v.as_str()))
+        .collect();
+    // Prompt version of each reply, from its `reply` analytics row
+    let prompt_versions: HashMap<&str, &str> = rows
+        .iter()
+        .filter(|r| r.event == "reply")
+        .filter_map(|r| Some((r.data.get("reply_uri")?.as_str()?, r.data.get("prompt_version")?.as_str()?)))
+        .collect();
+
+    // Only generated replies carry a knowledge base version; command replies aren't judged
+    let mut replies: Vec<&ReplyRecord> = memory
+        .replies
+        .iter()
+        .filter(|r| r.kb_version.is_some())
+        .filter(|r| options.since.is_none_or(|since| r.timestamp >= since))
+        .filter(|r| options.rescore || !scored.contains(r.uri.as_str()))
+        .collect();
+    replies.sort_by_key(|r| std::cmp::Reverse(r.timestamp));
+    if let Some(limit) = options.limit {
+        replies.truncate(limit);
+    }
+    println!("Scoring {} repl{}", replies.len(), if replies.len() == 1 { "y" } else { "ies" });
+
+    let model = models::resolve(&env::var("EVALUATE_MODEL").unwrap_or_else(|_| "default".to_string()));
+    let max_chars = reply_max_chars();
+    // Prompt version -> (replies, accuracy, tone, length, within limit)
+    let mut totals: BTreeMap<String, (u32, u32, u32, u32, u32)> = BTreeMap::new();
+    for reply in replies {
+        let prompt_version = prompt_versions.get(reply.uri.as_str()).copied().unwrap_or("unknown");
+        let scores = match judge(&model, reply, memory.language(&reply.to_user), max_chars).await {
+            Ok(scores) => scores,
+            Err(e) => {
+                println!("  {}: failed: {}", reply.uri, e);
+                continue;
+            }
+        };
+        let chars = reply.content.chars().count();
+        let within_limit = chars <= max_chars;
+        println!("  {}: accuracy {}, tone {}, length {} ({} chars)", reply.uri, scores.accuracy, scores.tone, scores.length, chars);
+        analytics::record(
+            AnalyticsRow::new("reply_score", Some(&reply.to_user), Some(&reply.parent_uri))
+                .with("reply_uri", reply.uri.as_str())
+                .with("accuracy", scores.accuracy)
+                .with("tone", scores.tone)
+                .with("length", scores.length)
+                .with("chars", chars)
+                .with("within_limit", within_limit)
+                .with("notes", scores.notes.as_str())
+                .with("prompt_version", prompt_version)
+                .with("kb_version", reply.kb_version.as_deref())
+                .with("judge_model", model.as_str()),
+        );
+        let total = totals.entry(prompt_version.to_string()).or_default();
+        total.0 += 1;
+        total.1 += scores.accuracy as u32;
+        total.2 += scores.tone as u32;
+        total.3 += scores.length as u32;
+        total.4 += within_limit as u32;
+    }
+
+    for (version, (count, accuracy, tone, length, within)) in totals {
+        let avg = |sum: u32| sum as f64 / count as f64;
+        println!(
+            "prompt {}: {} scored, accuracy {:.2}, tone {:.2}, length {:.2}, {}/{} within the limit",
+            version, count, avg(accuracy), avg(tone), avg(length), within, count
+        );
+    }
+    Ok(())
+}
+
+// Scores one reply against the knowledge base retrieved for its question now, which may
+// differ from what it was generated with when the knowledge base changed since.
+async fn judge(model: &str, reply: &ReplyRecord, language: Option<&str>, max_chars: usize) -> Result<Scores> {
+    let knowledge = kb::retrieve(&reply.prompt, language).await?;
+    let mut system = format!("{}\n\nThe character limit is {}.", JUDGE_INSTRUCTION, max_chars);
+    if let Some(instruction) = &reply.instruction {
+        system.push_str(&format!("\n\nThe assistant was asked to: {}", instruction));
+    }
+    system.push_str(&format!("\n\nKNOWLEDGE BASE:\n{}", knowledge.text));
+    let request = ChatRequest {
+        model: model.to_string(),
+        messages: vec![
+            ChatMessage { role: "system".to_string(), content: system },
+            ChatMessage {
+                role: "user".to_string(),
+                content: format!("POST:\n{}\n\nREPLY:\n{}", reply.prompt, reply.content),
+            },
+        ],
+        temperature: 0.0,
+        max_tokens: 200,
+    };
+    let score = json!({ "type": "integer", "enum": [1, 2, 3, 4, 5] });
+    let schema = json!({
+        "type": "object",
+        "properties": { "accuracy": score, "tone": score, "length": score, "notes": { "type": "string" } },
+        "required": ["accuracy", "tone", "length", "notes"],
+        "additionalProperties": false,
+    });
+    Ok(structured::complete(&request, "reply_scores", schema, Priority::Digest).await?)
+}
@@ -0,0 +1,186 @@
+use crate::embeddings::{cosine_similarity, embed, model as embedding_model};
+use crate::memory::ReplyRecord;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::sync::{Mutex, OnceLock};
+
+const DEFAULT_POSITIVE: &str = "👍,❤️,🔥,helpful,great,+1";
+const DEFAULT_NEGATIVE: &str = "👎,wrong,unhelpful,-1";
+
+// The bot's best-rated past replies, embedded by the post they answered, used as
+// in-context examples for similar new mentions.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ExampleIndex {
+    #[serde(default)]
+    model: String,
+    #[serde(default)]
+    entries: Vec<ExampleEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ExampleEntry {
+    reply_uri: String,
+    prompt: String,
+    reply: String,
+    score: i32,
+    embedding: Vec<f32>,
+}
+
+// A past question and the reply people liked.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Example {
+    pub prompt: String,
+    pub reply: String,
+}
+
+// Past-reply examples in the prompt, enabled with EXAMPLES=true.
+pub fn is_enabled() -> bool {
+    env::var("EXAMPLES").map(|v| v == "true").unwrap_or(false)
+}
+
+fn index_path() -> String {
+    env::var("EXAMPLES_INDEX").unwrap_or_else(|_| "examples-index.json".to_string())
+}
+
+fn min_score() -> i32 {
+    env::var("EXAMPLES_MIN_SCORE").ok().and_then(|v| v.parse().ok()).unwrap_or(2)
+}
+
+fn min_similarity() -> f32 {
+    env::var("EXAMPLES_MIN_SIMILARITY").ok().and_then(|v| v.parse().ok()).unwrap_or(0.8)
+}
+
+fn labels(var: &str, default: &str) -> Vec<String> {
+    env::var(var).unwrap_or_else(|_| default.to_string()).split(',').map(|l| l.trim().to_lowercase()).filter(|l| !l.is_empty()).collect()
+}
+
+// +1 / -1 when a tag label is positive or negative feedback (FEEDBACK_POSITIVE_TAGS /
+// FEEDBACK_NEGATIVE_TAGS, comma-separated).
+pub fn feedback_value(label: &str) -> Option<i8> {
+    let label = label.to_lowercase();
+    if labels("FEEDBACK_POSITIVE_TAGS", DEFAULT_POSITIVE).contains(&label) {
+        Some(1)
+    } else if labels("FEEDBACK_NEGATIVE_TAGS", DEFAULT_NEGATIVE).contains(&label) {
+        Some(-1)
+    } else {
+        None
+    }
+}
+
+fn index() -> &'static Mutex<ExampleIndex> {
+    static INDEX: OnceLock<Mutex<ExampleIndex>> = OnceLock::new();
+    INDEX.get_or_init(|| {
+        let index = fs::read_to_string(index_path())
+            .ok()
+            .and_then(|content| serde_json::from_str::<ExampleIndex>(&content).ok())
+            .filter(|index| index.model == embedding_model())
+            .unwrap_or_else(|| ExampleIndex { model: embedding_model(), entries: Vec::new() });
+        Mutex::new(index)
+    })
+}
+
+// Adds the reply to the index once its score reaches EXAMPLES_MIN_SCORE, updates it,
+// or drops it when feedback pulls it back below.
+pub async fn update(record: &ReplyRecord) -> Result<()> {
+    let score = record.score();
+    let indexed = index().lock().map(|i| i.entries.iter().any(|e| e.reply_uri == record.uri)).unwrap_or(false);
+    // Commands like !eli5 don't make good examples for plain questions
+    let qualifies = score >= min_score() && record.instruction.is_none();
+
+    let embedding = if qualifies && !indexed { Some(embed(&record.prompt).await?) } else { None };
+    let mut index = index().lock().map_err(|_| anyhow::anyhow!("Example index lock poisoned"))?;
+    if !qualifies {
+        if !indexed {
+            return Ok(());
+        }
+        index.entries.retain(|e| e.reply_uri != record.uri);
+    } else if let Some(entry) = index.entries.iter_mut().find(|e| e.reply_uri == record.uri) {
+        entry.score = score;
+        entry.reply = record.content.clone();
+    } else if let Some(embedding) = embedding {
+        log!("Adding reply {} (score {}) to the examples", record.uri, score);
+        index.entries.push(ExampleEntry {
+            reply_uri: record.uri.clone(),
+            prompt: record.prompt.clone(),
+            reply: record.content.clone(),
+            score,
+            embedding,
+        });
+    }
+    save(&index);
+    Ok(())
+}
+
+// The EXAMPLES_COUNT best-rated replies whose question is similar to the new one.
+pub fn find(embedding: &[f32]) -> Vec<Example> {
+    let count = env::var("EXAMPLES_COUNT").ok().and_then(|v| v.parse().ok()).unwrap_or(2);
+    let Ok(index) = index().lock() else { return Vec::new() };
+    let mut scored: Vec<(f32, &ExampleEntry)> = index
+        .entries
+        .iter()
+        .map(|entry| (cosine_similarity(embedding, &entry.embedding), entry))
+        .filter(|(similarity, _)| *similarity >= min_similarity())
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0).then(b.1.score.cmp(&a.1.score)));
+    scored
+        .into_iter()
+        .take(count)
+        .map(|(_, entry)| Example { prompt: entry.prompt.clone(), reply: entry.reply.clone() })
+        .collect()
+}
+
+// Removes a deleted reply from the examples.
+pub fn remove(reply_uri: &str) {
+    let Ok(mut index) = index().lock() else { return };
+    let before = index.entries.len();
+    index.entries.retain(|e| e.reply_uri != reply_uri);
+    if index.entries.len() != before {
+        save(&index);
+    }
+}
+
+// The index is rebuilt from feedback, so failing to persist it is only logged.
+// The `count` best-rated examples, for sharing in a persona bundle.
+pub fn best(count: usize) -> Vec<Example> {
+    let Ok(index) = index().lock() else { return Vec::new() };
+    let mut entries: Vec<&ExampleEntry> = index.entries.iter().collect();
+    entries.sort_by_key(|e| std::cmp::Reverse(e.score));
+    entries.into_iter().take(count).map(|entry| Example { prompt: entry.prompt.clone(), reply: entry.reply.clone() }).collect()
+}
+
+// Adds examples from elsewhere (an imported persona) at EXAMPLES_MIN_SCORE, keyed
+// `<source>:<n>` so importing the same bundle again replaces them. Returns how many
+// were added.
+pub async fn import(source: &str, examples: &[Example]) -> Result<usize> {
+    let mut entries = Vec::with_capacity(examples.len());
+    for (n, example) in examples.iter().enumerate() {
+        entries.push(ExampleEntry {
+            reply_uri: format!("{}:{}", source, n),
+            prompt: example.prompt.clone(),
+            reply: example.reply.clone(),
+            score: min_score(),
+            embedding: embed(&example.prompt).await?,
+        });
+    }
+    let mut index = index().lock().map_err(|_| anyhow::anyhow!("Example index lock poisoned"))?;
+    let prefix = format!("{}:", source);
+    index.entries.retain(|e| !e.reply_uri.starts_with(&prefix));
+    let added = entries.len();
+    index.entries.extend(entries);
+    save(&index);
+    Ok(added)
+}
+
+fn save(index: &ExampleIndex) {
+    let result = serde_json::to_string(index).map_err(anyhow::Error::from).and_then(|json| {
+        let tmp = format!("{}.tmp", index_path());
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, index_path())?;
+        Ok(())
+    });
+    if let Err(e) = result {
+        log!("Failed to save example index: {}", e);
+    }
+}
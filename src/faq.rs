@@ -0,0 +1,171 @@
+use crate::embeddings::{cosine_similarity, embed, model as embedding_model};
+use crate::llm::{chat_completion, reply_max_chars, ChatMessage, ChatRequest};
+use crate::llm_queue::{self, Priority};
+use crate::memory::now_millis;
+use crate::models;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::sync::{Mutex, OnceLock};
+
+// Previously answered questions with their embeddings. A new mention that is
+// near-identical to a cached question is answered from the cache.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct FaqCache {
+    #[serde(default)]
+    model: String,
+    #[serde(default)]
+    entries: Vec<FaqEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FaqEntry {
+    question: String,
+    answer: String,
+    embedding: Vec<f32>,
+    // Answers generated from another knowledge base version are not reused
+    kb_version: Option<String>,
+    created_at: i64,
+    #[serde(default)]
+    hits: u64,
+    // Who asked, so the entry goes when they ask to be forgotten
+    #[serde(default)]
+    user: Option<String>,
+}
+
+pub struct FaqLookup {
+    // Embedding of the question, kept so a fresh answer can be cached without re-embedding
+    pub embedding: Vec<f32>,
+    pub hit: Option<FaqHit>,
+}
+
+pub struct FaqHit {
+    pub question: String,
+    pub answer: String,
+    pub score: f32,
+}
+
+// Semantic FAQ cache, enabled with FAQ_CACHE=true.
+pub fn is_enabled() -> bool {
+    env::var("FAQ_CACHE").map(|v| v == "true").unwrap_or(false)
+}
+
+fn cache_path() -> String {
+    env::var("FAQ_CACHE_PATH").unwrap_or_else(|_| "faq-cache.json".to_string())
+}
+
+fn threshold() -> f32 {
+    env::var("FAQ_CACHE_THRESHOLD").ok().and_then(|v| v.parse().ok()).unwrap_or(0.95)
+}
+
+fn max_entries() -> usize {
+    env::var("FAQ_CACHE_SIZE").ok().and_then(|v| v.parse().ok()).unwrap_or(500)
+}
+
+fn cache() -> &'static Mutex<FaqCache> {
+    static CACHE: OnceLock<Mutex<FaqCache>> = OnceLock::new();
+    CACHE.get_or_init(|| {
+        let cache = fs::read_to_string(cache_path())
+            .ok()
+            .and_then(|content| serde_json::from_str::<FaqCache>(&content).ok())
+            .filter(|cache| cache.model == embedding_model())
+            .unwrap_or_else(|| FaqCache { model: embedding_model(), entries: Vec::new() });
+        Mutex::new(cache)
+    })
+}
+
+// Embeds the question and finds the closest cached answer for the same knowledge
+// base version, if it scores at least FAQ_CACHE_THRESHOLD.
+pub async fn lookup(question: &str, kb_version: Option<&str>) -> Result<FaqLookup> {
+    let embedding = embed(question).await?;
+    let mut cache = cache().lock().map_err(|_| anyhow::anyhow!("FAQ cache lock poisoned"))?;
+    let best = cache
+        .entries
+        .iter_mut()
+        .filter(|entry| entry.kb_version.as_deref() == kb_version)
+        .map(|entry| (cosine_similarity(&embedding, &entry.embedding), entry))
+        .max_by(|a, b| a.0.total_cmp(&b.0));
+
+    let hit = match best {
+        Some((score, entry)) if score >= threshold() => {
+            entry.hits += 1;
+            Some(FaqHit { question: entry.question.clone(), answer: entry.answer.clone(), score })
+        }
+        _ => None,
+    };
+    if hit.is_some() {
+        save(&cache);
+    }
+    Ok(FaqLookup { embedding, hit })
+}
+
+// Caches a freshly generated answer, evicting the least used (then oldest) entries
+// beyond FAQ_CACHE_SIZE.
+pub fn insert(user: &str, question: &str, answer: &str, embedding: Vec<f32>, kb_version: Option<&str>) {
+    let Ok(mut cache) = cache().lock() else { return };
+    cache.entries.push(FaqEntry {
+        question: question.to_string(),
+        answer: answer.to_string(),
+        embedding,
+        kb_version: kb_version.map(String::from),
+        created_at: now_millis(),
+        hits: 0,
+        user: Some(user.to_string()),
+    });
+    let max = max_entries();
+    if cache.entries.len() > max {
+        cache.entries.sort_by(|a, b| b.hits.cmp(&a.hits).then(b.created_at.cmp(&a.created_at)));
+        cache.entries.truncate(max);
+    }
+    save(&cache);
+}
+
+// Drops the cached answers to `user`'s questions and returns how many went.
+pub fn forget_user(user: &str) -> usize {
+    let Ok(mut cache) = cache().lock() else { return 0 };
+    let before = cache.entries.len();
+    cache.entries.retain(|entry| entry.user.as_deref() != Some(user));
+    let removed = before - cache.entries.len();
+    if removed > 0 {
+        save(&cache);
+    }
+    removed
+}
+
+// The cache is an optimization, so failing to persist it is only logged.
+fn save(cache: &FaqCache) {
+    let result = serde_json::to_string(cache).map_err(anyhow::Error::from).and_then(|json| {
+        let tmp = format!("{}.tmp", cache_path());
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, cache_path())?;
+        Ok(())
+    });
+    if let Err(e) = result {
+        log!("Failed to save FAQ cache: {}", e);
+    }
+}
+
+// With FAQ_PARAPHRASE=true cached answers are lightly reworded by a short, cheap call
+// so repeated questions don't get word-for-word identical replies.
+pub async fn paraphrase(answer: &str) -> Result<String> {
+    if env::var("FAQ_PARAPHRASE").map(|v| v != "true").unwrap_or(true) {
+        return Ok(answer.to_string());
+    }
+    let request = ChatRequest {
+        model: models::resolve("default"),
+        messages: vec![
+            ChatMessage {
+                role: "system".to_string(),
+                content: "Lightly reword the following reply so it doesn't read word-for-word the same. Keep its meaning, facts, language and length. Reply only with the reworded text.".to_string(),
+            },
+            ChatMessage {
+                role: "user".to_string(),
+                content: answer.to_string(),
+            },
+        ],
+        temperature: 0.7,
+        max_tokens: llm_queue::completion_tokens(reply_max_chars(), answer),
+    };
+    Ok(chat_completion(&request, Priority::Mention).await?)
+}
@@ -0,0 +1,164 @@
+use crate::analytics::AnalyticsRow;
+use crate::bots;
+use crate::error::{BotError, BotResult};
+use crate::homeserver::{delete_file, list_dir, put_file};
+use crate::http;
+use crate::memory::{now_millis, DAY_MILLIS};
+use crate::privacy;
+use crate::profiles;
+use anyhow::Result;
+use pubky::{Client, Keypair};
+use pubky_app_specs::PubkyAppFollow;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::env;
+use std::sync::{Arc, Mutex};
+
+// What the follow sync changed (or would change, in a dry run).
+#[derive(Debug, Default)]
+pub struct FollowSync {
+    pub followed: Vec<String>,
+    pub unfollowed: Vec<String>,
+}
+
+// Periodic follow sync, enabled with FOLLOW_SYNC=true.
+pub fn is_enabled() -> bool {
+    env::var("FOLLOW_SYNC").map(|v| v == "true").unwrap_or(false)
+}
+
+fn days(name: &str, default: i64) -> i64 {
+    env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+// Reconciles the bot's follow records with its policy:
+// - follow back followers who interacted with the bot in the last FOLLOW_ACTIVE_DAYS,
+//   except other bots (BOT_ETIQUETTE)
+// - unfollow accounts the bot has muted or that posted nothing in FOLLOW_SILENT_DAYS
+pub async fn sync(client: &Client, keypair: &Keypair, rows: &[AnalyticsRow], dry_run: bool) -> Result<FollowSync> {
+    let nexus_url = env::var("NEXT_PUBLIC_NEXUS").map_err(|_| anyhow::anyhow!("NEXT_PUBLIC_NEXUS not found in .env"))?;
+    let http = http::nexus();
+    let base = format!("pubky://{}/pub/pubky.app/", keypair.public_key());
+    let following = user_ids(list_dir(client, &format!("{}follows/", base)).await?);
+    let muted = user_ids(list_dir(client, &format!("{}mutes/", base)).await?);
+    let followers = fetch_followers(http, &nexus_url, &keypair.public_key().to_string()).await?;
+
+    let active_since = now_millis() - days("FOLLOW_ACTIVE_DAYS", 30) * DAY_MILLIS;
+    let active: HashSet<&str> = rows
+        .iter()
+        .filter(|r| r.timestamp >= active_since && (r.event == "mention" || r.event == "tag"))
+        .filter_map(|r| r.user.as_deref())
+        .collect();
+
+    let mut result = FollowSync::default();
+    for follower in &followers {
+        if !following.contains(follower) && !muted.contains(follower) && active.contains(follower.as_str()) {
+            if bots::is_enabled() && is_bot(client, follower).await {
+                log!("Not following back bot {}", privacy::user(follower));
+                continue;
+            }
+            result.followed.push(follower.clone());
+        }
+    }
+
+    let silent_since = now_millis() - days("FOLLOW_SILENT_DAYS", 90) * DAY_MILLIS;
+    for user in &following {
+        let unfollow = if muted.contains(user) {
+            true
+        } else {
+            // Unknown activity (e.g. Nexus error) keeps the follow
+            match last_post_at(http, &nexus_url, user).await {
+                Ok(last) => last.is_none_or(|at| at < silent_since),
+                Err(e) => {
+                    log!("Could not check activity of {}: {}", privacy::user(user), e);
+                    false
+                }
+            }
+        };
+        if unfollow {
+            result.unfollowed.push(user.clone());
+        }
+    }
+
+    for user in &result.followed {
+        if dry_run {
+            log!("Would follow back: {}", privacy::user(user));
+            continue;
+        }
+        let follow = serde_json::to_vec(&PubkyAppFollow::new())?;
+        put_file(client, &format!("{}follows/{}", base, user), follow).await?;
+        log!("Followed back: {}", privacy::user(user));
+    }
+    for user in &result.unfollowed {
+        if dry_run {
+            log!("Would unfollow: {}", privacy::user(user));
+            continue;
+        }
+        delete_file(client, &format!("{}follows/{}", base, user)).await?;
+        log!("Unfollowed: {}", privacy::user(user));
+    }
+    Ok(result)
+}
+
+// An unreadable profile counts as human.
+async fn is_bot(client: &Client, user: &str) -> bool {
+    match profiles::fetch_profile(client, user).await {
+        Ok(profile) => profile.is_some_and(|p| p.bot),
+        Err(e) => {
+            log!("Failed to fetch profile of {}: {}", privacy::user(user), e);
+            false
+        }
+    }
+}
+
+// The last path segment of each listed follow/mute record is the user id.
+fn user_ids(uris: Vec<String>) -> HashSet<String> {
+    uris.iter().filter_map(|uri| uri.rsplit('/').next()).filter(|id| !id.is_empty()).map(String::from).collect()
+}
+
+// How long the follower list used for mention priority is reused before refetching.
+const FOLLOWERS_TTL_MILLIS: i64 = 10 * 60 * 1000;
+
+static FOLLOWERS: Mutex<Option<(i64, Arc<HashSet<String>>)>> = Mutex::new(None);
+
+// The bot's followers according to Nexus, cached for FOLLOWERS_TTL_MILLIS.
+pub async fn followers(keypair: &Keypair) -> BotResult<Arc<HashSet<String>>> {
+    if let Some((fetched_at, followers)) = FOLLOWERS.lock().ok().and_then(|cached| cached.clone()) {
+        if now_millis() - fetched_at < FOLLOWERS_TTL_MILLIS {
+            return Ok(followers);
+        }
+    }
+    let nexus_url = env::var("NEXT_PUBLIC_NEXUS").map_err(|_| BotError::Validation("NEXT_PUBLIC_NEXUS not found in .env".to_string()))?;
+    let followers: Arc<HashSet<String>> = Arc::new(fetch_followers(http::nexus(), &nexus_url, &keypair.public_key().to_string()).await?.into_iter().collect());
+    if let Ok(mut cached) = FOLLOWERS.lock() {
+        *cached = Some((now_millis(), followers.clone()));
+    }
+    Ok(followers)
+}
+
+async fn fetch_followers(http: &reqwest::Client, nexus_url: &str, user: &str) -> BotResult<Vec<String>> {
+    let mut followers = Vec::new();
+    loop {
+        let url = format!("{}/v0/user/{}/followers?skip={}&limit=100", nexus_url, user, followers.len());
+        let response = http.get(&url).send().await.map_err(BotError::nexus)?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(followers);
+        }
+        let page: Vec<String> = response.error_for_status().map_err(BotError::nexus)?.json().await.map_err(BotError::nexus)?;
+        let done = page.len() < 100;
+        followers.extend(page);
+        if done {
+            return Ok(followers);
+        }
+    }
+}
+
+// When the user last posted (unix ms), according to Nexus.
+async fn last_post_at(http: &reqwest::Client, nexus_url: &str, user: &str) -> BotResult<Option<i64>> {
+    let url = format!("{}/v0/stream/posts?source=author&author_id={}&limit=1", nexus_url, user);
+    let response = http.get(&url).send().await.map_err(BotError::nexus)?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let posts: Vec<Value> = response.error_for_status().map_err(BotError::nexus)?.json().await.map_err(BotError::nexus)?;
+    Ok(posts.first().and_then(|post| post["details"]["indexed_at"].as_i64()))
+}
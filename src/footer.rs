@@ -0,0 +1,29 @@
+use crate::llm::reply_max_chars;
+use crate::perks;
+use std::env;
+
+// Blank line between the reply and the footer
+const SEPARATOR: &str = "\n\n";
+
+// REPLY_FOOTER, e.g. "🤖 AI-generated — mention me to chat", appended to every reply;
+// `\n` starts a new line. None when unset.
+fn footer() -> Option<String> {
+    env::var("REPLY_FOOTER").ok().map(|v| v.replace("\\n", "\n").trim().to_string()).filter(|v| !v.is_empty())
+}
+
+// Characters the footer takes in a post, taken off the reply's own budget.
+pub fn reserved_chars() -> usize {
+    footer().map_or(0, |footer| SEPARATOR.chars().count() + footer.chars().count())
+}
+
+// The reply as published, with the footer. A reply longer than its budget (e.g. a
+// cached answer from before the footer was set) is cut so the post stays in the limit.
+pub fn append(content: &str) -> String {
+    match footer() {
+        Some(footer) => {
+            let content: String = content.trim_end().chars().take(perks::longest_reply_chars(reply_max_chars())).collect();
+            format!("{}{}{}", content, SEPARATOR, footer)
+        }
+        None => content.to_string(),
+    }
+}
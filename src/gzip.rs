@@ -0,0 +1,472 @@
+use anyhow::{bail, Result};
+
+// Gzip (RFC 1952) around DEFLATE (RFC 1951), for notification archive segments and
+// recordings. Compression finds repeats with LZ77 and codes them with the fixed Huffman
+// tables: JSON lines are repetitive enough that it gets most of what a full encoder
+// would. Decompression handles any valid stream, including ones from the system gzip.
+
+const WINDOW: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+// Earlier positions with the same 3-byte prefix tried per match
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+// Order in which the code length code lengths of a dynamic block are sent
+const CLEN_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, b| CRC_TABLE[((crc ^ *b as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    // No name or time, so the same input always compresses to the same bytes
+    let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+    out.extend_from_slice(&deflate(data));
+    out.extend_from_slice(&crc32(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
+
+// Decompresses every member of a gzip file, checking each one's CRC and size.
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let member = &data[pos..];
+        if member.len() < 18 || member[0] != 0x1f || member[1] != 0x8b {
+            bail!("Not a gzip stream");
+        }
+        if member[2] != 8 {
+            bail!("Unsupported gzip compression method {}", member[2]);
+        }
+        let flags = member[3];
+        let mut header = 10;
+        if flags & 4 != 0 {
+            let extra = u16::from_le_bytes([*member.get(header).unwrap_or(&0), *member.get(header + 1).unwrap_or(&0)]) as usize;
+            header += 2 + extra;
+        }
+        // File name and comment, zero-terminated
+        for flag in [8, 16] {
+            if flags & flag != 0 {
+                let end = member.get(header..).and_then(|rest| rest.iter().position(|b| *b == 0));
+                let Some(end) = end else { bail!("Truncated gzip header") };
+                header += end + 1;
+            }
+        }
+        if flags & 2 != 0 {
+            header += 2;
+        }
+        let Some(body) = member.get(header..) else { bail!("Truncated gzip header") };
+        let start = out.len();
+        let used = inflate(body, &mut out)?;
+        let Some(trailer) = body.get(used..used + 8) else { bail!("Truncated gzip trailer") };
+        let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+        if crc32(&out[start..]) != crc || (out.len() - start) as u32 != size {
+            bail!("Corrupt gzip stream: checksum mismatch");
+        }
+        pos += header + used + 8;
+    }
+    Ok(out)
+}
+
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn put(&mut self, value: u32, count: u32) {
+        self.bits |= (value as u64) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes are sent starting from their most significant bit
+    fn put_code(&mut self, code: u32, length: u32) {
+        self.put(code.reverse_bits() >> (32 - length), length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.bits as u8);
+        }
+        self.out
+    }
+}
+
+fn put_literal(writer: &mut BitWriter, symbol: u32) {
+    match symbol {
+        0..=143 => writer.put_code(0x30 + symbol, 8),
+        144..=255 => writer.put_code(0x190 + symbol - 144, 9),
+        256..=279 => writer.put_code(symbol - 256, 7),
+        _ => writer.put_code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn put_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let code = LENGTH_BASE.iter().rposition(|base| *base as usize <= length).unwrap_or(0);
+    put_literal(writer, 257 + code as u32);
+    writer.put((length - LENGTH_BASE[code] as usize) as u32, LENGTH_EXTRA[code] as u32);
+    let code = DIST_BASE.iter().rposition(|base| *base as usize <= distance).unwrap_or(0);
+    writer.put_code(code as u32, 5);
+    writer.put((distance - DIST_BASE[code] as usize) as u32, DIST_EXTRA[code] as u32);
+}
+
+fn hash(data: &[u8], pos: usize) -> usize {
+    let key = (data[pos] as u32) << 16 | (data[pos + 1] as u32) << 8 | data[pos + 2] as u32;
+    (key.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+}
+
+fn insert(data: &[u8], pos: usize, head: &mut [usize], previous: &mut [usize]) {
+    if pos + MIN_MATCH <= data.len() {
+        let h = hash(data, pos);
+        previous[pos % WINDOW] = head[h];
+        head[h] = pos;
+    }
+}
+
+// One final block with the fixed Huffman codes.
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter { out: Vec::new(), bits: 0, count: 0 };
+    writer.put(1, 1);
+    writer.put(1, 2);
+    // Most recent position of each hash, and the previous position with the same hash
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut previous = vec![usize::MAX; WINDOW];
+    let mut pos = 0;
+    while pos < data.len() {
+        let (mut best_length, mut best_distance) = (0, 0);
+        if pos + MIN_MATCH <= data.len() {
+            let mut candidate = head[hash(data, pos)];
+            let longest = MAX_MATCH.min(data.len() - pos);
+            for _ in 0..MAX_CHAIN {
+                if candidate == usize::MAX || pos - candidate > WINDOW - 1 {
+                    break;
+                }
+                let length = data[candidate..].iter().zip(&data[pos..pos + longest]).take_while(|(a, b)| a == b).count();
+                if length > best_length {
+                    (best_length, best_distance) = (length, pos - candidate);
+                    if length == longest {
+                        break;
+                    }
+                }
+                let next = previous[candidate % WINDOW];
+                // Entries overwritten by newer positions no longer chain backwards
+                if next == usize::MAX || next >= candidate {
+                    break;
+                }
+                candidate = next;
+            }
+        }
+        if best_length >= MIN_MATCH {
+            put_match(&mut writer, best_length, best_distance);
+            for p in pos..pos + best_length {
+                insert(data, p, &mut head, &mut previous);
+            }
+            pos += best_length;
+        } else {
+            put_literal(&mut writer, data[pos] as u32);
+            insert(data, pos, &mut head, &mut previous);
+            pos += 1;
+        }
+    }
+    put_literal(&mut writer, 256);
+    writer.finish()
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bits: u32,
+    count: u32,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, need: u32) -> Result<u32> {
+        while self.count < need {
+            let Some(byte) = self.data.get(self.pos) else { bail!("Truncated deflate stream") };
+            self.bits |= (*byte as u32) << self.count;
+            self.pos += 1;
+            self.count += 8;
+        }
+        let value = self.bits & ((1u64 << need) - 1) as u32;
+        self.bits = if need == 32 { 0 } else { self.bits >> need };
+        self.count -= need;
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        self.bits = 0;
+        self.count = 0;
+    }
+}
+
+// A canonical Huffman code: how many codes there are of each length, and the symbols
+// in code order.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman> {
+        let mut counts = [0u16; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut left: i32 = 1;
+        for count in &counts[1..] {
+            left = left * 2 - *count as i32;
+            if left < 0 {
+                bail!("Invalid Huffman code in deflate stream");
+            }
+        }
+        let mut offsets = [0u16; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length != 0 {
+                symbols[offsets[*length as usize] as usize] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+        Ok(Huffman { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        bail!("Invalid Huffman code in deflate stream")
+    }
+}
+
+fn fixed_codes() -> Result<(Huffman, Huffman)> {
+    let mut lengths = [8u8; 288];
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman)> {
+    let literals = reader.bits(5)? as usize + 257;
+    let distances = reader.bits(5)? as usize + 1;
+    let code_lengths = reader.bits(4)? as usize + 4;
+    if literals > 286 || distances > 30 {
+        bail!("Invalid dynamic block in deflate stream");
+    }
+    let mut lengths = [0u8; 19];
+    for index in CLEN_ORDER.iter().take(code_lengths) {
+        lengths[*index] = reader.bits(3)? as u8;
+    }
+    let clen = Huffman::new(&lengths)?;
+
+    let mut lengths = vec![0u8; literals + distances];
+    let mut index = 0;
+    while index < lengths.len() {
+        let symbol = clen.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let Some(previous) = index.checked_sub(1).map(|i| lengths[i]) else { bail!("Invalid dynamic block in deflate stream") };
+                (previous, 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if index + repeat > lengths.len() {
+            bail!("Invalid dynamic block in deflate stream");
+        }
+        lengths[index..index + repeat].fill(value);
+        index += repeat;
+    }
+    Ok((Huffman::new(&lengths[..literals])?, Huffman::new(&lengths[literals..])?))
+}
+
+// Inflates one deflate stream onto `out` and returns how many bytes of `data` it took.
+fn inflate(data: &[u8], out: &mut Vec<u8>) -> Result<usize> {
+    let start = out.len();
+    let mut reader = BitReader { data, pos: 0, bits: 0, count: 0 };
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align();
+                let Some(header) = data.get(reader.pos..reader.pos + 4) else { bail!("Truncated deflate stream") };
+                let length = u16::from_le_bytes([header[0], header[1]]) as usize;
+                if length != !u16::from_le_bytes([header[2], header[3]]) as usize {
+                    bail!("Invalid stored block in deflate stream");
+                }
+                let Some(block) = data.get(reader.pos + 4..reader.pos + 4 + length) else { bail!("Truncated deflate stream") };
+                out.extend_from_slice(block);
+                reader.pos += 4 + length;
+            }
+            kind @ (1 | 2) => {
+                let (literals, distances) = if kind == 1 { fixed_codes()? } else { dynamic_codes(&mut reader)? };
+                loop {
+                    let symbol = literals.decode(&mut reader)? as usize;
+                    match symbol {
+                        0..=255 => out.push(symbol as u8),
+                        256 => break,
+                        _ => {
+                            let code = symbol - 257;
+                            if code >= LENGTH_BASE.len() {
+                                bail!("Invalid length in deflate stream");
+                            }
+                            let length = LENGTH_BASE[code] as usize + reader.bits(LENGTH_EXTRA[code] as u32)? as usize;
+                            let code = distances.decode(&mut reader)? as usize;
+                            if code >= DIST_BASE.len() {
+                                bail!("Invalid distance in deflate stream");
+                            }
+                            let distance = DIST_BASE[code] as usize + reader.bits(DIST_EXTRA[code] as u32)? as usize;
+                            if distance > out.len() - start {
+                                bail!("Distance too far back in deflate stream");
+                            }
+                            // Copied byte by byte: a match may overlap the bytes it produces
+                            for _ in 0..length {
+                                out.push(out[out.len() - distance]);
+                            }
+                        }
+                    }
+                }
+            }
+            _ => bail!("Invalid block type in deflate stream"),
+        }
+        if last {
+            return Ok(reader.pos);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let lines: String = (0..2000).map(|i| format!("{{\"kind\":\"nexus_notifications\",\"recorded_at\":{},\"body\":\"[]\"}}\n", 1_700_000_000_000u64 + i * 997)).collect();
+        let pseudo_random: Vec<u8> = (0..100_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8).collect();
+        for data in [&b""[..], b"a", b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", lines.as_bytes(), &pseudo_random] {
+            let compressed = compress(data);
+            assert_eq!(decompress(&compressed).unwrap(), data);
+        }
+        assert!(compress(lines.as_bytes()).len() < lines.len() / 5);
+    }
+
+    // Decoded by the system gzip as the reference; skipped where it isn't installed
+    #[test]
+    fn system_gzip_reads_output() {
+        use std::io::Write;
+        use std::process::{Command, Stdio};
+        let lines: String = (0..5000).map(|i| format!("{{\"kind\":\"nexus_notifications\",\"recorded_at\":{},\"body\":\"[{}]\"}}\n", 1_700_000_000_000u64 + i * 997, i % 37)).collect();
+        let pseudo_random: Vec<u8> = (0..70_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8).collect();
+        for data in [&b""[..], b"a", &[0u8; 100_000], lines.as_bytes(), &pseudo_random] {
+            let Ok(mut child) = Command::new("gzip").arg("-dc").stdin(Stdio::piped()).stdout(Stdio::piped()).spawn() else {
+                eprintln!("gzip not installed, skipping");
+                return;
+            };
+            let mut stdin = child.stdin.take().unwrap();
+            let compressed = compress(data);
+            let writer = std::thread::spawn(move || stdin.write_all(&compressed));
+            let output = child.wait_with_output().unwrap();
+            writer.join().unwrap().unwrap();
+            assert!(output.status.success());
+            assert_eq!(output.stdout, data);
+        }
+    }
+
+    #[test]
+    fn crc_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    // `printf 'hello hello hello hello\n' | gzip -9n`, which uses a fixed block
+    #[test]
+    fn reads_system_gzip_fixed_block() {
+        let data = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x27, 0xb9, 0x00, 0x00,
+            0x88, 0x59, 0x0b, 0x18, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(decompress(&data).unwrap(), b"hello hello hello hello\n");
+    }
+
+    // The words below joined with spaces, through `gzip -9n`, which uses a dynamic block
+    #[test]
+    fn reads_system_gzip_dynamic_block() {
+        let words = ["mention", "reply", "tag", "follow", "post", "pubky", "nexus", "uri", "abc", "xyz", "hello", "world", "notification", "timestamp"];
+        let text = (0..60u64).map(|i| words[((i * 2_654_435_761) >> 7) as usize % words.len()]).collect::<Vec<_>>().join(" ");
+        let data = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x95, 0xd0, 0xeb, 0x0a, 0xc3, 0x20, 0x0c, 0x05, 0xe0, 0x57, 0xc9, 0xab,
+            0xd9, 0x2e, 0x5d, 0x43, 0x8d, 0x11, 0x8d, 0xb4, 0xee, 0xe9, 0xb7, 0x79, 0x01, 0xcb, 0x3a, 0xd8, 0x7e, 0x9c, 0x03, 0x09, 0x04, 0x3e,
+            0xc2, 0xe8, 0x94, 0xc4, 0x81, 0x12, 0x63, 0x54, 0xc3, 0x1e, 0x9c, 0x28, 0x2d, 0x34, 0x9b, 0xb2, 0x3e, 0x0d, 0xbb, 0x04, 0x7b, 0x83,
+            0x15, 0xad, 0x95, 0xd6, 0x47, 0x7e, 0x94, 0x98, 0x69, 0x86, 0x14, 0xa8, 0xc4, 0xe1, 0x91, 0x22, 0xf8, 0x34, 0x6d, 0xb9, 0xb7, 0x44,
+            0xad, 0xb5, 0xc8, 0xeb, 0x6a, 0x07, 0x35, 0xf7, 0x92, 0x80, 0xde, 0xe6, 0xd6, 0xfc, 0x01, 0xf9, 0x42, 0xaa, 0x8a, 0x6b, 0xcb, 0xdb,
+            0xf1, 0x83, 0xa5, 0x31, 0x2e, 0x35, 0xdd, 0xc1, 0x7f, 0x3f, 0x66, 0x24, 0x8d, 0x8f, 0xe9, 0xa0, 0x8a, 0x19, 0x48, 0x4f, 0x03, 0x08,
+            0x56, 0x99, 0x7c, 0x01, 0x00, 0x00,
+        ];
+        assert_eq!(decompress(&data).unwrap(), text.as_bytes());
+    }
+
+    #[test]
+    fn reads_stored_blocks_and_concatenated_members() {
+        // A stored block holding "abc"
+        let mut member = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255, 0x01, 3, 0, 0xfc, 0xff, b'a', b'b', b'c'];
+        member.extend_from_slice(&crc32(b"abc").to_le_bytes());
+        member.extend_from_slice(&3u32.to_le_bytes());
+        let mut data = member.clone();
+        data.extend_from_slice(&compress(b"def"));
+        assert_eq!(decompress(&data).unwrap(), b"abcdef");
+    }
+
+    #[test]
+    fn rejects_corrupt_input() {
+        let mut data = compress(b"some archived notifications");
+        assert!(decompress(&data[..data.len() - 3]).is_err());
+        let last = data.len() - 5;
+        data[last] ^= 1;
+        assert!(decompress(&data).is_err());
+        assert!(decompress(b"not gzip at all").is_err());
+    }
+}
@@ -0,0 +1,100 @@
+use crate::budget;
+use crate::homeserver::put_file;
+use crate::intake;
+use crate::lease;
+use crate::llm_queue;
+use crate::memory::now_millis;
+use crate::notifications;
+use crate::secrets::Secret;
+use crate::storage;
+use anyhow::Result;
+use pubky::{Client, Keypair};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// When the bot started (for the uptime) and when the last heartbeat was written.
+struct HeartbeatState {
+    started_at: i64,
+    written_at: Option<i64>,
+}
+
+static STATE: Mutex<HeartbeatState> = Mutex::new(HeartbeatState { started_at: 0, written_at: None });
+
+// How often the heartbeat is written, from HEARTBEAT_MINUTES. None (the default, or 0)
+// disables it.
+fn interval() -> Option<Duration> {
+    env::var("HEARTBEAT_MINUTES").ok().and_then(|v| v.parse::<u64>().ok()).filter(|m| *m > 0).map(|m| Duration::from_secs(m * 60))
+}
+
+pub fn is_enabled() -> bool {
+    interval().is_some()
+}
+
+// Marks the start of the run the uptime is counted from.
+pub fn mark_started() {
+    if let Ok(mut state) = STATE.lock() {
+        state.started_at = now_millis();
+    }
+}
+
+// The heartbeat lives at an unlisted path only the owner knows: HEARTBEAT_TOKEN, or
+// one derived from the bot's secret key so it stays the same across restarts. Pubky
+// has no private files yet, so it holds nothing that isn't safe to be public.
+pub fn url(keypair: &Keypair) -> String {
+    let token = match Secret::from_env("HEARTBEAT_TOKEN") {
+        Some(token) => token.expose().trim().to_string(),
+        None => {
+            let mut hasher = Sha256::new();
+            hasher.update(b"ai-rand/heartbeat");
+            hasher.update(keypair.secret_key());
+            hasher.finalize().iter().take(16).map(|b| format!("{:02x}", b)).collect()
+        }
+    };
+    format!("pubky://{}/pub/ai-rand/heartbeat/{}.json", keypair.public_key(), token)
+}
+
+// Writes the heartbeat when HEARTBEAT_MINUTES have passed since the last one.
+// `approvals_pending` comes from the bot's memory, which this module can't see.
+pub async fn write_if_due(client: &Client, keypair: &Keypair, approvals_pending: usize) -> Result<()> {
+    let Some(interval) = interval() else { return Ok(()) };
+    let now = now_millis();
+    let started_at = {
+        let state = STATE.lock().map_err(|_| anyhow::anyhow!("heartbeat lock poisoned"))?;
+        if state.written_at.is_some_and(|at| now - at < interval.as_millis() as i64) {
+            return Ok(());
+        }
+        state.started_at
+    };
+    let heartbeat = json!({
+        "bot": keypair.public_key().to_string(),
+        "version": env!("CARGO_PKG_VERSION"),
+        "written_at": now,
+        "next_expected_at": now + interval.as_millis() as i64,
+        "started_at": started_at,
+        "uptime_secs": (now - started_at).max(0) / 1000,
+        "leader": lease::is_leader(),
+        "backlog": {
+            "notifications": notifications::backlog_stats(),
+            "llm_queue_depth": llm_queue::depth(),
+            "approvals_pending": approvals_pending,
+        },
+        "budget": {
+            "llm": llm_queue::queue_stats(),
+            "llm_rate_limits": llm_queue::rate_limit_stats(),
+            "llm_by_category": budget::stats(),
+            "storage": storage::usage(),
+        },
+        "nexus": intake::availability(),
+    });
+    // Counted as written even when it fails, so an unreachable homeserver is retried on
+    // the next interval instead of on every poll
+    if let Ok(mut state) = STATE.lock() {
+        state.written_at = Some(now);
+    }
+    put_file(client, &url(keypair), serde_json::to_vec(&heartbeat)?).await?;
+    log!("Heartbeat written (uptime {}s)", (now - started_at).max(0) / 1000);
+    Ok(())
+}
@@ -0,0 +1,276 @@
+use crate::backup;
+use crate::chaos;
+use crate::error::{BotError, BotResult};
+use crate::http;
+use crate::links;
+use crate::privacy;
+use crate::replay;
+use crate::storage;
+use pubky::{Client, Keypair};
+use pubky_app_specs::traits::Validatable;
+use pubky_app_specs::{PubkyAppPost, PubkyAppPostKind};
+use pubky_timestamp::Timestamp;
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Mutex, OnceLock};
+use tokio::time::{Duration, Instant};
+
+// Short-lived cache of fetched files keyed by URI, so building context, commands and
+// retries don't refetch the same posts. Writes and deletes through this module
+// invalidate their entry.
+struct FetchCache {
+    entries: HashMap<String, (Instant, Vec<u8>)>,
+    ttl: Duration,
+    max_entries: usize,
+}
+
+fn fetch_cache() -> &'static Mutex<FetchCache> {
+    static CACHE: OnceLock<Mutex<FetchCache>> = OnceLock::new();
+    CACHE.get_or_init(|| {
+        let var = |name: &str, default: u64| env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default);
+        Mutex::new(FetchCache {
+            entries: HashMap::new(),
+            ttl: Duration::from_secs(var("POST_CACHE_TTL", 60)),
+            max_entries: var("POST_CACHE_SIZE", 500) as usize,
+        })
+    })
+}
+
+pub fn invalidate(uri: &str) {
+    if let Ok(mut cache) = fetch_cache().lock() {
+        cache.entries.remove(uri);
+    }
+}
+
+// GETs a file, served from the fetch cache while fresh. None when it doesn't exist.
+pub async fn get_cached(client: &Client, uri: &str) -> BotResult<Option<Vec<u8>>> {
+    if let Ok(cache) = fetch_cache().lock() {
+        if let Some((fetched_at, body)) = cache.entries.get(uri) {
+            if fetched_at.elapsed() < cache.ttl {
+                return Ok(Some(body.clone()));
+            }
+        }
+    }
+
+    let Some(body) = get_file(client, uri).await? else {
+        return Ok(None);
+    };
+    if let Ok(mut cache) = fetch_cache().lock() {
+        if cache.max_entries > 0 {
+            if cache.entries.len() >= cache.max_entries {
+                let ttl = cache.ttl;
+                cache.entries.retain(|_, (at, _)| at.elapsed() < ttl);
+            }
+            if cache.entries.len() >= cache.max_entries {
+                if let Some(oldest) = cache.entries.iter().min_by_key(|(_, (at, _))| *at).map(|(k, _)| k.clone()) {
+                    cache.entries.remove(&oldest);
+                }
+            }
+            cache.entries.insert(uri.to_string(), (Instant::now(), body.clone()));
+        }
+    }
+    Ok(Some(body))
+}
+
+// GETs a file without caching. None when it doesn't exist. Reads are recorded when
+// RECORD_TRAFFIC is set and answered from the recording during a replay. Files over
+// HOMESERVER_MAX_FETCH_MB are refused.
+pub async fn get_file(client: &Client, uri: &str) -> BotResult<Option<Vec<u8>>> {
+    get_file_limited(client, uri, max_fetch_bytes()).await
+}
+
+fn max_fetch_bytes() -> usize {
+    env::var("HOMESERVER_MAX_FETCH_MB").ok().and_then(|v| v.parse::<usize>().ok()).filter(|n| *n > 0).unwrap_or(50) * 1024 * 1024
+}
+
+// Like get_file, but aborts the download once it exceeds `max_bytes`, so someone
+// else's 2 GB blob never ends up in memory.
+pub async fn get_file_limited(client: &Client, uri: &str, max_bytes: usize) -> BotResult<Option<Vec<u8>>> {
+    if replay::is_replaying() {
+        return Ok(replay::recorded_file(uri));
+    }
+    let sent = client.get(uri).send().await;
+    if backup::is_enabled() && backup::primary_unreachable(&sent) {
+        // Only the bot's own files are on the backup
+        if let Ok(body) = backup::get(client, uri).await {
+            return Ok(body);
+        }
+    }
+    let response = sent.map_err(BotError::homeserver)?;
+    let status = response.status();
+    if status == reqwest::StatusCode::NOT_FOUND {
+        replay::record_get(uri, None);
+        return Ok(None);
+    }
+    if !status.is_success() {
+        return Err(BotError::Homeserver(format!("Fetching {} failed: {}", uri, status)));
+    }
+    let body = http::read_limited(response, max_bytes)
+        .await
+        .map_err(BotError::homeserver)?
+        .ok_or_else(|| BotError::Validation(format!("{} is larger than {} bytes", uri, max_bytes)))?;
+    replay::record_get(uri, Some(&body));
+    Ok(Some(body))
+}
+
+// Maximum content length, in characters, the spec allows for a post kind. Read once from
+// the spec's own sanitizer, which truncates content to the kind's limit.
+pub fn max_post_chars(kind: PubkyAppPostKind) -> usize {
+    static LIMITS: OnceLock<Vec<(PubkyAppPostKind, usize)>> = OnceLock::new();
+    let limits = LIMITS.get_or_init(|| {
+        const PROBE_CHARS: usize = 1_000_000;
+        let probe = "a".repeat(PROBE_CHARS);
+        [PubkyAppPostKind::Short, PubkyAppPostKind::Long, PubkyAppPostKind::Image, PubkyAppPostKind::Video, PubkyAppPostKind::Link, PubkyAppPostKind::File]
+            .into_iter()
+            .map(|kind| {
+                let post = PubkyAppPost { content: probe.clone(), kind: kind.clone(), parent: None, embed: None, attachments: None };
+                (kind, post.sanitize().content.chars().count())
+            })
+            .collect()
+    });
+    limits.iter().find(|(known, _)| *known == kind).map(|(_, limit)| *limit).unwrap_or(limits[0].1)
+}
+
+// Publishes a new post of `kind`; `parent_uri` makes it a reply.
+pub async fn publish_post(client: &Client, keypair: &Keypair, parent_uri: Option<&str>, content: String, kind: PubkyAppPostKind) -> BotResult<String> {
+    let url = new_post_uri(keypair);
+    write_post(client, &url, parent_uri, content, kind).await?;
+    Ok(url)
+}
+
+// URI for a new post of the bot, unique per call.
+pub fn new_post_uri(keypair: &Keypair) -> String {
+    format!("pubky://{}/pub/pubky.app/posts/{}", keypair.public_key(), Timestamp::now())
+}
+
+// PUTs a post at an exact URI, replacing whatever was stored there.
+pub async fn write_post(client: &Client, url: &str, parent_uri: Option<&str>, content: String, kind: PubkyAppPostKind) -> BotResult<()> {
+    put_file(client, url, post_body(parent_uri, content, kind).await?).await
+}
+
+// The JSON of a post, with the link preview of its first link.
+pub async fn post_body(parent_uri: Option<&str>, content: String, kind: PubkyAppPostKind) -> BotResult<Vec<u8>> {
+    let embed = links::embed_for(&content).await;
+    let post = PubkyAppPost {
+        content,
+        kind,
+        parent: parent_uri.map(String::from),
+        embed,
+        attachments: None,
+    };
+    serde_json::to_vec(&post).map_err(BotError::validation)
+}
+
+// PUTs any file on the bot's homeserver and tracks how many bytes it takes.
+pub async fn put_file(client: &Client, url: &str, body: Vec<u8>) -> BotResult<()> {
+    if replay::is_replaying() {
+        log!("Replay: skipped writing {}: {}", url, privacy::content(&String::from_utf8_lossy(&body)));
+        return Ok(());
+    }
+    if chaos::fail_homeserver_put() {
+        return Err(BotError::Homeserver(format!("Failed to write {}: injected by CHAOS_HOMESERVER_PUT_FAIL_PERCENT", url)));
+    }
+    let size = body.len() as u64;
+    let copy = backup::is_enabled().then(|| body.clone());
+    let sent = client.put(url)
+        .body(body)
+        .send()
+        .await;
+    if let Some(copy) = copy {
+        if backup::primary_unreachable(&sent) {
+            backup::failover_put(client, url, copy).await?;
+            invalidate(url);
+            storage::record_write(url, size);
+            return Ok(());
+        }
+        if sent.as_ref().is_ok_and(|response| response.status().is_success()) {
+            backup::mirror_put(client, url, copy).await;
+        }
+    }
+    let response = sent.map_err(BotError::homeserver)?;
+    if !response.status().is_success() {
+        return Err(BotError::Homeserver(format!("Failed to write {}: {}", url, response.status())));
+    }
+    invalidate(url);
+    storage::record_write(url, size);
+    Ok(())
+}
+
+pub async fn delete_post(client: &Client, uri: &str) -> BotResult<()> {
+    delete_file(client, uri).await
+}
+
+// Deletes a file from the bot's homeserver; a file that is already gone is not an error.
+pub async fn delete_file(client: &Client, uri: &str) -> BotResult<()> {
+    if replay::is_replaying() {
+        log!("Replay: skipped deleting {}", uri);
+        return Ok(());
+    }
+    let sent = client.delete(uri).send().await;
+    if backup::is_enabled() && backup::primary_unreachable(&sent) {
+        backup::failover_delete(client, uri).await?;
+        invalidate(uri);
+        storage::record_delete(uri);
+        return Ok(());
+    }
+    let response = sent.map_err(BotError::homeserver)?;
+    let status = response.status();
+    if !status.is_success() && status != reqwest::StatusCode::NOT_FOUND {
+        return Err(BotError::Homeserver(format!("Failed to delete {}: {}", uri, status)));
+    }
+    invalidate(uri);
+    storage::record_delete(uri);
+    if backup::is_enabled() {
+        backup::mirror_delete(client, uri).await;
+    }
+    Ok(())
+}
+
+// Lists every post the bot has published, following the homeserver's pagination.
+pub async fn list_own_posts(client: &Client, keypair: &Keypair) -> BotResult<Vec<String>> {
+    list_dir(client, &format!("pubky://{}/pub/pubky.app/posts/", keypair.public_key())).await
+}
+
+// Lists every file under a homeserver directory, following the pagination.
+pub async fn list_dir(client: &Client, base: &str) -> BotResult<Vec<String>> {
+    let mut uris = Vec::new();
+    loop {
+        let cursor = uris.last().cloned();
+        let mut list = client.list(base).map_err(BotError::homeserver)?.limit(100);
+        if let Some(cursor) = cursor.as_deref() {
+            list = list.cursor(cursor);
+        }
+        let page = list.send().await.map_err(BotError::homeserver)?;
+        let done = page.len() < 100;
+        uris.extend(page);
+        if done {
+            return Ok(uris);
+        }
+    }
+}
+
+pub async fn fetch_post(client: &Client, uri: &str) -> BotResult<Option<PubkyAppPost>> {
+    let Some(body) = get_cached(client, uri).await? else {
+        return Ok(None);
+    };
+    Ok(serde_json::from_slice::<PubkyAppPost>(&body).ok())
+}
+
+// Re-measures everything the bot stores (posts, profile, last_read) by fetching it,
+// for when the local usage tracking is missing or out of date.
+pub async fn scan_storage(client: &Client, keypair: &Keypair) -> BotResult<HashMap<String, u64>> {
+    let mut uris = list_own_posts(client, keypair).await?;
+    uris.push(format!("pubky://{}/pub/pubky.app/profile.json", keypair.public_key()));
+    uris.push(format!("pubky://{}/pub/pubky.app/last_read", keypair.public_key()));
+
+    let mut files = HashMap::new();
+    for uri in uris {
+        let response = client.get(&uri).send().await.map_err(BotError::homeserver)?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            continue;
+        }
+        let size = response.bytes().await.map_err(BotError::homeserver)?.len() as u64;
+        files.insert(uri, size);
+    }
+    Ok(files)
+}
@@ -0,0 +1,148 @@
+use crate::privacy;
+use crate::script::{Ending, Script, Value};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+// Points in the reply pipeline where operator scripts can step in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    // A mention arrived; `text` is the post content
+    OnMention,
+    // About to call the model; `text` is the prompt
+    PreGenerate,
+    // The model answered; `text` is the reply
+    PostGenerate,
+    // Last look at the reply before it is published or queued for approval
+    PrePublish,
+}
+
+impl Hook {
+    pub fn name(self) -> &'static str {
+        match self {
+            Hook::OnMention => "on_mention",
+            Hook::PreGenerate => "pre_generate",
+            Hook::PostGenerate => "post_generate",
+            Hook::PrePublish => "pre_publish",
+        }
+    }
+}
+
+// What a hook decided.
+#[derive(Debug, PartialEq, Eq)]
+pub enum HookOutcome {
+    // Carry on with this text (possibly rewritten)
+    Continue(String),
+    // Drop the mention or reply
+    Skip,
+}
+
+fn timeout() -> Duration {
+    Duration::from_secs(env::var("HOOK_TIMEOUT").ok().and_then(|v| v.parse().ok()).unwrap_or(5))
+}
+
+// The scripts configured for `hook` in HOOK_SCRIPTS, as `hook:path` pairs separated by
+// commas, e.g. `on_mention:hooks/skip-spam.hook,pre_publish:hooks/footer.hook`. A hook
+// may be listed more than once; its scripts run in the order given.
+fn script_paths(config: &str, hook: Hook) -> Vec<PathBuf> {
+    config
+        .split(',')
+        .filter_map(|entry| entry.split_once(':'))
+        .filter(|(name, path)| name.trim().eq_ignore_ascii_case(hook.name()) && !path.trim().is_empty())
+        .map(|(_, path)| PathBuf::from(path.trim()))
+        .collect()
+}
+
+// Runs the operator's scripts for `hook` (see src/script.rs for the language). Each
+// script sees the variables `hook`, `user`, `post_uri` and `text`, and may change `text`
+// or `skip;` the mention or reply. Scripts are read on every run, so edits apply without
+// a restart. A script that fails to load, errors or runs past HOOK_TIMEOUT is logged and
+// its changes are dropped, so a broken hook can't stop the bot.
+pub async fn run(hook: Hook, user: &str, post_uri: &str, text: String) -> HookOutcome {
+    let paths = script_paths(&env::var("HOOK_SCRIPTS").unwrap_or_default(), hook);
+    if paths.is_empty() {
+        return HookOutcome::Continue(text);
+    }
+    let (user, post_uri) = (user.to_string(), post_uri.to_string());
+    let fallback = text.clone();
+    tokio::task::spawn_blocking(move || run_scripts(hook, &paths, &user, &post_uri, text)).await.unwrap_or_else(|e| {
+        log!("Hook {} panicked, continuing unchanged: {}", hook.name(), e);
+        HookOutcome::Continue(fallback)
+    })
+}
+
+fn run_scripts(hook: Hook, paths: &[PathBuf], user: &str, post_uri: &str, mut text: String) -> HookOutcome {
+    for path in paths {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => {
+                log!("Hook {} can't read {}, skipping it: {}", hook.name(), path.display(), e);
+                continue;
+            }
+        };
+        match run_script(hook, &source, user, post_uri, &text) {
+            Ok(HookOutcome::Skip) => {
+                log!("Hook {} ({}) skipped {}", hook.name(), path.display(), privacy::uri(post_uri));
+                return HookOutcome::Skip;
+            }
+            Ok(HookOutcome::Continue(rewritten)) => text = rewritten,
+            Err(e) => log!("Hook {} ({}) failed, continuing unchanged: {}", hook.name(), path.display(), e),
+        }
+    }
+    HookOutcome::Continue(text)
+}
+
+fn run_script(hook: Hook, source: &str, user: &str, post_uri: &str, text: &str) -> anyhow::Result<HookOutcome> {
+    let script = Script::parse(source)?;
+    let mut vars = HashMap::from([
+        ("hook".to_string(), Value::Str(hook.name().to_string())),
+        ("user".to_string(), Value::Str(user.to_string())),
+        ("post_uri".to_string(), Value::Str(post_uri.to_string())),
+        ("text".to_string(), Value::Str(text.to_string())),
+    ]);
+    if script.run(&mut vars, Instant::now() + timeout())? == Ending::Skipped {
+        return Ok(HookOutcome::Skip);
+    }
+    match vars.remove("text") {
+        Some(Value::Str(text)) => Ok(HookOutcome::Continue(text)),
+        _ => anyhow::bail!("text must be a string"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_configured_paths() {
+        let config = "on_mention:a.hook, pre_publish : b.hook,ON_MENTION:c.hook,broken,post_generate:";
+        assert_eq!(script_paths(config, Hook::OnMention), vec![PathBuf::from("a.hook"), PathBuf::from("c.hook")]);
+        assert_eq!(script_paths(config, Hook::PrePublish), vec![PathBuf::from("b.hook")]);
+        assert!(script_paths(config, Hook::PostGenerate).is_empty());
+        assert!(script_paths("", Hook::PreGenerate).is_empty());
+    }
+
+    #[test]
+    fn runs_scripts_in_order() {
+        let dir = std::env::temp_dir().join(format!("hooks-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let footer = dir.join("footer.hook");
+        let upper = dir.join("upper.hook");
+        let broken = dir.join("broken.hook");
+        let skip = dir.join("skip.hook");
+        fs::write(&footer, "if hook == \"pre_publish\" { text = text + \" #bot\"; }").unwrap();
+        fs::write(&upper, "text = upper(text) + \" @\" + user;").unwrap();
+        fs::write(&broken, "text = 1 +;").unwrap();
+        fs::write(&skip, "if user == \"spammer\" { skip; }").unwrap();
+
+        let paths = [upper.clone(), broken.clone(), dir.join("missing.hook"), footer.clone(), skip.clone()];
+        assert_eq!(run_scripts(Hook::PrePublish, &paths, "bob", "uri", "hi".to_string()), HookOutcome::Continue("HI @bob #bot".to_string()));
+        assert_eq!(run_scripts(Hook::OnMention, &paths, "bob", "uri", "hi".to_string()), HookOutcome::Continue("HI @bob".to_string()));
+        assert_eq!(run_scripts(Hook::OnMention, &paths, "spammer", "uri", "hi".to_string()), HookOutcome::Skip);
+        // A script that leaves `text` as something else changes nothing
+        assert!(run_script(Hook::OnMention, "text = 3;", "bob", "uri", "hi").is_err());
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,66 @@
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::Client;
+use std::env;
+use std::sync::OnceLock;
+
+// Shared client for Nexus requests, with HTTP_USER_AGENT and NEXUS_HEADERS.
+pub fn nexus() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(|| build("NEXUS_HEADERS"))
+}
+
+// Shared client for requests to the OpenAI-compatible API, with HTTP_USER_AGENT and
+// LLM_HEADERS.
+pub fn llm() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(|| build("LLM_HEADERS"))
+}
+
+fn user_agent() -> String {
+    env::var("HTTP_USER_AGENT")
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+        .unwrap_or_else(|| format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")))
+}
+
+// Parses comma-separated `Name=value` pairs, skipping (and logging) invalid ones.
+fn headers(var: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let value = env::var(var).unwrap_or_default();
+    for pair in value.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let parsed = pair.split_once('=').and_then(|(name, value)| {
+            Some((HeaderName::from_bytes(name.trim().as_bytes()).ok()?, HeaderValue::from_str(value.trim()).ok()?))
+        });
+        match parsed {
+            Some((name, value)) => {
+                headers.insert(name, value);
+            }
+            None => log!("Ignoring invalid header in {}: {}", var, pair),
+        }
+    }
+    headers
+}
+
+fn build(headers_var: &str) -> Client {
+    Client::builder().user_agent(user_agent()).default_headers(headers(headers_var)).build().unwrap_or_else(|e| {
+        log!("Failed to build the HTTP client with {}: {}", headers_var, e);
+        Client::new()
+    })
+}
+
+// Reads a response body of at most `max_bytes`, streaming it so an oversized download
+// is abandoned (without being held in memory) as soon as its Content-Length or the
+// bytes received so far exceed the limit. None when it is too large.
+pub async fn read_limited(mut response: reqwest::Response, max_bytes: usize) -> reqwest::Result<Option<Vec<u8>>> {
+    if response.content_length().is_some_and(|length| length > max_bytes as u64) {
+        return Ok(None);
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > max_bytes {
+            return Ok(None);
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(Some(body))
+}
@@ -0,0 +1,306 @@
+use crate::analytics::{self, AnalyticsRow};
+use crate::archive;
+use crate::chaos;
+use crate::correlation;
+use crate::error::BotError;
+use crate::http;
+use crate::memory::now_millis;
+use crate::notifications::{self, Notification};
+use crate::privacy;
+use crate::replay;
+use crate::throttle;
+use crate::timings;
+use pubky::{Client, Keypair};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{self, Receiver, Sender};
+
+// Time between polls when Nexus has nothing new (or fails).
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+// Notifications fetched but not yet handled, from NOTIFICATION_QUEUE_SIZE. When the
+// queue is full the poller waits for room instead of fetching more, so a burst slows
+// intake down to the pace of generation and publishing rather than piling up in memory.
+fn queue_size() -> usize {
+    env::var("NOTIFICATION_QUEUE_SIZE").ok().and_then(|v| v.parse().ok()).filter(|n| *n > 0).unwrap_or(page_size() * 3)
+}
+
+// Notifications asked of Nexus per request, from NOTIFICATION_LIMIT. Also the most
+// the handler takes off the queue as one batch.
+pub fn page_size() -> usize {
+    env::var("NOTIFICATION_LIMIT").ok().and_then(|v| v.parse().ok()).filter(|n| *n > 0).unwrap_or(30)
+}
+
+// How far before the newest timestamp seen the next query starts, from
+// NOTIFICATION_SINCE_OVERLAP_MS. Notifications sharing the boundary timestamp (or
+// indexed late by Nexus) come back again and are dropped as already seen instead of
+// being skipped over.
+fn since_overlap_ms() -> i64 {
+    env::var("NOTIFICATION_SINCE_OVERLAP_MS").ok().and_then(|v| v.parse().ok()).filter(|n| *n >= 0).unwrap_or(1000)
+}
+
+// How a full page is followed up, from NOTIFICATION_SKIP_STRATEGY.
+#[derive(Clone, Copy, PartialEq)]
+enum SkipStrategy {
+    // Move `since` up to the newest timestamp and query again, only paging with
+    // `skip` when a full page brought nothing new (all on the boundary timestamp).
+    Since,
+    // Keep `since` and page through with `skip` until a short page, then move `since`.
+    Offset,
+}
+
+fn skip_strategy() -> SkipStrategy {
+    match env::var("NOTIFICATION_SKIP_STRATEGY").unwrap_or_default().trim().to_lowercase().as_str() {
+        "offset" => SkipStrategy::Offset,
+        "" | "since" => SkipStrategy::Since,
+        other => {
+            log!("Unknown NOTIFICATION_SKIP_STRATEGY {:?}, using since", other);
+            SkipStrategy::Since
+        }
+    }
+}
+
+// Starts the task polling Nexus from the bot's last_read and returns the queue it fills.
+// The consumer moves last_read forward only once notifications are handled, so a
+// restart picks up whatever was still queued.
+pub fn spawn(client: Client, keypair: Keypair) -> Receiver<Notification> {
+    let (tx, rx) = mpsc::channel(queue_size());
+    tokio::spawn(poll_loop(client, keypair, tx));
+    rx
+}
+
+async fn poll_loop(client: Client, keypair: Keypair, tx: Sender<Notification>) {
+    // last_read is one past the newest handled timestamp, so anything at or after it is new
+    let floor = loop {
+        match crate::get_last_read(&client, &keypair).await {
+            Ok(last_read) => break last_read,
+            Err(e) => {
+                log!("Error reading last_read: {}", e);
+                tokio::time::sleep(POLL_INTERVAL).await;
+                if tx.is_closed() {
+                    return;
+                }
+            }
+        }
+    };
+    log!("Current last_read: {}", floor);
+    let http = http::nexus();
+    let limit = page_size();
+    let overlap = since_overlap_ms();
+    let strategy = skip_strategy();
+    // Newest timestamp fetched so far, and what was fetched since the query window began
+    let mut cursor = floor;
+    let mut seen: HashMap<String, i64> = HashMap::new();
+    let mut since = floor;
+    let mut skip = 0;
+    // Polls in a row that brought nothing new
+    let mut idle_polls = 0;
+    loop {
+        let wait = match fetch_page(http, &keypair, since, skip, limit).await {
+            Ok((notifications, full_page)) => {
+                record_available();
+                let fresh: Vec<Notification> = notifications
+                    .into_iter()
+                    .filter(|n| n.timestamp >= floor)
+                    .filter(|n| seen.insert(correlation::for_notification(n.timestamp, &n.body), n.timestamp).is_none())
+                    .collect();
+                let progressed = !fresh.is_empty();
+                idle_polls = if progressed { 0 } else { idle_polls + 1 };
+                notifications::track(fresh.iter().map(|n| n.timestamp), full_page);
+                for notification in fresh {
+                    cursor = cursor.max(notification.timestamp);
+                    // Waits while the queue is full
+                    if tx.send(notification).await.is_err() {
+                        return;
+                    }
+                }
+                if full_page && (strategy == SkipStrategy::Offset || !progressed) {
+                    skip += limit;
+                } else {
+                    since = floor.max(cursor - overlap);
+                    skip = 0;
+                    seen.retain(|_, timestamp| *timestamp >= since);
+                }
+                // A full page means more is waiting: fetch it as soon as the queue has room
+                if full_page {
+                    Duration::ZERO
+                } else {
+                    throttle::idle_interval(POLL_INTERVAL, idle_polls)
+                }
+            }
+            Err(failure) => {
+                let wait = record_unavailable(&failure);
+                log!("Nexus unavailable ({}): {}; next poll in {}s", failure.class, failure.detail, wait.as_secs());
+                wait
+            }
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+        if tx.is_closed() {
+            return;
+        }
+    }
+}
+
+// Why a poll got no usable notifications. `class` is one of `http_<status>`, `html`,
+// `text`, `empty`, `invalid_json` or `unreachable`.
+struct Unavailable {
+    class: String,
+    detail: String,
+    retry_after: Option<Duration>,
+}
+
+impl Unavailable {
+    fn new(class: impl Into<String>, detail: impl Into<String>) -> Unavailable {
+        Unavailable { class: class.into(), detail: detail.into(), retry_after: None }
+    }
+}
+
+async fn fetch_page(http: &reqwest::Client, keypair: &Keypair, since: i64, skip: usize, limit: usize) -> Result<(Vec<Notification>, bool), Unavailable> {
+    let nexus_url = env::var("NEXT_PUBLIC_NEXUS").map_err(|_| Unavailable::new("unreachable", "NEXT_PUBLIC_NEXUS not found in .env"))?;
+    let url = format!("{}/v0/user/{}/notifications?skip={}&limit={}&since={}", nexus_url, keypair.public_key(), skip, limit, since);
+    log!("Checking notifications from: {}", url);
+    if chaos::drop_nexus_response() {
+        return Err(Unavailable::new("chaos", "response dropped by CHAOS_NEXUS_DROP_PERCENT"));
+    }
+
+    let response = http.get(&url).send().await.map_err(|e| Unavailable::new("unreachable", BotError::nexus(e).to_string()))?;
+    let status = response.status();
+    log!("Response status: {}", status);
+    let retry_after = response.headers().get("retry-after").and_then(|v| v.to_str().ok()).and_then(|v| v.trim().parse().ok()).map(Duration::from_secs);
+    let content_type = response.headers().get("content-type").and_then(|v| v.to_str().ok()).unwrap_or_default().to_lowercase();
+    let response_text = response.text().await.map_err(|e| Unavailable::new("unreachable", BotError::nexus(e).to_string()))?;
+    replay::record_notifications(&response_text);
+    archive::record(&response_text);
+
+    let class = classify(&content_type, &response_text);
+    if status == reqwest::StatusCode::NO_CONTENT || (status.is_success() && class == BodyClass::Empty) {
+        log!("Warning: Received empty response");
+        return Ok((Vec::new(), false));
+    }
+    if !status.is_success() || class != BodyClass::Json {
+        let detail = format!("{} with {} body: {}", status, class.name(), summarize(&response_text));
+        let class = if status.is_success() { class.name().to_string() } else { format!("http_{}", status.as_u16()) };
+        return Err(Unavailable { class, detail, retry_after });
+    }
+    log!("Raw response: {}", privacy::content(&response_text));
+
+    let started = Instant::now();
+    let notifications = notifications::parse_batch(&response_text).map_err(|e| Unavailable::new("invalid_json", e.to_string()))?;
+    timings::observe("notification_parse", started.elapsed());
+    log!("Successfully parsed {} notifications", notifications.len());
+    let full_page = notifications.len() >= limit;
+    Ok((notifications, full_page))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum BodyClass {
+    Json,
+    Empty,
+    Html,
+    Text,
+}
+
+impl BodyClass {
+    fn name(&self) -> &'static str {
+        match self {
+            BodyClass::Json => "json",
+            BodyClass::Empty => "empty",
+            BodyClass::Html => "html",
+            BodyClass::Text => "text",
+        }
+    }
+}
+
+// Maintenance pages and gateway errors come back as HTML (or plain text) instead of
+// the JSON array Nexus normally returns.
+fn classify(content_type: &str, body: &str) -> BodyClass {
+    let trimmed = body.trim_start();
+    if trimmed.is_empty() {
+        BodyClass::Empty
+    } else if content_type.contains("html") || trimmed.starts_with('<') {
+        BodyClass::Html
+    } else if trimmed.starts_with('[') || trimmed.starts_with('{') {
+        BodyClass::Json
+    } else {
+        BodyClass::Text
+    }
+}
+
+// One-line description of a body that isn't notifications: the title of an HTML page,
+// otherwise its first line, so the log doesn't fill with whole error pages.
+fn summarize(body: &str) -> String {
+    let lower = body.to_lowercase();
+    let title = lower.find("<title>").and_then(|start| {
+        let start = start + "<title>".len();
+        lower[start..].find("</title>").map(|end| body[start..start + end].trim())
+    });
+    let text = title.unwrap_or_else(|| body.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or_default());
+    let short: String = text.chars().take(120).collect();
+    if short.is_empty() {
+        "(empty)".to_string()
+    } else {
+        short
+    }
+}
+
+// How reachable Nexus has been for notification polls.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct NexusAvailability {
+    pub ok_polls: u64,
+    pub failed_polls: u64,
+    // Failed polls by class (`http_502`, `html`, `unreachable`, ...)
+    pub failures: BTreeMap<String, u64>,
+    pub consecutive_failures: u32,
+    pub unavailable_since: Option<i64>,
+    pub last_failure: Option<String>,
+}
+
+static AVAILABILITY: Mutex<NexusAvailability> = Mutex::new(NexusAvailability {
+    ok_polls: 0,
+    failed_polls: 0,
+    failures: BTreeMap::new(),
+    consecutive_failures: 0,
+    unavailable_since: None,
+    last_failure: None,
+});
+
+pub fn availability() -> NexusAvailability {
+    AVAILABILITY.lock().map(|a| a.clone()).unwrap_or_default()
+}
+
+fn record_available() {
+    let Ok(mut availability) = AVAILABILITY.lock() else { return };
+    availability.ok_polls += 1;
+    availability.consecutive_failures = 0;
+    if let Some(since) = availability.unavailable_since.take() {
+        let down_ms = (now_millis() - since).max(0);
+        log!("Nexus is available again after {}s", down_ms / 1000);
+        analytics::record(AnalyticsRow::new("nexus_recovered", None, None).with("down_ms", down_ms));
+    }
+}
+
+// Counts a failed poll and returns how long to wait before the next one.
+fn record_unavailable(failure: &Unavailable) -> Duration {
+    let Ok(mut availability) = AVAILABILITY.lock() else { return POLL_INTERVAL };
+    availability.failed_polls += 1;
+    *availability.failures.entry(failure.class.clone()).or_default() += 1;
+    availability.consecutive_failures += 1;
+    availability.last_failure = Some(failure.detail.clone());
+    if availability.unavailable_since.is_none() {
+        availability.unavailable_since = Some(now_millis());
+        analytics::record(AnalyticsRow::new("nexus_unavailable", None, None).with("class", failure.class.as_str()));
+    }
+    let exponent = availability.consecutive_failures.saturating_sub(1).min(16);
+    let backoff = POLL_INTERVAL.saturating_mul(1 << exponent).min(max_backoff());
+    backoff.max(failure.retry_after.unwrap_or_default().min(max_backoff()))
+}
+
+// Longest wait between polls while Nexus keeps failing, from NEXUS_BACKOFF_MAX_SECS.
+fn max_backoff() -> Duration {
+    Duration::from_secs(env::var("NEXUS_BACKOFF_MAX_SECS").ok().and_then(|v| v.parse().ok()).filter(|n| *n > 0).unwrap_or(300))
+}
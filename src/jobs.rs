@@ -0,0 +1,277 @@
+use crate::context::PromptVars;
+use crate::correlation;
+use crate::examples::Example;
+use crate::llm::{generate_response, ChatMessage, PromptContext};
+use crate::llm_queue::Priority;
+use crate::memory::now_millis;
+use crate::privacy;
+use crate::shadow::{self, Candidate};
+use anyhow::Result;
+use dotenv::dotenv;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+// Generation handed off to `worker` processes. The poller (`run`) keeps the
+// notification cursor, memory and publishing to itself and only queues the model
+// call; workers take jobs from a shared directory, so they can run as several
+// processes, or on other machines sharing the directory:
+//   pending/  queued jobs, named so that sorting them gives priority then age
+//   claimed/  jobs a worker took (moved there atomically, so only one worker wins)
+//   done/     results waiting for the poller to publish them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenerationJob {
+    pub id: String,
+    pub created_at: i64,
+    pub to_user: String,
+    pub parent_uri: String,
+    pub prompt: String,
+    pub prefs: Option<BTreeMap<String, String>>,
+    pub instruction: Option<String>,
+    pub trigger_label: Option<String>,
+    pub vars: PromptVars,
+    pub history: Vec<ChatMessage>,
+    pub language: Option<String>,
+    pub examples: Vec<Example>,
+    pub priority: Priority,
+    pub max_chars: Option<usize>,
+    #[serde(default)]
+    pub people: Option<String>,
+    pub kb_version: Option<String>,
+    // Added to the FAQ cache with the answer once it comes back
+    pub faq_embedding: Option<Vec<f32>>,
+    pub correlation_id: Option<String>,
+}
+
+impl GenerationJob {
+    pub fn context<'a>(&'a self, candidate: Option<&'a Candidate>) -> PromptContext<'a> {
+        PromptContext {
+            prefs: self.prefs.as_ref(),
+            instruction: self.instruction.as_deref(),
+            vars: Some(&self.vars),
+            history: &self.history,
+            language: self.language.as_deref(),
+            examples: &self.examples,
+            priority: self.priority,
+            candidate,
+            max_chars: self.max_chars,
+            people: self.people.as_deref(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobResult {
+    pub job: GenerationJob,
+    pub response: Option<String>,
+    pub error: Option<String>,
+    pub worker: String,
+    pub generation_ms: u64,
+}
+
+// Jobs in the queue, for /status.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct QueueStats {
+    pub pending: usize,
+    pub claimed: usize,
+    pub done: usize,
+}
+
+// The queue directory, from JOB_QUEUE_DIR. Unset, replies are generated in process.
+fn dir() -> Option<PathBuf> {
+    env::var("JOB_QUEUE_DIR").ok().filter(|d| !d.trim().is_empty()).map(PathBuf::from)
+}
+
+pub fn is_enabled() -> bool {
+    dir().is_some()
+}
+
+// A claimed job older than JOB_CLAIM_TIMEOUT_SECS (default 600) is taken to belong to
+// a worker that died and goes back to pending. Live workers keep their claims fresh
+// with `heartbeat`, so a slow generation isn't queued twice.
+fn claim_timeout() -> Duration {
+    Duration::from_secs(env::var("JOB_CLAIM_TIMEOUT_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(600))
+}
+
+fn subdir(name: &str) -> Result<PathBuf> {
+    let dir = dir().ok_or_else(|| anyhow::anyhow!("JOB_QUEUE_DIR is not set"))?.join(name);
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+// Writes through a dot-file and renames it, so readers never see a half-written job.
+fn write_atomic(dir: &Path, name: &str, value: &impl Serialize) -> Result<()> {
+    let tmp = dir.join(format!(".{}.tmp", name));
+    fs::write(&tmp, serde_json::to_vec(value)?)?;
+    fs::rename(&tmp, dir.join(name))?;
+    Ok(())
+}
+
+// Job files in `dir` (not the ones still being written), sorted by name.
+fn job_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json") && !path.file_name().and_then(|n| n.to_str()).unwrap_or(".").starts_with('.'))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+pub fn enqueue(job: &GenerationJob) -> Result<()> {
+    let name = format!("{}-{:013}-{}.json", job.priority as u8, job.created_at, job.id);
+    write_atomic(&subdir("pending")?, &name, job)
+}
+
+// The next job by priority and age, moved to claimed/ by this worker. None when the
+// queue is empty.
+fn claim() -> Result<Option<(PathBuf, GenerationJob)>> {
+    let claimed = subdir("claimed")?;
+    for path in job_files(&subdir("pending")?)? {
+        let Some(name) = path.file_name() else { continue };
+        let target = claimed.join(name);
+        match fs::rename(&path, &target) {
+            Ok(()) => {}
+            // Another worker was faster
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        }
+        // A rename keeps the enqueue time; the claim time is what requeue_stale checks
+        fs::File::options().write(true).open(&target)?.set_modified(SystemTime::now())?;
+        match serde_json::from_slice(&fs::read(&target)?) {
+            Ok(job) => return Ok(Some((target, job))),
+            Err(e) => {
+                log!("Dropping unreadable job {}: {}", target.display(), e);
+                fs::remove_file(&target)?;
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn complete(claimed: &Path, result: &JobResult) -> Result<()> {
+    // Withdrawn while it was generated, by !deletemydata
+    if !claimed.exists() {
+        log!("Job {} was withdrawn, dropping its result", claimed.display());
+        return Ok(());
+    }
+    let name = claimed.file_name().and_then(|n| n.to_str()).unwrap_or("job.json");
+    write_atomic(&subdir("done")?, name, result)?;
+    fs::remove_file(claimed)?;
+    Ok(())
+}
+
+// Results ready to publish, oldest first, taken out of the queue. Also puts jobs
+// claimed by workers that stopped answering back in line.
+pub fn take_results() -> Result<Vec<JobResult>> {
+    requeue_stale()?;
+    let mut results = Vec::new();
+    for path in job_files(&subdir("done")?)? {
+        let result = serde_json::from_slice(&fs::read(&path)?);
+        fs::remove_file(&path)?;
+        match result {
+            Ok(result) => results.push(result),
+            Err(e) => log!("Dropping unreadable job result {}: {}", path.display(), e),
+        }
+    }
+    results.sort_by_key(|r: &JobResult| r.job.created_at);
+    Ok(results)
+}
+
+fn requeue_stale() -> Result<()> {
+    let pending = subdir("pending")?;
+    for path in job_files(&subdir("claimed")?)? {
+        let claimed_at = fs::metadata(&path).and_then(|m| m.modified()).unwrap_or_else(|_| SystemTime::now());
+        if claimed_at.elapsed().unwrap_or_default() < claim_timeout() {
+            continue;
+        }
+        let Some(name) = path.file_name() else { continue };
+        log!("Job {} was claimed over {}s ago without a result, queueing it again", path.display(), claim_timeout().as_secs());
+        fs::rename(&path, pending.join(name))?;
+    }
+    Ok(())
+}
+
+// Withdraws the queued, claimed and finished jobs for `user` and returns how many went.
+pub fn forget_user(user: &str) -> Result<usize> {
+    if !is_enabled() {
+        return Ok(0);
+    }
+    let mut removed = 0;
+    for name in ["pending", "claimed", "done"] {
+        for path in job_files(&subdir(name)?)? {
+            let Ok(value) = serde_json::from_slice::<serde_json::Value>(&fs::read(&path)?) else { continue };
+            // Results wrap their job
+            let job = if value.get("job").is_some() { &value["job"] } else { &value };
+            if job["to_user"].as_str() == Some(user) {
+                fs::remove_file(&path)?;
+                removed += 1;
+            }
+        }
+    }
+    Ok(removed)
+}
+
+pub fn stats() -> Option<QueueStats> {
+    dir()?;
+    let count = |name: &str| subdir(name).and_then(|d| job_files(&d)).map(|files| files.len()).unwrap_or(0);
+    Some(QueueStats { pending: count("pending"), claimed: count("claimed"), done: count("done") })
+}
+
+// `worker`: takes jobs from JOB_QUEUE_DIR one at a time and generates their replies
+// until stopped. Run as many as the model provider's limits allow.
+pub async fn run_worker() -> Result<()> {
+    dotenv().ok();
+    if !is_enabled() {
+        return Err(anyhow::anyhow!("Set JOB_QUEUE_DIR to the queue directory shared with the bot"));
+    }
+    let worker = env::var("WORKER_ID").ok().filter(|id| !id.trim().is_empty()).unwrap_or_else(|| format!("worker-{}", std::process::id()));
+    log!("Worker {} taking generation jobs from {}", worker, dir().unwrap_or_default().display());
+    loop {
+        let Some((claimed, job)) = claim()? else {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            continue;
+        };
+        let heartbeat = tokio::spawn(heartbeat(claimed.clone()));
+        let result = match job.correlation_id.clone() {
+            Some(id) => correlation::scope(id, generate(&worker, job)).await,
+            None => generate(&worker, job).await,
+        };
+        heartbeat.abort();
+        complete(&claimed, &result)?;
+    }
+}
+
+// Refreshes the claim's modification time a few times per claim timeout while the job
+// is generated, which is what requeue_stale goes by. Stops once the claim is gone.
+async fn heartbeat(claimed: PathBuf) {
+    let interval = (claim_timeout() / 3).max(Duration::from_secs(1));
+    loop {
+        tokio::time::sleep(interval).await;
+        let touched = fs::File::options().write(true).open(&claimed).and_then(|file| file.set_modified(SystemTime::now()));
+        match touched {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+            Err(e) => log!("Failed to refresh the claim on {}: {}", claimed.display(), e),
+        }
+    }
+}
+
+async fn generate(worker: &str, job: GenerationJob) -> JobResult {
+    log!("Generating reply to {} (job {})", privacy::uri(&job.parent_uri), job.id);
+    // In shadow-only mode the candidate replaces the live configuration outright
+    let candidate = if shadow::is_only() { shadow::candidate() } else { None };
+    let started = now_millis();
+    let generated = generate_response(&job.prompt, &job.context(candidate.as_ref())).await;
+    let generation_ms = (now_millis() - started).max(0) as u64;
+    let (response, error) = match generated {
+        Ok(response) => (Some(response), None),
+        Err(e) => {
+            log!("Generation failed for job {}: {}", job.id, e);
+            (None, Some(e.to_string()))
+        }
+    };
+    JobResult { job, response, error, worker: worker.to_string(), generation_ms }
+}
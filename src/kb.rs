@@ -0,0 +1,315 @@
+use crate::embeddings::{cosine_similarity, embed, embed_batch, model as embedding_model};
+use crate::lang;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+const MAX_CHUNK_CHARS: usize = 1200;
+
+// Embedded chunks of the knowledge base, cached on disk by content hash so a
+// rebuild only embeds chunks that changed.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct KnowledgeIndex {
+    pub model: String,
+    // Version of the knowledge base file the index was built from
+    #[serde(default)]
+    pub version: String,
+    pub chunks: Vec<KbChunk>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KbChunk {
+    pub hash: String,
+    pub title: String,
+    pub text: String,
+    pub embedding: Vec<f32>,
+}
+
+// Loaded indexes: the default knowledge base under None, language variants by code.
+static INDEXES: Mutex<BTreeMap<Option<String>, Arc<KnowledgeIndex>>> = Mutex::new(BTreeMap::new());
+
+pub fn knowledge_base_path() -> String {
+    env::var("KNOWLEDGE_BASE").unwrap_or_else(|_| "knowledge-base.txt".to_string())
+}
+
+// Directory of per-language knowledge bases: `<dir>/pt.txt` (or `.md`), or a `<dir>/pt/`
+// directory whose files are read in name order. From KNOWLEDGE_DIR, default `knowledge`.
+fn knowledge_dir() -> PathBuf {
+    PathBuf::from(env::var("KNOWLEDGE_DIR").unwrap_or_else(|_| "knowledge".to_string()))
+}
+
+// Language the default knowledge base is written in, from KNOWLEDGE_BASE_LANG (default en).
+pub fn default_language() -> String {
+    env::var("KNOWLEDGE_BASE_LANG").ok().filter(|v| !v.trim().is_empty()).map_or_else(|| "en".to_string(), |v| v.trim().to_lowercase())
+}
+
+fn variant_path(language: &str) -> Option<PathBuf> {
+    let dir = knowledge_dir();
+    [dir.join(language), dir.join(format!("{}.txt", language)), dir.join(format!("{}.md", language))].into_iter().find(|path| path.exists())
+}
+
+// Language codes that have their own knowledge base.
+pub fn variants() -> Vec<String> {
+    let Ok(entries) = fs::read_dir(knowledge_dir()) else {
+        return Vec::new();
+    };
+    let mut languages: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.path().file_stem().map(|stem| stem.to_string_lossy().to_lowercase()))
+        .filter(|language| variant_path(language).is_some())
+        .collect();
+    languages.sort();
+    languages.dedup();
+    languages
+}
+
+// The knowledge base variant to answer from: the reply language (or, when it isn't
+// known, the language `query` is written in) if it has its own knowledge base. None
+// means the default knowledge base.
+pub fn variant_for(query: &str, language: Option<&str>) -> Option<String> {
+    let language = language.or_else(|| lang::detect(query))?.to_lowercase();
+    variant_path(&language).map(|_| language)
+}
+
+// The language the reply has to be translated into from the default knowledge base,
+// when it is written in one that has no variant and isn't the default's.
+pub fn translation_target(query: &str, language: Option<&str>) -> Option<String> {
+    let language = language.or_else(|| lang::detect(query))?.to_lowercase();
+    (variant_path(&language).is_none() && language != default_language()).then_some(language)
+}
+
+// Text of the default knowledge base or of a language variant.
+fn read_source(variant: Option<&str>) -> Result<String> {
+    let Some(language) = variant else {
+        return Ok(fs::read_to_string(knowledge_base_path())?);
+    };
+    let path = variant_path(language).ok_or_else(|| anyhow::anyhow!("No {} knowledge base in {}", language, knowledge_dir().display()))?;
+    if !path.is_dir() {
+        return Ok(fs::read_to_string(path)?);
+    }
+    let mut files: Vec<PathBuf> = fs::read_dir(&path)?.filter_map(|entry| entry.ok().map(|e| e.path())).filter(|p| p.is_file()).collect();
+    files.sort();
+    let mut content = String::new();
+    for file in files {
+        // Each file starts a new chunk titled after it, unless it opens with its own heading
+        let text = fs::read_to_string(&file)?;
+        if !text.trim_start().starts_with('#') {
+            content.push_str(&format!("# {}\n", file.file_stem().unwrap_or_default().to_string_lossy()));
+        }
+        content.push_str(&text);
+        content.push_str("\n\n");
+    }
+    Ok(content)
+}
+
+// With KB_RETRIEVAL=true only the most relevant chunks go into the prompt
+// instead of the whole knowledge base file.
+pub fn is_retrieval_enabled() -> bool {
+    env::var("KB_RETRIEVAL").map(|v| v == "true").unwrap_or(false)
+}
+
+// KB_INDEX for the default knowledge base; variants get the language code inserted,
+// e.g. kb-index.pt.json.
+fn index_path(variant: Option<&str>) -> String {
+    let path = env::var("KB_INDEX").unwrap_or_else(|_| "kb-index.json".to_string());
+    match (variant, path.strip_suffix(".json")) {
+        (None, _) => path,
+        (Some(language), Some(stem)) => format!("{}.{}.json", stem, language),
+        (Some(language), None) => format!("{}.{}", path, language),
+    }
+}
+
+// Short content hash identifying a knowledge base revision.
+pub fn content_version(content: &str) -> String {
+    Sha256::digest(content.as_bytes()).iter().take(6).map(|b| format!("{:02x}", b)).collect()
+}
+
+// Version of a knowledge base the bot currently answers from: the loaded index when
+// retrieval is on, otherwise the file on disk (which is re-read for every reply).
+pub fn version(variant: Option<&str>) -> Result<String> {
+    if let Some(index) = INDEXES.lock().ok().and_then(|indexes| indexes.get(&variant.map(String::from)).cloned()) {
+        return Ok(index.version.clone());
+    }
+    Ok(content_version(&read_source(variant)?))
+}
+
+// How long ago the knowledge base file was last modified.
+pub fn age() -> Option<std::time::Duration> {
+    let modified = fs::metadata(knowledge_base_path()).ok()?.modified().ok()?;
+    SystemTime::now().duration_since(modified).ok()
+}
+
+// Knowledge base text for a prompt, with the chunks it was retrieved from.
+#[derive(Debug, Default)]
+pub struct Knowledge {
+    pub text: String,
+    // Empty when the whole knowledge base is included
+    pub sources: Vec<KbSource>,
+}
+
+#[derive(Debug, Clone)]
+pub struct KbSource {
+    // The chunk's "Source:" line (a pubky:// URI or URL) when it has one, otherwise its title
+    pub reference: String,
+    pub title: String,
+    // Content hash of the chunk, as in the index
+    pub hash: String,
+    // Similarity of the chunk to the query
+    pub score: f32,
+}
+
+// Returns the knowledge base text to include in the prompt for `query`, from the
+// variant in the reply language when there is one.
+pub async fn knowledge_for(query: &str, language: Option<&str>) -> Result<String> {
+    Ok(retrieve(query, language).await?.text)
+}
+
+// Like `knowledge_for`, also returning which chunks were retrieved.
+pub async fn retrieve(query: &str, language: Option<&str>) -> Result<Knowledge> {
+    let variant = variant_for(query, language);
+    if !is_retrieval_enabled() {
+        return Ok(Knowledge { text: read_source(variant.as_deref())?, sources: Vec::new() });
+    }
+
+    let index = loaded_index(variant).await?;
+    let top_k = env::var("KB_TOP_K").ok().and_then(|v| v.parse().ok()).unwrap_or(4);
+    let query_embedding = embed(query).await?;
+
+    let mut scored: Vec<(f32, &KbChunk)> = index
+        .chunks
+        .iter()
+        .map(|chunk| (cosine_similarity(&query_embedding, &chunk.embedding), chunk))
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.truncate(top_k);
+
+    Ok(Knowledge {
+        text: scored.iter().map(|(_, chunk)| format!("### {}\n{}", chunk.title, chunk.text)).collect::<Vec<_>>().join("\n\n"),
+        sources: scored.iter().map(|(score, chunk)| KbSource { reference: chunk.reference(), title: chunk.title.clone(), hash: chunk.hash.clone(), score: *score }).collect(),
+    })
+}
+
+impl KbChunk {
+    fn reference(&self) -> String {
+        self.text
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("Source:").map(|source| source.trim().to_string()))
+            .filter(|source| !source.is_empty())
+            .unwrap_or_else(|| self.title.clone())
+    }
+}
+
+// The index of a knowledge base, built on first use.
+async fn loaded_index(variant: Option<String>) -> Result<Arc<KnowledgeIndex>> {
+    if let Some(index) = INDEXES.lock().ok().and_then(|indexes| indexes.get(&variant).cloned()) {
+        return Ok(index);
+    }
+    let index = Arc::new(build_index(variant.as_deref()).await?);
+    if let Ok(mut indexes) = INDEXES.lock() {
+        indexes.insert(variant, index.clone());
+    }
+    Ok(index)
+}
+
+// Chunks the knowledge base and embeds every chunk not already in the cached index,
+// in as few batched requests as possible.
+pub async fn build_index(variant: Option<&str>) -> Result<KnowledgeIndex> {
+    let content = read_source(variant)?;
+    let chunks = chunk_text(&content);
+
+    let mut cached: HashMap<String, Vec<f32>> = HashMap::new();
+    if let Ok(existing) = fs::read_to_string(index_path(variant)) {
+        if let Ok(index) = serde_json::from_str::<KnowledgeIndex>(&existing) {
+            if index.model == embedding_model() {
+                cached = index.chunks.into_iter().map(|c| (c.hash, c.embedding)).collect();
+            }
+        }
+    }
+
+    let missing: Vec<String> = chunks
+        .iter()
+        .filter(|(_, _, hash)| !cached.contains_key(hash))
+        .map(|(title, text, _)| format!("{}\n{}", title, text))
+        .collect();
+    let name = variant.map_or_else(|| "Knowledge base".to_string(), |language| format!("{} knowledge base", lang::name(language)));
+    log!("{} has {} chunks, {} need embedding", name, chunks.len(), missing.len());
+    let mut fresh = embed_batch(&missing).await?.into_iter();
+
+    let mut index = KnowledgeIndex {
+        model: embedding_model(),
+        version: content_version(&content),
+        chunks: Vec::with_capacity(chunks.len()),
+    };
+    for (title, text, hash) in chunks {
+        let embedding = match cached.get(&hash).cloned() {
+            Some(embedding) => embedding,
+            None => fresh.next().ok_or_else(|| anyhow::anyhow!("Missing embedding for chunk {}", hash))?,
+        };
+        index.chunks.push(KbChunk { hash, title, text, embedding });
+    }
+
+    fs::write(index_path(variant), serde_json::to_string(&index)?)?;
+    Ok(index)
+}
+
+// Splits the knowledge base into (title, text, hash) chunks along headings and file
+// markers, keeping each chunk under MAX_CHUNK_CHARS.
+fn chunk_text(content: &str) -> Vec<(String, String, String)> {
+    let mut chunks = Vec::new();
+    let mut title = String::from("Knowledge base");
+    let mut current = String::new();
+
+    let mut flush = |title: &str, current: &mut String| {
+        let text = current.trim().to_string();
+        if !text.is_empty() {
+            let hash = Sha256::digest(format!("{}\n{}", title, text).as_bytes())
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect();
+            chunks.push((title.to_string(), text, hash));
+        }
+        current.clear();
+    };
+
+    for raw_line in content.lines() {
+        let line = strip_line_number(raw_line);
+        let trimmed = line.trim();
+        if !trimmed.is_empty() && trimmed.chars().all(|c| c == '-' || c == '=') {
+            continue;
+        }
+        let heading = trimmed.strip_prefix('#').map(|h| h.trim_start_matches('#').trim());
+        let file_marker = trimmed.strip_suffix(".md:").filter(|_| trimmed.starts_with('/'));
+        if let Some(new_title) = heading.or(file_marker) {
+            flush(&title, &mut current);
+            title = new_title.to_string();
+            continue;
+        }
+        if current.len() + line.len() > MAX_CHUNK_CHARS && trimmed.is_empty() {
+            flush(&title, &mut current);
+            continue;
+        }
+        current.push_str(line);
+        current.push('\n');
+        if current.len() > MAX_CHUNK_CHARS * 2 {
+            flush(&title, &mut current);
+        }
+    }
+    flush(&title, &mut current);
+    chunks
+}
+
+// Exported docs prefix every line with "  12 | "; drop that so it isn't embedded.
+fn strip_line_number(line: &str) -> &str {
+    match line.split_once(" |") {
+        Some((number, rest)) if !number.trim().is_empty() && number.trim().chars().all(|c| c.is_ascii_digit()) => {
+            rest.strip_prefix(' ').unwrap_or(rest)
+        }
+        _ => line,
+    }
+}
@@ -0,0 +1,83 @@
+use crate::memory::now_millis;
+use pubky::{Client, Keypair, PublicKey};
+use serde::Serialize;
+use std::env;
+use std::sync::Mutex;
+use tokio::time::Duration;
+
+// Outcome of the PKARR keep-alive since startup, for the admin API's /status.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct KeepaliveStats {
+    pub homeserver: Option<String>,
+    pub attempts: u64,
+    pub failures: u64,
+    // Last time the record was confirmed published (unix ms)
+    pub last_success_at: Option<i64>,
+    pub last_error: Option<String>,
+}
+
+static STATS: Mutex<KeepaliveStats> =
+    Mutex::new(KeepaliveStats { homeserver: None, attempts: 0, failures: 0, last_success_at: None, last_error: None });
+
+pub fn stats() -> KeepaliveStats {
+    STATS.lock().map(|stats| stats.clone()).unwrap_or_default()
+}
+
+// PKARR_REPUBLISH_INTERVAL (minutes, default 60; 0 disables the keep-alive).
+fn interval() -> Option<Duration> {
+    let minutes = env::var("PKARR_REPUBLISH_INTERVAL").ok().and_then(|v| v.parse::<u64>().ok()).unwrap_or(60);
+    (minutes > 0).then(|| Duration::from_secs(minutes * 60))
+}
+
+// Republishes the bot's PKARR record on an interval, so its key keeps resolving to
+// its homeserver even when it goes a long time without signing in. DHT nodes drop
+// records after a few hours, and the client only refreshes it on sign-in.
+pub async fn spawn(client: Client, keypair: Keypair) {
+    let Some(interval) = interval() else {
+        return;
+    };
+    // The homeserver is remembered from the first lookup, so a record that did expire
+    // can still be restored; PKARR_HOMESERVER overrides it
+    let configured = env::var("PKARR_HOMESERVER").ok().filter(|v| !v.trim().is_empty());
+    let homeserver = match configured {
+        Some(host) => Some(host.trim().to_string()),
+        None => client.get_homeserver(&keypair.public_key()).await,
+    };
+    if let Ok(mut stats) = STATS.lock() {
+        stats.homeserver = homeserver.clone();
+    }
+    let Some(homeserver) = homeserver else {
+        log!("PKARR keep-alive disabled: the bot's homeserver could not be resolved (set PKARR_HOMESERVER)");
+        return;
+    };
+    let host = match PublicKey::try_from(homeserver.as_str()) {
+        Ok(host) => host,
+        Err(e) => {
+            log!("PKARR keep-alive disabled: {} is not a homeserver public key: {}", homeserver, e);
+            return;
+        }
+    };
+    log!("PKARR keep-alive: republishing the record for {} every {:?}", homeserver, interval);
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            // Publishes only when the current record is older than the client's maximum
+            // record age (an hour), so frequent runs are cheap
+            let result = client.republish_homeserver(&keypair, &host).await;
+            let Ok(mut stats) = STATS.lock() else { continue };
+            stats.attempts += 1;
+            match result {
+                Ok(()) => {
+                    stats.last_success_at = Some(now_millis());
+                    stats.last_error = None;
+                }
+                Err(e) => {
+                    log!("PKARR keep-alive failed: {}", e);
+                    stats.failures += 1;
+                    stats.last_error = Some(e.to_string());
+                }
+            }
+        }
+    });
+}
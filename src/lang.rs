@@ -0,0 +1,37 @@
+// Languages the bot can recognize from a user's posts: (code, name, common words).
+const LANGUAGES: [(&str, &str, &[&str]); 6] = [
+    ("en", "English", &["the", "and", "is", "are", "you", "what", "how", "of", "to", "it", "that", "this", "with", "can", "does", "why"]),
+    ("pt", "Portuguese", &["não", "que", "é", "como", "você", "para", "com", "uma", "os", "isso", "muito", "qual", "por", "mais", "está", "então"]),
+    ("es", "Spanish", &["que", "el", "los", "es", "cómo", "para", "con", "una", "por", "qué", "del", "pero", "está", "muy", "esto", "usted"]),
+    ("fr", "French", &["le", "les", "est", "que", "vous", "pour", "avec", "une", "des", "et", "pas", "c'est", "comment", "je", "mais", "pourquoi"]),
+    ("de", "German", &["der", "die", "das", "und", "ist", "nicht", "wie", "ich", "du", "mit", "für", "ein", "eine", "auf", "was", "warum"]),
+    ("it", "Italian", &["il", "che", "è", "non", "per", "con", "una", "come", "sono", "della", "gli", "perché", "questo", "anche", "cosa", "mi"]),
+];
+
+// Fewest common-word hits before a guess is trusted.
+const MIN_HITS: usize = 3;
+
+// Guesses the language of `text` by counting common words. Returns None when the text
+// is too short or too mixed to tell.
+pub fn detect(text: &str) -> Option<&'static str> {
+    let words: Vec<String> = text
+        .split_whitespace()
+        .filter(|w| !w.starts_with("pubky://") && !w.starts_with("pk:") && !w.starts_with('!'))
+        .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric() && c != '\'').to_lowercase())
+        .collect();
+
+    let mut scores: Vec<(usize, &'static str)> = LANGUAGES
+        .iter()
+        .map(|(code, _, common)| (words.iter().filter(|w| common.contains(&w.as_str())).count(), *code))
+        .collect();
+    scores.sort_by_key(|(hits, _)| std::cmp::Reverse(*hits));
+    match scores.as_slice() {
+        [(best, code), (second, _), ..] if *best >= MIN_HITS && best > second => Some(code),
+        _ => None,
+    }
+}
+
+// Human-readable name for a language code, falling back to the code itself.
+pub fn name(code: &str) -> &str {
+    LANGUAGES.iter().find(|(c, _, _)| c.eq_ignore_ascii_case(code)).map_or(code, |(_, name, _)| name)
+}
@@ -0,0 +1,49 @@
+use crate::analytics::AnalyticsRow;
+use crate::memory::MemoryDb;
+use std::collections::HashMap;
+use std::env;
+
+pub const WEEK_MILLIS: i64 = 7 * 24 * 60 * 60 * 1000;
+
+const DEFAULT_TEMPLATE: &str = "🏆 This week's top chatters with AI Rand:\n\n{entries}\n\nThanks for all the great conversations! (Reply `!leaderboard optout` to be left out.)";
+
+// Weekly leaderboard post, enabled with LEADERBOARD=true.
+pub fn is_enabled() -> bool {
+    env::var("LEADERBOARD").map(|v| v == "true").unwrap_or(false)
+}
+
+// Counts mentions per user since `since` (unix ms), skipping users who opted out,
+// and renders LEADERBOARD_TEMPLATE. Returns None when nobody qualifies.
+pub fn build_post(rows: &[AnalyticsRow], memory: &MemoryDb, since: i64) -> Option<String> {
+    let size = env::var("LEADERBOARD_SIZE").ok().and_then(|v| v.parse().ok()).unwrap_or(5);
+
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for row in rows.iter().filter(|r| r.event == "mention" && r.timestamp >= since) {
+        if let Some(user) = row.user.as_deref() {
+            if !memory.leaderboard_opt_out(user) {
+                *counts.entry(user).or_default() += 1;
+            }
+        }
+    }
+    if counts.is_empty() {
+        return None;
+    }
+
+    let mut ranked: Vec<(&str, usize)> = counts.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    let medals = ["🥇", "🥈", "🥉"];
+    let entries = ranked
+        .iter()
+        .take(size)
+        .enumerate()
+        .map(|(i, (user, count))| {
+            let place = medals.get(i).map(|m| m.to_string()).unwrap_or_else(|| format!("{}.", i + 1));
+            let noun = if *count == 1 { "mention" } else { "mentions" };
+            format!("{} pk:{} ({} {})", place, user, count, noun)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let template = env::var("LEADERBOARD_TEMPLATE").ok().filter(|t| !t.trim().is_empty()).unwrap_or_else(|| DEFAULT_TEMPLATE.to_string());
+    Some(template.replace("\\n", "\n").replace("{entries}", &entries).replace("{count}", &ranked.len().to_string()))
+}
@@ -0,0 +1,138 @@
+use crate::error::BotResult;
+use crate::homeserver::{get_file, put_file};
+use crate::memory::now_millis;
+use pubky::{Client, Keypair};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+// How long after writing its lease an instance reads it back, so of two instances
+// taking a free lease at once only the last writer goes on.
+const CONFIRM_DELAY: Duration = Duration::from_secs(2);
+// A leader stops this long before its lease expires, to absorb clock differences.
+const SAFETY_MARGIN_MILLIS: i64 = 5_000;
+
+// Until when (unix ms) this instance may act as the bot; 0 when it isn't the leader.
+static LEADING_UNTIL: AtomicI64 = AtomicI64::new(0);
+
+// The lease object on the homeserver: which instance runs the bot and until when.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Lease {
+    holder: String,
+    expires_at: i64,
+}
+
+// Unless INSTANCE_LEASE=false, only one instance with the bot's keypair handles
+// notifications at a time: the one holding the lease. Others idle until it expires.
+pub fn is_enabled() -> bool {
+    env::var("INSTANCE_LEASE").map(|v| v != "false").unwrap_or(true)
+}
+
+fn ttl_millis() -> i64 {
+    env::var("INSTANCE_LEASE_TTL_SECS").ok().and_then(|v| v.parse::<i64>().ok()).unwrap_or(60).max(15) * 1000
+}
+
+fn lock_path() -> String {
+    env::var("INSTANCE_LOCK_FILE").unwrap_or_else(|_| "bot.lock".to_string())
+}
+
+// This process's name in the lease and lockfile.
+fn instance_id() -> &'static str {
+    static ID: OnceLock<String> = OnceLock::new();
+    ID.get_or_init(|| {
+        let host = env::var("HOSTNAME").unwrap_or_else(|_| "local".to_string());
+        format!("{}-{}-{}", host, std::process::id(), now_millis())
+    })
+}
+
+fn lease_url(keypair: &Keypair) -> String {
+    format!("pubky://{}/pub/ai-rand/lease.json", keypair.public_key())
+}
+
+pub fn is_leader() -> bool {
+    !is_enabled() || now_millis() < LEADING_UNTIL.load(Ordering::Relaxed)
+}
+
+// The local lockfile's holder when another process on this machine renewed it within
+// the lease TTL. Leaders rewrite it on every renewal, so a crashed one goes stale.
+fn local_holder() -> Option<String> {
+    let path = lock_path();
+    let holder = fs::read_to_string(&path).ok()?.trim().to_string();
+    let age = fs::metadata(&path).ok()?.modified().ok()?.elapsed().unwrap_or_default();
+    (holder != instance_id() && (age.as_millis() as i64) < ttl_millis()).then_some(holder)
+}
+
+async fn read_lease(client: &Client, keypair: &Keypair) -> BotResult<Option<Lease>> {
+    Ok(get_file(client, &lease_url(keypair)).await?.and_then(|body| serde_json::from_slice(&body).ok()))
+}
+
+// Takes or renews the lease. Returns the other holder when it's not ours.
+async fn renew(client: &Client, keypair: &Keypair) -> BotResult<Result<i64, String>> {
+    if let Some(holder) = local_holder() {
+        return Ok(Err(holder));
+    }
+    let now = now_millis();
+    let leading = now < LEADING_UNTIL.load(Ordering::Relaxed);
+    if let Some(lease) = read_lease(client, keypair).await? {
+        if lease.holder != instance_id() && lease.expires_at > now {
+            return Ok(Err(lease.holder));
+        }
+    }
+    let lease = Lease { holder: instance_id().to_string(), expires_at: now + ttl_millis() };
+    put_file(client, &lease_url(keypair), serde_json::to_vec(&lease).unwrap_or_default()).await?;
+    if let Err(e) = fs::write(lock_path(), instance_id()) {
+        log!("Failed to write the instance lockfile {}: {}", lock_path(), e);
+    }
+    if !leading {
+        tokio::time::sleep(CONFIRM_DELAY).await;
+        if let Some(current) = read_lease(client, keypair).await? {
+            if current.holder != instance_id() {
+                return Ok(Err(current.holder));
+            }
+        }
+    }
+    Ok(Ok(lease.expires_at))
+}
+
+// Keeps renewing the lease in the background, a third of the TTL apart. While the
+// homeserver is unreachable the leader keeps going until its lease runs out, since no
+// one else can take it before then.
+pub fn spawn(client: Client, keypair: Keypair) {
+    if !is_enabled() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut idle_reported = None;
+        loop {
+            match renew(&client, &keypair).await {
+                Ok(Ok(expires_at)) => {
+                    if !is_leader() {
+                        log!("Holding the instance lease as {}", instance_id());
+                    }
+                    LEADING_UNTIL.store(expires_at - SAFETY_MARGIN_MILLIS, Ordering::Relaxed);
+                    idle_reported = None;
+                }
+                Ok(Err(holder)) => {
+                    LEADING_UNTIL.store(0, Ordering::Relaxed);
+                    if idle_reported.as_ref() != Some(&holder) {
+                        log!("Another instance ({}) is running the bot; idling until its lease expires", holder);
+                        idle_reported = Some(holder);
+                    }
+                }
+                Err(e) => log!("Failed to renew the instance lease: {}", e),
+            }
+            tokio::time::sleep(Duration::from_millis(ttl_millis() as u64 / 3)).await;
+        }
+    });
+}
+
+// Waits until this instance holds the lease.
+pub async fn wait_until_leader() {
+    while !is_leader() {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
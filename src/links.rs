@@ -0,0 +1,123 @@
+use pubky_app_specs::{PubkyAppPostEmbed, PubkyAppPostKind};
+use std::env;
+use std::time::Duration;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Default)]
+pub struct LinkPreview {
+    pub title: Option<String>,
+    pub description: Option<String>,
+}
+
+// How much of a page is read for its metadata, from LINK_MAX_PAGE_KB. Metadata lives
+// in the <head>, so there is no need to read whole pages.
+fn max_page_bytes() -> usize {
+    env::var("LINK_MAX_PAGE_KB").ok().and_then(|v| v.parse::<usize>().ok()).filter(|n| *n > 0).unwrap_or(256) * 1024
+}
+
+// Link embeds on the bot's posts, on unless LINK_EMBEDS=false.
+pub fn is_enabled() -> bool {
+    env::var("LINK_EMBEDS").map(|v| v != "false").unwrap_or(true)
+}
+
+// First http(s) URL in the text, without trailing punctuation.
+pub fn first_url(content: &str) -> Option<&str> {
+    content
+        .split_whitespace()
+        .find(|w| w.starts_with("https://") || w.starts_with("http://"))
+        .map(|w| w.trim_start_matches(['(', '<']).trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '>', '"', '\'']))
+}
+
+// Builds a Link embed for the first URL in a post so clients show a preview card.
+// Pubky embeds only carry the URL; the page is fetched to make sure it exists, so a
+// made-up or dead link is left as plain text.
+pub async fn embed_for(content: &str) -> Option<PubkyAppPostEmbed> {
+    if !is_enabled() {
+        return None;
+    }
+    let url = first_url(content)?;
+    match fetch_preview(url).await {
+        Some(preview) => {
+            log!(
+                "Embedding link {} ({})",
+                url,
+                preview.title.as_deref().or(preview.description.as_deref()).unwrap_or("no title")
+            );
+            Some(PubkyAppPostEmbed { kind: PubkyAppPostKind::Link, uri: url.to_string() })
+        }
+        None => {
+            log!("Not embedding {}: the page could not be loaded", url);
+            None
+        }
+    }
+}
+
+// Fetches the page's title and description; None when it doesn't load.
+pub async fn fetch_preview(url: &str) -> Option<LinkPreview> {
+    let client = reqwest::Client::builder().timeout(FETCH_TIMEOUT).user_agent("ai-rand link preview").build().ok()?;
+    let mut response = client.get(url).send().await.ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+    let is_html = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("html"));
+    if !is_html {
+        return Some(LinkPreview::default());
+    }
+
+    let max_bytes = max_page_bytes();
+    let mut page = Vec::new();
+    while let Ok(Some(chunk)) = response.chunk().await {
+        page.extend_from_slice(&chunk[..chunk.len().min(max_bytes - page.len())]);
+        if page.len() >= max_bytes {
+            break;
+        }
+    }
+    let page = String::from_utf8_lossy(&page);
+    Some(LinkPreview {
+        title: meta_content(&page, "og:title").or_else(|| title_tag(&page)),
+        description: meta_content(&page, "og:description").or_else(|| meta_content(&page, "description")),
+    })
+}
+
+// Value of <meta property|name="{name}" content="...">.
+fn meta_content(page: &str, name: &str) -> Option<String> {
+    let lower = page.to_ascii_lowercase();
+    let mut search = 0;
+    while let Some(start) = lower[search..].find("<meta") {
+        let start = search + start;
+        let end = start + lower[start..].find('>')?;
+        let tag = &page[start..end];
+        let tag_lower = &lower[start..end];
+        if tag_lower.contains(&format!("property=\"{}\"", name)) || tag_lower.contains(&format!("name=\"{}\"", name)) {
+            let value_start = tag_lower.find("content=\"")? + "content=\"".len();
+            let value_end = value_start + tag[value_start..].find('"')?;
+            return clean(&tag[value_start..value_end]);
+        }
+        search = end;
+    }
+    None
+}
+
+fn title_tag(page: &str) -> Option<String> {
+    let lower = page.to_ascii_lowercase();
+    let start = lower.find("<title")?;
+    let start = start + lower[start..].find('>')? + 1;
+    let end = start + lower[start..].find("</title>")?;
+    clean(&page[start..end])
+}
+
+fn clean(text: &str) -> Option<String> {
+    let text = text
+        .replace("&amp;", "&")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">");
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!text.is_empty()).then_some(text)
+}
@@ -0,0 +1,437 @@
+use crate::analytics::{self, AnalyticsRow};
+use crate::budget;
+use crate::chaos;
+use crate::citations;
+use crate::commands::format_prefs;
+use crate::context::PromptVars;
+use crate::critique;
+use crate::guardrails::{self, Guardrail, Violation};
+use crate::error::{BotError, BotResult};
+use crate::examples::Example;
+use crate::footer;
+use crate::homeserver::max_post_chars;
+use crate::http;
+use crate::injection;
+use crate::kb;
+use crate::lang;
+use crate::llm_queue::{self, Priority};
+use crate::models;
+use crate::post_kind::{self, Purpose};
+use crate::privacy;
+use crate::prompt;
+use crate::race;
+use crate::secrets::{self, Secret};
+use crate::shadow::Candidate;
+use crate::throttle;
+use crate::tools;
+use anyhow::Result;
+use pubky_app_specs::PubkyAppPostKind;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::env;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    pub temperature: f32,
+    pub max_tokens: i32,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponse {
+    choices: Vec<Choice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Choice {
+    message: Message,
+}
+
+#[derive(Debug, Deserialize)]
+struct Message {
+    content: String,
+}
+
+// Per-request additions to the system prompt.
+#[derive(Debug, Default)]
+pub struct PromptContext<'a> {
+    pub prefs: Option<&'a BTreeMap<String, String>>,
+    pub instruction: Option<&'a str>,
+    // Template variables; only date, time and persona are filled when unset
+    pub vars: Option<&'a PromptVars>,
+    // Earlier turns of the thread, oldest first
+    pub history: &'a [ChatMessage],
+    // Pinned reply language code for this user
+    pub language: Option<&'a str>,
+    // Well-rated past replies to similar questions
+    pub examples: &'a [Example],
+    pub priority: Priority,
+    // Prompt, model or persona under shadow evaluation, replacing the live ones
+    pub candidate: Option<&'a Candidate>,
+    // Reply length limit for this user, when it isn't reply_max_chars()
+    pub max_chars: Option<usize>,
+    // Other users the post mentions, one per line (see mentions.rs)
+    pub people: Option<&'a str>,
+}
+
+// URL of an OpenAI API endpoint, e.g. `openai_url("embeddings")`. OPENAI_BASE_URL points
+// the bot at another OpenAI-compatible server, such as the mock used by `bench`.
+pub fn openai_url(path: &str) -> String {
+    let base = env::var("OPENAI_BASE_URL").ok().filter(|u| !u.is_empty()).unwrap_or_else(|| "https://api.openai.com/v1".to_string());
+    format!("{}/{}", base.trim_end_matches('/'), path)
+}
+
+// Reply length limit in characters: the spec's limit for short posts, or
+// REPLY_MAX_CHARS when set. A higher limit is capped at what the post kind policy can
+// publish, as a thread or a long post.
+pub fn reply_max_chars() -> usize {
+    let spec_limit = max_post_chars(PubkyAppPostKind::Short);
+    let limit = env::var("REPLY_MAX_CHARS")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|max| *max > 0)
+        .map_or(spec_limit, |max| max.min(post_kind::max_chars(Purpose::Reply).max(spec_limit)));
+    // The footer is added after generation, so the reply itself gets what's left
+    limit.saturating_sub(footer::reserved_chars()).max(1)
+}
+
+pub async fn generate_response(content: &str, context: &PromptContext<'_>) -> Result<String> {
+    let knowledge = kb::retrieve(content, context.language).await?;
+    let knowledge_base = knowledge.text.clone();
+    let sources = citations::line(&knowledge.sources);
+
+    // The sources line is added under the reply, so the reply gets what's left
+    let max_chars = context.max_chars.unwrap_or_else(reply_max_chars).saturating_sub(citations::reserved_chars(sources.as_deref())).max(1);
+    let mut vars = context.vars.cloned().unwrap_or_else(PromptVars::basic);
+    if let Some(persona) = context.candidate.and_then(|c| c.persona.clone()) {
+        vars.persona = persona;
+    }
+    // Per-request rules appended to the template
+    let mut extra = String::new();
+    if let Some(prefs) = context.prefs {
+        extra.push_str(&format!("\n\nThis user has set the following reply preferences. Follow them unless they conflict with the rules above: {}", format_prefs(prefs)));
+    }
+    if let Some(language) = context.language {
+        extra.push_str(&format!("\n\nLANGUAGE: Always reply to this user in {}, whatever language the post or thread is in, unless the task below asks for a specific language. This overrides the default-language rule above.", lang::name(language)));
+    }
+    if let Some(language) = kb::translation_target(content, context.language) {
+        extra.push_str(&format!("\n\nThe knowledge base above is written in {}; translate whatever you use from it into {}.", lang::name(&kb::default_language()), lang::name(&language)));
+    }
+    if let Some(people) = context.people {
+        let author = context.vars.map(|v| v.user_name.as_str()).filter(|name| !name.is_empty() && *name != "there").unwrap_or("the user");
+        extra.push_str(&format!("\n\nPEOPLE: The post was written by {}, who you are replying to. It also mentions these users; when the question is about one of them, answer about that person, and when it asks you to tell them something, address them by name:\n{}", author, people));
+    }
+    if let Some(instruction) = context.instruction {
+        extra.push_str(&format!("\n\nTASK FOR THIS REPLY: {}", instruction));
+    }
+    if !context.examples.is_empty() {
+        extra.push_str("\n\nEXAMPLES: Replies you gave to similar questions that people found helpful. Match their tone and level of detail, but answer the new question on its own terms and don't copy them:");
+        for example in context.examples {
+            extra.push_str(&format!("\n\nQuestion: {}\nReply: {}", example.prompt, example.reply));
+        }
+    }
+    if !context.history.is_empty() {
+        extra.push_str("\n\nYou already took part in this thread; the earlier messages follow. Stay consistent with your previous replies and don't repeat them.");
+    }
+    let guarded = injection::is_enabled();
+    if guarded {
+        extra.push_str(&injection::instructions());
+    }
+
+    let (template, system_prompt) = match context.candidate.and_then(|c| c.prompt_file.as_deref()) {
+        Some(path) => (prompt::system_prompt_from(path, "", &vars)?, prompt::system_prompt_from(path, &knowledge_base, &vars)? + &extra),
+        None => (prompt::system_prompt("", &vars)?, prompt::system_prompt(&knowledge_base, &vars)? + &extra),
+    };
+
+    let mut messages = vec![ChatMessage {
+        role: "system".to_string(),
+        content: system_prompt,
+    }];
+    // Thread messages from users are as untrusted as the post itself
+    messages.extend(context.history.iter().map(|message| match message.role.as_str() {
+        "user" if guarded => ChatMessage { role: message.role.clone(), content: injection::wrap(&message.content) },
+        _ => message.clone(),
+    }));
+    messages.push(ChatMessage {
+        role: "user".to_string(),
+        content: if guarded { injection::wrap(content) } else { content.to_string() },
+    });
+
+    let request = ChatRequest {
+        model: models::resolve(context.candidate.and_then(|c| c.model.as_deref()).unwrap_or("default")),
+        messages,
+        temperature: 0.7,
+        // The reply is written in the language of the post
+        max_tokens: llm_queue::completion_tokens(max_chars, content),
+    };
+
+    record_generation(&request, &knowledge.sources, context);
+
+    // Checked while streaming; tool calls and race mode need whole responses, so there
+    // the final answer is checked once it arrives
+    let mut guard = Guardrail::new(max_chars, &template);
+    let violation = if tools::is_enabled() {
+        let content = tools::complete(&request, context.priority).await?;
+        guard.feed(&content)
+    } else if race::is_enabled() {
+        let content = race::complete(&request, context.priority).await?;
+        guard.feed(&content)
+    } else {
+        guardrails::stream_completion(&request, context.priority, &mut guard).await?
+    };
+
+    let draft: String = match violation {
+        None => {
+            log!("Response length: {} characters", guard.text().chars().count());
+            guard.text().to_string()
+        }
+        // A reply past the limit would have to be cut mid-sentence, so it gets the
+        // fallback like any other violation
+        Some(violation) => {
+            if violation == Violation::TooLong {
+                log!("Warning: Response exceeded {} characters despite instructions!", max_chars);
+            }
+            analytics::record(AnalyticsRow::new("guardrail_abort", None, None).with("reason", violation.reason()));
+            return Ok(guardrails::fallback_reply(context.language));
+        }
+    };
+    if !critique::is_enabled() {
+        return Ok(citations::append(draft, sources.as_deref()));
+    }
+
+    let rules = format!("{}{}", template, extra);
+    let reply = match critique::revise(content, &draft, &knowledge_base, &rules, max_chars, context.priority).await {
+        Ok(revised) => {
+            // The revision goes through the same checks; a failing one keeps the draft
+            let mut guard = Guardrail::new(max_chars, &template);
+            match guard.feed(&revised) {
+                None => revised,
+                Some(violation) => {
+                    log!("Revision failed the guardrails ({:?}), keeping the draft", violation);
+                    draft
+                }
+            }
+        }
+        Err(e) => {
+            log!("Self-critique failed, keeping the draft: {}", e);
+            draft
+        }
+    };
+    Ok(citations::append(reply, sources.as_deref()))
+}
+
+// Records what a reply is generated from as a `generation` analytics row, joined to the
+// `reply` row by correlation ID: the knowledge base chunks retrieved with their
+// similarity scores, and a hash of the exact prompt sent, to debug why the bot answered
+// the way it did and tune chunking and retrieval on real questions.
+fn record_generation(request: &ChatRequest, sources: &[kb::KbSource], context: &PromptContext<'_>) {
+    let prompt: String = request.messages.iter().map(|m| format!("{}\n{}\n", m.role, m.content)).collect();
+    let chunks: Vec<Value> = sources
+        .iter()
+        .map(|source| serde_json::json!({ "title": source.title, "reference": source.reference, "hash": source.hash, "score": source.score }))
+        .collect();
+    let mut row = AnalyticsRow::new("generation", None, None)
+        .with("model", request.model.as_str())
+        .with("prompt_hash", kb::content_version(&prompt))
+        .with("prompt_version", prompt::version())
+        .with("retrieval", kb::is_retrieval_enabled())
+        .with("chunks", chunks);
+    if let Some(candidate) = context.candidate {
+        row = row.with("candidate", candidate.label());
+    }
+    analytics::record(row);
+}
+
+// Sends a chat completion request through the rate-limited queue and returns the
+// first choice's text.
+pub async fn chat_completion(request: &ChatRequest, priority: Priority) -> BotResult<String> {
+    let prompt_chars = request.messages.iter().map(|m| m.content.len()).sum();
+    let body = serde_json::to_value(request).map_err(BotError::llm)?;
+    let response = send_chat(&body, priority, llm_queue::estimate_tokens(prompt_chars, request.max_tokens)).await?;
+    let chat_response: ChatResponse =
+        serde_json::from_value(response).map_err(|e| BotError::Llm(format!("Unexpected OpenAI response: {}", e)))?;
+    chat_response
+        .choices
+        .into_iter()
+        .next()
+        .map(|choice| choice.message.content)
+        .ok_or_else(|| BotError::Llm("OpenAI response contained no choices".to_string()))
+}
+
+// Posts a raw chat completion body once the queue has room for `tokens` and returns
+// the response JSON.
+pub async fn send_chat(body: &Value, priority: Priority, tokens: usize) -> BotResult<Value> {
+    let response = post_chat(body, priority, tokens).await?;
+    let status = response.status();
+    let response_text = response.text().await.map_err(BotError::llm)?;
+    log!("OpenAI API response body: {}", secrets::scrub(&privacy::content(&response_text)));
+    
+    serde_json::from_str(&response_text).map_err(|e| BotError::Llm(format!("Unexpected OpenAI response ({}): {}", status, e)))
+}
+
+// Sends a chat completion request and returns the successful response, unread so it
+// can also be streamed. A 429 or 503 pauses the whole queue for as long as the
+// provider asks and retries, up to LLM_MAX_RETRIES times.
+pub async fn post_chat(body: &Value, priority: Priority, tokens: usize) -> BotResult<reqwest::Response> {
+    if let Some(delay) = chaos::llm_delay() {
+        log!("Chaos: delaying LLM request by {}ms", delay.as_millis());
+        tokio::time::sleep(delay).await;
+    }
+    budget::take(priority, tokens)?;
+    let api_key = Secret::from_env("OPENAI_API_KEY").ok_or_else(|| BotError::Validation("OPENAI_API_KEY not found in .env".to_string()))?;
+    let client = http::llm();
+    let max_retries = env::var("LLM_MAX_RETRIES").ok().and_then(|v| v.parse().ok()).unwrap_or(3);
+
+    // Switched to the successor when the provider reports the model retired
+    let mut body = Cow::Borrowed(body);
+    let mut attempt: u32 = 0;
+    loop {
+        llm_queue::acquire(priority, tokens).await;
+
+        log!("Sending request to OpenAI API...");
+        let sent_at = Instant::now();
+        let response = client
+            .post(openai_url("chat/completions"))
+            .header("Authorization", format!("Bearer {}", api_key.expose()))
+            .header("Content-Type", "application/json")
+            .json(&*body)
+            .send()
+            .await;
+        throttle::record(sent_at.elapsed(), response.as_ref().is_ok_and(|r| r.status().is_success()));
+        let response = response.map_err(BotError::llm)?;
+
+        let status = response.status();
+        log!("OpenAI API response status: {}", status);
+        if status.is_success() {
+            return Ok(response);
+        }
+        let wait = retry_after(response.headers());
+        let response_text = response.text().await.map_err(BotError::llm)?;
+        log!("OpenAI API response body: {}", secrets::scrub(&privacy::content(&response_text)));
+
+        if models::is_retired_error(status, &response_text) {
+            if let Some(successor) = body["model"].as_str().and_then(models::retire) {
+                body.to_mut()["model"] = Value::from(successor);
+                continue;
+            }
+        }
+
+        let retryable = status == reqwest::StatusCode::TOO_MANY_REQUESTS || status == reqwest::StatusCode::SERVICE_UNAVAILABLE;
+        // An exhausted quota won't come back by waiting
+        if retryable && attempt < max_retries && !response_text.contains("insufficient_quota") {
+            attempt += 1;
+            let wait = wait.unwrap_or_else(|| Duration::from_secs(2u64.pow(attempt))).min(MAX_RETRY_WAIT);
+            log!("OpenAI rate limited the request ({}), retry {} of {} in {:?}", status, attempt, max_retries, wait);
+            analytics::record(
+                AnalyticsRow::new("llm_rate_limited", None, None)
+                    .with("status", status.as_u16())
+                    .with("wait_ms", wait.as_millis() as u64)
+                    .with("attempt", attempt),
+            );
+            llm_queue::pause(wait).await;
+            continue;
+        }
+        return Err(BotError::Llm(format!("OpenAI request failed: {} {}", status, response_text)));
+    }
+}
+
+// Longest the bot waits on a single rate-limit response
+const MAX_RETRY_WAIT: Duration = Duration::from_secs(120);
+
+// How long the provider asked us to wait: `retry-after-ms`, `retry-after` (seconds),
+// or the later of OpenAI's `x-ratelimit-reset-requests` / `-tokens` (e.g. `6m0s`).
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim);
+    if let Some(ms) = header("retry-after-ms").and_then(|v| v.parse::<f64>().ok()) {
+        return wait_secs(ms / 1000.0);
+    }
+    if let Some(secs) = header("retry-after").and_then(|v| v.parse::<f64>().ok()) {
+        return wait_secs(secs);
+    }
+    ["x-ratelimit-reset-requests", "x-ratelimit-reset-tokens"]
+        .iter()
+        .filter_map(|name| header(name).and_then(parse_reset))
+        .max()
+}
+
+// Parses Go-style durations such as `20ms`, `1.5s` or `6m0s`.
+fn parse_reset(value: &str) -> Option<Duration> {
+    let mut total = 0.0;
+    let mut number = String::new();
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() || c == '.' {
+            number.push(c);
+            continue;
+        }
+        let amount: f64 = number.parse().ok()?;
+        number.clear();
+        total += match c {
+            'h' => amount * 3600.0,
+            'm' if chars.peek() == Some(&'s') => {
+                chars.next();
+                amount / 1000.0
+            }
+            'm' => amount * 60.0,
+            's' => amount,
+            _ => return None,
+        };
+    }
+    if !number.is_empty() {
+        return None;
+    }
+    wait_secs(total)
+}
+
+// A wait in seconds from a header, capped at MAX_RETRY_WAIT before the conversion:
+// `Duration::from_secs_f64` panics on values like `inf` or `1e30`.
+fn wait_secs(secs: f64) -> Option<Duration> {
+    if secs.is_nan() {
+        return None;
+    }
+    Some(Duration::from_secs_f64(secs.clamp(0.0, MAX_RETRY_WAIT.as_secs_f64())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    fn wait(headers: &[(&'static str, &'static str)]) -> Option<Duration> {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.insert(*name, HeaderValue::from_static(value));
+        }
+        retry_after(&map)
+    }
+
+    #[test]
+    fn reads_retry_headers() {
+        assert_eq!(wait(&[("retry-after-ms", "1500")]), Some(Duration::from_millis(1500)));
+        assert_eq!(wait(&[("retry-after", "3")]), Some(Duration::from_secs(3)));
+        assert_eq!(wait(&[("x-ratelimit-reset-requests", "1m30s"), ("x-ratelimit-reset-tokens", "20ms")]), Some(Duration::from_secs(90)));
+        assert_eq!(wait(&[("retry-after", "-5")]), Some(Duration::ZERO));
+        assert_eq!(wait(&[]), None);
+    }
+
+    #[test]
+    fn caps_huge_and_invalid_waits() {
+        assert_eq!(wait(&[("retry-after", "inf")]), Some(MAX_RETRY_WAIT));
+        assert_eq!(wait(&[("retry-after", "1e30")]), Some(MAX_RETRY_WAIT));
+        assert_eq!(wait(&[("retry-after-ms", "-inf")]), Some(Duration::ZERO));
+        assert_eq!(wait(&[("retry-after", "NaN")]), None);
+        assert_eq!(wait(&[("x-ratelimit-reset-requests", "99999999999999999999h")]), Some(MAX_RETRY_WAIT));
+        assert_eq!(parse_reset("1.5s"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_reset("5x"), None);
+    }
+}
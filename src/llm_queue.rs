@@ -0,0 +1,312 @@
+use crate::memory::now_millis;
+use crate::throttle;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, VecDeque};
+use std::env;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+// Priority classes for LLM calls, highest first. Replies to people always go
+// ahead of the bot's own scheduled content. They double as the trigger categories
+// with separate spending budgets (see budget.rs).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Priority {
+    #[default]
+    Mention,
+    // Questions the bot answers unasked, in CHANNELS
+    Engagement,
+    Scheduled,
+    Digest,
+}
+
+impl Priority {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Priority::Mention => "mention",
+            Priority::Engagement => "engagement",
+            Priority::Scheduled => "scheduled",
+            Priority::Digest => "digest",
+        }
+    }
+}
+
+// Sliding-window RPM/TPM limiter shared by every chat completion call.
+// Waiting requests are served strictly by (priority, arrival order).
+struct LlmQueue {
+    rpm: usize,
+    tpm: usize,
+    state: Mutex<QueueState>,
+}
+
+#[derive(Default)]
+struct QueueState {
+    // (sent at, estimated tokens) for requests in the last minute
+    sent: VecDeque<(Instant, usize)>,
+    waiting: BTreeSet<(Priority, u64)>,
+    next_ticket: u64,
+    // The provider rate limited us; nothing is sent before this
+    paused_until: Option<Instant>,
+}
+
+// Rate-limit responses from the provider since startup.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RateLimitStats {
+    pub count: u64,
+    pub total_wait_ms: u64,
+    pub last_wait_ms: u64,
+}
+
+static RATE_LIMITS: std::sync::Mutex<RateLimitStats> =
+    std::sync::Mutex::new(RateLimitStats { count: 0, total_wait_ms: 0, last_wait_ms: 0 });
+
+// Requests let through the queue since startup, and how long they waited for it.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct QueueStats {
+    pub requests: u64,
+    pub estimated_tokens: u64,
+    // Requests held back by LLM_RPM / LLM_TPM, a provider pause or a higher priority
+    pub delayed: u64,
+    pub total_delay_ms: u64,
+}
+
+static QUEUE_STATS: std::sync::Mutex<QueueStats> =
+    std::sync::Mutex::new(QueueStats { requests: 0, estimated_tokens: 0, delayed: 0, total_delay_ms: 0 });
+
+pub fn queue_stats() -> QueueStats {
+    QUEUE_STATS.lock().map(|stats| stats.clone()).unwrap_or_default()
+}
+
+// Requests waiting for their turn, including retries after a rate limit.
+static DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+pub fn depth() -> usize {
+    DEPTH.load(Ordering::Relaxed)
+}
+
+fn queue() -> &'static LlmQueue {
+    static QUEUE: OnceLock<LlmQueue> = OnceLock::new();
+    QUEUE.get_or_init(|| {
+        let limit = |name: &str, default: usize| env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default);
+        LlmQueue {
+            rpm: limit("LLM_RPM", 500),
+            tpm: limit("LLM_TPM", 200_000),
+            state: Mutex::new(QueueState::default()),
+        }
+    })
+}
+
+// The rate-limit window as kept in the state snapshot, with wall-clock times (unix ms)
+// since Instants don't survive a restart.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WindowSnapshot {
+    // (sent at, estimated tokens) for requests in the last minute
+    pub sent: Vec<(i64, usize)>,
+    pub paused_until: Option<i64>,
+}
+
+fn to_millis(at: Instant, now: Instant, now_ms: i64) -> i64 {
+    if at >= now {
+        now_ms + at.duration_since(now).as_millis() as i64
+    } else {
+        now_ms - now.duration_since(at).as_millis() as i64
+    }
+}
+
+// None when a request holds the queue at that moment; the next snapshot gets it.
+pub fn snapshot() -> Option<WindowSnapshot> {
+    let state = queue().state.try_lock().ok()?;
+    let (now, now_ms) = (Instant::now(), now_millis());
+    Some(WindowSnapshot {
+        sent: state.sent.iter().map(|(at, tokens)| (to_millis(*at, now, now_ms), *tokens)).collect(),
+        paused_until: state.paused_until.filter(|until| *until > now).map(|until| to_millis(until, now, now_ms)),
+    })
+}
+
+// Puts back the requests of a snapshot still inside the window, and a provider pause
+// that hasn't ended, so a restart doesn't send a minute's worth of requests twice.
+pub fn restore(snapshot: &WindowSnapshot) {
+    let Ok(mut state) = queue().state.try_lock() else { return };
+    let (now, now_ms) = (Instant::now(), now_millis());
+    for (sent_at, tokens) in &snapshot.sent {
+        let age = Duration::from_millis((now_ms - sent_at).max(0) as u64);
+        if age < WINDOW {
+            state.sent.push_back((now.checked_sub(age).unwrap_or(now), *tokens));
+        }
+    }
+    if let Some(until) = snapshot.paused_until.filter(|until| *until > now_ms) {
+        state.paused_until = Some(now + Duration::from_millis((until - now_ms) as u64));
+    }
+}
+
+// Waits until a request of `tokens` estimated tokens can be sent without exceeding
+// LLM_RPM / LLM_TPM (0 disables a limit) and no higher-priority request is waiting.
+pub async fn acquire(priority: Priority, tokens: usize) {
+    let queue = queue();
+    let ticket = {
+        let mut state = queue.state.lock().await;
+        state.next_ticket += 1;
+        let ticket = (priority, state.next_ticket);
+        state.waiting.insert(ticket);
+        DEPTH.fetch_add(1, Ordering::Relaxed);
+        ticket
+    };
+    // A caller can give up while waiting (e.g. the losing side of a race); the
+    // guard then takes the ticket out of line so it can't block everyone behind it
+    let mut guard = WaitingTicket { ticket, served: false };
+    let arrived = Instant::now();
+
+    let mut logged = false;
+    loop {
+        let paused_until = queue.state.lock().await.paused_until.filter(|until| *until > Instant::now());
+        if let Some(until) = paused_until {
+            tokio::time::sleep_until(until).await;
+            continue;
+        }
+        let wait = {
+            let mut state = queue.state.lock().await;
+            let now = Instant::now();
+            while state.sent.front().is_some_and(|(at, _)| now.duration_since(*at) >= WINDOW) {
+                state.sent.pop_front();
+            }
+
+            let used_tokens: usize = state.sent.iter().map(|(_, t)| t).sum();
+            let (rpm, tpm) = (throttle::limit(queue.rpm), throttle::limit(queue.tpm));
+            let rpm_ok = rpm == 0 || state.sent.len() < rpm;
+            // A single request larger than the whole budget is let through on an empty window
+            let tpm_ok = tpm == 0 || used_tokens + tokens <= tpm || state.sent.is_empty();
+            let first = state.waiting.first() == Some(&ticket);
+
+            if first && rpm_ok && tpm_ok {
+                state.waiting.remove(&ticket);
+                DEPTH.fetch_sub(1, Ordering::Relaxed);
+                guard.served = true;
+                state.sent.push_back((now, tokens));
+                if let Ok(mut stats) = QUEUE_STATS.lock() {
+                    stats.requests += 1;
+                    stats.estimated_tokens += tokens as u64;
+                    // Under a millisecond is just the lock, not a wait
+                    let waited = now.duration_since(arrived).as_millis() as u64;
+                    if waited > 0 {
+                        stats.delayed += 1;
+                        stats.total_delay_ms += waited;
+                    }
+                }
+                return;
+            }
+            match state.sent.front() {
+                Some((at, _)) if first => WINDOW.saturating_sub(now.duration_since(*at)),
+                _ => Duration::from_millis(100),
+            }
+        };
+
+        if !logged && wait > Duration::from_millis(100) {
+            log!("LLM rate limit reached, {:?} request waiting {:?}", priority, wait);
+            logged = true;
+        }
+        tokio::time::sleep(wait.clamp(Duration::from_millis(50), Duration::from_secs(1))).await;
+    }
+}
+
+struct WaitingTicket {
+    ticket: (Priority, u64),
+    served: bool,
+}
+
+impl Drop for WaitingTicket {
+    fn drop(&mut self) {
+        if self.served {
+            return;
+        }
+        DEPTH.fetch_sub(1, Ordering::Relaxed);
+        let ticket = self.ticket;
+        match queue().state.try_lock() {
+            Ok(mut state) => {
+                state.waiting.remove(&ticket);
+            }
+            Err(_) => {
+                tokio::spawn(async move {
+                    queue().state.lock().await.waiting.remove(&ticket);
+                });
+            }
+        }
+    }
+}
+
+// Rough token estimate (about 4 characters per token) plus the completion budget.
+pub fn estimate_tokens(prompt_chars: usize, max_tokens: i32) -> usize {
+    prompt_chars / 4 + max_tokens.max(0) as usize
+}
+
+// Rough tokenizer estimate for `text`: English and code run about 4 characters per
+// token, accented Latin, Cyrillic, Greek and Arabic closer to 2, CJK about one token
+// per character and emoji more than one.
+pub fn text_tokens(text: &str) -> f64 {
+    text.chars()
+        .map(|c| match c as u32 {
+            0..=0x7F => 0.25,
+            0x80..=0x6FF => 0.5,
+            0x3040..=0x30FF | 0x3400..=0x9FFF | 0xAC00..=0xD7AF => 1.0,
+            0x1F000.. => 2.0,
+            _ => 0.75,
+        })
+        .sum()
+}
+
+// Extra completion tokens on top of the estimate, so a reply near its character budget
+// isn't cut mid-sentence by the provider; the guardrails enforce the length itself.
+const COMPLETION_HEADROOM: f64 = 1.25;
+
+// `max_tokens` for a reply of up to `max_chars` characters written like `sample` (the
+// post being answered, or a draft), using its tokens per character.
+pub fn completion_tokens(max_chars: usize, sample: &str) -> i32 {
+    let chars = sample.chars().count();
+    let per_char = if chars == 0 { 0.25 } else { (text_tokens(sample) / chars as f64).max(0.25) };
+    ((max_chars as f64 * per_char * COMPLETION_HEADROOM).ceil() as i32).max(50)
+}
+
+// Holds every queued request for `wait` after the provider answered 429, as told by
+// its Retry-After / rate-limit reset headers.
+pub async fn pause(wait: Duration) {
+    let until = Instant::now() + wait;
+    let mut state = queue().state.lock().await;
+    if state.paused_until.is_none_or(|current| current < until) {
+        state.paused_until = Some(until);
+    }
+    drop(state);
+
+    if let Ok(mut stats) = RATE_LIMITS.lock() {
+        stats.count += 1;
+        stats.total_wait_ms += wait.as_millis() as u64;
+        stats.last_wait_ms = wait.as_millis() as u64;
+    }
+}
+
+// Whether the provider's rate limit is currently holding every request back.
+pub async fn is_paused() -> bool {
+    queue().state.lock().await.paused_until.is_some_and(|until| until > Instant::now())
+}
+
+pub fn rate_limit_stats() -> RateLimitStats {
+    RATE_LIMITS.lock().map(|stats| stats.clone()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn window_survives_a_snapshot() {
+        let now = now_millis();
+        restore(&WindowSnapshot { sent: vec![(now - 120_000, 500), (now - 10_000, 700)], paused_until: Some(now + 30_000) });
+        let saved = snapshot().unwrap();
+        // The request from two minutes ago is out of the window
+        assert_eq!(saved.sent.len(), 1);
+        assert_eq!(saved.sent[0].1, 700);
+        assert!((saved.sent[0].0 - (now - 10_000)).abs() < 1000);
+        assert!((saved.paused_until.unwrap() - (now + 30_000)).abs() < 1000);
+    }
+}
@@ -1,70 +1,147 @@
+// println! that prefixes the line with the correlation ID of the notification being
+// handled, if there is one.
+macro_rules! log {
+    ($($arg:tt)*) => {
+        match $crate::correlation::current() {
+            Some(id) => println!("[{}] {}", id, format_args!($($arg)*)),
+            None => println!($($arg)*),
+        }
+    };
+}
+
+mod ack;
+mod activity;
+mod admin;
+mod analytics;
+mod approval;
+mod archive;
+mod attachments;
+mod backup;
+mod bench;
+mod bert;
+mod bots;
+mod broadcasts;
+mod budget;
+mod calendar;
+mod calc;
+mod canned;
+mod catalog;
+mod chaos;
+mod channels;
+mod check;
+mod citations;
+mod cleanup;
+mod commands;
+mod context;
+mod correlation;
+mod critique;
+mod cron;
+mod doctor;
+mod embeddings;
+mod error;
+mod evaluate;
+mod examples;
+mod faq;
+mod follows;
+mod footer;
+mod guardrails;
+mod gzip;
+mod heartbeat;
+mod homeserver;
+mod hooks;
+mod http;
+mod injection;
+mod intake;
+mod jobs;
+mod kb;
+mod keepalive;
+mod lang;
+mod leaderboard;
+mod lease;
+mod links;
+mod llm;
+mod llm_queue;
+mod mentions;
+mod memory;
+mod migrations;
+mod milestones;
+mod mock_llm;
+mod models;
+mod nostr;
+mod notifications;
+mod perks;
+mod permissions;
+mod persona;
+mod profiles;
+mod post_kind;
+mod privacy;
+mod prompt;
+mod provenance;
+mod race;
+mod receipts;
+mod reactions;
+mod repetition;
+mod replay;
+mod retention;
+mod script;
+mod search;
+mod secrets;
+mod selftest;
+mod sentiment;
+mod shadow;
+mod simulate;
+mod slo;
+mod snapshot;
+mod storage;
+mod structured;
+mod stats;
+mod telegram;
+mod thread;
+mod throttle;
+mod timings;
+mod tools;
+mod topics;
+mod tz;
+mod watch;
+mod wikipedia;
+mod writes;
+
+use admin::AdminApi;
+use analytics::AnalyticsRow;
 use anyhow::Result;
+use approval::{is_supervised, OperatorCommand, PendingView};
+use canned::Canned;
+use cleanup::{delete_replies, parse_age, ReplyFilter};
+use commands::{handle_command, parse_command, tag_trigger, CommandContext, CommandReply, Trigger};
 use dotenv::dotenv;
+use homeserver::{fetch_post, get_cached, put_file};
+use hooks::{Hook, HookOutcome};
+use jobs::GenerationJob;
+use llm::{generate_response, PromptContext};
+use llm_queue::Priority;
+use memory::{now_millis, Draft, MemoryDb, ReplyRecord, DAY_MILLIS};
+use nostr::NostrBridge;
+use notifications::{AuthorClass, Notification, NotificationKind};
+use perks::Perks;
+use post_kind::Purpose;
+use profiles::ProfileCache;
+use reactions::ReactionPolicy;
+use retention::RetentionPolicy;
+use secrets::Secret;
+use sentiment::SentimentAction;
+use shadow::ShadowRequest;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use telegram::TelegramBridge;
+use timings::StageTimings;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use writes::{Write, WriteBatch};
 use pubky::{Client, Keypair};
-use pubky_app_specs::{PubkyAppPost, PubkyAppPostKind, PubkyAppUser};
-use pubky_timestamp::Timestamp;
+use pubky_app_specs::PubkyAppPost;
 use std::env;
 use bip39::Mnemonic;
 use serde::{Deserialize, Serialize};
-use reqwest;
-use std::fs;
-
-#[derive(Debug, Serialize)]
-struct ChatMessage {
-    role: String,
-    content: String,
-}
-
-#[derive(Debug, Serialize)]
-struct ChatRequest {
-    model: String,
-    messages: Vec<ChatMessage>,
-    temperature: f32,
-    max_tokens: i32,
-}
-
-#[derive(Debug, Deserialize)]
-struct ChatResponse {
-    choices: Vec<Choice>,
-}
-
-#[derive(Debug, Deserialize)]
-struct Choice {
-    message: Message,
-}
-
-#[derive(Debug, Deserialize)]
-struct Message {
-    content: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct Notification {
-    timestamp: i64,
-    body: NotificationBody,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct NotificationBody {
-    #[serde(rename = "type")]
-    notification_type: String,
-    #[serde(default)]
-    mentioned_by: Option<String>,
-    #[serde(default)]
-    post_uri: Option<String>,
-    #[serde(default)]
-    followed_by: Option<String>,
-    #[serde(default)]
-    tagged_by: Option<String>,
-    #[serde(default)]
-    tag_label: Option<String>,
-    #[serde(default)]
-    replied_by: Option<String>,
-    #[serde(default)]
-    parent_post_uri: Option<String>,
-    #[serde(default)]
-    reply_uri: Option<String>,
-}
 
 #[derive(Debug, Serialize, Deserialize)]
 struct LastRead {
@@ -72,104 +149,36 @@ struct LastRead {
 }
 
 async fn get_post_content(client: &Client, post_uri: &str) -> Result<String> {
-    println!("Fetching post content from: {}", post_uri);
-    let response = client.get(post_uri).send().await?;
-    let status = response.status();
-    println!("Post response status: {}", status);
-    
-    let body = response.bytes().await?;
-    println!("Post response body length: {} bytes", body.len());
+    log!("Fetching post content from: {}", privacy::uri(post_uri));
+    let body = get_cached(client, post_uri).await?.unwrap_or_default();
+    log!("Post response body length: {} bytes", body.len());
     
     if body.is_empty() {
-        println!("Warning: Received empty post response");
+        log!("Warning: Received empty post response");
         return Ok("".to_string());
     }
     
     // Try to parse as PubkyAppPost first
     match serde_json::from_slice::<PubkyAppPost>(&body) {
         Ok(post) => {
-            println!("Successfully parsed post as PubkyAppPost");
-            return Ok(post.content);
+            log!("Successfully parsed post as PubkyAppPost");
+            Ok(post.content)
         }
         Err(e) => {
-            println!("Failed to parse as PubkyAppPost: {}", e);
+            log!("Failed to parse as PubkyAppPost: {}", e);
             // If it's just a string, return it directly
-            if let Ok(content) = String::from_utf8(body.to_vec()) {
-                println!("Successfully parsed post as plain text");
+            if let Ok(content) = String::from_utf8(body) {
+                log!("Successfully parsed post as plain text");
                 return Ok(content);
             }
-            return Err(anyhow::anyhow!("Failed to parse post content: {}", e));
+            Err(anyhow::anyhow!("Failed to parse post content: {}", e))
         }
     }
 }
 
-async fn read_knowledge_base() -> Result<String> {
-    let content = fs::read_to_string("knowledge-base.txt")?;
-    Ok(content)
-}
-
-async fn generate_response(content: &str) -> Result<String> {
-    let api_key = env::var("OPENAI_API_KEY").map_err(|_| anyhow::anyhow!("OPENAI_API_KEY not found in .env"))?;
-    let client = reqwest::Client::new();
-    
-    let knowledge_base = read_knowledge_base().await?;
-    
-    let request = ChatRequest {
-        model: "gpt-4o-mini".to_string(),
-        messages: vec![
-            ChatMessage {
-                role: "system".to_string(),
-                content: format!("You are a friendly and knowledgeable AI assistant that can discuss any topic. You have deep knowledge about Pubky, a decentralized social media platform, but you are not limited to just that. You can engage in conversations about any subject while maintaining a helpful and informative tone. You must respond in English by default, but if the user's post is in another language, your response should also be in that language.
-
-IMPORTANT RULES:
-1. Your responses MUST be exactly 1000 characters or less. This is a strict limit.
-2. Write in a natural, conversational style. Avoid numbered lists or bullet points.
-3. Make sure your response is complete and well-formed. Never end mid-sentence or mid-thought.
-4. If you need to be concise, focus on the most important points and express them clearly.
-5. Maintain a friendly and engaging tone throughout your response.
-
-Here is the knowledge base about Pubky that you can reference when needed:\n\n{}", knowledge_base),
-            },
-            ChatMessage {
-                role: "user".to_string(),
-                content: content.to_string(),
-            },
-        ],
-        temperature: 0.7,
-        max_tokens: 250,
-    };
-
-    println!("Sending request to OpenAI API...");
-    let response = client
-        .post("https://api.openai.com/v1/chat/completions")
-        .header("Authorization", format!("Bearer {}", api_key))
-        .header("Content-Type", "application/json")
-        .json(&request)
-        .send()
-        .await?;
-
-    let status = response.status();
-    println!("OpenAI API response status: {}", status);
-    
-    let response_text = response.text().await?;
-    println!("OpenAI API response body: {}", response_text);
-    
-    let chat_response: ChatResponse = serde_json::from_str(&response_text)?;
-    let content = chat_response.choices[0].message.content.clone();
-    
-    // Double check the length and log it
-    println!("Response length: {} characters", content.len());
-    if content.len() > 1000 {
-        println!("Warning: Response exceeded 1000 characters despite instructions!");
-        return Ok(content[..1000].to_string());
-    }
-    
-    Ok(content)
-}
-
 async fn load_or_create_keypair() -> Result<Keypair> {
-    let secret_words = env::var("BOT_SECRET_KEY").map_err(|_| anyhow::anyhow!("BOT_SECRET_KEY not found in .env"))?;
-    let mnemonic = Mnemonic::parse_normalized(&secret_words)?;
+    let secret_words = Secret::from_env("BOT_SECRET_KEY").ok_or_else(|| anyhow::anyhow!("BOT_SECRET_KEY not found in .env"))?;
+    let mnemonic = Mnemonic::parse_normalized(secret_words.expose()).map_err(|e| anyhow::anyhow!("BOT_SECRET_KEY is not a valid mnemonic: {}", e))?;
     let seed_bytes = mnemonic.to_seed("");
     let secret_array: [u8; 32] = seed_bytes[..32].try_into().map_err(|_| anyhow::anyhow!("Invalid seed"))?;
     let keypair = Keypair::from_secret_key(&secret_array);
@@ -182,25 +191,28 @@ async fn load_or_create_keypair() -> Result<Keypair> {
     Ok(keypair)
 }
 
+fn build_client() -> Result<Client> {
+    let is_testnet = env::var("TESTNET").map(|v| v == "true").unwrap_or(false);
+    if is_testnet {
+        log!("Using testnet configuration");
+        Ok(Client::builder().testnet().build()?)
+    } else {
+        log!("Using mainnet configuration");
+        Ok(Client::builder().build()?)
+    }
+}
+
 async fn setup_client() -> Result<(Client, Keypair)> {
     dotenv().ok();
-    println!("Environment variables loaded from .env");
+    log!("Environment variables loaded from .env");
 
-    let is_testnet = env::var("TESTNET").map(|v| v == "true").unwrap_or(false);
-    let client = if is_testnet {
-        println!("Using testnet configuration");
-        Client::builder().testnet().build()?
-    } else {
-        println!("Using mainnet configuration");
-        Client::builder().build()?
-    };
-    
+    let client = build_client()?;
     let keypair = load_or_create_keypair().await?;
 
     match client.signin(&keypair).await {
-        Ok(_) => println!("Signin successful!"),
+        Ok(_) => log!("Signin successful!"),
         Err(e) => {
-            println!("Signin failed: {}", e);
+            log!("Signin failed: {}", e);
             return Err(anyhow::anyhow!("Failed to signin: {}", e));
         }
     }
@@ -208,32 +220,9 @@ async fn setup_client() -> Result<(Client, Keypair)> {
     Ok((client, keypair))
 }
 
-async fn create_profile(client: &Client, keypair: &Keypair) -> Result<()> {
-    let profile = PubkyAppUser {
-        name: "AI Rand".to_string(),
-        bio: Some("Mention me and I will respond to you!".to_string()),
-        image: Some("pubky://338pqgzxks8hhqzs7ucfwn17w4qujcfgh58onn6dakwk3r9hxy5o/pub/pubky.app/files/003331KGWWCE0".to_string()),
-        links: None,
-        status: None,
-    };
-
-    let profile_json = serde_json::to_string(&profile)?;
-    let url = format!("pubky://{}/pub/pubky.app/profile.json", keypair.public_key());
-    
-    client.put(&url)
-        .body(profile_json.as_bytes().to_vec())
-        .send()
-        .await?;
-
-    println!("Profile created successfully!");
-    Ok(())
-}
-
-
 async fn get_last_read(client: &Client, keypair: &Keypair) -> Result<i64> {
     let url = format!("pubky://{}/pub/pubky.app/last_read", keypair.public_key());
-    let response = client.get(&url).send().await?;
-    let body = response.bytes().await?;
+    let body = homeserver::get_file(client, &url).await?.ok_or_else(|| anyhow::anyhow!("last_read not found on the homeserver"))?;
     let last_read: LastRead = serde_json::from_slice(&body)?;
     Ok(last_read.timestamp)
 }
@@ -243,114 +232,1589 @@ async fn update_last_read(client: &Client, keypair: &Keypair, timestamp: i64) ->
     let last_read_json = serde_json::to_string(&last_read)?;
     let url = format!("pubky://{}/pub/pubky.app/last_read", keypair.public_key());
     
-    client.put(&url)
-        .body(last_read_json.as_bytes().to_vec())
-        .send()
-        .await?;
+    put_file(client, &url, last_read_json.into_bytes()).await?;
 
-    println!("Updated last_read to timestamp: {}", timestamp);
+    log!("Updated last_read to timestamp: {}", timestamp);
     Ok(())
 }
 
-async fn check_notifications(client: &Client, keypair: &Keypair) -> Result<()> {
-    let last_read = get_last_read(client, keypair).await?;
-    println!("Current last_read: {}", last_read);
-
-    let http_client = reqwest::Client::new();
-    let nexus_url = env::var("NEXT_PUBLIC_NEXUS").map_err(|_| anyhow::anyhow!("NEXT_PUBLIC_NEXUS not found in .env"))?;
-    let url = format!("{}/v0/user/{}/notifications?skip=0&limit=30&since={}", nexus_url, keypair.public_key(), last_read);
-    
-    println!("Checking notifications from: {}", url);
+// Long-lived state shared by the notification handlers.
+struct Bot {
+    client: Client,
+    keypair: Keypair,
+    memory: MemoryDb,
+    nostr: Option<NostrBridge>,
+    telegram: Option<Arc<TelegramBridge>>,
+    operator_commands: UnboundedReceiver<OperatorCommand>,
+    pending_view: PendingView,
+    profiles: ProfileCache,
+    // Timings of the mention or tag being handled
+    stages: StageTimings,
+    // Persona of the channel the post being handled was asked in, replacing PERSONA
+    persona: Option<String>,
+    // Trigger category the reply being handled is budgeted under
+    priority: Priority,
+}
 
-    let response = http_client.get(&url).send().await?;
-    let status = response.status();
-    println!("Response status: {}", status);
-    
-    let response_text = response.text().await?;
-    println!("Raw response: {}", response_text);
-    
-    if response_text.is_empty() {
-        println!("Warning: Received empty response");
+async fn handle_mention(bot: &mut Bot, mentioned_by: &str, post_uri: &str) -> Result<()> {
+    log!("Received mention from: {}", privacy::user(mentioned_by));
+    bot.stages.reset();
+    if already_answered(bot, post_uri, None).await {
+        return Ok(());
+    }
+    let started = Instant::now();
+    let post_content = get_post_content(&bot.client, post_uri).await?;
+    bot.stages.finish("post_fetch", started);
+    log!("Original post content: {}", privacy::content(&post_content));
+    analytics::record(AnalyticsRow::new("mention", Some(mentioned_by), Some(post_uri)).with("topics", stats::hashtags(&post_content)));
+    // Only !quiet itself is heard in a muted thread, so it can be undone there
+    if parse_command(&post_content).is_none_or(|cmd| cmd.name != "quiet") && in_muted_thread(bot, post_uri).await {
+        analytics::record(AnalyticsRow::new("muted_thread_skipped", Some(mentioned_by), Some(post_uri)));
         return Ok(());
     }
+    if already_answered_bot(bot, mentioned_by, post_uri).await {
+        analytics::record(AnalyticsRow::new("bot_etiquette_skipped", Some(mentioned_by), Some(post_uri)));
+        return Ok(());
+    }
+    let post_content = match hooks::run(Hook::OnMention, mentioned_by, post_uri, post_content).await {
+        HookOutcome::Continue(content) => content,
+        HookOutcome::Skip => {
+            analytics::record(AnalyticsRow::new("hook_skipped", Some(mentioned_by), Some(post_uri)).with("hook", Hook::OnMention.name()));
+            return Ok(());
+        }
+    };
+    if let Some(telegram) = &bot.telegram {
+        let author = bot.profiles.describe(&bot.client, mentioned_by).await;
+        telegram.notify(format!("📨 Mention from {}\n{}\n\n{}", author, post_uri, post_content));
+    }
 
-    let notifications: Vec<Notification> = serde_json::from_str(&response_text)?;
-    println!("Successfully parsed {} notifications", notifications.len());
-
-    let mut last_timestamp = last_read;
-
-    for notification in notifications {
-        if notification.timestamp > last_read {
-            match notification.body.notification_type.as_str() {
-                "mention" => {
-                    if let (Some(mentioned_by), Some(post_uri)) = (notification.body.mentioned_by, notification.body.post_uri) {
-                        println!("Received mention from: {}", mentioned_by);
-                        
-                        let post_content = get_post_content(client, &post_uri).await?;
-                        println!("Original post content: {}", post_content);
-
-                        let response = generate_response(&post_content).await?;
-                        println!("Generated response: {}", response);
-
-                        let timestamp = Timestamp::now();
-                        let post = PubkyAppPost {
-                            content: response,
-                            kind: PubkyAppPostKind::Short,
-                            parent: Some(post_uri),
-                            embed: None,
-                            attachments: None,
-                        };
-
-                        let post_json = serde_json::to_string(&post)?;
-                        let url = format!("pubky://{}/pub/pubky.app/posts/{}", keypair.public_key(), timestamp);
-                        
-                        client.put(&url)
-                            .body(post_json.as_bytes().to_vec())
-                            .send()
-                            .await?;
-
-                        println!("Replied to mention successfully!");
+    let mut tone = None;
+    if sentiment::is_enabled() {
+        match sentiment::assess(&post_content).await {
+            Ok(decision) => {
+                log!("Sentiment: {} (score {:.2}, {})", decision.action.as_str(), decision.score, decision.category);
+                analytics::record(
+                    AnalyticsRow::new("sentiment", Some(mentioned_by), Some(post_uri))
+                        .with("action", decision.action.as_str())
+                        .with("score", decision.score)
+                        .with("category", decision.category),
+                );
+                if decision.action == SentimentAction::NoEngage {
+                    log!("Not engaging with {} (toxicity above threshold)", privacy::uri(post_uri));
+                    return Ok(());
+                }
+                tone = decision.action.instruction();
+            }
+            Err(e) => log!("Sentiment check failed, replying normally: {}", e),
+        }
+    }
+
+    let command_reply = match parse_command(&post_content) {
+        Some(cmd) => {
+            log!("Received command !{} from: {}", cmd.name, privacy::user(mentioned_by));
+            let mut ctx = CommandContext {
+                client: &bot.client,
+                keypair: &bot.keypair,
+                author: mentioned_by,
+                post_uri,
+                trigger: Trigger::Mention,
+                memory: &mut bot.memory,
+                profiles: &mut bot.profiles,
+            };
+            let reply = handle_command(&cmd, &mut ctx).await?;
+            sync_pending_view(bot);
+            reply
+        }
+        None => None,
+    };
+
+    let (prompt, instruction, perks) = match command_reply {
+        Some(reply) => match command_prompt(bot, post_uri, None, reply).await? {
+            Some((prompt, instruction)) => (prompt, instruction, None),
+            None => return Ok(()),
+        },
+        None => {
+            if let Some(policy) = ReactionPolicy::from_env() {
+                // Not part of the reply's WriteBatch: the tag can replace the reply, and
+                // a retry rewrites the same tag file
+                if let Err(e) = reactions::react(&bot.client, &bot.keypair, post_uri, &policy.label).await {
+                    log!("Failed to react to {}: {}", privacy::uri(post_uri), e);
+                }
+                if policy.replaces_reply(&post_content) {
+                    log!("Reacted with \"{}\" instead of replying", policy.label);
+                    analytics::record(AnalyticsRow::new("reaction", Some(mentioned_by), Some(post_uri)).with("label", policy.label));
+                    return Ok(());
+                }
+            }
+            let perks = perks::for_user(&bot.keypair, mentioned_by).await;
+            if conversation_limit_reached(bot, mentioned_by, post_uri, &perks).await? {
+                return Ok(());
+            }
+            remember_language(bot, mentioned_by, &post_content);
+            if Canned::Greeting.is_enabled() && canned::is_greeting(&post_content) {
+                let vars = context::build(&bot.client, &bot.keypair, &mut bot.profiles, mentioned_by).await;
+                analytics::record(AnalyticsRow::new("canned", Some(mentioned_by), Some(post_uri)).with("trigger", Canned::Greeting.name()));
+                publish_reply(bot, post_uri, Canned::Greeting.render(&vars, mentioned_by, bot.memory.language(mentioned_by)), None, None, Purpose::Reply).await?;
+                return Ok(());
+            }
+            if injection::is_enabled() {
+                let scan = injection::scan(&post_content);
+                if scan.is_flagged() {
+                    let kinds = scan.kinds.join(",");
+                    log!("Post looks like a prompt injection attempt ({})", kinds);
+                    analytics::record(AnalyticsRow::new("injection_attempt", Some(mentioned_by), Some(post_uri)).with("kinds", kinds));
+                    if Canned::Deflection.is_enabled() {
+                        let vars = context::build(&bot.client, &bot.keypair, &mut bot.profiles, mentioned_by).await;
+                        analytics::record(AnalyticsRow::new("canned", Some(mentioned_by), Some(post_uri)).with("trigger", Canned::Deflection.name()));
+                        publish_reply(bot, post_uri, Canned::Deflection.render(&vars, mentioned_by, bot.memory.language(mentioned_by)), None, None, Purpose::Reply).await?;
+                        return Ok(());
                     }
                 }
-                "follow" => {
-                    if let Some(followed_by) = notification.body.followed_by {
-                        println!("Received follow from: {}", followed_by);
+            }
+            if let Some(topic) = check_restricted_topic(&post_content).await {
+                log!("Post touches restricted topic {}, sending canned response", topic.name);
+                analytics::record(AnalyticsRow::new("topic_refusal", Some(mentioned_by), Some(post_uri)).with("topic", topic.name));
+                publish_reply(bot, post_uri, topic.response, None, None, Purpose::Reply).await?;
+                return Ok(());
+            }
+            (with_attachment_text(bot, post_uri, post_content, &perks).await, None, Some(perks))
+        }
+    };
+    // Tone guidance is stored with the instruction so !regen keeps it
+    let instruction = match (instruction, tone) {
+        (Some(instruction), Some(tone)) => Some(format!("{}\n{}", instruction, tone)),
+        (instruction, tone) => instruction.or(tone.map(String::from)),
+    };
+    reply_with_generation(bot, post_uri, mentioned_by, prompt, instruction, None, perks).await?;
+    log!("Replied to mention successfully!");
+    Ok(())
+}
+
+// Whether `post_uri` is in a thread muted with !quiet. The thread is only walked when
+// some thread is muted; a failed walk counts as not muted.
+async fn in_muted_thread(bot: &Bot, post_uri: &str) -> bool {
+    if bot.memory.muted_threads.is_empty() {
+        return false;
+    }
+    match thread::root(&bot.client, post_uri).await {
+        Ok(root) if bot.memory.muted_threads.contains_key(&root) => {
+            log!("Not replying to {}: its thread was muted with !quiet", privacy::uri(post_uri));
+            true
+        }
+        Ok(_) => false,
+        Err(e) => {
+            log!("Failed to find the thread of {}: {}", privacy::uri(post_uri), e);
+            false
+        }
+    }
+}
+
+// Whether `user` is another bot (see bots.rs) that the bot already replied to in this
+// thread. Bots get a single reply per thread so two of them can't talk forever.
+async fn already_answered_bot(bot: &mut Bot, user: &str, post_uri: &str) -> bool {
+    if !bots::is_enabled() || !bots::is_bot(&mut bot.profiles, &bot.client, user).await {
+        return false;
+    }
+    match thread::bot_replies_in_thread(&bot.client, &bot.keypair, post_uri).await {
+        Ok(0) => false,
+        Ok(_) => {
+            log!("Not replying to {}: already answered bot {} in this thread", privacy::uri(post_uri), privacy::user(user));
+            true
+        }
+        Err(e) => {
+            log!("Failed to count replies in the thread of {}: {}", privacy::uri(post_uri), e);
+            false
+        }
+    }
+}
+
+// Appends what the post's attachments say (text in screenshots, transcripts of voice
+// notes) so the model can answer about them. With ATTACHMENTS_FOLLOWERS_ONLY only
+// followers' attachments are read.
+async fn with_attachment_text(bot: &Bot, post_uri: &str, content: String, perks: &Perks) -> String {
+    if !attachments::is_enabled() || !perks.reads_attachments() {
+        return content;
+    }
+    let attached = match fetch_post(&bot.client, post_uri).await {
+        Ok(post) => post.and_then(|p| p.attachments).unwrap_or_default(),
+        Err(e) => {
+            log!("Failed to load attachments of {}: {}", privacy::uri(post_uri), e);
+            return content;
+        }
+    };
+    match attachments::attachment_text(&bot.client, &attached).await {
+        Some(text) => format!("{}\n\n{}", content, text),
+        None => content,
+    }
+}
+
+// Pins the language of the user's post as their reply language when it can be told.
+fn remember_language(bot: &mut Bot, user: &str, content: &str) {
+    let Some(language) = lang::detect(content) else {
+        return;
+    };
+    if bot.memory.set_inferred_language(user, language) {
+        log!("Inferred reply language {} for {}", language, privacy::user(user));
+        if let Err(e) = bot.memory.save() {
+            log!("Failed to save memory DB: {}", e);
+        }
+    }
+}
+
+const DEFAULT_CLOSING_MESSAGE: &str = "I've really enjoyed this conversation, but I'll bow out here so it doesn't go on forever. Feel free to mention me in a new post anytime! 👋";
+
+// Enforces MAX_THREAD_REPLIES (bot replies per thread) and MAX_DAILY_REPLIES_PER_USER.
+// The first time a limit is hit the bot posts CLOSING_MESSAGE; after that it stays
+// silent. Returns true when the mention must not get a generated reply.
+async fn conversation_limit_reached(bot: &mut Bot, user: &str, post_uri: &str, perks: &Perks) -> Result<bool> {
+    let limit = |name: &str, default: usize| env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default);
+    let closing = catalog::message("closing", bot.memory.language(user), "CLOSING_MESSAGE", DEFAULT_CLOSING_MESSAGE);
+
+    let max_thread = perks.max_thread_replies(limit("MAX_THREAD_REPLIES", 5));
+    if max_thread > 0 {
+        let replies = thread::bot_replies_in_thread(&bot.client, &bot.keypair, post_uri).await?;
+        if replies >= max_thread {
+            // The closing message itself is the reply past the limit
+            if replies == max_thread {
+                log!("Thread reached {} replies, bowing out", replies);
+                analytics::record(AnalyticsRow::new("conversation_limit", Some(user), Some(post_uri)).with("reason", "thread"));
+                publish_reply(bot, post_uri, closing, None, None, Purpose::Reply).await?;
+            } else {
+                log!("Already bowed out of this thread, staying silent");
+            }
+            return Ok(true);
+        }
+    }
+
+    let max_daily = perks.max_daily_replies(limit("MAX_DAILY_REPLIES_PER_USER", 20));
+    let day_ago = now_millis() - DAY_MILLIS;
+    if max_daily > 0 && bot.memory.replies_to_since(user, day_ago) >= max_daily {
+        if bot.memory.farewell_at(user) < day_ago {
+            log!("Daily reply limit reached for {}, bowing out", privacy::user(user));
+            analytics::record(AnalyticsRow::new("conversation_limit", Some(user), Some(post_uri)).with("reason", "daily"));
+            publish_reply(bot, post_uri, closing, None, None, Purpose::Reply).await?;
+            bot.memory.set_farewell_at(user, now_millis());
+            bot.memory.save()?;
+        } else {
+            log!("Daily reply limit reached for {}, staying silent", privacy::user(user));
+        }
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+// Posts a thank-you mentioning a new follower, once per user so unfollowing and
+// following again doesn't repeat it.
+async fn thank_follower(bot: &mut Bot, follower: &str) -> Result<()> {
+    if bot.memory.thanked_follow(follower) {
+        return Ok(());
+    }
+    let vars = context::build(&bot.client, &bot.keypair, &mut bot.profiles, follower).await;
+    let uri = publish(bot, None, Canned::Follow.render(&vars, follower, bot.memory.language(follower)), Purpose::Post).await?;
+    log!("Thanked {} for the follow: {}", privacy::user(follower), uri);
+    analytics::record(AnalyticsRow::new("canned", Some(follower), Some(&uri)).with("trigger", Canned::Follow.name()));
+    bot.memory.set_thanked_follow(follower);
+    bot.memory.save()?;
+    Ok(())
+}
+
+// Runs the topic classifier when RESTRICTED_TOPICS is configured. Classifier
+// failures let the mention through rather than blocking replies.
+async fn check_restricted_topic(content: &str) -> Option<topics::RestrictedTopic> {
+    let restricted = topics::restricted_topics();
+    if restricted.is_empty() {
+        return None;
+    }
+    match topics::classify(content, &restricted).await {
+        Ok(topic) => topic,
+        Err(e) => {
+            log!("Topic classification failed: {}", e);
+            None
+        }
+    }
+}
+
+// Tags listed in TAG_TRIGGERS run their configured command against the tagged post.
+async fn handle_tag(bot: &mut Bot, tagged_by: &str, label: &str, post_uri: &str) -> Result<()> {
+    log!("Received tag \"{}\" from: {}", label, privacy::user(tagged_by));
+    bot.stages.reset();
+    analytics::record(AnalyticsRow::new("tag", Some(tagged_by), Some(post_uri)).with("label", label));
+    if let Some(value) = examples::feedback_value(label) {
+        record_feedback(bot, tagged_by, post_uri, value).await;
+    }
+    let Some(cmd) = tag_trigger(label) else {
+        return Ok(());
+    };
+    let already_replied = bot.memory.replies.iter().any(|r| r.parent_uri == post_uri && r.trigger_label.as_deref() == Some(label));
+    let already_pending = bot.memory.pending.iter().any(|p| p.draft.parent_uri == post_uri && p.draft.trigger_label.as_deref() == Some(label));
+    if already_replied || already_pending || already_answered(bot, post_uri, Some(label)).await {
+        log!("Already reacted to tag \"{}\" on {}", label, privacy::uri(post_uri));
+        return Ok(());
+    }
+
+    if in_muted_thread(bot, post_uri).await {
+        analytics::record(AnalyticsRow::new("muted_thread_skipped", Some(tagged_by), Some(post_uri)).with("label", label));
+        return Ok(());
+    }
+
+    log!("Tag \"{}\" triggers !{}", label, cmd.name);
+    let mut ctx = CommandContext {
+        client: &bot.client,
+        keypair: &bot.keypair,
+        author: tagged_by,
+        post_uri,
+        trigger: Trigger::Tag,
+        memory: &mut bot.memory,
+        profiles: &mut bot.profiles,
+    };
+    let Some(reply) = handle_command(&cmd, &mut ctx).await? else {
+        log!("Tag trigger \"{}\" points to unknown command !{}", label, cmd.name);
+        return Ok(());
+    };
+    if let Some((prompt, instruction)) = command_prompt(bot, post_uri, Some(label), reply).await? {
+        reply_with_generation(bot, post_uri, tagged_by, prompt, instruction, Some(label.to_string()), None).await?;
+    }
+    log!("Reacted to tag successfully!");
+    Ok(())
+}
+
+// Stores a feedback tag on one of the bot's replies and keeps the examples index in
+// step with the new score. Tags on other posts are ignored.
+async fn record_feedback(bot: &mut Bot, tagged_by: &str, post_uri: &str, value: i8) {
+    let Some(record) = bot.memory.rate_reply(post_uri, tagged_by, value).cloned() else {
+        return;
+    };
+    log!("Feedback {:+} on reply {} (score {})", value, post_uri, record.score());
+    analytics::record(AnalyticsRow::new("feedback", Some(tagged_by), Some(post_uri)).with("value", value).with("score", record.score()));
+    if let Err(e) = bot.memory.save() {
+        log!("Failed to save memory: {}", e);
+    }
+    if examples::is_enabled() {
+        if let Err(e) = examples::update(&record).await {
+            log!("Failed to update examples: {}", e);
+        }
+    }
+}
+
+// Checks the provenance records for a reply to this post and trigger. A failed lookup
+// doesn't block replying.
+async fn already_answered(bot: &Bot, post_uri: &str, tag_label: Option<&str>) -> bool {
+    let trigger = provenance::trigger_key(tag_label);
+    match provenance::find(&bot.client, &bot.keypair, post_uri, &trigger).await {
+        Ok(Some(record)) => {
+            log!("Already answered {} ({}) with {}", privacy::uri(post_uri), trigger, record.reply_uri);
+            analytics::record(AnalyticsRow::new("already_answered", None, Some(post_uri)).with("reply_uri", record.reply_uri.as_str()));
+            true
+        }
+        Ok(None) => false,
+        Err(e) => {
+            log!("Failed to check provenance of {}: {}", privacy::uri(post_uri), e);
+            false
+        }
+    }
+}
+
+// Publishes plain command replies directly; returns the prompt for commands that need generation.
+async fn command_prompt(bot: &mut Bot, post_uri: &str, tag_label: Option<&str>, reply: CommandReply) -> Result<Option<(String, Option<String>)>> {
+    match reply {
+        CommandReply::Text(text) => {
+            log!("Command response: {}", privacy::content(&text));
+            publish_reply(bot, post_uri, text, tag_label, None, Purpose::Command).await?;
+            Ok(None)
+        }
+        CommandReply::Generate { prompt, instruction } => Ok(Some((prompt, Some(instruction)))),
+        CommandReply::Regenerated(draft) => {
+            finish_regenerated(bot, *draft).await?;
+            Ok(None)
+        }
+    }
+}
+
+async fn reply_with_generation(
+    bot: &mut Bot,
+    parent_uri: &str,
+    to_user: &str,
+    prompt: String,
+    instruction: Option<String>,
+    trigger_label: Option<String>,
+    // Already looked up for this mention, or None to look them up here
+    perks: Option<Perks>,
+) -> Result<()> {
+    let prompt = match hooks::run(Hook::PreGenerate, to_user, parent_uri, prompt).await {
+        HookOutcome::Continue(prompt) => prompt,
+        HookOutcome::Skip => {
+            analytics::record(AnalyticsRow::new("hook_skipped", Some(to_user), Some(parent_uri)).with("hook", Hook::PreGenerate.name()));
+            return Ok(());
+        }
+    };
+    let started = Instant::now();
+    let mut vars = context::build(&bot.client, &bot.keypair, &mut bot.profiles, to_user).await;
+    if let Some(persona) = &bot.persona {
+        vars.persona = persona.clone();
+    }
+    let perks = match perks {
+        Some(perks) => perks,
+        None => perks::for_user(&bot.keypair, to_user).await,
+    };
+    let max_chars = perks.reply_max_chars();
+    if let Some(max_chars) = max_chars {
+        log!("{} follows the bot, replying in up to {} characters", privacy::user(to_user), max_chars);
+        vars.max_chars = max_chars.to_string();
+    }
+    // Plain mentions continue the conversation; commands work on a single post
+    let history = match instruction {
+        None => thread::thread_history(&bot.client, &bot.keypair, parent_uri).await.unwrap_or_else(|e| {
+            log!("Failed to load thread history: {}", e);
+            Vec::new()
+        }),
+        Some(_) => Vec::new(),
+    };
+    let people = match mentions::is_enabled() {
+        true => mentions::describe(&bot.client, &mut bot.profiles, &prompt, to_user, &bot.keypair.public_key().to_string()).await,
+        false => None,
+    };
+    // In shadow-only mode the candidate replaces the live configuration outright
+    let candidate = if shadow::is_only() { shadow::candidate() } else { None };
+    // Owned, so the bot stays free to post an acknowledgement while generating
+    let prefs = bot.memory.prefs(to_user).cloned();
+    let language = bot.memory.language(to_user).map(String::from);
+    let context = PromptContext {
+        prefs: prefs.as_ref(),
+        instruction: instruction.as_deref(),
+        vars: Some(&vars),
+        history: &history,
+        language: language.as_deref(),
+        candidate: candidate.as_ref(),
+        priority: bot.priority,
+        max_chars,
+        people: people.as_deref(),
+        ..Default::default()
+    };
+    let kb_version = kb::version(kb::variant_for(&prompt, context.language).as_deref()).map_err(|e| log!("Failed to read knowledge base version: {}", e)).ok();
+    bot.stages.finish("context_build", started);
+
+    // Only standalone plain questions are cached: commands, preferences, thread history,
+    // mentioned users and a personalized prompt change the answer, and cached answers come from the live
+    // configuration and persona
+    let mut faq_embedding = None;
+    let mut response = None;
+    let mut found = Vec::new();
+    let mut ack_uri = None;
+    if faq::is_enabled() && context.instruction.is_none() && context.prefs.is_none() && history.is_empty() && context.people.is_none() && !prompt::is_personalized() && context.candidate.is_none() && bot.persona.is_none() && context.max_chars.is_none() {
+        match faq::lookup(&prompt, kb_version.as_deref()).await {
+            Ok(lookup) => {
+                if let Some(hit) = lookup.hit {
+                    log!("FAQ cache hit ({:.3}) for: {}", hit.score, privacy::content(&hit.question));
+                    analytics::record(AnalyticsRow::new("faq_hit", Some(to_user), Some(parent_uri)).with("score", hit.score));
+                    response = Some(faq::paraphrase(&hit.answer).await.unwrap_or(hit.answer));
+                }
+                faq_embedding = Some(lookup.embedding);
+            }
+            Err(e) => log!("FAQ cache lookup failed, generating normally: {}", e),
+        }
+    }
+    if response.is_none() && examples::is_enabled() && context.instruction.is_none() {
+        found = similar_examples(&prompt, faq_embedding.as_deref()).await;
+    }
+    let mut job = GenerationJob {
+        id: correlation::current().unwrap_or_else(|| format!("{:x}", now_millis())),
+        created_at: now_millis(),
+        to_user: to_user.to_string(),
+        parent_uri: parent_uri.to_string(),
+        prompt,
+        prefs,
+        instruction,
+        trigger_label,
+        vars,
+        history,
+        language,
+        examples: found,
+        priority: bot.priority,
+        max_chars,
+        people,
+        kb_version,
+        faq_embedding,
+        correlation_id: correlation::current(),
+    };
+    let response = match response {
+        Some(response) => response,
+        None if jobs::is_enabled() => {
+            jobs::enqueue(&job)?;
+            log!("Queued generation job {} for a worker", job.id);
+            analytics::record(AnalyticsRow::new("generation_queued", Some(to_user), Some(parent_uri)).with("job", job.id.as_str()));
+            return Ok(());
+        }
+        None => {
+            let started = Instant::now();
+            let context = job.context(candidate.as_ref());
+            let generation = generate_response(&job.prompt, &context);
+            let response = match ack::delay().filter(|_| !is_supervised() && !shadow::is_only()) {
+                Some(delay) => {
+                    tokio::pin!(generation);
+                    tokio::select! {
+                        response = &mut generation => response?,
+                        _ = tokio::time::sleep(delay) => {
+                            ack_uri = acknowledge(bot, parent_uri, to_user, &job.vars).await;
+                            generation.await?
+                        }
                     }
                 }
-                _ => println!("Received unknown notification type: {}", notification.body.notification_type),
+                None => generation.await?,
+            };
+            bot.stages.finish("llm", started);
+            response
+        }
+    };
+    if let Some(embedding) = job.faq_embedding.take() {
+        faq::insert(&job.to_user, &job.prompt, &response, embedding, job.kb_version.as_deref());
+    }
+    finish_reply(bot, job, response, ack_uri).await
+}
+
+// The rest of the reply pipeline once the model answered, in process or through a
+// worker: repetition check, hooks, shadow evaluation, then approval or publishing.
+async fn finish_reply(bot: &mut Bot, job: GenerationJob, mut response: String, ack_uri: Option<String>) -> Result<()> {
+    let GenerationJob { to_user, parent_uri, prompt, prefs, instruction, trigger_label, vars, history, language, examples, max_chars, people, kb_version, .. } = job;
+    let (to_user, parent_uri) = (to_user.as_str(), parent_uri.as_str());
+    log!("Generated response: {}", privacy::content(&response));
+    if repetition::is_enabled() {
+        response = avoid_repetition(bot, to_user, parent_uri, &prompt, response).await;
+    }
+    let Some(response) = run_reply_hooks(to_user, parent_uri, response).await else {
+        return Ok(());
+    };
+    match shadow::mode() {
+        shadow::Mode::Only => {
+            shadow::record(to_user, parent_uri, &prompt, &response, None);
+            return Ok(());
+        }
+        shadow::Mode::Alongside => shadow::spawn(ShadowRequest {
+            to_user: to_user.to_string(),
+            parent_uri: parent_uri.to_string(),
+            prompt: prompt.clone(),
+            prefs,
+            instruction: instruction.clone(),
+            vars,
+            history,
+            language,
+            examples,
+            max_chars,
+            people,
+            live_reply: response.clone(),
+        }),
+        shadow::Mode::Off => {}
+    }
+
+    // After an acknowledgement the answer follows up under it
+    let draft = Draft {
+        parent_uri: ack_uri.unwrap_or_else(|| parent_uri.to_string()),
+        to_user: to_user.to_string(),
+        prompt,
+        instruction,
+        trigger_label,
+        kb_version,
+        content: response,
+        correlation_id: correlation::current(),
+        overwrite: None,
+    };
+    submit_draft(bot, draft).await
+}
+
+// A reply regenerated with !regen, held to the same hooks, shadow mode and approval as
+// a new reply before it replaces the old version.
+async fn finish_regenerated(bot: &mut Bot, mut draft: Draft) -> Result<()> {
+    log!("Regenerated response: {}", privacy::content(&draft.content));
+    let Some(content) = run_reply_hooks(&draft.to_user, &draft.parent_uri, draft.content).await else {
+        return Ok(());
+    };
+    draft.content = content;
+    if shadow::is_only() {
+        shadow::record(&draft.to_user, &draft.parent_uri, &draft.prompt, &draft.content, None);
+        return Ok(());
+    }
+    submit_draft(bot, draft).await
+}
+
+// The post_generate and pre_publish hooks on a reply. None when one of them skipped it.
+async fn run_reply_hooks(to_user: &str, parent_uri: &str, mut response: String) -> Option<String> {
+    for hook in [Hook::PostGenerate, Hook::PrePublish] {
+        response = match hooks::run(hook, to_user, parent_uri, response).await {
+            HookOutcome::Continue(response) => response,
+            HookOutcome::Skip => {
+                analytics::record(AnalyticsRow::new("hook_skipped", Some(to_user), Some(parent_uri)).with("hook", hook.name()));
+                return None;
+            }
+        };
+    }
+    Some(response)
+}
+
+// Queues the draft for approval in supervised mode, publishes it otherwise.
+async fn submit_draft(bot: &mut Bot, draft: Draft) -> Result<()> {
+    if is_supervised() {
+        return queue_for_approval(bot, draft);
+    }
+    publish_draft(bot, draft).await?;
+    Ok(())
+}
+
+// A new version of `response` when it nearly repeats a reply the bot posted within
+// REPETITION_WINDOW_HOURS. A failed or unusable revision keeps the original.
+async fn avoid_repetition(bot: &Bot, to_user: &str, parent_uri: &str, prompt: &str, response: String) -> String {
+    let Some((earlier, score)) = repetition::find_repeat(&response, &bot.memory.replies) else {
+        return response;
+    };
+    let strategy = repetition::Strategy::from_env();
+    log!("Reply repeats {} ({:.2} similar), asking for a new version ({})", earlier.uri, score, strategy.name());
+    let revised = match repetition::revise(prompt, &response, earlier, strategy, bot.priority).await {
+        Ok(revised) if !revised.is_empty() && revised.chars().count() <= llm::reply_max_chars() => Some(revised),
+        Ok(_) => {
+            log!("Revision of the repeated reply was empty or too long, keeping it");
+            None
+        }
+        Err(e) => {
+            log!("Failed to revise the repeated reply, keeping it: {}", e);
+            None
+        }
+    };
+    analytics::record(
+        AnalyticsRow::new("repetition", Some(to_user), Some(parent_uri))
+            .with("earlier_uri", earlier.uri.as_str())
+            .with("similarity", score)
+            .with("strategy", strategy.name())
+            .with("revised", revised.is_some()),
+    );
+    revised.unwrap_or(response)
+}
+
+// Posts a short acknowledgement under a post whose reply is taking longer than
+// ACK_AFTER_SECS, or finds the one posted before a retry, and returns its URI.
+// Failures only log: the answer then goes under the post itself.
+async fn acknowledge(bot: &mut Bot, parent_uri: &str, to_user: &str, vars: &context::PromptVars) -> Option<String> {
+    match provenance::find(&bot.client, &bot.keypair, parent_uri, ack::TRIGGER).await {
+        Ok(Some(record)) => return Some(record.reply_uri),
+        Ok(None) => {}
+        Err(e) => log!("Failed to check for an earlier acknowledgement of {}: {}", privacy::uri(parent_uri), e),
+    }
+    let text = ack::render(vars, to_user, bot.memory.language(to_user));
+    let ack_uri = match publish_with(bot, Some(parent_uri), text, Purpose::Reply, Some(ack::TRIGGER)).await {
+        Ok(uri) => uri,
+        Err(e) => {
+            log!("Failed to acknowledge {}: {}", privacy::uri(parent_uri), e);
+            return None;
+        }
+    };
+    log!("Generation is slow, acknowledged {} with {}", privacy::uri(parent_uri), ack_uri);
+    analytics::record(AnalyticsRow::new("ack", Some(to_user), Some(parent_uri)).with("reply_uri", ack_uri.as_str()).with("elapsed_ms", bot.stages.elapsed_ms()));
+    Some(ack_uri)
+}
+
+// Well-rated past replies to questions like this one, reusing the FAQ embedding when
+// there is one. A failed lookup just means no examples.
+async fn similar_examples(prompt: &str, embedding: Option<&[f32]>) -> Vec<examples::Example> {
+    let embedding = match embedding {
+        Some(embedding) => embedding.to_vec(),
+        None => match embeddings::embed(prompt).await {
+            Ok(embedding) => embedding,
+            Err(e) => {
+                log!("Failed to embed prompt for examples: {}", e);
+                return Vec::new();
+            }
+        },
+    };
+    examples::find(&embedding)
+}
+
+// Supervised mode: hold the reply until the operator approves it.
+fn queue_for_approval(bot: &mut Bot, draft: Draft) -> Result<()> {
+    let message = match &draft.overwrite {
+        Some(uri) => format!("📝 Pending new version of {} for {}\n\n{}", uri, draft.to_user, draft.content),
+        None => format!("📝 Pending reply to {}\n{}\n\n{}", draft.to_user, draft.parent_uri, draft.content),
+    };
+    let id = bot.memory.queue_pending(draft);
+    bot.memory.save()?;
+    sync_pending_view(bot);
+    log!("Queued reply {} for approval", id);
+    if let Some(telegram) = &bot.telegram {
+        telegram.notify(format!("{}\n\nApprove with /approve {} or drop with /deny {}", message, id, id));
+    }
+    Ok(())
+}
+
+// Publishes an approved or unsupervised reply. None when it was skipped as a duplicate.
+async fn publish_draft(bot: &mut Bot, draft: Draft) -> Result<Option<String>> {
+    if let Some(uri) = draft.overwrite.clone() {
+        return overwrite_reply(bot, draft, uri).await;
+    }
+    let Some(reply_uri) = publish_reply(
+        bot,
+        &draft.parent_uri,
+        draft.content.clone(),
+        draft.trigger_label.as_deref(),
+        draft.kb_version.as_deref(),
+        Purpose::Reply,
+    )
+    .await?
+    else {
+        return Ok(None);
+    };
+    watch::start(&reply_uri, &draft.to_user);
+    bot.memory.record_reply(ReplyRecord {
+        uri: reply_uri.clone(),
+        parent_uri: draft.parent_uri,
+        to_user: draft.to_user,
+        prompt: draft.prompt,
+        instruction: draft.instruction,
+        trigger_label: draft.trigger_label,
+        kb_version: draft.kb_version,
+        content: draft.content,
+        timestamp: now_millis(),
+        ratings: Default::default(),
+    });
+    bot.memory.save()?;
+    Ok(Some(reply_uri))
+}
+
+// Replaces one of the bot's replies with the draft's content. Overwritten in place, so
+// the new version can't be split into a thread. None in shadow-only mode.
+async fn overwrite_reply(bot: &mut Bot, draft: Draft, uri: String) -> Result<Option<String>> {
+    if shadow::is_only() {
+        log!("Shadow mode: not overwriting reply {}: {}", uri, privacy::content(&draft.content));
+        analytics::record(AnalyticsRow::new("shadow_suppressed", None, Some(&draft.parent_uri)).with("content", draft.content.as_str()));
+        return Ok(None);
+    }
+    let published = footer::append(&draft.content);
+    let kind = post_kind::kind_for(&published);
+    homeserver::write_post(&bot.client, &uri, Some(&draft.parent_uri), published, kind).await?;
+    if let Some(stored) = bot.memory.reply_mut(&uri) {
+        stored.content = draft.content;
+        stored.kb_version = draft.kb_version;
+    }
+    bot.memory.save()?;
+    log!("Overwrote reply {}", uri);
+    Ok(Some(uri))
+}
+
+fn sync_pending_view(bot: &Bot) {
+    if let Ok(mut view) = bot.pending_view.lock() {
+        *view = bot.memory.pending.clone();
+    }
+}
+
+// Publishes a reply to a mention (or tag, when `tag_label` is set), recording it in the
+// analytics log (with the knowledge base version of generated replies) and writing its
+// provenance record. Returns None without publishing when the bot already posted the
+// same reply in this thread.
+async fn publish_reply(bot: &mut Bot, parent_uri: &str, content: String, tag_label: Option<&str>, kb_version: Option<&str>, purpose: Purpose) -> Result<Option<String>> {
+    if shadow::is_only() {
+        log!("Shadow mode: not publishing reply to {}: {}", privacy::uri(parent_uri), privacy::content(&content));
+        analytics::record(AnalyticsRow::new("shadow_suppressed", None, Some(parent_uri)).with("content", content.as_str()));
+        return Ok(None);
+    }
+    if let Some(existing) = duplicate_reply(bot, parent_uri, &content, tag_label).await {
+        log!("Skipping reply to {}: the same reply is already published as {}", privacy::uri(parent_uri), existing);
+        analytics::record(AnalyticsRow::new("duplicate_skipped", None, Some(parent_uri)).with("reply_uri", existing.as_str()));
+        return Ok(None);
+    }
+    let started = Instant::now();
+    let trigger = provenance::trigger_key(tag_label);
+    let reply_uri = publish_with(bot, Some(parent_uri), footer::append(&content), purpose, Some(&trigger)).await?;
+    bot.stages.finish("publish", started);
+    slo::record(bot.stages.stage_ms("llm"), bot.stages.elapsed_ms());
+    let mut row = AnalyticsRow::new("reply", None, Some(parent_uri))
+        .with("reply_uri", reply_uri.as_str())
+        .with("trigger", trigger.as_str())
+        .with("timings_ms", bot.stages.to_json());
+    if let Some(version) = kb_version {
+        row = row.with("kb_version", version).with("prompt_version", prompt::version());
+    }
+    analytics::record(row);
+    Ok(Some(reply_uri))
+}
+
+// A reply the bot already published that this one would duplicate: the provenance
+// record for the same post and trigger (a duplicate notification, or a retry that
+// raced the first attempt), or a reply with the same text to a post in this thread.
+// Lookup failures only log, so they never block a reply.
+async fn duplicate_reply(bot: &Bot, parent_uri: &str, content: &str, tag_label: Option<&str>) -> Option<String> {
+    let trigger = provenance::trigger_key(tag_label);
+    match provenance::find(&bot.client, &bot.keypair, parent_uri, &trigger).await {
+        Ok(Some(record)) => return Some(record.reply_uri),
+        Ok(None) => {}
+        Err(e) => log!("Failed to check provenance of {}: {}", privacy::uri(parent_uri), e),
+    }
+
+    let thread = match thread::ancestors(&bot.client, parent_uri).await {
+        Ok(thread) => thread,
+        Err(e) => {
+            log!("Failed to load the thread of {}: {}", privacy::uri(parent_uri), e);
+            return None;
+        }
+    };
+    let hash = provenance::content_hash(content);
+    let published_hash = provenance::content_hash(&footer::append(content));
+    let own_prefix = format!("pubky://{}/", bot.keypair.public_key());
+    // The bot's replies above this post, then its replies to posts in the thread
+    let own_post = thread.iter().find(|(uri, text)| uri.starts_with(&own_prefix) && provenance::content_hash(text) == published_hash);
+    if let Some((uri, _)) = own_post {
+        return Some(uri.clone());
+    }
+    bot.memory
+        .replies
+        .iter()
+        .find(|reply| thread.iter().any(|(uri, _)| *uri == reply.parent_uri) && provenance::content_hash(&reply.content) == hash)
+        .map(|reply| reply.uri.clone())
+}
+
+// Publishes a post or reply on Pubky, with the kind the post kind policy picks for
+// `purpose`, and returns its URI. Content too long for one post goes out as a thread,
+// each part replying to the one before; the URI is the first part's.
+async fn publish(bot: &mut Bot, parent_uri: Option<&str>, content: String, purpose: Purpose) -> Result<String> {
+    publish_with(bot, parent_uri, content, purpose, None).await
+}
+
+// `publish`, writing the reply's provenance record for `trigger` in the same batch:
+// the record goes out alongside the first post and each further part after its
+// parent, and a failed write rolls back the whole reply.
+async fn publish_with(bot: &mut Bot, parent_uri: Option<&str>, content: String, purpose: Purpose, trigger: Option<&str>) -> Result<String> {
+    let plan = post_kind::plan(&content, purpose);
+    if plan.parts.len() > 1 {
+        log!("Publishing {} characters as a thread of {} posts", content.chars().count(), plan.parts.len());
+    }
+    let mut batch = WriteBatch::new();
+    let mut posts = Vec::new();
+    let mut parent = parent_uri.map(String::from);
+    for part in plan.parts {
+        let uri = homeserver::new_post_uri(&bot.keypair);
+        let mut step = vec![Write { uri: uri.clone(), body: homeserver::post_body(parent.as_deref(), part.clone(), plan.kind.clone()).await? }];
+        if let (None, Some(parent_uri), Some(trigger)) = (posts.first(), parent_uri, trigger) {
+            step.push(provenance::record(&bot.keypair, &uri, parent_uri, trigger)?);
+        }
+        batch.step(step);
+        posts.push((parent.replace(uri.clone()), uri, part));
+    }
+    let first_uri = posts.first().map(|(_, uri, _)| uri.clone()).ok_or_else(|| anyhow::anyhow!("Nothing to publish"))?;
+    batch.execute(&bot.client).await?;
+    for (parent, uri, part) in posts {
+        announce(bot, parent.as_deref(), &uri, part)?;
+    }
+    Ok(first_uri)
+}
+
+// Tells the operator about a published post and mirrors it to Nostr when the bridge
+// is enabled.
+fn announce(bot: &mut Bot, parent_uri: Option<&str>, reply_uri: &str, content: String) -> Result<()> {
+    if let Some(telegram) = &bot.telegram {
+        let target = parent_uri.map(|p| format!("Replied to {}", p)).unwrap_or_else(|| "Posted".to_string());
+        telegram.notify(format!("✅ {}\n{}\n\n{}", target, reply_uri, content));
+    }
+
+    if let Some(nostr) = &bot.nostr {
+        let parent_event = parent_uri.and_then(|p| bot.memory.nostr_events.get(p).cloned());
+        match nostr.build_note(&content, reply_uri, parent_event.as_deref()) {
+            Ok(event) => {
+                bot.memory.nostr_events.insert(reply_uri.to_string(), event.id.clone());
+                bot.memory.save()?;
+                nostr.publish(event);
+            }
+            Err(e) => log!("Failed to build Nostr event: {}", e),
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_operator_command(bot: &mut Bot, command: OperatorCommand) {
+    let (id, approve) = match command {
+        OperatorCommand::Approve(id) => (id, true),
+        OperatorCommand::Deny(id) => (id, false),
+    };
+    bot.stages.reset();
+    let message = match bot.memory.take_pending(&id) {
+        None => format!("No pending reply with id {}", id),
+        Some(pending) if approve => {
+            // Published under the ID of the mention the draft answers
+            let published = match pending.draft.correlation_id.clone() {
+                Some(correlation_id) => correlation::scope(correlation_id, publish_draft(bot, pending.draft.clone())).await,
+                None => publish_draft(bot, pending.draft.clone()).await,
+            };
+            match published {
+                Ok(Some(uri)) => format!("Approved {}: published {}", id, uri),
+                Ok(None) => format!("Approved {}: the same reply was already published in the thread, skipped", id),
+                Err(e) => {
+                    // Put it back so the operator can retry
+                    bot.memory.pending.push(pending);
+                    format!("Failed to publish {}: {}", id, e)
+                }
             }
+        }
+        Some(_) => format!("Denied {}: reply dropped", id),
+    };
+    if let Err(e) = bot.memory.save() {
+        log!("Failed to save memory DB: {}", e);
+    }
+    sync_pending_view(bot);
+    log!("{}", message);
+    if let Some(telegram) = &bot.telegram {
+        telegram.notify(message);
+    }
+}
 
-            if notification.timestamp > last_timestamp {
-                last_timestamp = notification.timestamp;
+// Periodic posts that don't depend on notifications.
+async fn run_scheduled_jobs(bot: &mut Bot) -> Result<()> {
+    if let Some(warning) = storage::take_warning() {
+        if let Some(telegram) = &bot.telegram {
+            telegram.notify(format!("⚠️ {}", warning));
+        }
+    }
+    for warning in models::take_warnings() {
+        if let Some(telegram) = &bot.telegram {
+            telegram.notify(format!("⚠️ {}", warning));
+        }
+    }
+    for alert in slo::check() {
+        log!("{}", alert);
+        if let Some(telegram) = &bot.telegram {
+            telegram.notify(format!("⚠️ {}", alert));
+        }
+        slo::send_webhook(alert);
+    }
+    if leaderboard::is_enabled() && bot.memory.last_leaderboard_at == 0 {
+        // First run: start counting the week now instead of posting right away
+        bot.memory.last_leaderboard_at = now_millis();
+        bot.memory.save()?;
+    }
+    if leaderboard::is_enabled() && now_millis() - bot.memory.last_leaderboard_at >= leaderboard::WEEK_MILLIS {
+        let rows = analytics::load_rows()?;
+        match leaderboard::build_post(&rows, &bot.memory, now_millis() - leaderboard::WEEK_MILLIS) {
+            Some(post) => {
+                let uri = publish(bot, None, post, Purpose::Post).await?;
+                log!("Published weekly leaderboard: {}", uri);
+                broadcasts::queue_delivery(&mut bot.memory, broadcasts::LEADERBOARD, &uri);
             }
+            None => log!("No interactions this week, skipping leaderboard"),
+        }
+        bot.memory.last_leaderboard_at = now_millis();
+        bot.memory.save()?;
+    }
+    if broadcasts::daily_tip_enabled() {
+        let last = bot.memory.last_broadcast_at.get(broadcasts::DAILY_TIP).copied();
+        if last.is_none_or(|at| now_millis() - at >= DAY_MILLIS) {
+            // Recorded first, so a failing model isn't asked again on every poll
+            bot.memory.last_broadcast_at.insert(broadcasts::DAILY_TIP.to_string(), now_millis());
+            bot.memory.save()?;
+            let tip = broadcasts::build_daily_tip(&bot.memory.recent_tips).await?;
+            let uri = publish(bot, None, tip.clone(), Purpose::Post).await?;
+            log!("Published daily tip: {}", uri);
+            analytics::record(AnalyticsRow::new("broadcast", None, Some(&uri)).with("topic", broadcasts::DAILY_TIP));
+            bot.memory.recent_tips.push(tip);
+            let excess = bot.memory.recent_tips.len().saturating_sub(7);
+            bot.memory.recent_tips.drain(..excess);
+            broadcasts::queue_delivery(&mut bot.memory, broadcasts::DAILY_TIP, &uri);
+            bot.memory.save()?;
+        }
+    }
+    if calendar::is_enabled() {
+        let before = bot.memory.calendar_runs.clone();
+        let posts = calendar::due(&mut bot.memory.calendar_runs);
+        if bot.memory.calendar_runs != before {
+            bot.memory.save()?;
+        }
+        for post in posts {
+            let uri = publish(bot, None, calendar::build_post(&post).await?, Purpose::Post).await?;
+            log!("Published calendar post {}: {}", post.name, uri);
+            analytics::record(AnalyticsRow::new("calendar_post", None, Some(&uri)).with("entry", post.name.as_str()).with("scheduled_at", post.scheduled_at));
+        }
+    }
+    if !bot.memory.broadcast_deliveries.is_empty() && now_millis() - bot.memory.last_delivery_batch_at >= broadcasts::batch_interval_millis() {
+        bot.memory.last_delivery_batch_at = now_millis();
+        if let Some((post_uri, count, text)) = broadcasts::next_batch(&bot.memory) {
+            let uri = publish(bot, Some(&post_uri), text, Purpose::Post).await?;
+            log!("Mentioned {} subscriber(s) under {}: {}", count, post_uri, uri);
+            broadcasts::mark_delivered(&mut bot.memory, count);
+        }
+        bot.memory.save()?;
+    }
+    if now_millis() - bot.memory.last_retention_at >= DAY_MILLIS {
+        if let Some(policy) = RetentionPolicy::from_env()? {
+            let pruned = retention::prune(&bot.client, &bot.keypair, &mut bot.memory, &policy, false).await?;
+            log!("Retention job pruned {} old replies", pruned.len());
+        }
+        bot.memory.last_retention_at = now_millis();
+        bot.memory.save()?;
+    }
+    if follows::is_enabled() && now_millis() - bot.memory.last_follow_sync_at >= DAY_MILLIS {
+        let rows = analytics::load_rows()?;
+        let result = follows::sync(&bot.client, &bot.keypair, &rows, false).await?;
+        log!("Follow sync: followed {}, unfollowed {}", result.followed.len(), result.unfollowed.len());
+        bot.memory.last_follow_sync_at = now_millis();
+        bot.memory.save()?;
+    }
+    if backup::is_enabled() {
+        // A homeserver that is still down just leaves the rest for the next run
+        if let Err(e) = backup::sync(&bot.client).await {
+            log!("Backup homeserver sync incomplete: {}", e);
+        }
+    }
+    if profiles::is_status_enabled() {
+        profiles::update_status(&bot.client, &bot.keypair).await?;
+    }
+    if let Err(e) = heartbeat::write_if_due(&bot.client, &bot.keypair, bot.memory.pending.len()).await {
+        log!("Failed to write heartbeat: {}", e);
+    }
+    if let Err(e) = snapshot::write_if_due() {
+        log!("Failed to write state snapshot: {}", e);
+    }
+    if milestones::is_enabled() && now_millis() - bot.memory.last_milestone_check_at >= milestones::CHECK_INTERVAL_MILLIS {
+        check_follower_milestone(bot).await?;
+        bot.memory.last_milestone_check_at = now_millis();
+        bot.memory.save()?;
+    }
+    Ok(())
+}
+
+// Posts about the highest milestone the follower count has passed since the last
+// announcement. The first check only records where the count stands, so enabling the
+// feature on an established bot doesn't celebrate old milestones.
+async fn check_follower_milestone(bot: &mut Bot) -> Result<()> {
+    let followers = context::follower_count(&bot.keypair.public_key().to_string()).await?;
+    let Some(milestone) = milestones::reached(&milestones::configured(), followers) else {
+        bot.memory.announced_milestone.get_or_insert(0);
+        return Ok(());
+    };
+    match bot.memory.announced_milestone {
+        None => {
+            log!("Follower milestones: {} followers, counting from the {} milestone", followers, milestone);
+            bot.memory.announced_milestone = Some(milestone);
         }
+        Some(announced) if milestone > announced => {
+            let uri = publish(bot, None, milestones::build_post(milestone, followers), Purpose::Post).await?;
+            log!("Published follower milestone post for {}: {}", milestone, uri);
+            analytics::record(AnalyticsRow::new("follower_milestone", None, Some(&uri)).with("milestone", milestone).with("followers", followers));
+            bot.memory.announced_milestone = Some(milestone);
+        }
+        Some(_) => {}
     }
+    Ok(())
+}
 
-    if last_timestamp > last_read {
-        update_last_read(client, keypair, last_timestamp + 1).await?;
-        
-        let new_last_read = get_last_read(client, keypair).await?;
-        println!("Verifying last_read update - New value: {}", new_last_read);
-        if new_last_read != last_timestamp + 1 {
-            println!("WARNING: last_read was not updated correctly!");
-            println!("Expected: {}, Got: {}", last_timestamp + 1, new_last_read);
+// MAX_MENTION_AGE (e.g. "24h"): mentions and tags older than this are skipped, so a
+// bot coming back after a long downtime doesn't answer week-old posts.
+fn max_mention_age_millis() -> Option<i64> {
+    let value = env::var("MAX_MENTION_AGE").ok().filter(|v| !v.trim().is_empty())?;
+    match parse_age(value.trim()) {
+        Ok(secs) => Some(secs as i64 * 1000),
+        Err(e) => {
+            log!("Ignoring MAX_MENTION_AGE: {}", e);
+            None
         }
     }
+}
 
+// Handles one new notification; mentions and tags older than MAX_MENTION_AGE are skipped.
+async fn handle_notification(bot: &mut Bot, notification: &Notification, stale_before: Option<i64>) -> Result<()> {
+    let kind = notification.kind();
+    let stale = stale_before.is_some_and(|before| notification.timestamp < before);
+    if stale && matches!(kind, NotificationKind::Mention { .. } | NotificationKind::TagPost { .. }) {
+        let type_name = notification.type_name();
+        log!("Skipping stale {} notification from {}", type_name, notification.timestamp);
+        analytics::record(AnalyticsRow::new("stale_skipped", None, notification.post_uri()).with("type", type_name));
+        return Ok(());
+    }
+    match kind {
+        NotificationKind::Mention { mentioned_by, post_uri } => handle_mention(bot, &mentioned_by, &post_uri).await?,
+        NotificationKind::TagPost { tagged_by, tag_label, post_uri } => handle_tag(bot, &tagged_by, &tag_label, &post_uri).await?,
+        NotificationKind::Follow { followed_by } => {
+            log!("Received follow from: {}", privacy::user(&followed_by));
+            if Canned::Follow.is_enabled() {
+                thank_follower(bot, &followed_by).await?;
+            }
+        }
+        NotificationKind::Reply { replied_by, reply_uri } => {
+            log!("Received reply from {}: {}", privacy::user(&replied_by), privacy::uri(&reply_uri));
+        }
+        NotificationKind::Unknown { kind, raw } => notifications::record_unknown(&kind, &raw),
+    }
+    Ok(())
+}
+
+// Remembers what became of a mention or tag for its author's !didyousee.
+fn record_receipt(memory: &mut MemoryDb, notification: &Notification, receipt: impl FnOnce(&MemoryDb, &str) -> memory::Receipt) {
+    let (Some(user), Some(uri)) = (notification.actor(), notification.post_uri()) else { return };
+    let receipt = receipt(memory, uri);
+    memory.record_receipt(user, receipt);
+}
+
+// Handles a batch taken off the intake queue, together with notifications deferred
+// earlier, then moves last_read past everything in it. A failing notification and
+// the ones after it are deferred too, so they are retried with the next batch; one that
+// failed NOTIFICATION_MAX_ATTEMPTS times is quarantined and the batch goes on without it.
+async fn handle_notifications(bot: &mut Bot, batch: Vec<Notification>) -> Result<()> {
+    let last_read = get_last_read(&bot.client, &bot.keypair).await?;
+    let mut last_timestamp = None;
+    let stale_before = max_mention_age_millis().map(|age| now_millis() - age);
+
+    let mut notifications: Vec<Notification> = bot.memory.deferred_notifications.iter().cloned().chain(batch).collect();
+    if notifications.len() > 1 && notifications::is_priority_enabled() {
+        let followers = follows::followers(&bot.keypair).await.unwrap_or_else(|e| {
+            log!("Failed to load followers for mention priority: {}", e);
+            Default::default()
+        });
+        notifications = notifications::prioritize(notifications, |actor| match actor {
+            Some(user) if commands::is_owner(user) => AuthorClass::Owner,
+            Some(user) if followers.contains(user) => AuthorClass::Follower,
+            _ => AuthorClass::Other,
+        });
+    }
+    let cap = notifications::per_author_cap();
+    let mut handled: HashMap<String, usize> = HashMap::new();
+    let mut deferred = Vec::new();
+    let mut queue = notifications.into_iter();
+    while let Some(notification) = queue.next() {
+        last_timestamp = last_timestamp.max(Some(notification.timestamp));
+        if cap > 0 && notification.post_uri().is_some() {
+            let count = handled.entry(notification.actor().unwrap_or_default().to_string()).or_default();
+            if *count >= cap {
+                record_receipt(&mut bot.memory, &notification, |_, uri| receipts::deferred(uri));
+                deferred.push(notification);
+                continue;
+            }
+            *count += 1;
+        }
+        let id = correlation::for_notification(notification.timestamp, &notification.body);
+        let result = correlation::scope(id.clone(), handle_notification(bot, &notification, stale_before)).await;
+        let rows = analytics::recent(&id);
+        // A receipt would store the mention again under the user who just had their data deleted
+        let forgotten = rows.iter().any(|row| row.event == commands::DATA_DELETED_EVENT);
+        match &result {
+            _ if forgotten => {}
+            Ok(()) => record_receipt(&mut bot.memory, &notification, |memory, uri| {
+                receipts::from_rows(uri, &rows, memory.pending.iter().any(|p| p.draft.parent_uri == uri))
+            }),
+            Err(e) => record_receipt(&mut bot.memory, &notification, |_, uri| receipts::failed(uri, &e.to_string())),
+        }
+        if let Err(e) = result {
+            activity::error("notification", &e);
+            let attempts = bot.memory.notification_attempts.entry(id.clone()).or_default();
+            *attempts += 1;
+            if *attempts >= notifications::max_attempts() {
+                log!("Notification failed {} times, quarantining it: {}", attempts, e);
+                bot.memory.notification_attempts.remove(&id);
+                let item = serde_json::to_value(&notification).unwrap_or_default();
+                notifications::quarantine(&item, &e.to_string());
+                notifications::mark_processed(notification.timestamp);
+                continue;
+            }
+            log!("Error handling notification, retrying with the next batch: {}", e);
+            deferred.push(notification);
+            for rest in queue {
+                last_timestamp = last_timestamp.max(Some(rest.timestamp));
+                deferred.push(rest);
+            }
+            break;
+        }
+        bot.memory.notification_attempts.remove(&id);
+        notifications::mark_processed(notification.timestamp);
+    }
+    if !deferred.is_empty() {
+        log!("Deferred {} notification(s) to the next batch", deferred.len());
+    }
+    // Also keeps the receipts recorded for the batch
+    bot.memory.deferred_notifications = deferred;
+    bot.memory.save()?;
+
+    // Deferred notifications are kept in the memory DB, so last_read can move past them
+    if let Some(last_timestamp) = last_timestamp.filter(|t| *t > last_read) {
+        // Written last, once replies and the memory DB are saved; a failed PUT is an error
+        // already, so it isn't read back
+        update_last_read(&bot.client, &bot.keypair, last_timestamp + 1).await?;
+    }
+
+    Ok(())
+}
+
+// Startup self-test (SELF_TEST): a scratch account mentions the bot under a probe post
+// and the mention is handled like any other until the reply shows up, or is waiting
+// for approval in supervised mode. Reports pass or fail, then removes the test posts.
+async fn run_self_test(bot: &mut Bot, intake: &mut tokio::sync::mpsc::Receiver<Notification>) -> bool {
+    if !selftest::is_testnet() {
+        log!("Self-test skipped: it only runs with TESTNET=true");
+        return false;
+    }
+    if shadow::is_only() {
+        log!("Self-test skipped: nothing is published with SHADOW_MODE=only");
+        return false;
+    }
+    let probe = match selftest::Probe::start(&bot.client, &bot.keypair).await {
+        Ok(probe) => probe,
+        Err(e) => {
+            log!("Self-test FAILED: could not set up the probe: {}", e);
+            analytics::record(AnalyticsRow::new("self_test", None, None).with("passed", false).with("error", e.to_string()));
+            return false;
+        }
+    };
+    let started = Instant::now();
+    let deadline = started + selftest::timeout();
+    let mut reply_uri = None;
+    let mut passed = false;
+    while Instant::now() < deadline {
+        if let Some(reply) = bot.memory.replies.iter().find(|r| r.parent_uri == probe.mention_uri) {
+            reply_uri = Some(reply.uri.clone());
+            passed = true;
+            break;
+        }
+        if let Some(pending) = bot.memory.pending.iter().find(|p| p.draft.parent_uri == probe.mention_uri).map(|p| p.id.clone()) {
+            log!("Self-test: reply generated and waiting for approval, dropping it");
+            bot.memory.take_pending(&pending);
+            sync_pending_view(bot);
+            passed = true;
+            break;
+        }
+        let mut batch = Vec::new();
+        if let Ok(Some(notification)) = tokio::time::timeout(tokio::time::Duration::from_secs(5), intake.recv()).await {
+            batch.push(notification);
+            while let Ok(notification) = intake.try_recv() {
+                batch.push(notification);
+            }
+        }
+        if !batch.is_empty() {
+            if let Err(e) = handle_notifications(bot, batch).await {
+                log!("Self-test: error handling notifications: {}", e);
+            }
+        }
+        if let Err(e) = publish_generated(bot).await {
+            log!("Self-test: error publishing generated replies: {}", e);
+        }
+    }
+    let elapsed = started.elapsed();
+    if passed {
+        log!("Self-test PASSED: the mention was answered in {:.1}s", elapsed.as_secs_f64());
+    } else {
+        log!("Self-test FAILED: no reply to {} within {}s", probe.mention_uri, selftest::timeout().as_secs());
+    }
+    analytics::record(AnalyticsRow::new("self_test", None, Some(&probe.mention_uri)).with("passed", passed).with("elapsed_ms", elapsed.as_millis() as u64));
+    if let Some(telegram) = &bot.telegram {
+        telegram.notify(format!("{} Startup self-test {}", if passed { "✅" } else { "❌" }, if passed { "passed" } else { "failed" }));
+    }
+    probe.clean_up(&bot.client, reply_uri.as_deref()).await;
+    if let Err(e) = bot.memory.save() {
+        log!("Failed to save memory DB: {}", e);
+    }
+    passed
+}
+
+// Finishes the replies generation workers came back with (see jobs.rs). A failed
+// generation is dropped like one that failed in process.
+async fn publish_generated(bot: &mut Bot) -> Result<()> {
+    if !jobs::is_enabled() {
+        return Ok(());
+    }
+    for result in jobs::take_results()? {
+        bot.stages.reset();
+        bot.priority = result.job.priority;
+        let id = result.job.correlation_id.clone().unwrap_or_else(|| result.job.id.clone());
+        let job_id = result.job.id.clone();
+        let outcome = correlation::scope(id, async {
+            let mut job = result.job;
+            let Some(response) = result.response else {
+                log!("Worker {} failed to generate job {}: {}", result.worker, job.id, result.error.unwrap_or_default());
+                analytics::record(AnalyticsRow::new("generation_failed", Some(&job.to_user), Some(&job.parent_uri)).with("job", job.id.as_str()).with("worker", result.worker));
+                return Ok(());
+            };
+            log!("Worker {} generated job {} in {}ms", result.worker, job.id, result.generation_ms);
+            if let Some(embedding) = job.faq_embedding.take() {
+                faq::insert(&job.to_user, &job.prompt, &response, embedding, job.kb_version.as_deref());
+            }
+            finish_reply(bot, job, response, None).await
+        })
+        .await;
+        bot.priority = Priority::Mention;
+        if let Err(e) = outcome {
+            log!("Failed to publish the reply of job {}: {}", job_id, e);
+            activity::error("job", &e);
+        }
+    }
+    Ok(())
+}
+
+// Answers follow-ups from the users the bot just replied to, found by watching those
+// threads for THREAD_WATCH_MINUTES. One that mentions the bot is answered only once,
+// whichever path sees it first.
+async fn check_watched_threads(bot: &mut Bot) -> Result<()> {
+    if !watch::is_enabled() {
+        return Ok(());
+    }
+    for (author, post_uri) in watch::poll().await? {
+        let id = correlation::for_notification(0, &serde_json::json!({ "type": "thread_follow_up", "post_uri": post_uri }));
+        let result = correlation::scope(id, async {
+            log!("Follow-up from {} in a watched thread: {}", privacy::user(&author), privacy::uri(&post_uri));
+            analytics::record(AnalyticsRow::new("thread_follow_up", Some(&author), Some(&post_uri)));
+            handle_mention(bot, &author, &post_uri).await
+        })
+        .await;
+        if let Err(e) = result {
+            log!("Failed to answer follow-up {}: {}", privacy::uri(&post_uri), e);
+            activity::error("follow_up", &e);
+        }
+    }
+    Ok(())
+}
+
+// Answers questions asked in the configured channels without mentioning the bot, with
+// the channel's persona.
+async fn check_channels(bot: &mut Bot) -> Result<()> {
+    if !channels::is_enabled() {
+        return Ok(());
+    }
+    let bot_key = bot.keypair.public_key().to_string();
+    for question in channels::poll(&bot.client, &bot_key).await? {
+        let id = correlation::for_notification(0, &serde_json::json!({ "type": "channel_question", "post_uri": question.post_uri }));
+        bot.persona = channels::persona(&question.channel);
+        bot.priority = Priority::Engagement;
+        let result = correlation::scope(id, async {
+            log!("Question from {} in channel {}: {}", privacy::user(&question.author), question.channel, privacy::uri(&question.post_uri));
+            analytics::record(AnalyticsRow::new("channel_question", Some(&question.author), Some(&question.post_uri)).with("channel", question.channel.as_str()));
+            handle_mention(bot, &question.author, &question.post_uri).await
+        })
+        .await;
+        bot.persona = None;
+        bot.priority = Priority::Mention;
+        if let Err(e) = result {
+            log!("Failed to answer channel question {}: {}", privacy::uri(&question.post_uri), e);
+            activity::error("channel_question", &e);
+        }
+    }
     Ok(())
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(|s| s.as_str()) {
+        None | Some("run") => run_bot().await,
+        Some("backup-sync") => run_backup_sync().await,
+        Some("bench") => bench::run(&args[1..]).await,
+        Some("check") => check::run().await,
+        Some("delete-replies") => run_delete_replies(&args[1..]).await,
+        Some("doctor") => doctor::run().await,
+        Some("evaluate") => evaluate::run(&args[1..]).await,
+        Some("index-kb") => run_index_kb().await,
+        Some("persona") => persona::run(&args[1..]).await,
+        Some("prune") => run_prune(&args[1..]).await,
+        Some("replay") => replay::run(&args[1..]).await,
+        Some("schedule") => calendar::run(&args[1..]).await,
+        Some("simulate") => simulate::run(&args[1..]).await,
+        Some("storage") => run_storage(&args[1..]).await,
+        Some("sync-follows") => run_sync_follows(&args[1..]).await,
+        Some("tail") => activity::run_tail(&args[1..]).await,
+        Some("worker") => jobs::run_worker().await,
+        Some(other) => Err(anyhow::anyhow!(
+            "Unknown command: {} (expected run, backup-sync, bench, check, delete-replies, doctor, evaluate, index-kb, persona, prune, replay, schedule, simulate, storage, sync-follows, tail or worker)",
+            other
+        )),
+    }
+}
+
+async fn run_delete_replies(args: &[String]) -> Result<()> {
+    let filter = ReplyFilter::parse(args)?;
+    let (client, keypair) = setup_client().await?;
+    let mut memory = MemoryDb::load()?;
+    let matched = delete_replies(&client, &keypair, &mut memory, &filter).await?;
+    if filter.dry_run {
+        log!("{} matching replies (dry run, nothing deleted)", matched.len());
+    } else {
+        log!("Deleted {} replies", matched.len());
+    }
+    Ok(())
+}
+
+async fn run_prune(args: &[String]) -> Result<()> {
+    let dry_run = match args {
+        [] => false,
+        [flag] if flag == "--dry-run" => true,
+        _ => return Err(anyhow::anyhow!("Usage: prune [--dry-run]")),
+    };
+    let (client, keypair) = setup_client().await?;
+    let policy = RetentionPolicy::from_env()?
+        .ok_or_else(|| anyhow::anyhow!("Set RETENTION_MAX_AGE and/or RETENTION_MAX_POSTS to prune"))?;
+    let mut memory = MemoryDb::load()?;
+    let pruned = retention::prune(&client, &keypair, &mut memory, &policy, dry_run).await?;
+    if dry_run {
+        log!("{} replies past the retention limits (dry run, nothing deleted)", pruned.len());
+    } else {
+        log!("Pruned {} replies", pruned.len());
+    }
+    Ok(())
+}
+
+async fn run_sync_follows(args: &[String]) -> Result<()> {
+    let dry_run = match args {
+        [] => false,
+        [flag] if flag == "--dry-run" => true,
+        _ => return Err(anyhow::anyhow!("Usage: sync-follows [--dry-run]")),
+    };
+    let (client, keypair) = setup_client().await?;
+    let result = follows::sync(&client, &keypair, &analytics::load_rows()?, dry_run).await?;
+    if dry_run {
+        log!("Would follow {} and unfollow {} account(s) (dry run, nothing changed)", result.followed.len(), result.unfollowed.len());
+    } else {
+        log!("Followed {} and unfollowed {} account(s)", result.followed.len(), result.unfollowed.len());
+    }
+    Ok(())
+}
+
+async fn run_storage(args: &[String]) -> Result<()> {
+    match args {
+        [] => {
+            dotenv().ok();
+        }
+        [flag] if flag == "--scan" => {
+            let (client, keypair) = setup_client().await?;
+            let files = homeserver::scan_storage(&client, &keypair).await?;
+            log!("Scanned {} files on the homeserver", files.len());
+            storage::replace_all(files);
+        }
+        _ => return Err(anyhow::anyhow!("Usage: storage [--scan]")),
+    }
+    log!("Storage usage: {}", storage::format_usage(&storage::usage()));
+    Ok(())
+}
+
+async fn run_backup_sync() -> Result<()> {
     let (client, keypair) = setup_client().await?;
-    create_profile(&client, &keypair).await?;
+    backup::connect(&client, &keypair).await?;
+    if !backup::is_enabled() {
+        return Err(anyhow::anyhow!("Set BACKUP_HOMESERVER to the backup homeserver's public key"));
+    }
+    // Everything the bot has written, including posts from before usage tracking
+    let mut uris = homeserver::list_own_posts(&client, &keypair).await?;
+    uris.extend(storage::tracked_uris());
+    uris.push(format!("pubky://{}/pub/pubky.app/profile.json", keypair.public_key()));
+    uris.push(format!("pubky://{}/pub/pubky.app/last_read", keypair.public_key()));
+    backup::queue_for_backup(uris);
+    let synced = backup::sync(&client).await?;
+    log!("Copied {} file(s) to the backup homeserver", synced);
+    Ok(())
+}
+
+async fn run_index_kb() -> Result<()> {
+    dotenv().ok();
+    let started = std::time::Instant::now();
+    let index = kb::build_index(None).await?;
+    log!(
+        "Indexed {} chunks from {} (version {}) in {:?}",
+        index.chunks.len(),
+        kb::knowledge_base_path(),
+        index.version,
+        started.elapsed()
+    );
+    for language in kb::variants() {
+        let index = kb::build_index(Some(&language)).await?;
+        log!("Indexed {} chunks from the {} knowledge base (version {})", index.chunks.len(), lang::name(&language), index.version);
+    }
+    Ok(())
+}
+
+async fn run_bot() -> Result<()> {
+    let (client, keypair) = setup_client().await?;
+    keepalive::spawn(client.clone(), keypair.clone()).await;
+    if let Err(e) = backup::connect(&client, &keypair).await {
+        log!("Backup homeserver unavailable, continuing without it: {}", e);
+    }
+    migrations::upgrade_homeserver(&client, &keypair).await?;
+    let memory = MemoryDb::load()?;
+    snapshot::restore();
+    let nostr = NostrBridge::from_env(&keypair)?;
+    profiles::create_bot_profile(&client, &keypair).await?;
     // create_hello_world_post(&client, &keypair).await?;
+    let telegram = TelegramBridge::from_env()?.map(Arc::new);
+    let (operator_tx, operator_commands) = mpsc::unbounded_channel();
+    if let Some(telegram) = &telegram {
+        telegram.spawn_listener(operator_tx.clone());
+    }
+    let pending_view: PendingView = Arc::new(Mutex::new(memory.pending.clone()));
+    AdminApi::spawn_from_env(pending_view.clone(), operator_tx).await?;
+    if is_supervised() {
+        log!("Supervised mode: generated replies wait for operator approval ({} pending)", memory.pending.len());
+    }
+    analytics::record(AnalyticsRow::new("startup", None, None));
+    log!("Homeserver storage: {}", storage::format_usage(&storage::usage()));
+    let mut bot = Bot { client, keypair, memory, nostr, telegram, operator_commands, pending_view, profiles: ProfileCache::from_env(), stages: StageTimings::default(), persona: None, priority: Priority::Mention };
 
-    println!("Starting notification polling...");
+    chaos::announce();
+    heartbeat::mark_started();
+    if heartbeat::is_enabled() {
+        log!("Heartbeat: {}", heartbeat::url(&bot.keypair));
+    }
+    lease::spawn(bot.client.clone(), bot.keypair.clone());
+    lease::wait_until_leader().await;
+    log!("Starting notification polling...");
+    let mut intake = intake::spawn(bot.client.clone(), bot.keypair.clone());
+    let page_size = intake::page_size();
+    if selftest::mode() != selftest::Mode::Off && !run_self_test(&mut bot, &mut intake).await && selftest::mode() == selftest::Mode::Required {
+        return Err(anyhow::anyhow!("Startup self-test failed (SELF_TEST=required)"));
+    }
+    let mut next = None;
+    let mut idle_rounds = 0;
     loop {
-        if let Err(e) = check_notifications(&client, &keypair).await {
-            println!("Error checking notifications: {}", e);
+        if !lease::is_leader() {
+            // Stop polling; whatever was queued is still unread and the leader handles it
+            log!("Lost the instance lease, idling");
+            drop(intake);
+            next = None;
+            lease::wait_until_leader().await;
+            intake = intake::spawn(bot.client.clone(), bot.keypair.clone());
+            continue;
+        }
+        // Whatever is queued right now, up to a page
+        let mut batch: Vec<Notification> = next.take().into_iter().collect();
+        while batch.len() < page_size {
+            match intake.try_recv() {
+                Ok(notification) => batch.push(notification),
+                Err(_) => break,
+            }
+        }
+        if !batch.is_empty() || !bot.memory.deferred_notifications.is_empty() {
+            idle_rounds = 0;
+            if let Err(e) = handle_notifications(&mut bot, batch).await {
+                log!("Error checking notifications: {}", e);
+                activity::error("notifications", &e);
+            }
+        } else {
+            idle_rounds += 1;
+        }
+        if let Err(e) = publish_generated(&mut bot).await {
+            log!("Error publishing generated replies: {}", e);
+            activity::error("generation_jobs", &e);
+        }
+        if let Err(e) = check_watched_threads(&mut bot).await {
+            log!("Error checking watched threads: {}", e);
+            activity::error("watched_threads", &e);
+        }
+        if let Err(e) = check_channels(&mut bot).await {
+            log!("Error checking channels: {}", e);
+            activity::error("channels", &e);
+        }
+        if let Err(e) = run_scheduled_jobs(&mut bot).await {
+            log!("Error running scheduled jobs: {}", e);
+            activity::error("scheduled_jobs", &e);
+        }
+
+        // Handle operator decisions as they arrive while waiting for the next notification;
+        // the wait stretches with the throttle level and while nothing comes in
+        let sleep = tokio::time::sleep(throttle::idle_interval(tokio::time::Duration::from_secs(5), idle_rounds));
+        tokio::pin!(sleep);
+        loop {
+            let command = tokio::select! {
+                _ = &mut sleep => None,
+                Some(notification) = intake.recv(), if next.is_none() => {
+                    next = Some(notification);
+                    None
+                }
+                Some(command) = bot.operator_commands.recv() => Some(command),
+            };
+            let Some(command) = command else { break };
+            handle_operator_command(&mut bot, command).await;
         }
-        tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
     }
-} 
\ No newline at end of file
+}
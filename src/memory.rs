@@ -0,0 +1,448 @@
+use crate::correlation;
+use crate::migrations;
+use crate::notifications::Notification;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Local JSON store for everything the bot remembers between restarts.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MemoryDb {
+    // Format version, see migrations::MEMORY_DB
+    #[serde(default)]
+    pub schema_version: u32,
+    #[serde(default)]
+    pub users: HashMap<String, UserMemory>,
+    #[serde(default)]
+    pub replies: Vec<ReplyRecord>,
+    // Pubky post URI -> id of the Nostr event it was mirrored as
+    #[serde(default)]
+    pub nostr_events: HashMap<String, String>,
+    // Generated replies waiting for operator approval (supervised mode)
+    #[serde(default)]
+    pub pending: Vec<PendingReply>,
+    #[serde(default)]
+    pub next_pending_id: u64,
+    // When the last weekly leaderboard was posted (unix ms)
+    #[serde(default)]
+    pub last_leaderboard_at: i64,
+    // When the retention job last pruned old replies (unix ms)
+    #[serde(default)]
+    pub last_retention_at: i64,
+    // When the follow graph was last synchronized (unix ms)
+    #[serde(default)]
+    pub last_follow_sync_at: i64,
+    // Highest follower milestone already announced; None until the first check
+    #[serde(default)]
+    pub announced_milestone: Option<u64>,
+    // When the follower count was last checked for milestones (unix ms)
+    #[serde(default)]
+    pub last_milestone_check_at: i64,
+    // Broadcast topic -> users subscribed to it
+    #[serde(default)]
+    pub subscriptions: BTreeMap<String, BTreeSet<String>>,
+    // When each scheduled broadcast last ran (unix ms)
+    #[serde(default)]
+    pub last_broadcast_at: BTreeMap<String, i64>,
+    // Broadcasts whose subscribers are still being notified, oldest first
+    #[serde(default)]
+    pub broadcast_deliveries: Vec<BroadcastDelivery>,
+    // When the last batch of subscriber mentions was posted (unix ms)
+    #[serde(default)]
+    pub last_delivery_batch_at: i64,
+    // Mentions and tags held back by the per-author cap, handled on a later poll
+    #[serde(default)]
+    pub deferred_notifications: Vec<Notification>,
+    // Failed attempts at handling a notification, by its correlation ID
+    #[serde(default)]
+    pub notification_attempts: BTreeMap<String, u32>,
+    // The last few daily tips, newest last, so the next one picks another subject
+    #[serde(default)]
+    pub recent_tips: Vec<String>,
+    // Content calendar entry -> scheduled time of its last handled post (unix ms)
+    #[serde(default)]
+    pub calendar_runs: BTreeMap<String, i64>,
+    // Threads muted with !quiet: root post URI -> when (unix ms)
+    #[serde(default)]
+    pub muted_threads: BTreeMap<String, i64>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UserMemory {
+    #[serde(default)]
+    pub prefs: BTreeMap<String, String>,
+    #[serde(default)]
+    pub leaderboard_opt_out: bool,
+    // When the bot last bowed out of the daily conversation limit with this user (unix ms)
+    #[serde(default)]
+    pub farewell_at: i64,
+    // Reply language inferred from the user's posts; an explicit `lang` pref wins
+    #[serde(default)]
+    pub language: Option<String>,
+    // The bot already thanked this user for following it
+    #[serde(default)]
+    pub thanked_follow: bool,
+    // What became of the user's latest mentions and tags, newest last, for !didyousee
+    #[serde(default)]
+    pub receipts: Vec<Receipt>,
+}
+
+// Receipts kept per user
+const MAX_RECEIPTS: usize = 20;
+
+// A post from a user that the bot saw, and what it did with it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Receipt {
+    pub post_uri: String,
+    pub seen_at: i64,
+    pub outcome: ReceiptOutcome,
+    // The reply for answered posts, the reason for skipped ones, the error for failed ones
+    #[serde(default)]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiptOutcome {
+    Answered,
+    // The reply waits for operator approval
+    AwaitingApproval,
+    Skipped,
+    // Held back by the per-author cap, handled on a later poll
+    Deferred,
+    // Handling failed; it is retried with the next batch
+    Failed,
+}
+
+// A published broadcast and the subscribers not yet mentioned under it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BroadcastDelivery {
+    pub topic: String,
+    pub post_uri: String,
+    pub remaining: Vec<String>,
+}
+
+// A reply the bot published, kept as conversation memory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplyRecord {
+    pub uri: String,
+    pub parent_uri: String,
+    pub to_user: String,
+    pub prompt: String,
+    // Extra system instruction from a command such as !eli5
+    #[serde(default)]
+    pub instruction: Option<String>,
+    // Tag label that triggered this reply, if any
+    #[serde(default)]
+    pub trigger_label: Option<String>,
+    // Knowledge base version the reply was generated with
+    #[serde(default)]
+    pub kb_version: Option<String>,
+    pub content: String,
+    pub timestamp: i64,
+    // Feedback tags on the reply: tagger -> +1 or -1
+    #[serde(default)]
+    pub ratings: BTreeMap<String, i8>,
+}
+
+impl ReplyRecord {
+    pub fn score(&self) -> i32 {
+        self.ratings.values().map(|v| *v as i32).sum()
+    }
+}
+
+// A generated reply that has not been published yet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Draft {
+    pub parent_uri: String,
+    pub to_user: String,
+    pub prompt: String,
+    #[serde(default)]
+    pub instruction: Option<String>,
+    #[serde(default)]
+    pub trigger_label: Option<String>,
+    #[serde(default)]
+    pub kb_version: Option<String>,
+    pub content: String,
+    // Correlation ID of the notification, restored when an approved draft is published
+    #[serde(default)]
+    pub correlation_id: Option<String>,
+    // The bot's reply this draft replaces in place (!regen) instead of posting a new one
+    #[serde(default)]
+    pub overwrite: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingReply {
+    pub id: String,
+    pub created_at: i64,
+    #[serde(flatten)]
+    pub draft: Draft,
+}
+
+impl MemoryDb {
+    pub fn load() -> Result<MemoryDb> {
+        let path = PathBuf::from(env::var("MEMORY_DB").unwrap_or_else(|_| "memory.json".to_string()));
+        let mut db = match migrations::upgrade(&path, &migrations::MEMORY_DB)? {
+            Some(value) => serde_json::from_value::<MemoryDb>(value)
+                .map_err(|e| anyhow::anyhow!("Failed to parse memory DB {}: {}", path.display(), e))?,
+            None => {
+                log!("Memory DB not found at {}, starting empty", path.display());
+                MemoryDb::default()
+            }
+        };
+        db.schema_version = migrations::MEMORY_DB.version();
+        db.path = path;
+        Ok(db)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        // Write to a temp file first so a crash mid-write can't truncate the DB
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, content)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    pub fn prefs(&self, user: &str) -> Option<&BTreeMap<String, String>> {
+        self.users.get(user).map(|u| &u.prefs).filter(|p| !p.is_empty())
+    }
+
+    pub fn set_prefs(&mut self, user: &str, prefs: BTreeMap<String, String>) {
+        self.users.entry(user.to_string()).or_default().prefs.extend(prefs);
+    }
+
+    pub fn clear_prefs(&mut self, user: &str) {
+        if let Some(u) = self.users.get_mut(user) {
+            u.prefs.clear();
+        }
+    }
+
+    // Language to answer `user` in: their `lang` pref, else the one inferred from their posts.
+    pub fn language(&self, user: &str) -> Option<&str> {
+        let user = self.users.get(user)?;
+        user.prefs.get("lang").or(user.language.as_ref()).map(String::as_str)
+    }
+
+    // Records what happened to a post, replacing an earlier receipt for it (a retry).
+    pub fn record_receipt(&mut self, user: &str, receipt: Receipt) {
+        let receipts = &mut self.users.entry(user.to_string()).or_default().receipts;
+        receipts.retain(|r| r.post_uri != receipt.post_uri);
+        receipts.push(receipt);
+        let excess = receipts.len().saturating_sub(MAX_RECEIPTS);
+        receipts.drain(..excess);
+    }
+
+    pub fn receipts(&self, user: &str) -> &[Receipt] {
+        self.users.get(user).map_or(&[], |u| u.receipts.as_slice())
+    }
+
+    // Stores the inferred language and reports whether it changed.
+    pub fn set_inferred_language(&mut self, user: &str, language: &str) -> bool {
+        let entry = self.users.entry(user.to_string()).or_default();
+        if entry.language.as_deref() == Some(language) {
+            return false;
+        }
+        entry.language = Some(language.to_string());
+        true
+    }
+
+    pub fn leaderboard_opt_out(&self, user: &str) -> bool {
+        self.users.get(user).is_some_and(|u| u.leaderboard_opt_out)
+    }
+
+    pub fn set_leaderboard_opt_out(&mut self, user: &str, opt_out: bool) {
+        self.users.entry(user.to_string()).or_default().leaderboard_opt_out = opt_out;
+    }
+
+    // Adds `user` to a broadcast topic; false if they were already subscribed.
+    pub fn subscribe(&mut self, topic: &str, user: &str) -> bool {
+        self.subscriptions.entry(topic.to_string()).or_default().insert(user.to_string())
+    }
+
+    // Removes `user` from a broadcast topic; false if they weren't subscribed.
+    pub fn unsubscribe(&mut self, topic: &str, user: &str) -> bool {
+        let removed = self.subscriptions.get_mut(topic).is_some_and(|users| users.remove(user));
+        self.subscriptions.retain(|_, users| !users.is_empty());
+        removed
+    }
+
+    // Mutes the thread starting at `root_uri`; false if it already was.
+    pub fn mute_thread(&mut self, root_uri: &str) -> bool {
+        self.muted_threads.insert(root_uri.to_string(), now_millis()).is_none()
+    }
+
+    // Unmutes the thread starting at `root_uri`; false if it wasn't muted.
+    pub fn unmute_thread(&mut self, root_uri: &str) -> bool {
+        self.muted_threads.remove(root_uri).is_some()
+    }
+
+    pub fn subscriptions_of(&self, user: &str) -> Vec<String> {
+        self.subscriptions.iter().filter(|(_, users)| users.contains(user)).map(|(topic, _)| topic.clone()).collect()
+    }
+
+    pub fn record_reply(&mut self, record: ReplyRecord) {
+        self.replies.push(record);
+    }
+
+    pub fn reply_mut(&mut self, uri: &str) -> Option<&mut ReplyRecord> {
+        self.replies.iter_mut().find(|r| r.uri == uri)
+    }
+
+    pub fn queue_pending(&mut self, draft: Draft) -> String {
+        self.next_pending_id += 1;
+        let id = format!("p{}", self.next_pending_id);
+        self.pending.push(PendingReply { id: id.clone(), created_at: now_millis(), draft });
+        id
+    }
+
+    pub fn take_pending(&mut self, id: &str) -> Option<PendingReply> {
+        let index = self.pending.iter().position(|p| p.id == id)?;
+        Some(self.pending.remove(index))
+    }
+
+    // Generated replies to `user` since `since` (unix ms).
+    pub fn replies_to_since(&self, user: &str, since: i64) -> usize {
+        self.replies.iter().filter(|r| r.to_user == user && r.timestamp >= since).count()
+    }
+
+    pub fn thanked_follow(&self, user: &str) -> bool {
+        self.users.get(user).is_some_and(|u| u.thanked_follow)
+    }
+
+    pub fn set_thanked_follow(&mut self, user: &str) {
+        self.users.entry(user.to_string()).or_default().thanked_follow = true;
+    }
+
+    pub fn farewell_at(&self, user: &str) -> i64 {
+        self.users.get(user).map(|u| u.farewell_at).unwrap_or(0)
+    }
+
+    pub fn set_farewell_at(&mut self, user: &str, at: i64) {
+        self.users.entry(user.to_string()).or_default().farewell_at = at;
+    }
+
+    // Records `user`'s feedback on one of the bot's replies and returns the reply.
+    pub fn rate_reply(&mut self, uri: &str, user: &str, value: i8) -> Option<&ReplyRecord> {
+        let record = self.replies.iter_mut().find(|r| r.uri == uri)?;
+        record.ratings.insert(user.to_string(), value);
+        Some(record)
+    }
+
+    pub fn remove_reply(&mut self, uri: &str) {
+        self.replies.retain(|r| r.uri != uri);
+    }
+
+    pub fn replies_to(&self, user: &str) -> Vec<&ReplyRecord> {
+        self.replies.iter().filter(|r| r.to_user == user).collect()
+    }
+
+    // Drops everything stored about a user and returns how many records were removed.
+    pub fn forget_user(&mut self, user: &str) -> usize {
+        // Nostr mirrors of the replies to the user and of the user's own posts, and
+        // threads the user started
+        let own_posts = format!("pubky://{}/", user);
+        let reply_uris: BTreeSet<String> = self.replies_to(user).iter().map(|r| r.uri.clone()).collect();
+        let mut removed = self.nostr_events.len() + self.muted_threads.len();
+        self.nostr_events.retain(|uri, _| !reply_uris.contains(uri) && !uri.starts_with(&own_posts));
+        self.muted_threads.retain(|root, _| !root.starts_with(&own_posts));
+        removed -= self.nostr_events.len() + self.muted_threads.len();
+        let before = self.replies.len();
+        self.replies.retain(|r| r.to_user != user);
+        self.pending.retain(|p| p.draft.to_user != user);
+        removed += before - self.replies.len();
+        for reply in &mut self.replies {
+            removed += reply.ratings.remove(user).map_or(0, |_| 1);
+        }
+        if let Some(u) = self.users.remove(user) {
+            removed += u.prefs.len() + u.receipts.len();
+        }
+        for topic in self.subscriptions_of(user) {
+            removed += usize::from(self.unsubscribe(&topic, user));
+        }
+        for delivery in &mut self.broadcast_deliveries {
+            delivery.remaining.retain(|u| u != user);
+        }
+        let (theirs, others) = std::mem::take(&mut self.deferred_notifications).into_iter().partition(|n| n.actor() == Some(user));
+        self.deferred_notifications = others;
+        for notification in &theirs {
+            self.notification_attempts.remove(&correlation::for_notification(notification.timestamp, &notification.body));
+        }
+        removed += theirs.len();
+        removed
+    }
+}
+
+pub const DAY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+pub fn now_millis() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn forget_user_leaves_nothing_about_them() {
+        let (alice, bob) = ("alice", "bob");
+        let post = |user: &str, id: u32| format!("pubky://{}/pub/pubky.app/posts/{}", user, id);
+        let reply = |to_user: &str, id: u32, parent: String| ReplyRecord {
+            uri: post("bot", id),
+            parent_uri: parent,
+            to_user: to_user.to_string(),
+            prompt: "question".to_string(),
+            instruction: None,
+            trigger_label: None,
+            kb_version: None,
+            content: "answer".to_string(),
+            timestamp: 0,
+            ratings: Default::default(),
+        };
+        let mut memory = MemoryDb::default();
+        memory.record_reply(reply(alice, 1, post(alice, 1)));
+        memory.record_reply(reply(alice, 2, post("carol", 5)));
+        let mut bobs = reply(bob, 3, post(bob, 1));
+        bobs.ratings.insert(alice.to_string(), 1);
+        memory.record_reply(bobs);
+        memory.set_prefs(alice, BTreeMap::from([("lang".to_string(), "pt".to_string())]));
+        memory.record_receipt(alice, Receipt { post_uri: post(alice, 1), seen_at: 0, outcome: ReceiptOutcome::Skipped, detail: None });
+        memory.subscribe("news", alice);
+        memory.subscribe("news", bob);
+        memory.broadcast_deliveries.push(BroadcastDelivery { topic: "news".to_string(), post_uri: post("bot", 9), remaining: vec![alice.to_string(), bob.to_string()] });
+        memory.queue_pending(Draft {
+            parent_uri: post(alice, 2),
+            to_user: alice.to_string(),
+            prompt: String::new(),
+            instruction: None,
+            trigger_label: None,
+            kb_version: None,
+            content: String::new(),
+            correlation_id: None,
+            overwrite: None,
+        });
+        let notification = Notification { timestamp: 1, body: json!({ "type": "mention", "mentioned_by": alice, "post_uri": post(alice, 3) }) };
+        memory.notification_attempts.insert(correlation::for_notification(notification.timestamp, &notification.body), 1);
+        memory.deferred_notifications.push(notification);
+        memory.nostr_events.insert(post("bot", 1), "event1".to_string());
+        memory.nostr_events.insert(post("bot", 2), "event2".to_string());
+        memory.nostr_events.insert(post(alice, 1), "event3".to_string());
+        memory.nostr_events.insert(post("bot", 3), "event4".to_string());
+        memory.muted_threads.insert(post(alice, 1), 0);
+
+        assert!(memory.forget_user(alice) > 0);
+        let stored = serde_json::to_string(&memory).unwrap();
+        assert!(!stored.contains(alice), "{}", stored);
+        for kept in [bob, "event4"] {
+            assert!(stored.contains(kept), "{}", stored);
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use crate::privacy;
+use crate::profiles::ProfileCache;
+use pubky::{Client, PublicKey};
+use std::env;
+
+// Most users mentioned in a post that are looked up and described to the model.
+const MAX_PEOPLE: usize = 5;
+// Bios are cut to this many characters in the prompt.
+const MAX_BIO_CHARS: usize = 160;
+
+// Resolving the users a post mentions, on unless MENTION_PROFILES=false: the model is
+// told who they are, so "what does pk:abc work on?" or "can you explain this to pk:xyz?"
+// is answered about, or addressed to, the right person.
+pub fn is_enabled() -> bool {
+    env::var("MENTION_PROFILES").map(|v| v != "false").unwrap_or(true)
+}
+
+// Distinct public keys referenced as `pk:<key>` in `content`, in order of appearance.
+// Tokens that aren't valid keys are ignored.
+pub fn references(content: &str) -> Vec<String> {
+    let mut keys: Vec<String> = Vec::new();
+    for (start, _) in content.match_indices("pk:") {
+        let key: String = content[start + 3..].chars().take_while(|c| c.is_ascii_alphanumeric()).collect();
+        if PublicKey::try_from(key.as_str()).is_ok() && !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+    keys
+}
+
+// The people `content` mentions besides the author and the bot, described for the
+// system prompt. None when there are none.
+pub async fn describe(client: &Client, profiles: &mut ProfileCache, content: &str, author: &str, bot: &str) -> Option<String> {
+    let keys: Vec<String> = references(content).into_iter().filter(|key| key != author && key != bot).take(MAX_PEOPLE).collect();
+    if keys.is_empty() {
+        return None;
+    }
+    let mut lines = Vec::new();
+    for key in keys {
+        let line = match profiles.get(client, &key).await {
+            Ok(Some(profile)) => {
+                let mut line = match profile.name.trim() {
+                    "" => format!("- pk:{}", key),
+                    name => format!("- pk:{} is {}", key, name),
+                };
+                if profile.bot {
+                    line.push_str(" (a bot)");
+                }
+                if let Some(bio) = profile.bio.as_deref().map(str::trim).filter(|b| !b.is_empty()) {
+                    line.push_str(&format!(": {}", bio.chars().take(MAX_BIO_CHARS).collect::<String>()));
+                }
+                line
+            }
+            Ok(None) => format!("- pk:{} has no profile", key),
+            Err(e) => {
+                log!("Failed to fetch profile of mentioned user {}: {}", privacy::user(&key), e);
+                format!("- pk:{}", key)
+            }
+        };
+        lines.push(line);
+    }
+    Some(lines.join("\n"))
+}
@@ -0,0 +1,132 @@
+use crate::homeserver::{get_file, put_file};
+use anyhow::Result;
+use pubky::{Client, Keypair};
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::path::Path;
+
+// Key holding the schema version in every versioned state file. Files written before
+// versioning have none and count as version 0.
+pub const VERSION_KEY: &str = "schema_version";
+
+// Upgrades a state file by one version, in place.
+type Step = fn(&mut Map<String, Value>) -> Result<()>;
+
+// A state file format: its current version is the number of steps, and step `n`
+// upgrades version `n` to `n + 1`. Append a step whenever a change can't be covered
+// by a serde default (a renamed or restructured field); never edit released steps.
+pub struct StateFormat {
+    pub name: &'static str,
+    steps: &'static [Step],
+}
+
+impl StateFormat {
+    pub fn version(&self) -> u32 {
+        self.steps.len() as u32
+    }
+}
+
+// v0 -> v1 for the formats versioned from the start: their unversioned files already
+// match version 1 (every field added so far has a default), so only the version is set.
+fn baseline(_: &mut Map<String, Value>) -> Result<()> {
+    Ok(())
+}
+
+pub const MEMORY_DB: StateFormat = StateFormat { name: "memory DB", steps: &[baseline] };
+pub const BACKUP_STATE: StateFormat = StateFormat { name: "backup sync state", steps: &[baseline] };
+pub const STORAGE_STATE: StateFormat = StateFormat { name: "storage tracking state", steps: &[baseline] };
+pub const STATE_SNAPSHOT: StateFormat = StateFormat { name: "state snapshot", steps: &[baseline] };
+
+// The bot's files under pub/ai-rand/ on its homeserver (provenance records). Their
+// version is kept in a single marker file rather than in each record.
+const HOMESERVER_VERSION: u32 = 1;
+
+fn homeserver_marker_url(keypair: &Keypair) -> String {
+    format!("pubky://{}/pub/ai-rand/state.json", keypair.public_key())
+}
+
+// Reads a state file and upgrades it to the current version of its format. Before
+// migrating, the original is kept next to it as `<file>.v<old version>.bak` and the
+// upgraded file is written back, so a crash mid-way never leaves a half-migrated file.
+// A file from a newer build is refused rather than loaded and overwritten with fields
+// dropped. None when the file doesn't exist.
+pub fn upgrade(path: &Path, format: &StateFormat) -> Result<Option<Value>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut value: Value = serde_json::from_str(&content).map_err(|e| anyhow::anyhow!("Failed to parse {} {}: {}", format.name, path.display(), e))?;
+    let Some(object) = value.as_object_mut() else {
+        return Err(anyhow::anyhow!("{} {} is not a JSON object", format.name, path.display()));
+    };
+    let version = object.get(VERSION_KEY).and_then(Value::as_u64).unwrap_or(0) as u32;
+    if version > format.version() {
+        return Err(anyhow::anyhow!(
+            "{} {} has schema version {}, newer than this build supports ({}); upgrade the bot or restore a backup",
+            format.name,
+            path.display(),
+            version,
+            format.version()
+        ));
+    }
+    if version == format.version() {
+        return Ok(Some(value));
+    }
+
+    let backup = path.with_file_name(format!("{}.v{}.bak", path.file_name().and_then(|n| n.to_str()).unwrap_or("state"), version));
+    if !backup.exists() {
+        fs::copy(path, &backup)?;
+    }
+    for step in &format.steps[version as usize..] {
+        step(object)?;
+    }
+    object.insert(VERSION_KEY.to_string(), json!(format.version()));
+    let tmp = path.with_extension("migrating");
+    fs::write(&tmp, serde_json::to_string_pretty(&value)?)?;
+    fs::rename(&tmp, path)?;
+    log!("Migrated {} {} from schema version {} to {} (original kept as {})", format.name, path.display(), version, format.version(), backup.display());
+    Ok(Some(value))
+}
+
+// `upgrade` for state that starts empty when it can't be loaded: an unreadable or
+// too-new file is moved aside to `<file>.unreadable` instead of being overwritten.
+pub fn upgrade_or_set_aside(path: &Path, format: &StateFormat) -> Option<Value> {
+    match upgrade(path, format) {
+        Ok(value) => value,
+        Err(e) => {
+            let aside = path.with_file_name(format!("{}.unreadable", path.file_name().and_then(|n| n.to_str()).unwrap_or("state")));
+            log!("{}; moving it to {} and starting empty", e, aside.display());
+            if let Err(e) = fs::rename(path, &aside) {
+                log!("Failed to move {} aside: {}", path.display(), e);
+            }
+            None
+        }
+    }
+}
+
+// Brings the bot's homeserver files to the current version at startup. Refuses to
+// start when they were written by a newer build, which could rely on records this one
+// would misread.
+pub async fn upgrade_homeserver(client: &Client, keypair: &Keypair) -> Result<()> {
+    let url = homeserver_marker_url(keypair);
+    let version = match get_file(client, &url).await? {
+        Some(body) => serde_json::from_slice::<Value>(&body).ok().and_then(|v| v[VERSION_KEY].as_u64()).unwrap_or(0) as u32,
+        None => 0,
+    };
+    if version > HOMESERVER_VERSION {
+        return Err(anyhow::anyhow!(
+            "Homeserver state has schema version {}, newer than this build supports ({}); upgrade the bot",
+            version,
+            HOMESERVER_VERSION
+        ));
+    }
+    if version == HOMESERVER_VERSION {
+        return Ok(());
+    }
+    // Steps go here as `if version < N { ... }`, each rewriting the records it changes.
+    // Version 1 is the baseline: provenance records are unchanged.
+    put_file(client, &url, serde_json::to_vec(&json!({ VERSION_KEY: HOMESERVER_VERSION }))?).await?;
+    log!("Homeserver state marked as schema version {} (was {})", HOMESERVER_VERSION, version);
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use std::env;
+
+pub const CHECK_INTERVAL_MILLIS: i64 = 60 * 60 * 1000;
+
+const DEFAULT_TEMPLATE: &str = "🎉 AI Rand just reached {milestone} followers! Thank you all for the questions and conversations, keep them coming.";
+
+// Follower counts to celebrate, from FOLLOWER_MILESTONES (e.g. "100,1k,10k").
+// Empty when unset, which disables the milestone posts.
+pub fn configured() -> Vec<u64> {
+    let mut milestones: Vec<u64> = env::var("FOLLOWER_MILESTONES")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .filter_map(|v| {
+            let parsed = parse_count(v);
+            if parsed.is_none() {
+                log!("Ignoring follower milestone {:?}: expected a number like 500, 1k or 1m", v);
+            }
+            parsed
+        })
+        .collect();
+    milestones.sort_unstable();
+    milestones.dedup();
+    milestones
+}
+
+pub fn is_enabled() -> bool {
+    !configured().is_empty()
+}
+
+fn parse_count(value: &str) -> Option<u64> {
+    let lower = value.to_lowercase();
+    let (number, multiplier) = match lower.strip_suffix('k') {
+        Some(number) => (number, 1_000),
+        None => match lower.strip_suffix('m') {
+            Some(number) => (number, 1_000_000),
+            None => (lower.as_str(), 1),
+        },
+    };
+    let count = (number.parse::<f64>().ok()? * multiplier as f64).round();
+    (count >= 1.0).then_some(count as u64)
+}
+
+// The highest milestone `followers` has reached, if any.
+pub fn reached(milestones: &[u64], followers: u64) -> Option<u64> {
+    milestones.iter().rev().find(|m| **m <= followers).copied()
+}
+
+// "1k" style label for a milestone, used in the post.
+pub fn label(milestone: u64) -> String {
+    match milestone {
+        m if m >= 1_000_000 && m % 1_000_000 == 0 => format!("{}M", m / 1_000_000),
+        m if m >= 1_000 && m % 1_000 == 0 => format!("{}k", m / 1_000),
+        m => m.to_string(),
+    }
+}
+
+// Renders MILESTONE_TEMPLATE with `{milestone}` and `{count}` (the actual follower count).
+pub fn build_post(milestone: u64, followers: u64) -> String {
+    let template = env::var("MILESTONE_TEMPLATE").ok().filter(|t| !t.trim().is_empty()).unwrap_or_else(|| DEFAULT_TEMPLATE.to_string());
+    template.replace("\\n", "\n").replace("{milestone}", &label(milestone)).replace("{count}", &followers.to_string())
+}
@@ -0,0 +1,165 @@
+use anyhow::Result;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const MAX_REQUEST_BYTES: usize = 8 * 1024 * 1024;
+const EMBEDDING_DIMENSIONS: usize = 64;
+
+// Local stand-in for the OpenAI API, for benchmarks and replays that must not spend
+// tokens or depend on the network. It answers the endpoints the bot uses:
+//
+//   POST /v1/chat/completions   a short canned reply, streamed when asked
+//   POST /v1/embeddings         deterministic vectors derived from the text
+//   POST /v1/moderations        nothing flagged
+//   POST /v1/audio/transcriptions  a canned transcript
+//   GET  /v1/models             an empty list
+//
+// Each answer waits MOCK_LLM_LATENCY_MS (default 0) to imitate the provider.
+//
+// Starts the mock on a free local port and points the bot at it by setting
+// OPENAI_BASE_URL (and a placeholder OPENAI_API_KEY).
+pub async fn install() -> Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let latency = Duration::from_millis(env::var("MOCK_LLM_LATENCY_MS").ok().and_then(|v| v.parse().ok()).unwrap_or(0));
+    tokio::spawn(async move {
+        loop {
+            let Ok((stream, _)) = listener.accept().await else { continue };
+            tokio::spawn(async move {
+                if let Err(e) = serve(stream, latency).await {
+                    log!("Mock LLM request failed: {}", e);
+                }
+            });
+        }
+    });
+    env::set_var("OPENAI_BASE_URL", format!("http://{}/v1", addr));
+    env::set_var("OPENAI_API_KEY", "mock");
+    log!("Mock LLM listening on http://{}", addr);
+    Ok(addr)
+}
+
+async fn serve(mut stream: TcpStream, latency: Duration) -> Result<()> {
+    stream.set_nodelay(true)?;
+    let (method, path, body) = read_request(&mut stream).await?;
+    tokio::time::sleep(latency).await;
+    let request: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    let endpoint = path.trim_start_matches("/v1/");
+    match (method.as_str(), endpoint) {
+        ("POST", "chat/completions") if request["stream"] == json!(true) => {
+            let mut events = String::new();
+            for word in canned_reply(&request).split_inclusive(' ') {
+                let event = json!({ "choices": [{ "index": 0, "delta": { "content": word } }] });
+                events.push_str(&format!("data: {}\n\n", event));
+            }
+            events.push_str("data: [DONE]\n\n");
+            write_response(&mut stream, "200 OK", "text/event-stream", &events).await
+        }
+        ("POST", "chat/completions") => {
+            let response = json!({
+                "choices": [{ "index": 0, "message": { "role": "assistant", "content": canned_reply(&request) }, "finish_reason": "stop" }],
+            });
+            write_json(&mut stream, &response).await
+        }
+        ("POST", "embeddings") => {
+            let inputs: Vec<String> = match &request["input"] {
+                Value::String(text) => vec![text.clone()],
+                Value::Array(items) => items.iter().map(|i| i.as_str().unwrap_or_default().to_string()).collect(),
+                _ => Vec::new(),
+            };
+            let data: Vec<Value> = inputs.iter().enumerate().map(|(index, text)| json!({ "index": index, "embedding": embedding(text) })).collect();
+            write_json(&mut stream, &json!({ "data": data })).await
+        }
+        ("POST", "moderations") => write_json(&mut stream, &json!({ "results": [{ "flagged": false, "category_scores": {} }] })).await,
+        ("POST", "audio/transcriptions") => write_json(&mut stream, &json!({ "text": "This is a mock transcript." })).await,
+        ("GET", "models") => write_json(&mut stream, &json!({ "data": [] })).await,
+        _ => write_response(&mut stream, "404 Not Found", "application/json", r#"{"error":"not found"}"#).await,
+    }
+}
+
+// A short reply that mentions the start of the question, so replays show which
+// mention it answered.
+fn canned_reply(request: &Value) -> String {
+    let question = request["messages"]
+        .as_array()
+        .and_then(|messages| messages.iter().rev().find(|m| m["role"] == "user"))
+        .and_then(|m| m["content"].as_str())
+        .unwrap_or_default();
+    // Posts arrive between injection delimiters
+    let question = question.trim_start().trim_start_matches("<post>");
+    let start: String = question.split_whitespace().take(8).collect::<Vec<_>>().join(" ");
+    format!("This is a mock reply to \"{}\".", start)
+}
+
+// Unit vector derived from the text's hash: identical texts match exactly, others
+// are effectively unrelated.
+fn embedding(text: &str) -> Vec<f32> {
+    let mut values = Vec::with_capacity(EMBEDDING_DIMENSIONS);
+    let mut round = 0u32;
+    while values.len() < EMBEDDING_DIMENSIONS {
+        let digest = Sha256::digest(format!("{}:{}", round, text).as_bytes());
+        values.extend(digest.iter().map(|b| *b as f32 / 127.5 - 1.0));
+        round += 1;
+    }
+    values.truncate(EMBEDDING_DIMENSIONS);
+    let norm = values.iter().map(|v| v * v).sum::<f32>().sqrt().max(f32::EPSILON);
+    values.iter().map(|v| v / norm).collect()
+}
+
+// Reads the request line, headers and a Content-Length body.
+async fn read_request(stream: &mut TcpStream) -> Result<(String, String, Vec<u8>)> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 8192];
+    let header_end = loop {
+        if let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end + 4;
+        }
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Err(anyhow::anyhow!("Connection closed before the request was complete"));
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        if buffer.len() > MAX_REQUEST_BYTES {
+            return Err(anyhow::anyhow!("Request too large"));
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let path = request_line.next().unwrap_or("/").split('?').next().unwrap_or("/").to_string();
+    let content_length = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    if content_length > MAX_REQUEST_BYTES {
+        return Err(anyhow::anyhow!("Request too large"));
+    }
+
+    let mut body = buffer[header_end..].to_vec();
+    while body.len() < content_length {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..read]);
+    }
+    Ok((method, path, body))
+}
+
+async fn write_json(stream: &mut TcpStream, body: &Value) -> Result<()> {
+    write_response(stream, "200 OK", "application/json", &body.to_string()).await
+}
+
+async fn write_response(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) -> Result<()> {
+    let head = format!("HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, content_type, body.len());
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    Ok(())
+}
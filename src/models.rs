@@ -0,0 +1,92 @@
+use crate::analytics::{self, AnalyticsRow};
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Mutex, OnceLock};
+
+// Built-in aliases; MODEL_ALIASES overrides or adds to them.
+const DEFAULT_ALIASES: &[(&str, &str)] = &[("default", "gpt-4o-mini"), ("vision", "gpt-4o-mini")];
+
+// Phrases providers use when a model id is unknown, retired or being shut down.
+const RETIRED_MARKERS: &[&str] = &["model_not_found", "does not exist", "deprecated", "decommissioned", "no longer available", "has been retired"];
+
+#[derive(Default)]
+struct Retired {
+    // Retired model id -> the one requests now go to
+    successors: HashMap<String, String>,
+    pending_warnings: Vec<String>,
+}
+
+fn retired() -> &'static Mutex<Retired> {
+    static RETIRED: OnceLock<Mutex<Retired>> = OnceLock::new();
+    RETIRED.get_or_init(|| Mutex::new(Retired::default()))
+}
+
+// "name=value" pairs separated by commas, e.g. "default=gpt-4o-mini,vision=gpt-4o".
+fn pairs(var: &str) -> HashMap<String, String> {
+    env::var(var)
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .filter(|(name, value)| !name.is_empty() && !value.is_empty())
+        .collect()
+}
+
+fn alias(name: &str) -> Option<String> {
+    pairs("MODEL_ALIASES")
+        .remove(name)
+        .or_else(|| DEFAULT_ALIASES.iter().find(|(alias, _)| *alias == name).map(|(_, model)| model.to_string()))
+}
+
+// The model id to send for an alias such as "default" or "vision", or for a model id.
+// A model the provider reported as retired resolves to its successor.
+pub fn resolve(name: &str) -> String {
+    let mut model = alias(name).unwrap_or_else(|| name.to_string());
+    if let Ok(retired) = retired().lock() {
+        // Successors can be retired in turn; the bound guards against a cycle
+        for _ in 0..retired.successors.len() {
+            match retired.successors.get(&model) {
+                Some(successor) => model = successor.clone(),
+                None => break,
+            }
+        }
+    }
+    model
+}
+
+// Whether a failed request was rejected because its model doesn't exist (any more).
+pub fn is_retired_error(status: reqwest::StatusCode, body: &str) -> bool {
+    let body = body.to_lowercase();
+    (status == reqwest::StatusCode::NOT_FOUND || status == reqwest::StatusCode::BAD_REQUEST || status == reqwest::StatusCode::GONE)
+        && body.contains("model")
+        && RETIRED_MARKERS.iter().any(|marker| body.contains(marker))
+}
+
+// Records that the provider retired `model` and returns the model to use instead: its
+// successor from MODEL_SUCCESSORS (e.g. "gpt-4o-mini=gpt-4.1-mini"), or else the
+// default model. None when there is nothing left to switch to.
+pub fn retire(model: &str) -> Option<String> {
+    let successor = pairs("MODEL_SUCCESSORS").remove(model).unwrap_or_else(|| resolve("default"));
+    let Ok(mut retired) = retired().lock() else { return None };
+    if successor == model || retired.successors.contains_key(&successor) {
+        return None;
+    }
+    retired.successors.insert(model.to_string(), successor.clone());
+    let warning = format!("Model {} was rejected as retired or unknown; switched to {}. Update MODEL_ALIASES or MODEL_SUCCESSORS.", model, successor);
+    log!("{}", warning);
+    retired.pending_warnings.push(warning);
+    drop(retired);
+    analytics::record(AnalyticsRow::new("model_retired", None, None).with("model", model).with("successor", successor.as_str()));
+    Some(successor)
+}
+
+// Returns the model switches made since the last call, so the bot can tell the
+// operator once.
+pub fn take_warnings() -> Vec<String> {
+    retired().lock().map(|mut retired| std::mem::take(&mut retired.pending_warnings)).unwrap_or_default()
+}
+
+// Retired models and their successors, for /status.
+pub fn switched() -> HashMap<String, String> {
+    retired().lock().map(|retired| retired.successors.clone()).unwrap_or_default()
+}
@@ -0,0 +1,138 @@
+use crate::secrets::Secret;
+use anyhow::Result;
+use futures_util::{SinkExt, StreamExt};
+use pubky::Keypair;
+use secp256k1::{Keypair as NostrKeypair, Message as SchnorrMessage, Secp256k1};
+use serde::Serialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+const KIND_TEXT_NOTE: u16 = 1;
+
+// Mirrors the bot's posts to Nostr relays. Enabled by setting NOSTR_RELAYS.
+pub struct NostrBridge {
+    keypair: NostrKeypair,
+    pubkey: String,
+    relays: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NostrEvent {
+    pub id: String,
+    pub pubkey: String,
+    pub created_at: u64,
+    pub kind: u16,
+    pub tags: Vec<Vec<String>>,
+    pub content: String,
+    pub sig: String,
+}
+
+impl NostrBridge {
+    // Uses NOSTR_SECRET_KEY (hex) if set, otherwise derives a key from the bot's Pubky secret.
+    pub fn from_env(keypair: &Keypair) -> Result<Option<NostrBridge>> {
+        let relays: Vec<String> = env::var("NOSTR_RELAYS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|r| r.trim().to_string())
+            .filter(|r| !r.is_empty())
+            .collect();
+        if relays.is_empty() {
+            return Ok(None);
+        }
+
+        let secret: [u8; 32] = match Secret::from_env("NOSTR_SECRET_KEY") {
+            Some(hex) => decode_hex(hex.expose().trim())?
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("NOSTR_SECRET_KEY must be 32 bytes of hex"))?,
+            None => {
+                let mut hasher = Sha256::new();
+                hasher.update(b"ai-rand/nostr");
+                hasher.update(keypair.secret_key());
+                hasher.finalize().into()
+            }
+        };
+
+        let secp = Secp256k1::new();
+        let nostr_keypair = NostrKeypair::from_seckey_slice(&secp, &secret)
+            .map_err(|e| anyhow::anyhow!("Invalid Nostr secret key: {}", e))?;
+        let pubkey = nostr_keypair.x_only_public_key().0.to_string();
+        log!("Nostr bridge enabled for {} as {}", relays.join(", "), pubkey);
+
+        Ok(Some(NostrBridge { keypair: nostr_keypair, pubkey, relays }))
+    }
+
+    // Builds a signed text note. `parent_event` links it as a NIP-10 reply when the
+    // parent was itself mirrored; `pubky_uri` keeps a reference back to the Pubky original.
+    pub fn build_note(&self, content: &str, pubky_uri: &str, parent_event: Option<&str>) -> Result<NostrEvent> {
+        let created_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let mut tags = vec![vec!["r".to_string(), pubky_uri.to_string()]];
+        if let Some(parent) = parent_event {
+            tags.push(vec!["e".to_string(), parent.to_string(), String::new(), "reply".to_string()]);
+        }
+
+        let serialized = serde_json::to_string(&json!([0, self.pubkey, created_at, KIND_TEXT_NOTE, tags, content]))?;
+        let id: [u8; 32] = Sha256::digest(serialized.as_bytes()).into();
+        let sig = Secp256k1::new().sign_schnorr_no_aux_rand(&SchnorrMessage::from_digest(id), &self.keypair);
+
+        Ok(NostrEvent {
+            id: encode_hex(&id),
+            pubkey: self.pubkey.clone(),
+            created_at,
+            kind: KIND_TEXT_NOTE,
+            tags,
+            content: content.to_string(),
+            sig: sig.to_string(),
+        })
+    }
+
+    // Sends the event to every relay in the background; failures are only logged.
+    pub fn publish(&self, event: NostrEvent) {
+        for relay in self.relays.clone() {
+            let event = event.clone();
+            tokio::spawn(async move {
+                match tokio::time::timeout(Duration::from_secs(10), send_event(&relay, &event)).await {
+                    Ok(Ok(())) => log!("Mirrored event {} to {}", event.id, relay),
+                    Ok(Err(e)) => log!("Failed to mirror event to {}: {}", relay, e),
+                    Err(_) => log!("Timed out mirroring event to {}", relay),
+                }
+            });
+        }
+    }
+}
+
+async fn send_event(relay: &str, event: &NostrEvent) -> Result<()> {
+    let (mut ws, _) = connect_async(relay).await?;
+    ws.send(Message::Text(serde_json::to_string(&json!(["EVENT", event]))?)).await?;
+
+    // Wait for the relay's ["OK", <id>, <accepted>, <message>] answer
+    while let Some(message) = ws.next().await {
+        if let Message::Text(text) = message? {
+            let value: serde_json::Value = serde_json::from_str(&text)?;
+            if value[0] == "OK" && value[1] == event.id.as_str() {
+                let _ = ws.close(None).await;
+                if value[2] == true {
+                    return Ok(());
+                }
+                return Err(anyhow::anyhow!("Relay rejected event: {}", value[3]));
+            }
+        }
+    }
+    Err(anyhow::anyhow!("Relay closed the connection without confirming"))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    if !hex.is_ascii() || !hex.len().is_multiple_of(2) {
+        return Err(anyhow::anyhow!("Invalid hex string"));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|e| anyhow::anyhow!("Invalid hex string: {}", e)))
+        .collect()
+}
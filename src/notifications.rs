@@ -0,0 +1,252 @@
+use crate::analytics::{self, AnalyticsRow};
+use crate::memory::now_millis;
+use crate::privacy;
+use anyhow::Result;
+use serde_json::json;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, VecDeque};
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::Mutex;
+
+// Raw samples logged per unknown notification type when LOG_UNKNOWN_NOTIFICATIONS=true.
+const MAX_SAMPLES_PER_TYPE: u64 = 3;
+
+// A Nexus notification. The body is kept as raw JSON and interpreted with `kind()`,
+// so new or changed notification types never break parsing of the others.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    pub timestamp: i64,
+    pub body: Value,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NotificationKind {
+    Mention {
+        mentioned_by: String,
+        post_uri: String,
+    },
+    TagPost {
+        tagged_by: String,
+        tag_label: String,
+        post_uri: String,
+    },
+    Follow {
+        followed_by: String,
+    },
+    Reply {
+        replied_by: String,
+        reply_uri: String,
+    },
+    // Any type (or shape) this version doesn't understand, with the body preserved
+    #[serde(skip)]
+    Unknown {
+        kind: String,
+        raw: Value,
+    },
+}
+
+// Parses a notifications response item by item: a malformed notification is
+// quarantined and reported instead of failing the whole batch. Only a response that
+// isn't a JSON array at all is an error.
+pub fn parse_batch(response: &str) -> Result<Vec<Notification>> {
+    let items: Vec<Value> = serde_json::from_str(response)?;
+    let mut notifications = Vec::with_capacity(items.len());
+    for item in items {
+        match serde_json::from_value::<Notification>(item.clone()) {
+            Ok(notification) => notifications.push(notification),
+            Err(e) => quarantine(&item, &e.to_string()),
+        }
+    }
+    Ok(notifications)
+}
+
+fn quarantine_path() -> String {
+    env::var("NOTIFICATION_QUARANTINE").unwrap_or_else(|_| "notifications-quarantine.jsonl".to_string())
+}
+
+// Failed attempts after which a notification is quarantined instead of retried, from
+// NOTIFICATION_MAX_ATTEMPTS.
+pub fn max_attempts() -> u32 {
+    env::var("NOTIFICATION_MAX_ATTEMPTS").ok().and_then(|v| v.parse().ok()).unwrap_or(3).max(1)
+}
+
+// Sets aside a notification that can't be parsed or keeps failing, so it doesn't hold
+// back the rest.
+pub fn quarantine(item: &Value, error: &str) {
+    log!("Quarantined notification ({}): {}", error, privacy::content(&item.to_string()));
+    analytics::record(AnalyticsRow::new("notification_quarantined", None, None).with("error", error));
+    let result = OpenOptions::new().create(true).append(true).open(quarantine_path()).and_then(|mut file| {
+        writeln!(file, "{}", json!({ "quarantined_at": now_millis(), "error": error, "notification": item }))
+    });
+    if let Err(e) = result {
+        log!("Failed to write quarantined notification: {}", e);
+    }
+}
+
+// Removes the quarantined notifications that name `user` and returns how many went.
+pub fn forget_user(user: &str) -> std::io::Result<usize> {
+    let path = quarantine_path();
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    let kept: Vec<&str> = content.lines().filter(|line| !line.contains(user)).collect();
+    let removed = content.lines().count() - kept.len();
+    if removed > 0 {
+        let tmp = format!("{}.tmp", path);
+        std::fs::write(&tmp, kept.iter().map(|line| format!("{}\n", line)).collect::<String>())?;
+        std::fs::rename(&tmp, &path)?;
+    }
+    Ok(removed)
+}
+
+static UNKNOWN_COUNTS: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+
+impl Notification {
+    pub fn kind(&self) -> NotificationKind {
+        serde_json::from_value(self.body.clone()).unwrap_or_else(|_| NotificationKind::Unknown {
+            kind: self.type_name().to_string(),
+            raw: self.body.clone(),
+        })
+    }
+
+    pub fn type_name(&self) -> &str {
+        self.body["type"].as_str().unwrap_or("<missing>")
+    }
+
+    pub fn post_uri(&self) -> Option<&str> {
+        self.body["post_uri"].as_str()
+    }
+
+    // The user whose action caused the notification.
+    pub fn actor(&self) -> Option<&str> {
+        ["mentioned_by", "tagged_by", "followed_by", "replied_by"].iter().find_map(|key| self.body[*key].as_str())
+    }
+}
+
+// Whose notifications are handled first when several are waiting: the bot's owner,
+// then its followers, then everyone else.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AuthorClass {
+    Owner,
+    Follower,
+    Other,
+}
+
+// Most mentions and tags handled per author in one poll, from MAX_MENTIONS_PER_AUTHOR
+// (0 = no cap). The rest wait for the next poll, behind other authors.
+pub fn per_author_cap() -> usize {
+    env::var("MAX_MENTIONS_PER_AUTHOR").ok().and_then(|v| v.parse().ok()).unwrap_or(3)
+}
+
+// Priority scheduling, on unless MENTION_PRIORITY=false.
+pub fn is_priority_enabled() -> bool {
+    env::var("MENTION_PRIORITY").map(|v| v != "false").unwrap_or(true)
+}
+
+// Orders a backlog by the class of its authors, so a flood of mentions from strangers
+// can't hold up the owner and followers. Within a class authors take turns: everyone's
+// oldest notification first, then everyone's second, and so on, oldest first within
+// a round, so one busy author can't monopolize the model either.
+pub fn prioritize(notifications: Vec<Notification>, class: impl Fn(Option<&str>) -> AuthorClass) -> Vec<Notification> {
+    let mut by_age: Vec<usize> = (0..notifications.len()).collect();
+    by_age.sort_by_key(|i| notifications[*i].timestamp);
+    let mut seen: HashMap<Option<&str>, usize> = HashMap::new();
+    let mut rounds = vec![0; notifications.len()];
+    for i in by_age {
+        let turn = seen.entry(notifications[i].actor()).or_default();
+        rounds[i] = *turn;
+        *turn += 1;
+    }
+    let mut queue: BinaryHeap<Reverse<(AuthorClass, usize, i64, usize)>> = notifications
+        .iter()
+        .enumerate()
+        .map(|(i, n)| Reverse((class(n.actor()), rounds[i], n.timestamp, i)))
+        .collect();
+    let mut slots: Vec<Option<Notification>> = notifications.into_iter().map(Some).collect();
+    let mut ordered = Vec::with_capacity(slots.len());
+    while let Some(Reverse((_, _, _, i))) = queue.pop() {
+        if let Some(notification) = slots[i].take() {
+            ordered.push(notification);
+        }
+    }
+    ordered
+}
+
+// Counts an unknown notification (in memory and in the analytics log) and, when
+// LOG_UNKNOWN_NOTIFICATIONS=true, logs the first few raw samples of each type.
+pub fn record_unknown(kind: &str, raw: &Value) {
+    let count = match UNKNOWN_COUNTS.lock() {
+        Ok(mut counts) => {
+            let count = counts.entry(kind.to_string()).or_default();
+            *count += 1;
+            *count
+        }
+        Err(_) => 0,
+    };
+    log!("Received unknown notification type: {}", kind);
+    if count <= MAX_SAMPLES_PER_TYPE && env::var("LOG_UNKNOWN_NOTIFICATIONS").map(|v| v == "true").unwrap_or(false) {
+        log!("Unknown notification sample: {}", privacy::content(&raw.to_string()));
+    }
+    analytics::record(AnalyticsRow::new("unknown_notification", None, None).with("type", kind));
+}
+
+// Timestamps of the notifications from the last poll that are still to be handled,
+// oldest first. A batch that fails midway keeps its rest here until the next poll
+// fetches it again.
+static BACKLOG: Mutex<Backlog> = Mutex::new(Backlog { timestamps: VecDeque::new(), full_page: false });
+
+struct Backlog {
+    timestamps: VecDeque<i64>,
+    full_page: bool,
+}
+
+// How far behind the bot is on notifications.
+#[derive(Debug, Clone, Serialize)]
+pub struct BacklogStats {
+    pub pending: usize,
+    // Age of the oldest notification not handled yet
+    pub oldest_age_ms: Option<i64>,
+    // The last poll returned a full page, so Nexus likely has more waiting
+    pub more_on_server: bool,
+}
+
+// Starts tracking fetched notifications until they are handled, and records whether
+// the poll that fetched them returned a full page.
+pub fn track(timestamps: impl IntoIterator<Item = i64>, full_page: bool) {
+    if let Ok(mut backlog) = BACKLOG.lock() {
+        backlog.timestamps.extend(timestamps);
+        backlog.full_page = full_page;
+    }
+}
+
+// Marks a tracked notification as handled.
+pub fn mark_processed(timestamp: i64) {
+    if let Ok(mut backlog) = BACKLOG.lock() {
+        if let Some(index) = backlog.timestamps.iter().position(|t| *t == timestamp) {
+            backlog.timestamps.remove(index);
+        }
+    }
+}
+
+pub fn backlog_stats() -> BacklogStats {
+    let Ok(backlog) = BACKLOG.lock() else {
+        return BacklogStats { pending: 0, oldest_age_ms: None, more_on_server: false };
+    };
+    BacklogStats {
+        pending: backlog.timestamps.len(),
+        oldest_age_ms: backlog.timestamps.iter().min().map(|oldest| (now_millis() - oldest).max(0)),
+        more_on_server: backlog.full_page,
+    }
+}
+
+// Unknown notifications seen since startup, per type.
+pub fn unknown_counts() -> BTreeMap<String, u64> {
+    UNKNOWN_COUNTS.lock().map(|counts| counts.clone()).unwrap_or_default()
+}
@@ -0,0 +1,79 @@
+use crate::follows;
+use crate::footer;
+use crate::post_kind::{self, Purpose};
+use crate::privacy;
+use pubky::Keypair;
+use std::env;
+
+// Perks for people who follow the bot, a small incentive to follow it. Each one is
+// off until its setting is given; image generation isn't offered, since the bot
+// doesn't generate images.
+fn setting(name: &str) -> Option<usize> {
+    env::var(name).ok().and_then(|v| v.parse().ok())
+}
+
+// Reply length limit for followers, from FOLLOWER_REPLY_MAX_CHARS, capped like
+// REPLY_MAX_CHARS at what the post kind policy can publish.
+fn follower_reply_max_chars() -> Option<usize> {
+    let max = setting("FOLLOWER_REPLY_MAX_CHARS").filter(|max| *max > 0)?;
+    Some(max.min(post_kind::max_chars(Purpose::Reply)).saturating_sub(footer::reserved_chars()).max(1))
+}
+
+// Attachments (OCR, transcripts) read only for followers, with ATTACHMENTS_FOLLOWERS_ONLY=true.
+fn attachments_followers_only() -> bool {
+    env::var("ATTACHMENTS_FOLLOWERS_ONLY").is_ok_and(|v| v == "true")
+}
+
+pub fn is_enabled() -> bool {
+    follower_reply_max_chars().is_some()
+        || setting("FOLLOWER_MAX_DAILY_REPLIES").is_some()
+        || setting("FOLLOWER_MAX_THREAD_REPLIES").is_some()
+        || attachments_followers_only()
+}
+
+// The longest reply any user can get, for cutting cached replies to size.
+pub fn longest_reply_chars(default: usize) -> usize {
+    follower_reply_max_chars().map_or(default, |max| max.max(default))
+}
+
+// What a user gets: the perks when they follow the bot, the defaults otherwise.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Perks {
+    pub follower: bool,
+}
+
+impl Perks {
+    // Reply length limit, when it differs from REPLY_MAX_CHARS.
+    pub fn reply_max_chars(&self) -> Option<usize> {
+        self.follower.then(follower_reply_max_chars).flatten()
+    }
+
+    // MAX_DAILY_REPLIES_PER_USER, or FOLLOWER_MAX_DAILY_REPLIES for followers.
+    pub fn max_daily_replies(&self, default: usize) -> usize {
+        self.follower.then(|| setting("FOLLOWER_MAX_DAILY_REPLIES")).flatten().unwrap_or(default)
+    }
+
+    // MAX_THREAD_REPLIES, or FOLLOWER_MAX_THREAD_REPLIES for followers.
+    pub fn max_thread_replies(&self, default: usize) -> usize {
+        self.follower.then(|| setting("FOLLOWER_MAX_THREAD_REPLIES")).flatten().unwrap_or(default)
+    }
+
+    pub fn reads_attachments(&self) -> bool {
+        self.follower || !attachments_followers_only()
+    }
+}
+
+// The perks of `user`, from the bot's follower list on Nexus (cached for a few
+// minutes). When it can't be loaded, nobody gets the perks.
+pub async fn for_user(keypair: &Keypair, user: &str) -> Perks {
+    if !is_enabled() {
+        return Perks::default();
+    }
+    match follows::followers(keypair).await {
+        Ok(followers) => Perks { follower: followers.contains(user) },
+        Err(e) => {
+            log!("Failed to load followers for the perks of {}: {}", privacy::user(user), e);
+            Perks::default()
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use crate::commands::{is_owner, CommandInfo};
+use crate::follows;
+use crate::privacy;
+use pubky::Keypair;
+use std::env;
+
+// Who may run a command. Each role includes the ones after it: the allowlist can run
+// followers' commands and the owner can run everything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Everyone,
+    Followers,
+    Allowlist,
+    Owner,
+}
+
+impl Role {
+    fn parse(name: &str) -> Option<Role> {
+        match name.trim().to_lowercase().as_str() {
+            "everyone" => Some(Role::Everyone),
+            "followers" => Some(Role::Followers),
+            "allowlist" => Some(Role::Allowlist),
+            "owner" => Some(Role::Owner),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::Everyone => "everyone",
+            Role::Followers => "followers",
+            Role::Allowlist => "allowlist",
+            Role::Owner => "owner",
+        }
+    }
+}
+
+// The role configured for a command in COMMAND_PERMISSIONS, as `command:role` pairs
+// separated by commas, e.g. `quiz:followers,stats:allowlist`. Commands not listed keep
+// their default: owner for owner-only commands, everyone otherwise.
+pub fn role(info: &CommandInfo) -> Role {
+    let configured = env::var("COMMAND_PERMISSIONS").unwrap_or_default().split(',').find_map(|entry| {
+        let (command, role) = entry.split_once(':')?;
+        if command.trim().trim_start_matches('!').to_lowercase() != info.name {
+            return None;
+        }
+        let parsed = Role::parse(role);
+        if parsed.is_none() {
+            log!("Ignoring unknown role \"{}\" for !{} in COMMAND_PERMISSIONS", role.trim(), info.name);
+        }
+        parsed
+    });
+    configured.unwrap_or(if info.owner_only { Role::Owner } else { Role::Everyone })
+}
+
+// Public keys in COMMAND_ALLOWLIST (comma-separated, `pk:` prefix optional).
+fn allowlisted(user: &str) -> bool {
+    env::var("COMMAND_ALLOWLIST").unwrap_or_default().split(',').any(|key| key.trim().trim_start_matches("pk:") == user)
+}
+
+// What roles a user has, resolved once per command.
+#[derive(Debug, Clone, Copy)]
+pub struct Access {
+    role: Role,
+}
+
+impl Access {
+    pub fn allows(&self, role: Role) -> bool {
+        self.role >= role
+    }
+}
+
+// The highest role of `user`. Followers are only looked up (from Nexus, cached) when a
+// command needs them; when they can't be loaded, the user counts as not following.
+pub async fn access(keypair: &Keypair, user: &str, needed: Role) -> Access {
+    if is_owner(user) {
+        return Access { role: Role::Owner };
+    }
+    if allowlisted(user) {
+        return Access { role: Role::Allowlist };
+    }
+    if needed != Role::Followers {
+        return Access { role: Role::Everyone };
+    }
+    match follows::followers(keypair).await {
+        Ok(followers) if followers.contains(user) => Access { role: Role::Followers },
+        Ok(_) => Access { role: Role::Everyone },
+        Err(e) => {
+            log!("Failed to load followers to check the permissions of {}: {}", privacy::user(user), e);
+            Access { role: Role::Everyone }
+        }
+    }
+}
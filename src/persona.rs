@@ -0,0 +1,208 @@
+use crate::examples::{self, Example};
+use crate::kb;
+use crate::memory::now_millis;
+use crate::prompt;
+use anyhow::Result;
+use dotenv::dotenv;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const FORMAT: &str = "ai-rand-persona";
+const VERSION: u32 = 1;
+
+// Settings that shape the bot's personality and are safe to share. Credentials, keys
+// and deployment settings are never exported.
+const SETTINGS: &[&str] = &[
+    "PERSONA",
+    "REPLY_MAX_CHARS",
+    "REPLY_FOOTER",
+    "SELF_CRITIQUE",
+    "CANNED_RESPONSES",
+    "ACK_AFTER_SECS",
+    "EXAMPLES",
+    "EXAMPLES_COUNT",
+    "EXAMPLES_MIN_SIMILARITY",
+    "KB_RETRIEVAL",
+    "KB_TOP_K",
+    "KNOWLEDGE_BASE_LANG",
+    "GUARDRAIL_BANNED_PHRASES",
+    "LINK_EMBEDS",
+];
+
+// Templates besides the system prompt, by the variable that holds them.
+const TEMPLATES: &[&str] = &["CANNED_HELP", "CANNED_PING", "CANNED_GREETING", "CANNED_FOLLOW", "CANNED_DEFLECTION", "ACK_TEXT"];
+
+// A shareable bot personality: the system prompt with its includes expanded, the
+// other reply templates, few-shot examples, the knowledge bases it was written for
+// (by reference, not content) and the settings that go with it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Bundle {
+    pub format: String,
+    pub version: u32,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub exported_at: i64,
+    pub system_prompt: String,
+    #[serde(default)]
+    pub templates: BTreeMap<String, String>,
+    #[serde(default)]
+    pub examples: Vec<Example>,
+    #[serde(default)]
+    pub knowledge: Vec<KnowledgeRef>,
+    #[serde(default)]
+    pub settings: BTreeMap<String, String>,
+}
+
+// A knowledge base the persona was written against, so an importer can tell whether
+// theirs matches.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KnowledgeRef {
+    pub language: String,
+    pub source: String,
+    pub version: String,
+}
+
+// `persona export <file> [--name NAME] [--description TEXT] [--author NAME] [--examples N]`
+// and `persona import <file> [--dir DIR]`.
+pub async fn run(args: &[String]) -> Result<()> {
+    dotenv().ok();
+    let usage = || anyhow::anyhow!("Usage: persona export <file> [--name NAME] [--description TEXT] [--author NAME] [--examples N] | persona import <file> [--dir DIR]");
+    let (Some(action), Some(file)) = (args.first(), args.get(1)) else {
+        return Err(usage());
+    };
+    let mut options = BTreeMap::new();
+    let mut rest = args[2..].iter();
+    while let Some(flag) = rest.next() {
+        let name = flag.strip_prefix("--").ok_or_else(usage)?;
+        options.insert(name.to_string(), rest.next().ok_or_else(usage)?.clone());
+    }
+    match action.as_str() {
+        "export" => export(Path::new(file), &options),
+        "import" => import(Path::new(file), options.get("dir").map(PathBuf::from)).await,
+        _ => Err(usage()),
+    }
+}
+
+fn export(file: &Path, options: &BTreeMap<String, String>) -> Result<()> {
+    let example_count = match options.get("examples") {
+        Some(n) => n.parse().map_err(|_| anyhow::anyhow!("--examples takes a number"))?,
+        None => 0,
+    };
+    let name = options.get("name").cloned().or_else(|| env::var("PERSONA").ok()).unwrap_or_else(|| "default".to_string());
+    let bundle = Bundle {
+        format: FORMAT.to_string(),
+        version: VERSION,
+        name,
+        description: options.get("description").cloned().unwrap_or_default(),
+        author: options.get("author").cloned(),
+        exported_at: now_millis(),
+        system_prompt: prompt::template(),
+        templates: set_vars(TEMPLATES),
+        examples: examples::best(example_count),
+        knowledge: knowledge_refs(),
+        settings: set_vars(SETTINGS),
+    };
+    fs::write(file, serde_json::to_string_pretty(&bundle)?)?;
+    println!(
+        "Exported persona \"{}\" to {}: {} templates, {} examples, {} knowledge base references, {} settings",
+        bundle.name,
+        file.display(),
+        bundle.templates.len() + 1,
+        bundle.examples.len(),
+        bundle.knowledge.len(),
+        bundle.settings.len()
+    );
+    if !bundle.examples.is_empty() {
+        println!("The examples quote users' questions; review them before sharing the file.");
+    }
+    Ok(())
+}
+
+fn set_vars(names: &[&str]) -> BTreeMap<String, String> {
+    names.iter().filter_map(|name| Some((name.to_string(), env::var(name).ok().filter(|v| !v.trim().is_empty())?))).collect()
+}
+
+fn knowledge_refs() -> Vec<KnowledgeRef> {
+    let default = kb::version(None).ok().map(|version| KnowledgeRef { language: kb::default_language(), source: kb::knowledge_base_path(), version });
+    let variants = kb::variants().into_iter().filter_map(|language| {
+        let version = kb::version(Some(&language)).ok()?;
+        Some(KnowledgeRef { source: format!("knowledge/{}", language), language, version })
+    });
+    default.into_iter().chain(variants).collect()
+}
+
+// Unpacks a bundle into DIR (default `personas/<name>`): `system-prompt.txt` and a
+// `persona.env` with its settings and templates to copy into `.env`. Its examples are
+// added to the example index, and its knowledge base references are checked against
+// the local ones.
+async fn import(file: &Path, dir: Option<PathBuf>) -> Result<()> {
+    let bundle: Bundle = serde_json::from_str(&fs::read_to_string(file)?).map_err(|e| anyhow::anyhow!("{} is not a persona bundle: {}", file.display(), e))?;
+    if bundle.format != FORMAT {
+        return Err(anyhow::anyhow!("{} is not a persona bundle (format {:?})", file.display(), bundle.format));
+    }
+    if bundle.version > VERSION {
+        return Err(anyhow::anyhow!("{} uses persona format version {}, newer than this bot supports ({})", file.display(), bundle.version, VERSION));
+    }
+    let slug = slug(&bundle.name);
+    let dir = dir.unwrap_or_else(|| Path::new("personas").join(&slug));
+    fs::create_dir_all(&dir)?;
+    let prompt_file = dir.join("system-prompt.txt");
+    fs::write(&prompt_file, &bundle.system_prompt)?;
+
+    let mut env_lines = vec![format!("# Persona \"{}\"{}", bundle.name, bundle.author.as_ref().map(|a| format!(" by {}", a)).unwrap_or_default())];
+    env_lines.push(format!("SYSTEM_PROMPT_FILE={}", prompt_file.display()));
+    // Only known variables, so a bundle can't slip in credentials or endpoints
+    let known = |name: &String| SETTINGS.contains(&name.as_str()) || TEMPLATES.contains(&name.as_str());
+    for (name, value) in bundle.settings.iter().chain(&bundle.templates) {
+        if known(name) {
+            env_lines.push(format!("{}={}", name, value.replace('\n', "\\n")));
+        } else {
+            println!("Ignoring unknown setting {}", name);
+        }
+    }
+    let env_file = dir.join("persona.env");
+    fs::write(&env_file, env_lines.join("\n") + "\n")?;
+    println!("Imported persona \"{}\" into {}", bundle.name, dir.display());
+    if !bundle.description.is_empty() {
+        println!("{}", bundle.description);
+    }
+
+    if !bundle.examples.is_empty() {
+        match examples::import(&format!("persona:{}", slug), &bundle.examples).await {
+            Ok(added) => println!("Added {} examples to the example index (used with EXAMPLES=true)", added),
+            Err(e) => println!("Failed to add the examples to the example index: {}", e),
+        }
+    }
+    for reference in &bundle.knowledge {
+        let variant = (reference.language != kb::default_language()).then_some(reference.language.as_str());
+        let local = match variant {
+            Some(language) if !kb::variants().iter().any(|v| v == language) => None,
+            _ => kb::version(variant).ok(),
+        };
+        match local {
+            Some(version) if version == reference.version => println!("Knowledge base {} ({}): matches", reference.source, reference.language),
+            Some(_) => println!("Knowledge base {} ({}): yours differs from the one the persona was written for", reference.source, reference.language),
+            None => println!("Knowledge base {} ({}): missing here", reference.source, reference.language),
+        }
+    }
+    println!("To use it, add the settings in {} to your .env", env_file.display());
+    Ok(())
+}
+
+// Directory-safe version of a persona name.
+fn slug(name: &str) -> String {
+    let slug: String = name.to_lowercase().chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '-' }).collect();
+    let slug = slug.split('-').filter(|part| !part.is_empty()).collect::<Vec<_>>().join("-");
+    if slug.is_empty() {
+        "persona".to_string()
+    } else {
+        slug
+    }
+}
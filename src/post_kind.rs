@@ -0,0 +1,163 @@
+use crate::homeserver::max_post_chars;
+use crate::links;
+use pubky_app_specs::PubkyAppPostKind;
+use std::env;
+
+// A caption at most this long next to a lone URL still makes a link post.
+const LINK_CAPTION_CHARS: usize = 80;
+
+// Room kept in every thread part for its " (i/n)" marker.
+const MARKER_CHARS: usize = 8;
+
+// What a post is for, which decides how one too long for a short post is published.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Purpose {
+    // A generated answer to a mention, read as a conversation
+    Reply,
+    // A command's output (stats, listings), which reads badly cut into pieces
+    Command,
+    // A post of the bot's own: calendar entries, tips, milestones
+    Post,
+}
+
+// How a post that doesn't fit in a short one is published, from POST_OVERFLOW:
+// `thread` splits it into a thread of short posts, `long` publishes one long post and
+// `auto` (the default) threads replies and publishes the rest as long posts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Overflow {
+    Thread,
+    Long,
+}
+
+fn overflow(purpose: Purpose) -> Overflow {
+    match env::var("POST_OVERFLOW").map(|v| v.trim().to_lowercase()).as_deref() {
+        Ok("thread") => Overflow::Thread,
+        Ok("long") => Overflow::Long,
+        _ if purpose == Purpose::Reply => Overflow::Thread,
+        _ => Overflow::Long,
+    }
+}
+
+// Most parts a thread is split into, from POST_THREAD_MAX_PARTS; anything longer is
+// published as a long post instead.
+fn max_parts() -> usize {
+    env::var("POST_THREAD_MAX_PARTS").ok().and_then(|v| v.parse().ok()).filter(|n| *n > 0).unwrap_or(4)
+}
+
+// Longest content the policy can publish for `purpose`, in characters.
+pub fn max_chars(purpose: Purpose) -> usize {
+    match overflow(purpose) {
+        Overflow::Thread => (max_post_chars(PubkyAppPostKind::Short) - MARKER_CHARS) * max_parts(),
+        Overflow::Long => max_post_chars(PubkyAppPostKind::Long),
+    }
+}
+
+// The kind of each post to publish and its content: one post, or the parts of a
+// thread, each a reply to the one before.
+#[derive(Debug)]
+pub struct Plan {
+    pub kind: PubkyAppPostKind,
+    pub parts: Vec<String>,
+}
+
+pub fn plan(content: &str, purpose: Purpose) -> Plan {
+    let short_limit = max_post_chars(PubkyAppPostKind::Short);
+    if content.chars().count() <= short_limit {
+        return Plan { kind: kind_for(content), parts: vec![content.to_string()] };
+    }
+    if overflow(purpose) == Overflow::Thread {
+        let parts = split(content, short_limit - MARKER_CHARS);
+        if parts.len() <= max_parts() {
+            let count = parts.len();
+            let parts = parts.into_iter().enumerate().map(|(i, part)| format!("{} ({}/{})", part, i + 1, count)).collect();
+            return Plan { kind: PubkyAppPostKind::Short, parts };
+        }
+    }
+    Plan { kind: PubkyAppPostKind::Long, parts: vec![content.to_string()] }
+}
+
+// Kind of a single post: Long when it doesn't fit in a short one, Link when it is a
+// lone URL with at most a short caption, Short otherwise.
+pub fn kind_for(content: &str) -> PubkyAppPostKind {
+    if content.chars().count() > max_post_chars(PubkyAppPostKind::Short) {
+        return PubkyAppPostKind::Long;
+    }
+    if let Some(url) = links::first_url(content) {
+        let caption: String = content.split_whitespace().filter(|word| !word.contains(url)).collect::<Vec<_>>().join(" ");
+        let single_url = content.split_whitespace().filter(|w| w.starts_with("https://") || w.starts_with("http://")).count() == 1;
+        if single_url && caption.chars().count() <= LINK_CAPTION_CHARS {
+            return PubkyAppPostKind::Link;
+        }
+    }
+    PubkyAppPostKind::Short
+}
+
+// Packs paragraphs, then sentences, then words into parts of at most `limit`
+// characters, so a thread breaks where the text does.
+fn split(content: &str, limit: usize) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    for paragraph in content.split("\n\n").map(str::trim).filter(|p| !p.is_empty()) {
+        for piece in pieces(paragraph, limit) {
+            let separator = if current.is_empty() { "" } else if piece.starts_new_paragraph { "\n\n" } else { " " };
+            if current.chars().count() + separator.len() + piece.text.chars().count() > limit && !current.is_empty() {
+                parts.push(std::mem::take(&mut current));
+                current.push_str(&piece.text);
+            } else {
+                current.push_str(separator);
+                current.push_str(&piece.text);
+            }
+        }
+    }
+    if !current.is_empty() {
+        parts.push(current);
+    }
+    parts
+}
+
+struct Piece {
+    text: String,
+    starts_new_paragraph: bool,
+}
+
+// A paragraph as pieces that each fit in `limit`: the whole paragraph, its sentences,
+// or for an overlong sentence its words.
+fn pieces(paragraph: &str, limit: usize) -> Vec<Piece> {
+    if paragraph.chars().count() <= limit {
+        return vec![Piece { text: paragraph.to_string(), starts_new_paragraph: true }];
+    }
+    let mut pieces = Vec::new();
+    let mut sentence = String::new();
+    for word in paragraph.split_whitespace() {
+        let ends_sentence = word.ends_with(['.', '!', '?']);
+        let mut word = word.to_string();
+        // A single word longer than a part is cut where it has to be
+        if word.chars().count() > limit {
+            push_piece(&mut pieces, std::mem::take(&mut sentence));
+            while word.chars().count() > limit {
+                push_piece(&mut pieces, word.chars().take(limit).collect());
+                word = word.chars().skip(limit).collect();
+            }
+        }
+        if !sentence.is_empty() && sentence.chars().count() + 1 + word.chars().count() > limit {
+            push_piece(&mut pieces, std::mem::take(&mut sentence));
+        }
+        if !sentence.is_empty() {
+            sentence.push(' ');
+        }
+        sentence.push_str(&word);
+        if ends_sentence {
+            push_piece(&mut pieces, std::mem::take(&mut sentence));
+        }
+    }
+    push_piece(&mut pieces, sentence);
+    pieces
+}
+
+fn push_piece(pieces: &mut Vec<Piece>, text: String) {
+    if text.is_empty() {
+        return;
+    }
+    let starts_new_paragraph = pieces.is_empty();
+    pieces.push(Piece { text, starts_new_paragraph });
+}
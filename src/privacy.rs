@@ -0,0 +1,47 @@
+use sha2::{Digest, Sha256};
+use std::env;
+
+// Privacy mode, enabled with PRIVACY_MODE=true: log lines name users by a pseudonym
+// instead of their pubkey and leave out what people wrote, so the log pipeline holds
+// as little personal data as possible.
+pub fn is_enabled() -> bool {
+    env::var("PRIVACY_MODE").map(|v| v == "true").unwrap_or(false)
+}
+
+// How a pubkey is logged in privacy mode. PRIVACY_PUBKEYS=hash (default) gives a salted
+// hash, stable across restarts so one user's lines can still be followed; `truncate`
+// keeps the first few characters.
+pub fn user(pubkey: &str) -> String {
+    if !is_enabled() {
+        return pubkey.to_string();
+    }
+    if env::var("PRIVACY_PUBKEYS").is_ok_and(|v| v == "truncate") {
+        let prefix: String = pubkey.chars().take(6).collect();
+        return format!("{}…", prefix);
+    }
+    let salt = env::var("PRIVACY_SALT").unwrap_or_default();
+    let hash: String = Sha256::digest(format!("{}{}", salt, pubkey).as_bytes()).iter().take(6).map(|b| format!("{:02x}", b)).collect();
+    format!("user:{}", hash)
+}
+
+// A pubky:// URI with the owner's pubkey replaced as in `user`.
+pub fn uri(uri: &str) -> String {
+    if !is_enabled() {
+        return uri.to_string();
+    }
+    let Some(rest) = uri.strip_prefix("pubky://") else {
+        return uri.to_string();
+    };
+    match rest.split_once('/') {
+        Some((pubkey, path)) => format!("pubky://{}/{}", user(pubkey), path),
+        None => format!("pubky://{}", user(rest)),
+    }
+}
+
+// Post text, prompts and replies: only their length is logged in privacy mode.
+pub fn content(text: &str) -> String {
+    if !is_enabled() {
+        return text.to_string();
+    }
+    format!("[{} chars]", text.chars().count())
+}
@@ -0,0 +1,194 @@
+use crate::bots;
+use crate::homeserver::{get_file, put_file};
+use crate::llm_queue;
+use crate::notifications;
+use crate::privacy;
+use crate::stats::format_duration;
+use anyhow::Result;
+use pubky::{Client, Keypair};
+use pubky_app_specs::PubkyAppUser;
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+use tokio::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub bio: Option<String>,
+    // The profile carries the bot marker (see bots.rs)
+    pub bot: bool,
+}
+
+// In-memory cache of user profiles so repeated mentions from the same person
+// don't refetch profile.json from their homeserver every time. Missing profiles
+// are cached too.
+pub struct ProfileCache {
+    entries: HashMap<String, (Instant, Option<Profile>)>,
+    ttl: Duration,
+    max_entries: usize,
+}
+
+impl ProfileCache {
+    // PROFILE_CACHE_TTL (seconds, default 3600) and PROFILE_CACHE_SIZE (default 1000).
+    pub fn from_env() -> ProfileCache {
+        let ttl = env::var("PROFILE_CACHE_TTL").ok().and_then(|v| v.parse().ok()).unwrap_or(3600);
+        let max_entries = env::var("PROFILE_CACHE_SIZE").ok().and_then(|v| v.parse().ok()).unwrap_or(1000);
+        ProfileCache { entries: HashMap::new(), ttl: Duration::from_secs(ttl), max_entries }
+    }
+
+    pub async fn get(&mut self, client: &Client, user: &str) -> Result<Option<Profile>> {
+        if let Some((fetched_at, profile)) = self.entries.get(user) {
+            if fetched_at.elapsed() < self.ttl {
+                return Ok(profile.clone());
+            }
+        }
+
+        let profile = fetch_profile(client, user).await?;
+        if self.max_entries == 0 {
+            return Ok(profile);
+        }
+        if self.entries.len() >= self.max_entries && !self.entries.contains_key(user) {
+            let ttl = self.ttl;
+            self.entries.retain(|_, (fetched_at, _)| fetched_at.elapsed() < ttl);
+            if self.entries.len() >= self.max_entries {
+                if let Some(oldest) = self.entries.iter().min_by_key(|(_, (at, _))| *at).map(|(k, _)| k.clone()) {
+                    self.entries.remove(&oldest);
+                }
+            }
+        }
+        self.entries.insert(user.to_string(), (Instant::now(), profile.clone()));
+        Ok(profile)
+    }
+
+    // "Name (pk)" plus the bio on its own line for operator notifications, falling
+    // back to the public key.
+    pub async fn describe(&mut self, client: &Client, user: &str) -> String {
+        match self.get(client, user).await {
+            Ok(Some(profile)) if !profile.name.trim().is_empty() => match profile.bio.as_deref().map(str::trim).filter(|b| !b.is_empty()) {
+                Some(bio) => format!("{} ({})\n{}", profile.name.trim(), user, bio),
+                None => format!("{} ({})", profile.name.trim(), user),
+            },
+            Ok(_) => user.to_string(),
+            Err(e) => {
+                log!("Failed to fetch profile of {}: {}", privacy::user(user), e);
+                user.to_string()
+            }
+        }
+    }
+}
+
+pub async fn fetch_profile(client: &Client, user: &str) -> Result<Option<Profile>> {
+    let url = format!("pubky://{}/pub/pubky.app/profile.json", user);
+    let Some(body) = get_file(client, &url).await? else {
+        return Ok(None);
+    };
+    Ok(serde_json::from_slice::<PubkyAppUser>(&body).ok().map(|user| Profile { bot: bots::is_marked(&user), name: user.name, bio: user.bio }))
+}
+
+// The status last written to the bot's profile, and when the process started.
+struct StatusState {
+    started: Instant,
+    published: Option<(BotStatus, Instant)>,
+}
+
+static STATUS: Mutex<Option<StatusState>> = Mutex::new(None);
+
+// What the bot is doing, shown in its profile's status with PROFILE_STATUS=true.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BotStatus {
+    Answering,
+    CatchingUp,
+    Paused,
+}
+
+impl BotStatus {
+    async fn current() -> BotStatus {
+        if llm_queue::is_paused().await {
+            BotStatus::Paused
+        } else if notifications::backlog_stats().more_on_server {
+            BotStatus::CatchingUp
+        } else {
+            BotStatus::Answering
+        }
+    }
+
+    // Kept under the spec's 50 character limit for statuses.
+    fn text(self, uptime: Duration) -> String {
+        match self {
+            BotStatus::Answering => format!("✅ Answering mentions · up {}", format_duration(uptime.as_millis() as i64)),
+            BotStatus::CatchingUp => "⏳ Catching up on mentions".to_string(),
+            BotStatus::Paused => "⏸️ Paused: AI provider rate limit".to_string(),
+        }
+    }
+}
+
+pub fn is_status_enabled() -> bool {
+    env::var("PROFILE_STATUS").map(|v| v == "true").unwrap_or(false)
+}
+
+// How often an unchanged status is rewritten to refresh the uptime, from
+// PROFILE_STATUS_INTERVAL (minutes, default 60).
+fn status_interval() -> Duration {
+    Duration::from_secs(env::var("PROFILE_STATUS_INTERVAL").ok().and_then(|v| v.parse().ok()).unwrap_or(60) * 60)
+}
+
+// Links carry the bot marker so other bots can apply their etiquette to this one.
+fn bot_profile(keypair: &Keypair, status: Option<String>) -> PubkyAppUser {
+    PubkyAppUser {
+        name: "AI Rand".to_string(),
+        bio: Some("Mention me and I will respond to you!".to_string()),
+        image: Some("pubky://338pqgzxks8hhqzs7ucfwn17w4qujcfgh58onn6dakwk3r9hxy5o/pub/pubky.app/files/003331KGWWCE0".to_string()),
+        links: Some(vec![bots::marker(&keypair.public_key().to_string())]),
+        status,
+    }
+}
+
+async fn write_bot_profile(client: &Client, keypair: &Keypair, status: Option<String>) -> Result<()> {
+    let profile_json = serde_json::to_string(&bot_profile(keypair, status))?;
+    let url = format!("pubky://{}/pub/pubky.app/profile.json", keypair.public_key());
+    put_file(client, &url, profile_json.into_bytes()).await?;
+    Ok(())
+}
+
+// Writes the bot's profile at startup, with its initial status when PROFILE_STATUS=true.
+pub async fn create_bot_profile(client: &Client, keypair: &Keypair) -> Result<()> {
+    let started = Instant::now();
+    if let Ok(mut state) = STATUS.lock() {
+        *state = Some(StatusState { started, published: None });
+    }
+    if is_status_enabled() {
+        update_status(client, keypair).await?;
+    } else {
+        write_bot_profile(client, keypair, None).await?;
+    }
+    log!("Profile created successfully!");
+    Ok(())
+}
+
+// Rewrites the profile when the bot's status changes, or every PROFILE_STATUS_INTERVAL
+// to keep the uptime current.
+pub async fn update_status(client: &Client, keypair: &Keypair) -> Result<()> {
+    let status = BotStatus::current().await;
+    let (started, due) = {
+        let mut state = STATUS.lock().map_err(|_| anyhow::anyhow!("profile status lock poisoned"))?;
+        let state = state.get_or_insert_with(|| StatusState { started: Instant::now(), published: None });
+        let due = match state.published {
+            Some((published, at)) => published != status || at.elapsed() >= status_interval(),
+            None => true,
+        };
+        (state.started, due)
+    };
+    if !due {
+        return Ok(());
+    }
+    let text = status.text(started.elapsed());
+    write_bot_profile(client, keypair, Some(text.clone())).await?;
+    log!("Profile status set to: {}", text);
+    if let Ok(mut state) = STATUS.lock() {
+        if let Some(state) = state.as_mut() {
+            state.published = Some((status, Instant::now()));
+        }
+    }
+    Ok(())
+}
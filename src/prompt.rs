@@ -0,0 +1,82 @@
+use crate::context::PromptVars;
+use crate::kb;
+use anyhow::Result;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Built into the binary so the bot still runs when the prompt file is missing.
+const DEFAULT_PROMPT: &str = include_str!("../system-prompt.txt");
+const MAX_INCLUDE_DEPTH: usize = 5;
+
+fn prompt_path() -> PathBuf {
+    PathBuf::from(env::var("SYSTEM_PROMPT_FILE").unwrap_or_else(|_| "system-prompt.txt".to_string()))
+}
+
+// Whether replies depend on who asked ({{user_name}}), so they must not be reused
+// for other users.
+pub fn is_personalized() -> bool {
+    fs::read_to_string(prompt_path()).unwrap_or_else(|_| DEFAULT_PROMPT.to_string()).contains("{{user_name}}")
+}
+
+// Loads the system prompt template (SYSTEM_PROMPT_FILE, re-read for every reply so
+// edits apply without a restart) and fills it in:
+//   {{include <path>}}   replaced by that file, relative to the including file
+//   {{knowledge_base}}   replaced by the knowledge base text for this reply
+//   {{date}} {{time}} {{bot_name}} {{follower_count}} {{user_name}} {{persona}} {{max_chars}}
+//                        replaced by the values resolved for this reply
+// A template without {{knowledge_base}} gets the knowledge base appended.
+pub fn system_prompt(knowledge_base: &str, vars: &PromptVars) -> Result<String> {
+    system_prompt_from(&prompt_path(), knowledge_base, vars)
+}
+
+// `system_prompt` with another template, e.g. a candidate under shadow evaluation.
+pub fn system_prompt_from(path: &Path, knowledge_base: &str, vars: &PromptVars) -> Result<String> {
+    let template = match fs::read_to_string(path) {
+        Ok(content) => expand_includes(&content, path.parent().unwrap_or(Path::new(".")), 0)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => DEFAULT_PROMPT.to_string(),
+        Err(e) => return Err(anyhow::anyhow!("Failed to read system prompt {}: {}", path.display(), e)),
+    };
+    // Variables are filled before the knowledge base so its text is never rewritten
+    let template = vars.apply(template.trim_end());
+
+    if template.contains("{{knowledge_base}}") {
+        Ok(template.replace("{{knowledge_base}}", knowledge_base))
+    } else {
+        Ok(format!("{}\n\nKnowledge base:\n\n{}", template, knowledge_base))
+    }
+}
+
+// Short hash of the template with its includes, before any variables are filled in, so
+// replies and their scores can be grouped by prompt version.
+pub fn version() -> String {
+    kb::content_version(&template())
+}
+
+// The template with its includes expanded and no variables filled in.
+pub fn template() -> String {
+    let path = prompt_path();
+    match fs::read_to_string(&path) {
+        Ok(content) => expand_includes(&content, path.parent().unwrap_or(Path::new(".")), 0).unwrap_or(content),
+        Err(_) => DEFAULT_PROMPT.to_string(),
+    }
+}
+
+fn expand_includes(content: &str, dir: &Path, depth: usize) -> Result<String> {
+    let mut output = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find("{{include ") {
+        let Some(len) = rest[start..].find("}}") else { break };
+        output.push_str(&rest[..start]);
+        let file = dir.join(rest[start + "{{include ".len()..start + len].trim());
+        if depth >= MAX_INCLUDE_DEPTH {
+            return Err(anyhow::anyhow!("System prompt includes nested too deeply at {}", file.display()));
+        }
+        let included = fs::read_to_string(&file)
+            .map_err(|e| anyhow::anyhow!("Failed to include {} in the system prompt: {}", file.display(), e))?;
+        output.push_str(expand_includes(&included, file.parent().unwrap_or(dir), depth + 1)?.trim_end());
+        rest = &rest[start + len + 2..];
+    }
+    output.push_str(rest);
+    Ok(output)
+}
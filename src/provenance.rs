@@ -0,0 +1,110 @@
+use crate::correlation;
+use crate::homeserver::{delete_file, get_cached, get_file, list_dir};
+use crate::memory::now_millis;
+use crate::writes::Write;
+use anyhow::Result;
+use pubky::{Client, Keypair};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// Small state record written next to every reply, linking it to the post and
+// trigger it answers. Stored at a path derived from (parent, trigger), so "did we
+// already answer this?" is a single lookup that survives a lost memory DB.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Provenance {
+    pub reply_uri: String,
+    pub parent_uri: String,
+    // "mention" or "tag:<label>"
+    pub trigger: String,
+    pub created_at: i64,
+    // Correlation ID of the notification the reply answers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+}
+
+pub fn trigger_key(tag_label: Option<&str>) -> String {
+    match tag_label {
+        Some(label) => format!("tag:{}", label),
+        None => "mention".to_string(),
+    }
+}
+
+fn records_dir(keypair: &Keypair) -> String {
+    format!("pubky://{}/pub/ai-rand/provenance/", keypair.public_key())
+}
+
+fn record_url(keypair: &Keypair, parent_uri: &str, trigger: &str) -> String {
+    let hash: String = Sha256::digest(format!("{}\n{}", parent_uri, trigger).as_bytes())
+        .iter()
+        .take(16)
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("{}{}", records_dir(keypair), hash)
+}
+
+// The record for a reply, written in the same batch as the reply's posts.
+pub fn record(keypair: &Keypair, reply_uri: &str, parent_uri: &str, trigger: &str) -> Result<Write> {
+    let record = Provenance {
+        reply_uri: reply_uri.to_string(),
+        parent_uri: parent_uri.to_string(),
+        trigger: trigger.to_string(),
+        created_at: now_millis(),
+        correlation_id: correlation::current(),
+    };
+    Ok(Write { uri: record_url(keypair, parent_uri, trigger), body: serde_json::to_vec(&record)? })
+}
+
+// Hash of a reply's text that ignores case, spacing and trailing punctuation, so a
+// regenerated answer that only differs in those counts as the same reply.
+pub fn content_hash(content: &str) -> String {
+    let normalized = content.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    let normalized = normalized.trim_end_matches(|c: char| c.is_ascii_punctuation());
+    Sha256::digest(normalized.as_bytes()).iter().take(16).map(|b| format!("{:02x}", b)).collect()
+}
+
+// The reply already published for this post and trigger, if any.
+pub async fn find(client: &Client, keypair: &Keypair, parent_uri: &str, trigger: &str) -> Result<Option<Provenance>> {
+    let Some(body) = get_cached(client, &record_url(keypair, parent_uri, trigger)).await? else {
+        return Ok(None);
+    };
+    Ok(serde_json::from_slice(&body).ok())
+}
+
+// Deletes the provenance records about `user`: those of replies to the user's posts
+// (acknowledgements included) and of `reply_uris`, the bot's replies to the user, which
+// cover tags on other people's posts. The records are public, so nothing may be left.
+pub async fn forget_user(client: &Client, keypair: &Keypair, user: &str, reply_uris: &[String]) -> Result<usize> {
+    let mut removed = 0;
+    for uri in list_dir(client, &records_dir(keypair)).await? {
+        let Some(body) = get_file(client, &uri).await? else { continue };
+        let Ok(record) = serde_json::from_slice::<Provenance>(&body) else { continue };
+        if concerns(&record, user, reply_uris) {
+            delete_file(client, &uri).await?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+fn concerns(record: &Provenance, user: &str, reply_uris: &[String]) -> bool {
+    record.parent_uri.starts_with(&format!("pubky://{}/", user)) || reply_uris.contains(&record.reply_uri)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(reply_uri: &str, parent_uri: &str) -> Provenance {
+        Provenance { reply_uri: reply_uri.to_string(), parent_uri: parent_uri.to_string(), trigger: "mention".to_string(), created_at: 0, correlation_id: None }
+    }
+
+    #[test]
+    fn finds_the_records_about_a_user() {
+        let replies = vec!["pubky://bot/pub/pubky.app/posts/2".to_string()];
+        assert!(concerns(&record("pubky://bot/pub/pubky.app/posts/1", "pubky://alice/pub/pubky.app/posts/9"), "alice", &[]));
+        // A reply to a tag the user put on someone else's post
+        assert!(concerns(&record("pubky://bot/pub/pubky.app/posts/2", "pubky://carol/pub/pubky.app/posts/8"), "alice", &replies));
+        assert!(!concerns(&record("pubky://bot/pub/pubky.app/posts/3", "pubky://alicex/pub/pubky.app/posts/7"), "alice", &replies));
+        assert!(!concerns(&record("pubky://bot/pub/pubky.app/posts/3", "pubky://bob/pub/pubky.app/posts/7"), "alice", &replies));
+    }
+}
@@ -0,0 +1,97 @@
+use crate::analytics::{self, AnalyticsRow};
+use crate::error::{BotError, BotResult};
+use crate::llm::{send_chat, ChatRequest};
+use crate::llm_queue::{self, Priority};
+use crate::models;
+use crate::secrets::Secret;
+use serde_json::Value;
+use std::env;
+use tokio::time::Instant;
+
+// Second, OpenAI-compatible provider the primary request races against.
+struct RaceProvider {
+    url: String,
+    api_key: Option<Secret>,
+    model: Option<String>,
+}
+
+// RACE_API_URL (a chat completions endpoint) enables race mode.
+fn provider() -> Option<RaceProvider> {
+    let url = env::var("RACE_API_URL").ok().filter(|u| !u.is_empty())?;
+    Some(RaceProvider {
+        url,
+        api_key: Secret::from_env("RACE_API_KEY"),
+        model: env::var("RACE_MODEL").ok().filter(|m| !m.is_empty()).map(|m| models::resolve(&m)),
+    })
+}
+
+pub fn is_enabled() -> bool {
+    provider().is_some()
+}
+
+// Sends the request to OpenAI and the race provider at once and returns the first
+// valid (successful, non-empty) answer. The slower request is dropped, which cancels it.
+pub async fn complete(request: &ChatRequest, priority: Priority) -> BotResult<String> {
+    let Some(secondary) = provider() else {
+        return Err(BotError::Validation("Race mode is not configured".to_string()));
+    };
+    let body = serde_json::to_value(request).map_err(BotError::llm)?;
+    let prompt_chars = request.messages.iter().map(|m| m.content.len()).sum();
+    let tokens = llm_queue::estimate_tokens(prompt_chars, request.max_tokens);
+    let started = Instant::now();
+
+    let primary = async { send_chat(&body, priority, tokens).await.and_then(|response| answer(&response)) };
+    let secondary = request_secondary(&secondary, body.clone());
+    tokio::pin!(primary, secondary);
+
+    let (winner, result) = tokio::select! {
+        result = &mut primary => match result {
+            Ok(text) => ("openai", Ok(text)),
+            Err(e) => {
+                log!("Primary provider failed in race, waiting for the other: {}", e);
+                ("race", secondary.await)
+            }
+        },
+        result = &mut secondary => match result {
+            Ok(text) => ("race", Ok(text)),
+            Err(e) => {
+                log!("Race provider failed, waiting for OpenAI: {}", e);
+                ("openai", primary.await)
+            }
+        },
+    };
+    let text = result?;
+    let latency_ms = started.elapsed().as_millis() as u64;
+    log!("Race won by {} after {} ms", winner, latency_ms);
+    analytics::record(AnalyticsRow::new("llm_race", None, None).with("winner", winner).with("latency_ms", latency_ms));
+    Ok(text)
+}
+
+// The race provider has its own rate limits, so it bypasses the shared queue and is
+// not retried: when it is slow or failing the primary simply wins.
+async fn request_secondary(provider: &RaceProvider, mut body: Value) -> BotResult<String> {
+    if let Some(model) = &provider.model {
+        body["model"] = Value::String(model.clone());
+    }
+    let mut request = reqwest::Client::new().post(&provider.url).json(&body);
+    if let Some(api_key) = &provider.api_key {
+        request = request.header("Authorization", format!("Bearer {}", api_key.expose()));
+    }
+    let response = request.send().await.map_err(BotError::llm)?;
+    let status = response.status();
+    if !status.is_success() {
+        let text = response.text().await.unwrap_or_default();
+        return Err(BotError::Llm(format!("Race provider request failed: {} {}", status, text)));
+    }
+    let response: Value = response.json().await.map_err(BotError::llm)?;
+    answer(&response)
+}
+
+fn answer(response: &Value) -> BotResult<String> {
+    response["choices"][0]["message"]["content"]
+        .as_str()
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .map(String::from)
+        .ok_or_else(|| BotError::Llm("Response contained no answer".to_string()))
+}
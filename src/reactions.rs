@@ -0,0 +1,69 @@
+use crate::error::{BotError, BotResult};
+use crate::homeserver::put_file;
+use pubky::{Client, Keypair};
+use pubky_app_specs::traits::{HasIdPath, HashId, Validatable};
+use pubky_app_specs::PubkyAppTag;
+use std::env;
+
+const THANKS_WORDS: [&str; 8] = ["thanks", "thank", "thx", "ty", "obrigado", "obrigada", "gracias", "merci"];
+// Longer posts are treated as real questions even if they say thanks
+const MAX_THANKS_WORDS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReactionMode {
+    // Tag the mention right away, then reply as usual
+    Ack,
+    // Tag the mention and don't reply
+    Only,
+    // Tag short thank-you posts instead of replying; reply to everything else
+    Thanks,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReactionPolicy {
+    pub label: String,
+    pub mode: ReactionMode,
+}
+
+impl ReactionPolicy {
+    // REACTION_TAG sets the label (unset disables reactions); REACTION_MODE is `ack`
+    // (default), `only` or `thanks`.
+    pub fn from_env() -> Option<ReactionPolicy> {
+        let label = env::var("REACTION_TAG").ok().filter(|l| !l.trim().is_empty())?;
+        let mode = match env::var("REACTION_MODE").unwrap_or_default().as_str() {
+            "only" => ReactionMode::Only,
+            "thanks" => ReactionMode::Thanks,
+            _ => ReactionMode::Ack,
+        };
+        Some(ReactionPolicy { label, mode })
+    }
+
+    // Whether the reaction stands in for a reply to `content`.
+    pub fn replaces_reply(&self, content: &str) -> bool {
+        match self.mode {
+            ReactionMode::Ack => false,
+            ReactionMode::Only => true,
+            ReactionMode::Thanks => is_thanks(content),
+        }
+    }
+}
+
+fn is_thanks(content: &str) -> bool {
+    let words: Vec<String> = content
+        .split_whitespace()
+        .filter(|w| !w.starts_with("pk:") && !w.starts_with('@'))
+        .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase())
+        .filter(|w| !w.is_empty())
+        .collect();
+    words.len() <= MAX_THANKS_WORDS && words.iter().any(|w| THANKS_WORDS.contains(&w.as_str()))
+}
+
+// Tags `post_uri` with `label` from the bot's account.
+pub async fn react(client: &Client, keypair: &Keypair, post_uri: &str, label: &str) -> BotResult<()> {
+    let tag = PubkyAppTag::new(post_uri.to_string(), label.to_string());
+    let id = tag.create_id();
+    tag.validate(Some(&id)).map_err(BotError::Validation)?;
+    let url = format!("pubky://{}{}", keypair.public_key(), tag.create_path(&id));
+    let body = serde_json::to_vec(&tag).map_err(BotError::homeserver)?;
+    put_file(client, &url, body).await
+}
@@ -0,0 +1,71 @@
+use crate::analytics::AnalyticsRow;
+use crate::context::format_utc;
+use crate::memory::{now_millis, Receipt, ReceiptOutcome};
+
+// Why a post got no reply, by the analytics event that recorded the decision.
+const SKIP_REASONS: &[(&str, &str)] = &[
+    ("stale_skipped", "it was too old by the time I got to it"),
+    ("hook_skipped", "an operator filter held it back"),
+    ("reaction", "I reacted to it instead of replying"),
+    ("conversation_limit", "we had reached today's conversation limit"),
+    ("shadow_suppressed", "I'm in a test mode and don't publish replies right now"),
+    ("injection_attempt", "it looked like an attempt to change my instructions"),
+    ("muted_thread_skipped", "someone in the thread asked me to keep quiet there"),
+    ("bot_etiquette_skipped", "I only answer other bots once per thread"),
+];
+
+// Works out what became of a post from the analytics rows recorded while handling it.
+pub fn from_rows(post_uri: &str, rows: &[AnalyticsRow], awaiting_approval: bool) -> Receipt {
+    let reply_uri = rows.iter().rev().find_map(|row| match row.event.as_str() {
+        "reply" | "duplicate_skipped" | "already_answered" => row.data.get("reply_uri").and_then(|v| v.as_str()).map(String::from),
+        _ => None,
+    });
+    let (outcome, detail) = match reply_uri {
+        Some(uri) => (ReceiptOutcome::Answered, Some(uri)),
+        None if awaiting_approval => (ReceiptOutcome::AwaitingApproval, None),
+        None => (ReceiptOutcome::Skipped, skip_reason(rows)),
+    };
+    Receipt { post_uri: post_uri.to_string(), seen_at: now_millis(), outcome, detail }
+}
+
+fn skip_reason(rows: &[AnalyticsRow]) -> Option<String> {
+    rows.iter().rev().find_map(|row| {
+        if row.event == "sentiment" && row.data.get("action").and_then(|v| v.as_str()) == Some("no_engage") {
+            return Some("I don't engage with posts like that".to_string());
+        }
+        SKIP_REASONS.iter().find(|(event, _)| *event == row.event).map(|(_, reason)| reason.to_string())
+    })
+}
+
+pub fn failed(post_uri: &str, error: &str) -> Receipt {
+    Receipt { post_uri: post_uri.to_string(), seen_at: now_millis(), outcome: ReceiptOutcome::Failed, detail: Some(error.to_string()) }
+}
+
+pub fn deferred(post_uri: &str) -> Receipt {
+    Receipt { post_uri: post_uri.to_string(), seen_at: now_millis(), outcome: ReceiptOutcome::Deferred, detail: None }
+}
+
+// Accepts post URIs as pubky:// or as pubky.app web links.
+pub fn normalize_uri(uri: &str) -> String {
+    let uri = uri.trim().trim_end_matches('/');
+    match uri.split_once("pubky.app/post/").and_then(|(_, rest)| rest.split_once('/')) {
+        Some((user, id)) if uri.starts_with("http") => format!("pubky://{}/pub/pubky.app/posts/{}", user, id),
+        _ => uri.to_string(),
+    }
+}
+
+// The answer to !didyousee for a receipt.
+pub fn describe(receipt: &Receipt) -> String {
+    let (date, time) = format_utc(receipt.seen_at);
+    let seen = format!("(seen {} {})", date, time);
+    match receipt.outcome {
+        ReceiptOutcome::Answered => format!("✅ Yes, I saw it and answered: {} {}", receipt.detail.as_deref().unwrap_or_default(), seen),
+        ReceiptOutcome::AwaitingApproval => format!("👀 Yes, I saw it. My reply is waiting for approval. {}", seen),
+        ReceiptOutcome::Skipped => match &receipt.detail {
+            Some(reason) => format!("👀 Yes, I saw it but didn't reply: {}. {}", reason, seen),
+            None => format!("👀 Yes, I saw it but didn't reply. {}", seen),
+        },
+        ReceiptOutcome::Deferred => format!("⏳ Yes, it's in my queue and I'll get to it shortly. {}", seen),
+        ReceiptOutcome::Failed => format!("⚠️ I saw it but ran into an error answering; I'll try again. {}", seen),
+    }
+}
@@ -0,0 +1,131 @@
+use crate::error::BotResult;
+use crate::llm::{chat_completion, reply_max_chars, ChatMessage, ChatRequest};
+use crate::llm_queue::{self, Priority};
+use crate::memory::{now_millis, ReplyRecord};
+use crate::models;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::env;
+use std::hash::{Hash, Hasher};
+
+// Words per shingle: long enough that two answers on the same topic don't match on
+// vocabulary alone, short enough to catch a reply with a few words swapped.
+const SHINGLE_WORDS: usize = 3;
+
+// How far back drafts are compared with the bot's replies, from
+// REPETITION_WINDOW_HOURS. None (the default, or 0) disables the check.
+fn window_millis() -> Option<i64> {
+    env::var("REPETITION_WINDOW_HOURS").ok().and_then(|v| v.parse::<f64>().ok()).filter(|h| *h > 0.0).map(|h| (h * 3_600_000.0) as i64)
+}
+
+pub fn is_enabled() -> bool {
+    window_millis().is_some()
+}
+
+// Share of shingles two replies must have in common to count as a repeat, from
+// REPETITION_SIMILARITY.
+fn threshold() -> f64 {
+    env::var("REPETITION_SIMILARITY").ok().and_then(|v| v.parse::<f64>().ok()).filter(|t| *t > 0.0 && *t <= 1.0).unwrap_or(0.8)
+}
+
+// What the model is asked to do with a repeat, from REPETITION_STRATEGY: `vary`
+// rewords it, `reference` points to the earlier reply and keeps only what's new.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    Vary,
+    Reference,
+}
+
+impl Strategy {
+    pub fn from_env() -> Strategy {
+        match env::var("REPETITION_STRATEGY").map(|v| v.trim().to_lowercase()).as_deref() {
+            Ok("reference") => Strategy::Reference,
+            _ => Strategy::Vary,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Strategy::Vary => "vary",
+            Strategy::Reference => "reference",
+        }
+    }
+}
+
+// Hashes of the reply's overlapping word triples, lowercased and without punctuation,
+// so case, spacing and emoji don't hide a repeat.
+fn fingerprint(text: &str) -> HashSet<u64> {
+    let words: Vec<String> = text
+        .split_whitespace()
+        .map(|w| w.chars().filter(|c| c.is_alphanumeric()).collect::<String>().to_lowercase())
+        .filter(|w| !w.is_empty())
+        .collect();
+    let hash = |shingle: &[String]| {
+        let mut hasher = DefaultHasher::new();
+        shingle.hash(&mut hasher);
+        hasher.finish()
+    };
+    if words.len() < SHINGLE_WORDS {
+        return std::iter::once(hash(&words)).collect();
+    }
+    words.windows(SHINGLE_WORDS).map(hash).collect()
+}
+
+// Jaccard similarity of two fingerprints.
+fn similarity(a: &HashSet<u64>, b: &HashSet<u64>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+// The most similar reply the bot posted within the window, when it's similar enough
+// to `draft` to count as a repeat.
+pub fn find_repeat<'a>(draft: &str, replies: &'a [ReplyRecord]) -> Option<(&'a ReplyRecord, f64)> {
+    let since = now_millis() - window_millis()?;
+    let draft = fingerprint(draft);
+    replies
+        .iter()
+        .filter(|reply| reply.timestamp >= since)
+        .map(|reply| (reply, similarity(&draft, &fingerprint(&reply.content))))
+        .filter(|(_, score)| *score >= threshold())
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+// pubky.app link to one of the bot's replies, for a reference in a post.
+fn web_link(uri: &str) -> String {
+    match uri.strip_prefix("pubky://").and_then(|rest| rest.split_once("/pub/pubky.app/posts/")) {
+        Some((user, id)) => format!("https://pubky.app/post/{}/{}", user, id),
+        None => uri.to_string(),
+    }
+}
+
+// Asks the model for a new version of `draft`, which repeats `earlier`.
+pub async fn revise(question: &str, draft: &str, earlier: &ReplyRecord, strategy: Strategy, priority: Priority) -> BotResult<String> {
+    let max_chars = reply_max_chars();
+    let instruction = match strategy {
+        Strategy::Vary => format!(
+            "The draft reply below is almost word for word a reply the assistant already posted. Rewrite it so it doesn't read the same: vary the wording, structure and opening while keeping its meaning, facts and language. Stay under {} characters. Reply only with the new reply text.",
+            max_chars
+        ),
+        Strategy::Reference => format!(
+            "The draft reply below is almost word for word a reply the assistant already posted at {}. Rewrite it as a short reply that answers the post, links to that earlier reply for the details and adds anything the draft has that it doesn't. Keep its facts and language. Stay under {} characters. Reply only with the new reply text.",
+            web_link(&earlier.uri),
+            max_chars
+        ),
+    };
+    let request = ChatRequest {
+        model: models::resolve("default"),
+        messages: vec![
+            ChatMessage { role: "system".to_string(), content: instruction },
+            ChatMessage {
+                role: "user".to_string(),
+                content: format!("POST:\n{}\n\nEARLIER REPLY:\n{}\n\nDRAFT REPLY:\n{}", question, earlier.content, draft),
+            },
+        ],
+        temperature: 0.8,
+        max_tokens: llm_queue::completion_tokens(max_chars, draft),
+    };
+    Ok(chat_completion(&request, priority).await?.trim().to_string())
+}
@@ -0,0 +1,307 @@
+use crate::approval::PendingView;
+use crate::correlation;
+use crate::gzip;
+use crate::llm_queue::Priority;
+use crate::memory::{now_millis, MemoryDb};
+use crate::mock_llm;
+use crate::notifications;
+use crate::profiles::ProfileCache;
+use crate::timings::StageTimings;
+use crate::{build_client, handle_notification, load_or_create_keypair, Bot};
+use anyhow::Result;
+use base64::Engine;
+use dotenv::dotenv;
+use pubky::Keypair;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::sync::mpsc;
+
+
+// One recorded response. Homeserver bodies are base64 since attachments are binary;
+// `body: None` records a file that didn't exist.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Recorded {
+    NexusNotifications { recorded_at: i64, body: String },
+    HomeserverGet { recorded_at: i64, uri: String, body: Option<String> },
+}
+
+// Homeserver files served during a replay, by URI.
+static REPLAY_FILES: OnceLock<HashMap<String, Option<Vec<u8>>>> = OnceLock::new();
+static RECORD_LOCK: Mutex<()> = Mutex::new(());
+
+// Recording, enabled by pointing RECORD_TRAFFIC at a file: every Nexus notifications
+// response and homeserver read is appended to it as a JSON line.
+fn record_path() -> Option<String> {
+    env::var("RECORD_TRAFFIC").ok().filter(|p| !p.is_empty())
+}
+
+fn append(entry: &Recorded) {
+    let Some(path) = record_path() else { return };
+    let _guard = RECORD_LOCK.lock();
+    let result = serde_json::to_string(entry).map_err(std::io::Error::other).and_then(|line| {
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        writeln!(file, "{}", line)
+    });
+    if let Err(e) = result {
+        log!("Failed to record traffic to {}: {}", path, e);
+    }
+}
+
+pub fn record_notifications(body: &str) {
+    if record_path().is_some() {
+        append(&Recorded::NexusNotifications { recorded_at: now_millis(), body: body.to_string() });
+    }
+}
+
+pub fn record_get(uri: &str, body: Option<&[u8]>) {
+    if record_path().is_some() {
+        let body = body.map(|b| base64::engine::general_purpose::STANDARD.encode(b));
+        append(&Recorded::HomeserverGet { recorded_at: now_millis(), uri: uri.to_string(), body });
+    }
+}
+
+// Drops from recorded JSON lines the notifications `user` caused or that point at their
+// posts, and the homeserver reads of their files. Returns the kept lines and how many
+// notifications and files were dropped.
+pub fn forget_user_in(content: &str, user: &str) -> (String, usize) {
+    let mut kept = String::new();
+    let mut removed = 0;
+    for line in content.lines() {
+        let entry = match serde_json::from_str::<Recorded>(line) {
+            Ok(Recorded::NexusNotifications { recorded_at, body }) => match serde_json::from_str::<Vec<serde_json::Value>>(&body) {
+                Ok(items) => {
+                    let before = items.len();
+                    let items: Vec<_> = items.into_iter().filter(|item| !item.to_string().contains(user)).collect();
+                    removed += before - items.len();
+                    let body = serde_json::to_string(&items).unwrap_or(body);
+                    Recorded::NexusNotifications { recorded_at, body }
+                }
+                // Not a list of notifications; kept whole unless it names the user
+                Err(_) if body.contains(user) => {
+                    removed += 1;
+                    continue;
+                }
+                Err(_) => Recorded::NexusNotifications { recorded_at, body },
+            },
+            Ok(Recorded::HomeserverGet { uri, .. }) if uri.contains(user) => {
+                removed += 1;
+                continue;
+            }
+            Ok(entry) => entry,
+            Err(_) => {
+                kept.push_str(line);
+                kept.push('\n');
+                continue;
+            }
+        };
+        if let Ok(line) = serde_json::to_string(&entry) {
+            kept.push_str(&line);
+            kept.push('\n');
+        }
+    }
+    (kept, removed)
+}
+
+// Removes `user` from the RECORD_TRAFFIC recording and returns how many entries went.
+pub fn forget_user(user: &str) -> std::io::Result<usize> {
+    let Some(path) = record_path() else { return Ok(0) };
+    let _guard = RECORD_LOCK.lock();
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    let (kept, removed) = forget_user_in(&content, user);
+    if removed > 0 {
+        let tmp = format!("{}.tmp", path);
+        fs::write(&tmp, kept)?;
+        fs::rename(&tmp, &path)?;
+    }
+    Ok(removed)
+}
+
+// Reads a recording, decompressing a gzipped notification archive segment.
+fn read_recording(path: &str) -> anyhow::Result<String> {
+    if !path.ends_with(".gz") {
+        return Ok(fs::read_to_string(path)?);
+    }
+    let data = gzip::decompress(&fs::read(path)?).map_err(|e| anyhow::anyhow!("{}: {}", path, e))?;
+    Ok(String::from_utf8(data)?)
+}
+
+// Reads a recording and returns its notification responses in order. From then on
+// homeserver reads are answered from the recording and writes are skipped.
+pub fn start(path: &str) -> anyhow::Result<Vec<String>> {
+    let mut batches = Vec::new();
+    let mut files = HashMap::new();
+    for (number, line) in read_recording(path)?.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: Recorded = serde_json::from_str(line).map_err(|e| anyhow::anyhow!("{} line {}: {}", path, number + 1, e))?;
+        match entry {
+            Recorded::NexusNotifications { body, .. } => batches.push(body),
+            Recorded::HomeserverGet { uri, body, .. } => {
+                let body = body.map(|b| base64::engine::general_purpose::STANDARD.decode(b)).transpose()?;
+                files.insert(uri, body);
+            }
+        }
+    }
+    serve(files)?;
+    Ok(batches)
+}
+
+// Answers homeserver reads from `files` and skips writes from now on.
+pub fn serve(files: HashMap<String, Option<Vec<u8>>>) -> anyhow::Result<()> {
+    REPLAY_FILES.set(files).map_err(|_| anyhow::anyhow!("A replay is already running"))
+}
+
+pub fn is_replaying() -> bool {
+    REPLAY_FILES.get().is_some()
+}
+
+// The recorded body of `uri`; files missing from the recording read as not found.
+pub fn recorded_file(uri: &str) -> Option<Vec<u8>> {
+    REPLAY_FILES.get()?.get(uri).cloned().flatten()
+}
+
+// `replay <file>`: feeds a recording made with RECORD_TRAFFIC through the full
+// notification pipeline against the mock LLM. Homeserver reads come from the recording,
+// writes are logged instead of made, and all state goes to a throwaway directory, so a
+// production incident can be reproduced locally without touching the live bot.
+pub async fn run(args: &[String]) -> Result<()> {
+    let [path] = args else {
+        return Err(anyhow::anyhow!("Usage: replay <recording.jsonl>"));
+    };
+    let mut sandbox = Sandbox::new("replay", false).await?;
+    let batches = start(path)?;
+    let (handled, failed) = sandbox.feed(&batches).await;
+    log!("Replayed {} notification(s) from {} response(s), {} failed", handled + failed, batches.len(), failed);
+    Ok(())
+}
+
+// A bot wired to the mock LLM, with its state in a throwaway directory, for running
+// notifications through the pipeline offline. Homeserver traffic is handled by
+// `start` or `serve`.
+pub struct Sandbox {
+    pub bot: Bot,
+    scratch: PathBuf,
+}
+
+impl Sandbox {
+    // Without `keep_limits` the LLM rate limits are lifted, since only the mock answers.
+    pub async fn new(name: &str, keep_limits: bool) -> Result<Sandbox> {
+        dotenv().ok();
+        env::remove_var("RECORD_TRAFFIC");
+        let scratch = env::temp_dir().join(format!("ai-rand-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&scratch)?;
+        for (var, file) in [
+            ("ANALYTICS_LOG", "analytics.jsonl"),
+            ("EXAMPLES_INDEX", "examples-index.json"),
+            ("FAQ_CACHE_PATH", "faq-cache.json"),
+            ("KB_INDEX", "kb-index.json"),
+            ("MEMORY_DB", "memory.json"),
+            ("NOTIFICATION_QUARANTINE", "notifications-quarantine.jsonl"),
+            ("STORAGE_STATE", "storage.json"),
+        ] {
+            env::set_var(var, scratch.join(file));
+        }
+        // Nothing is fetched from outside the sandbox or sent to a second provider
+        env::set_var("LINK_EMBEDS", "false");
+        env::set_var("RACE_API_URL", "");
+        if !keep_limits {
+            env::set_var("LLM_RPM", "0");
+            env::set_var("LLM_TPM", "0");
+        }
+        mock_llm::install().await?;
+
+        // The bot's own key only decides how its replies are addressed; any key will do
+        let keypair = match load_or_create_keypair().await {
+            Ok(keypair) => keypair,
+            Err(_) => Keypair::random(),
+        };
+        let memory = MemoryDb::load()?;
+        let (_operator_tx, operator_commands) = mpsc::unbounded_channel();
+        let pending_view: PendingView = Arc::new(Mutex::new(memory.pending.clone()));
+        let bot = Bot {
+            client: build_client()?,
+            keypair,
+            memory,
+            nostr: None,
+            telegram: None,
+            operator_commands,
+            pending_view,
+            profiles: ProfileCache::from_env(),
+            stages: StageTimings::default(),
+            persona: None,
+            priority: Priority::Mention,
+        };
+        Ok(Sandbox { bot, scratch })
+    }
+
+    // Handles every notification of each Nexus response in turn, as the poll loop
+    // would. Returns how many were handled and how many failed.
+    pub async fn feed(&mut self, batches: &[String]) -> (usize, usize) {
+        let (mut handled, mut failed) = (0, 0);
+        for (number, batch) in batches.iter().enumerate() {
+            let batch = match notifications::parse_batch(batch) {
+                Ok(batch) => batch,
+                Err(e) => {
+                    log!("Skipping notifications response {}: {}", number + 1, e);
+                    continue;
+                }
+            };
+            log!("Notifications response {} with {} notification(s)", number + 1, batch.len());
+            for notification in &batch {
+                let id = correlation::for_notification(notification.timestamp, &notification.body);
+                match correlation::scope(id, handle_notification(&mut self.bot, notification, None)).await {
+                    Ok(()) => handled += 1,
+                    Err(e) => {
+                        log!("Handling notification failed: {}", e);
+                        failed += 1;
+                    }
+                }
+            }
+        }
+        (handled, failed)
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.scratch);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forgets_a_users_notifications_and_files() {
+        let batch = serde_json::json!([
+            { "timestamp": 1, "body": { "type": "mention", "mentioned_by": "alice", "post_uri": "pubky://alice/pub/pubky.app/posts/1" } },
+            { "timestamp": 2, "body": { "type": "follow", "followed_by": "bob" } },
+        ]);
+        let content = [
+            Recorded::NexusNotifications { recorded_at: 10, body: batch.to_string() },
+            Recorded::HomeserverGet { recorded_at: 11, uri: "pubky://alice/pub/pubky.app/posts/1".to_string(), body: None },
+            Recorded::HomeserverGet { recorded_at: 12, uri: "pubky://bot/pub/pubky.app/posts/2".to_string(), body: None },
+        ]
+        .iter()
+        .map(|entry| serde_json::to_string(entry).unwrap() + "\n")
+        .collect::<String>();
+
+        let (kept, removed) = forget_user_in(&content, "alice");
+        assert_eq!(removed, 2);
+        assert!(!kept.contains("alice"));
+        assert!(kept.contains("bob") && kept.contains("pubky://bot/"));
+        assert_eq!(forget_user_in(&kept, "alice"), (kept.clone(), 0));
+    }
+}
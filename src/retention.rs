@@ -0,0 +1,82 @@
+use crate::cleanup::{now_micros, parse_age, post_micros};
+use crate::examples;
+use crate::homeserver::{delete_post, fetch_post, list_own_posts};
+use crate::memory::{now_millis, MemoryDb};
+use anyhow::Result;
+use pubky::{Client, Keypair};
+use serde_json::json;
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+
+// Bounds how many of its own replies the bot keeps on the homeserver.
+#[derive(Debug, Default)]
+pub struct RetentionPolicy {
+    pub max_age_secs: Option<u64>,
+    pub max_posts: Option<usize>,
+    pub archive_path: Option<String>,
+}
+
+impl RetentionPolicy {
+    // RETENTION_MAX_AGE ("30d"), RETENTION_MAX_POSTS and RETENTION_ARCHIVE; None when
+    // neither limit is set.
+    pub fn from_env() -> Result<Option<RetentionPolicy>> {
+        let var = |name: &str| env::var(name).ok().filter(|v| !v.trim().is_empty());
+        let policy = RetentionPolicy {
+            max_age_secs: var("RETENTION_MAX_AGE").map(|v| parse_age(v.trim())).transpose()?,
+            max_posts: var("RETENTION_MAX_POSTS")
+                .map(|v| v.trim().parse().map_err(|_| anyhow::anyhow!("Invalid RETENTION_MAX_POSTS: {}", v)))
+                .transpose()?,
+            archive_path: var("RETENTION_ARCHIVE"),
+        };
+        if policy.max_age_secs.is_none() && policy.max_posts.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(policy))
+    }
+}
+
+// Deletes the bot's replies that are older than the max age or fall outside the newest
+// `max_posts` posts, archiving each one first when RETENTION_ARCHIVE is set. Posts that
+// aren't replies (e.g. leaderboard posts) are kept. Returns the pruned URIs.
+pub async fn prune(client: &Client, keypair: &Keypair, memory: &mut MemoryDb, policy: &RetentionPolicy, dry_run: bool) -> Result<Vec<String>> {
+    let mut posts = list_own_posts(client, keypair).await?;
+    posts.sort_by_key(|uri| post_micros(uri).unwrap_or(0));
+
+    let over_count = policy.max_posts.map(|max| posts.len().saturating_sub(max)).unwrap_or(0);
+    let cutoff = policy.max_age_secs.map(|secs| now_micros().saturating_sub(secs * 1_000_000));
+    let candidates: Vec<&String> = posts
+        .iter()
+        .enumerate()
+        .filter(|(i, uri)| *i < over_count || cutoff.is_some_and(|c| post_micros(uri).is_some_and(|t| t < c)))
+        .map(|(_, uri)| uri)
+        .collect();
+    log!("Retention: {} of {} post(s) are past the limits", candidates.len(), posts.len());
+
+    let mut pruned = Vec::new();
+    for uri in candidates {
+        let Some(post) = fetch_post(client, uri).await? else {
+            continue;
+        };
+        if post.parent.is_none() {
+            continue;
+        }
+        if dry_run {
+            log!("Would prune: {}", uri);
+        } else {
+            if let Some(path) = &policy.archive_path {
+                let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                writeln!(file, "{}", json!({ "uri": uri, "archived_at": now_millis(), "post": post }))?;
+            }
+            delete_post(client, uri).await?;
+            memory.remove_reply(uri);
+            examples::remove(uri);
+            log!("Pruned: {}", uri);
+        }
+        pruned.push(uri.clone());
+    }
+    if !dry_run {
+        memory.save()?;
+    }
+    Ok(pruned)
+}
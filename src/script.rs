@@ -0,0 +1,652 @@
+use crate::privacy;
+use anyhow::{anyhow, bail, Result};
+use std::collections::HashMap;
+use std::time::Instant;
+
+// The small language operator hooks are written in. Scripts are interpreted inside the
+// bot with no access to files, the network, processes or the environment: all they can
+// do is read the variables they are given, compute with strings, integers and booleans,
+// and set variables. Scripts are cut off at a deadline and strings at MAX_STRING_BYTES,
+// checked before a string is built, so a broken script can't hang the bot or exhaust its
+// memory.
+//
+//   // Comments run to the end of the line
+//   let footer = "\n— posted by a bot";
+//   if contains(lower(text), "unsubscribe") { skip; }
+//   while contains(text, "  ") { text = replace(text, "  ", " "); }
+//   if len(text) + len(footer) <= 280 { text = text + footer; }
+//
+// Statements: `let name = expr;`, `name = expr;`, `if expr { ... } else if ... else { ... }`,
+// `while expr { ... }`, `return;` (stop here), `skip;` (stop and drop the text), and
+// function calls. Expressions: "strings" (with \n \t \" \\ escapes), integers, true, false,
+// variables, `+` (adds integers, concatenates when either side is a string), - * / %,
+// == != < > <= >=, && || ! and parentheses. Functions: len, lower, upper, trim,
+// contains, starts_with, ends_with, replace, truncate (to a number of characters) and log.
+
+const MAX_STRING_BYTES: usize = 400_000;
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Str(_) => "string",
+            Value::Int(_) => "integer",
+            Value::Bool(_) => "boolean",
+        }
+    }
+
+    fn display(&self) -> String {
+        match self {
+            Value::Str(s) => s.clone(),
+            Value::Int(n) => n.to_string(),
+            Value::Bool(b) => b.to_string(),
+        }
+    }
+}
+
+// How a script finished.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Ending {
+    Completed,
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Str(String),
+    Int(i64),
+    Ident(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 21] = ["==", "!=", "<=", ">=", "&&", "||", "+", "-", "*", "/", "%", "<", ">", "!", "=", "(", ")", "{", "}", ",", ";"];
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let (mut pos, mut line) = (0, 1);
+    while pos < chars.len() {
+        let c = chars[pos];
+        if c == '\n' {
+            line += 1;
+            pos += 1;
+        } else if c.is_whitespace() {
+            pos += 1;
+        } else if c == '/' && chars.get(pos + 1) == Some(&'/') {
+            while pos < chars.len() && chars[pos] != '\n' {
+                pos += 1;
+            }
+        } else if c == '"' {
+            let start_line = line;
+            let mut s = String::new();
+            pos += 1;
+            loop {
+                match chars.get(pos) {
+                    None => bail!("Unterminated string starting on line {}", start_line),
+                    Some('"') => break,
+                    Some('\\') => {
+                        s.push(match chars.get(pos + 1) {
+                            Some('n') => '\n',
+                            Some('t') => '\t',
+                            Some('"') => '"',
+                            Some('\\') => '\\',
+                            other => bail!("Unknown escape \\{} on line {}", other.map(|c| c.to_string()).unwrap_or_default(), line),
+                        });
+                        pos += 2;
+                    }
+                    Some(&c) => {
+                        if c == '\n' {
+                            line += 1;
+                        }
+                        s.push(c);
+                        pos += 1;
+                    }
+                }
+            }
+            pos += 1;
+            tokens.push((Token::Str(s), start_line));
+        } else if c.is_ascii_digit() {
+            let start = pos;
+            while chars.get(pos).is_some_and(char::is_ascii_digit) {
+                pos += 1;
+            }
+            let digits: String = chars[start..pos].iter().collect();
+            let n = digits.parse().map_err(|_| anyhow!("Number {} on line {} is too large", digits, line))?;
+            tokens.push((Token::Int(n), line));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = pos;
+            while chars.get(pos).is_some_and(|c| c.is_alphanumeric() || *c == '_') {
+                pos += 1;
+            }
+            tokens.push((Token::Ident(chars[start..pos].iter().collect()), line));
+        } else {
+            let symbol = SYMBOLS
+                .iter()
+                .find(|s| s.chars().enumerate().all(|(i, sc)| chars.get(pos + i) == Some(&sc)))
+                .ok_or_else(|| anyhow!("Unexpected \"{}\" on line {}", c, line))?;
+            pos += symbol.len();
+            tokens.push((Token::Symbol(symbol), line));
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug)]
+enum Expr {
+    Literal(Value),
+    Variable(String, usize),
+    Unary(&'static str, Box<Expr>, usize),
+    Binary(&'static str, Box<Expr>, Box<Expr>, usize),
+    Call(String, Vec<Expr>, usize),
+}
+
+#[derive(Debug)]
+enum Stmt {
+    Let(String, Expr),
+    Assign(String, Expr, usize),
+    If(Vec<(Expr, Vec<Stmt>)>, Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+    Expr(Expr),
+    Return,
+    Skip,
+}
+
+// A parsed script, checked for syntax errors before it ever runs.
+#[derive(Debug)]
+pub struct Script {
+    body: Vec<Stmt>,
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens.get(self.pos).or(self.tokens.last()).map(|(_, l)| *l).unwrap_or(1)
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<()> {
+        if self.eat(symbol) {
+            return Ok(());
+        }
+        match self.peek() {
+            Some(token) => bail!("Expected \"{}\" but found {} on line {}", symbol, describe(token), self.line()),
+            None => bail!("Expected \"{}\" at the end of the script", symbol),
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Ident(name)) if name == keyword) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn ident(&mut self) -> Result<String> {
+        match self.tokens.get(self.pos) {
+            Some((Token::Ident(name), _)) if !is_keyword(name) => {
+                self.pos += 1;
+                Ok(name.clone())
+            }
+            Some((token, line)) => bail!("Expected a name but found {} on line {}", describe(token), line),
+            None => bail!("Expected a name at the end of the script"),
+        }
+    }
+
+    fn enter(&mut self) -> Result<()> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            bail!("Script is nested too deeply on line {}", self.line());
+        }
+        Ok(())
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>> {
+        self.enter()?;
+        self.expect("{")?;
+        let mut body = Vec::new();
+        while !self.eat("}") {
+            if self.peek().is_none() {
+                bail!("Missing \"}}\" at the end of the script");
+            }
+            body.push(self.statement()?);
+        }
+        self.depth -= 1;
+        Ok(body)
+    }
+
+    fn statement(&mut self) -> Result<Stmt> {
+        let line = self.line();
+        if self.eat_keyword("let") {
+            let name = self.ident()?;
+            self.expect("=")?;
+            let value = self.expression()?;
+            self.expect(";")?;
+            return Ok(Stmt::Let(name, value));
+        }
+        if self.eat_keyword("if") {
+            let mut branches = vec![(self.expression()?, self.block()?)];
+            let mut otherwise = Vec::new();
+            while self.eat_keyword("else") {
+                if self.eat_keyword("if") {
+                    branches.push((self.expression()?, self.block()?));
+                } else {
+                    otherwise = self.block()?;
+                    break;
+                }
+            }
+            return Ok(Stmt::If(branches, otherwise));
+        }
+        if self.eat_keyword("while") {
+            return Ok(Stmt::While(self.expression()?, self.block()?));
+        }
+        for (keyword, stmt) in [("return", Stmt::Return), ("skip", Stmt::Skip)] {
+            if self.eat_keyword(keyword) {
+                self.expect(";")?;
+                return Ok(stmt);
+            }
+        }
+        if matches!(self.peek(), Some(Token::Ident(_))) && self.tokens.get(self.pos + 1).is_some_and(|(t, _)| *t == Token::Symbol("=")) {
+            let name = self.ident()?;
+            self.pos += 1;
+            let value = self.expression()?;
+            self.expect(";")?;
+            return Ok(Stmt::Assign(name, value, line));
+        }
+        let expr = self.expression()?;
+        if !matches!(expr, Expr::Call(..)) {
+            bail!("Expected a statement on line {}", line);
+        }
+        self.expect(";")?;
+        Ok(Stmt::Expr(expr))
+    }
+
+    fn expression(&mut self) -> Result<Expr> {
+        self.enter()?;
+        let expr = self.binary(0)?;
+        self.depth -= 1;
+        Ok(expr)
+    }
+
+    // Operators by precedence, loosest first; each level is left-associative.
+    fn binary(&mut self, level: usize) -> Result<Expr> {
+        const LEVELS: [&[&str]; 6] = [&["||"], &["&&"], &["==", "!="], &["<", ">", "<=", ">="], &["+", "-"], &["*", "/", "%"]];
+        let Some(operators) = LEVELS.get(level) else {
+            return self.unary();
+        };
+        let mut left = self.binary(level + 1)?;
+        loop {
+            let line = self.line();
+            let Some(op) = operators.iter().find(|op| matches!(self.peek(), Some(Token::Symbol(s)) if s == *op)).copied() else {
+                return Ok(left);
+            };
+            self.pos += 1;
+            let right = self.binary(level + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right), line);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        let line = self.line();
+        for op in ["!", "-"] {
+            if self.eat(op) {
+                self.enter()?;
+                let operand = self.unary()?;
+                self.depth -= 1;
+                return Ok(Expr::Unary(op, Box::new(operand), line));
+            }
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        let line = self.line();
+        let Some((token, _)) = self.tokens.get(self.pos).cloned() else {
+            bail!("Expected a value at the end of the script");
+        };
+        self.pos += 1;
+        match token {
+            Token::Str(s) => Ok(Expr::Literal(Value::Str(s))),
+            Token::Int(n) => Ok(Expr::Literal(Value::Int(n))),
+            Token::Ident(name) if name == "true" || name == "false" => Ok(Expr::Literal(Value::Bool(name == "true"))),
+            Token::Ident(name) if is_keyword(&name) => bail!("Unexpected \"{}\" on line {}", name, line),
+            Token::Ident(name) if self.eat("(") => {
+                let mut args = Vec::new();
+                if !self.eat(")") {
+                    loop {
+                        args.push(self.expression()?);
+                        if self.eat(")") {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+                Ok(Expr::Call(name, args, line))
+            }
+            Token::Ident(name) => Ok(Expr::Variable(name, line)),
+            Token::Symbol("(") => {
+                let expr = self.expression()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            token => bail!("Unexpected {} on line {}", describe(&token), line),
+        }
+    }
+}
+
+fn is_keyword(name: &str) -> bool {
+    matches!(name, "let" | "if" | "else" | "while" | "return" | "skip" | "true" | "false")
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Str(_) => "a string".to_string(),
+        Token::Int(n) => n.to_string(),
+        Token::Ident(name) => format!("\"{}\"", name),
+        Token::Symbol(s) => format!("\"{}\"", s),
+    }
+}
+
+impl Script {
+    pub fn parse(source: &str) -> Result<Script> {
+        let mut parser = Parser { tokens: tokenize(source)?, pos: 0, depth: 0 };
+        let mut body = Vec::new();
+        while parser.peek().is_some() {
+            body.push(parser.statement()?);
+        }
+        Ok(Script { body })
+    }
+
+    // Runs the script with `vars` as its variables, which hold what the script left in
+    // them afterwards. Errors if it is still running at `deadline`.
+    pub fn run(&self, vars: &mut HashMap<String, Value>, deadline: Instant) -> Result<Ending> {
+        let mut interpreter = Interpreter { vars, deadline };
+        Ok(match interpreter.block(&self.body)? {
+            Flow::Skip => Ending::Skipped,
+            Flow::Next | Flow::Return => Ending::Completed,
+        })
+    }
+}
+
+enum Flow {
+    Next,
+    Return,
+    Skip,
+}
+
+struct Interpreter<'a> {
+    vars: &'a mut HashMap<String, Value>,
+    deadline: Instant,
+}
+
+impl Interpreter<'_> {
+    // Variables declared in a block stay visible after it, as the script is one scope.
+    fn block(&mut self, body: &[Stmt]) -> Result<Flow> {
+        for stmt in body {
+            match self.statement(stmt)? {
+                Flow::Next => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn statement(&mut self, stmt: &Stmt) -> Result<Flow> {
+        if Instant::now() >= self.deadline {
+            bail!("Script ran out of time");
+        }
+        match stmt {
+            Stmt::Let(name, expr) => {
+                let value = self.eval(expr)?;
+                self.vars.insert(name.clone(), value);
+            }
+            Stmt::Assign(name, expr, line) => {
+                if !self.vars.contains_key(name) {
+                    bail!("Assignment to undeclared variable {} on line {} (use let)", name, line);
+                }
+                let value = self.eval(expr)?;
+                self.vars.insert(name.clone(), value);
+            }
+            Stmt::If(branches, otherwise) => {
+                for (condition, body) in branches {
+                    if self.condition(condition)? {
+                        return self.block(body);
+                    }
+                }
+                return self.block(otherwise);
+            }
+            Stmt::While(condition, body) => {
+                while self.condition(condition)? {
+                    // An empty body has no statements to check the deadline
+                    if Instant::now() >= self.deadline {
+                        bail!("Script ran out of time");
+                    }
+                    match self.block(body)? {
+                        Flow::Next => {}
+                        flow => return Ok(flow),
+                    }
+                }
+            }
+            Stmt::Expr(expr) => {
+                self.eval(expr)?;
+            }
+            Stmt::Return => return Ok(Flow::Return),
+            Stmt::Skip => return Ok(Flow::Skip),
+        }
+        Ok(Flow::Next)
+    }
+
+    fn condition(&mut self, expr: &Expr) -> Result<bool> {
+        match self.eval(expr)? {
+            Value::Bool(b) => Ok(b),
+            other => bail!("Condition is a {}, not a boolean", other.type_name()),
+        }
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Value> {
+        match expr {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Variable(name, line) => self.vars.get(name).cloned().ok_or_else(|| anyhow!("Unknown variable {} on line {}", name, line)),
+            Expr::Unary(op, operand, line) => match (*op, self.eval(operand)?) {
+                ("!", Value::Bool(b)) => Ok(Value::Bool(!b)),
+                ("-", Value::Int(n)) => n.checked_neg().map(Value::Int).ok_or_else(|| anyhow!("Integer overflow on line {}", line)),
+                (op, value) => bail!("Can't apply {} to a {} on line {}", op, value.type_name(), line),
+            },
+            // && and || only evaluate their right side when needed
+            Expr::Binary(op @ ("&&" | "||"), left, right, line) => {
+                let left = self.condition(left).map_err(|e| anyhow!("{} on line {}", e, line))?;
+                if left == (*op == "||") {
+                    return Ok(Value::Bool(left));
+                }
+                Ok(Value::Bool(self.condition(right).map_err(|e| anyhow!("{} on line {}", e, line))?))
+            }
+            Expr::Binary(op, left, right, line) => {
+                let (left, right) = (self.eval(left)?, self.eval(right)?);
+                binary(op, left, right).map_err(|e| anyhow!("{} on line {}", e, line))
+            }
+            Expr::Call(name, args, line) => {
+                let args = args.iter().map(|arg| self.eval(arg)).collect::<Result<Vec<_>>>()?;
+                call(name, args).map_err(|e| anyhow!("{}() on line {}: {}", name, line, e))
+            }
+        }
+    }
+}
+
+// Errors if a string of `bytes` would be over the limit; called before building it.
+fn check_size(bytes: Option<usize>) -> Result<()> {
+    match bytes {
+        Some(bytes) if bytes <= MAX_STRING_BYTES => Ok(()),
+        _ => bail!("String would be longer than {} bytes", MAX_STRING_BYTES),
+    }
+}
+
+fn binary(op: &str, left: Value, right: Value) -> Result<Value> {
+    let overflow = || anyhow!("Integer overflow");
+    match (op, left, right) {
+        ("==", l, r) => Ok(Value::Bool(l == r)),
+        ("!=", l, r) => Ok(Value::Bool(l != r)),
+        ("+", Value::Int(l), Value::Int(r)) => l.checked_add(r).map(Value::Int).ok_or_else(overflow),
+        ("+", l @ Value::Str(_), r) | ("+", l, r @ Value::Str(_)) => {
+            let (l, r) = (l.display(), r.display());
+            check_size(l.len().checked_add(r.len()))?;
+            Ok(Value::Str(l + &r))
+        }
+        ("-", Value::Int(l), Value::Int(r)) => l.checked_sub(r).map(Value::Int).ok_or_else(overflow),
+        ("*", Value::Int(l), Value::Int(r)) => l.checked_mul(r).map(Value::Int).ok_or_else(overflow),
+        ("/" | "%", Value::Int(_), Value::Int(0)) => bail!("Division by zero"),
+        ("/", Value::Int(l), Value::Int(r)) => l.checked_div(r).map(Value::Int).ok_or_else(overflow),
+        ("%", Value::Int(l), Value::Int(r)) => l.checked_rem(r).map(Value::Int).ok_or_else(overflow),
+        (op @ ("<" | ">" | "<=" | ">="), l, r) => {
+            let ordering = match (&l, &r) {
+                (Value::Int(l), Value::Int(r)) => l.cmp(r),
+                (Value::Str(l), Value::Str(r)) => l.cmp(r),
+                _ => bail!("Can't compare a {} with a {}", l.type_name(), r.type_name()),
+            };
+            Ok(Value::Bool(match op {
+                "<" => ordering.is_lt(),
+                ">" => ordering.is_gt(),
+                "<=" => ordering.is_le(),
+                _ => ordering.is_ge(),
+            }))
+        }
+        (op, l, r) => bail!("Can't apply {} to a {} and a {}", op, l.type_name(), r.type_name()),
+    }
+}
+
+fn call(name: &str, args: Vec<Value>) -> Result<Value> {
+    let strings = |count: usize| -> Result<Vec<&str>> {
+        if args.len() != count {
+            bail!("takes {} argument(s), got {}", count, args.len());
+        }
+        args.iter().map(|arg| if let Value::Str(s) = arg { Ok(s.as_str()) } else { Err(anyhow!("expects strings, got a {}", arg.type_name())) }).collect()
+    };
+    match name {
+        "len" => Ok(Value::Int(strings(1)?[0].chars().count() as i64)),
+        // Changing case can lengthen a string, e.g. "ß" becomes "SS"
+        "lower" => {
+            let s = strings(1)?[0];
+            check_size(Some(s.chars().flat_map(char::to_lowercase).map(char::len_utf8).sum()))?;
+            Ok(Value::Str(s.to_lowercase()))
+        }
+        "upper" => {
+            let s = strings(1)?[0];
+            check_size(Some(s.chars().flat_map(char::to_uppercase).map(char::len_utf8).sum()))?;
+            Ok(Value::Str(s.to_uppercase()))
+        }
+        "trim" => Ok(Value::Str(strings(1)?[0].trim().to_string())),
+        "contains" => strings(2).map(|s| Value::Bool(s[0].contains(s[1]))),
+        "starts_with" => strings(2).map(|s| Value::Bool(s[0].starts_with(s[1]))),
+        "ends_with" => strings(2).map(|s| Value::Bool(s[0].ends_with(s[1]))),
+        "replace" => {
+            let s = strings(3)?;
+            if s[1].is_empty() {
+                bail!("can't replace an empty string");
+            }
+            let matches = s[0].matches(s[1]).count();
+            check_size(matches.checked_mul(s[2].len()).and_then(|added| (s[0].len() - matches * s[1].len()).checked_add(added)))?;
+            Ok(Value::Str(s[0].replace(s[1], s[2])))
+        }
+        "truncate" => match args.as_slice() {
+            [Value::Str(s), Value::Int(n)] => Ok(Value::Str(s.chars().take((*n).max(0) as usize).collect())),
+            _ => bail!("expects a string and an integer"),
+        },
+        "log" => {
+            // Scripts mostly log `text` and `user`, so the whole line is treated as content
+            log!("Hook script: {}", privacy::content(&args.iter().map(Value::display).collect::<Vec<_>>().join(" ")));
+            Ok(Value::Bool(true))
+        }
+        _ => bail!("unknown function"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn run(source: &str, text: &str) -> Result<(Ending, Value)> {
+        let mut vars = HashMap::from([("text".to_string(), Value::Str(text.to_string())), ("user".to_string(), Value::Str("alice".to_string()))]);
+        let ending = Script::parse(source)?.run(&mut vars, Instant::now() + Duration::from_secs(1))?;
+        Ok((ending, vars.remove("text").unwrap()))
+    }
+
+    fn text(source: &str, text: &str) -> String {
+        match run(source, text).unwrap() {
+            (Ending::Completed, Value::Str(s)) => s,
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn rewrites_text() {
+        assert_eq!(text("text = text + \"\\n— a bot\";", "hi"), "hi\n— a bot");
+        assert_eq!(text("while contains(text, \"  \") { text = replace(text, \"  \", \" \"); }", "a    b  c"), "a b c");
+        assert_eq!(text("let n = 2 + 3 * 4 - -1; text = text + n + (n > 14) + (10 % 4);", "x"), "x15true2");
+        assert_eq!(text("if len(text) > 3 { text = truncate(text, 3) + \"…\"; } else { text = upper(text); }", "abcdef"), "abc…");
+        assert_eq!(text("if starts_with(text, \"!\") { return; } text = lower(trim(text));", "  HEY "), "hey");
+        assert_eq!(text("if user == \"bob\" { text = \"1\"; } else if user == \"alice\" { text = \"2\"; } else { text = \"3\"; }", ""), "2");
+        // Comments, and && short-circuiting past what would be an error
+        assert_eq!(text("// nothing\nif false && 1 / 0 == 0 { text = \"no\"; }", "yes"), "yes");
+    }
+
+    #[test]
+    fn skips() {
+        let (ending, _) = run("if contains(lower(text), \"spam\") { skip; } text = \"kept\";", "Buy SPAM").unwrap();
+        assert_eq!(ending, Ending::Skipped);
+        assert_eq!(text("if contains(lower(text), \"spam\") { skip; } text = \"kept\";", "hello"), "kept");
+    }
+
+    #[test]
+    fn reports_errors_with_lines() {
+        let error = |source: &str| run(source, "").err().unwrap().to_string();
+        assert!(error("let a = 1;\nlet b = a +;").contains("line 2"), "{}", error("let a = 1;\nlet b = a +;"));
+        assert!(error("text = \"open").contains("Unterminated"));
+        assert!(error("x = 1;").contains("undeclared"));
+        assert!(error("if text { }").contains("not a boolean"));
+        assert!(error("let a = 1 / 0;").contains("Division by zero"));
+        assert!(error("let a = 9223372036854775807 + 1;").contains("overflow"));
+        assert!(error("system(\"rm -rf /\");").contains("unknown function"));
+        assert!(error("text;").contains("Expected a statement"));
+        assert!(error(&"(".repeat(200)).contains("nested too deeply"));
+        assert!(error("if true { text = \"a\";").contains("Missing"));
+    }
+
+    #[test]
+    fn limits_time_and_memory() {
+        let mut vars = HashMap::new();
+        let script = Script::parse("while true { }").unwrap();
+        let error = script.run(&mut vars, Instant::now() + Duration::from_millis(20)).err().unwrap();
+        assert!(error.to_string().contains("out of time"));
+        let error = run("while true { text = text + text; }", "ab").err().unwrap();
+        assert!(error.to_string().contains("longer than"), "{}", error);
+        // Each replace multiplies the text; the size is checked before the result is built
+        let error = run("while true { text = replace(text, \"a\", text); }", "aaaa").err().unwrap();
+        assert!(error.to_string().contains("longer than"), "{}", error);
+        let error = run("let a = \"a\"; while len(a) < 200000 { a = a + a; } text = replace(a, \"a\", \"aa\");", "").err().unwrap();
+        assert!(error.to_string().contains("replace()"), "{}", error);
+        assert_eq!(text("text = upper(replace(text, \"ß\", \"ss\"));", "straße"), "STRASSE");
+    }
+}
@@ -0,0 +1,89 @@
+use crate::error::{BotError, BotResult};
+use crate::secrets::Secret;
+use serde_json::Value;
+use std::env;
+use std::time::Duration;
+
+const SEARCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchProvider {
+    // Self-hosted SearxNG instance at SEARCH_URL
+    Searxng,
+    Brave,
+    Bing,
+}
+
+#[derive(Debug)]
+pub struct SearchResult {
+    pub title: String,
+    pub url: String,
+    pub snippet: String,
+}
+
+// SEARCH_PROVIDER picks the web search backend; unset disables the search tool.
+pub fn provider() -> Option<SearchProvider> {
+    match env::var("SEARCH_PROVIDER").unwrap_or_default().to_lowercase().as_str() {
+        "searxng" => Some(SearchProvider::Searxng),
+        "brave" => Some(SearchProvider::Brave),
+        "bing" => Some(SearchProvider::Bing),
+        _ => None,
+    }
+}
+
+fn max_results() -> usize {
+    env::var("SEARCH_RESULTS").ok().and_then(|v| v.parse().ok()).unwrap_or(5)
+}
+
+fn api_key() -> BotResult<Secret> {
+    Secret::from_env("SEARCH_API_KEY").ok_or_else(|| BotError::Validation("SEARCH_API_KEY is required for this search provider".to_string()))
+}
+
+pub async fn search(query: &str) -> BotResult<Vec<SearchResult>> {
+    let Some(provider) = provider() else {
+        return Err(BotError::Validation("Web search is not configured".to_string()));
+    };
+    let client = reqwest::Client::builder().timeout(SEARCH_TIMEOUT).build().map_err(BotError::tool)?;
+    let request = match provider {
+        SearchProvider::Searxng => {
+            let base = env::var("SEARCH_URL").map_err(|_| BotError::Validation("SEARCH_URL is required for SearxNG".to_string()))?;
+            client.get(format!("{}/search", base.trim_end_matches('/'))).query(&[("q", query), ("format", "json")])
+        }
+        SearchProvider::Brave => client
+            .get("https://api.search.brave.com/res/v1/web/search")
+            .header("X-Subscription-Token", api_key()?.expose())
+            .query(&[("q", query)]),
+        SearchProvider::Bing => client
+            .get("https://api.bing.microsoft.com/v7.0/search")
+            .header("Ocp-Apim-Subscription-Key", api_key()?.expose())
+            .query(&[("q", query)]),
+    };
+
+    let response = request.send().await.map_err(|e| BotError::Tool(format!("Search request failed: {}", e)))?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(BotError::Tool(format!("Search request failed: {}", status)));
+    }
+    let body: Value = response.json().await.map_err(|e| BotError::Tool(format!("Unexpected search response: {}", e)))?;
+
+    // (path to the result list, title field, snippet field) for each provider
+    let (results, title_key, snippet_key) = match provider {
+        SearchProvider::Searxng => (&body["results"], "title", "content"),
+        SearchProvider::Brave => (&body["web"]["results"], "title", "description"),
+        SearchProvider::Bing => (&body["webPages"]["value"], "name", "snippet"),
+    };
+    Ok(results
+        .as_array()
+        .map(|items| items.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|item| {
+            Some(SearchResult {
+                title: item[title_key].as_str()?.to_string(),
+                url: item["url"].as_str()?.to_string(),
+                snippet: item[snippet_key].as_str().unwrap_or_default().to_string(),
+            })
+        })
+        .take(max_results())
+        .collect())
+}
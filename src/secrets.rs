@@ -0,0 +1,121 @@
+use std::env;
+use std::fmt;
+use std::sync::OnceLock;
+
+const REDACTED: &str = "[REDACTED]";
+
+// Environment variables holding credentials. Their values are scrubbed from any text
+// passed through `scrub`, wherever they turn up (URLs, echoed request bodies).
+const SECRET_VARS: &[&str] = &[
+    "BOT_SECRET_KEY",
+    "OPENAI_API_KEY",
+    "RACE_API_KEY",
+    "SEARCH_API_KEY",
+    "TELEGRAM_BOT_TOKEN",
+    "ADMIN_API_TOKEN",
+    "NOSTR_SECRET_KEY",
+    "PRIVACY_SALT",
+];
+
+// A credential read from the environment. Debug and Display print a placeholder, so a
+// secret can't end up in a log line or error message by accident; `expose` is the
+// only way to the value and should only be used to build the request that needs it.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    // The variable's value, if it is set and not empty.
+    pub fn from_env(name: &str) -> Option<Secret> {
+        env::var(name).ok().filter(|v| !v.trim().is_empty()).map(Secret)
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+fn known_secrets() -> &'static [String] {
+    static KNOWN: OnceLock<Vec<String>> = OnceLock::new();
+    KNOWN.get_or_init(|| {
+        let mut secrets: Vec<String> = SECRET_VARS
+            .iter()
+            .filter_map(|name| env::var(name).ok())
+            .map(|v| v.trim().to_string())
+            // Very short values would redact ordinary words
+            .filter(|v| v.len() >= 8)
+            .collect();
+        // Longest first, so a secret containing another is replaced whole
+        secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+        secrets
+    })
+}
+
+// Removes credentials from text that is about to be logged or wrapped in an error:
+// the configured secrets, bearer tokens, OpenAI-style `sk-` keys and Telegram bot
+// tokens in URLs.
+pub fn scrub(text: &str) -> String {
+    let mut text = text.to_string();
+    for secret in known_secrets() {
+        if text.contains(secret.as_str()) {
+            text = text.replace(secret.as_str(), REDACTED);
+        }
+    }
+    let text = redact_after(&text, "Bearer ", |c| c.is_ascii_alphanumeric() || "-._~+/=".contains(c));
+    let text = redact_token_prefix(&text, "sk-");
+    redact_after(&text, "api.telegram.org/bot", |c| c != '/' && !c.is_whitespace())
+}
+
+// Replaces the run of `token` characters following each `marker`.
+fn redact_after(text: &str, marker: &str, token: impl Fn(char) -> bool) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(marker) {
+        let (before, after) = rest.split_at(start + marker.len());
+        out.push_str(before);
+        let end = after.find(|c: char| !token(c)).unwrap_or(after.len());
+        if end > 0 {
+            out.push_str(REDACTED);
+        }
+        rest = &after[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// Replaces key-shaped words starting with `prefix` (e.g. sk-proj-abc123...).
+fn redact_token_prefix(text: &str, prefix: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(prefix) {
+        let at_word_start = rest[..start].chars().next_back().is_none_or(|c| !c.is_ascii_alphanumeric());
+        let after = &rest[start..];
+        let end = after.find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_')).unwrap_or(after.len());
+        out.push_str(&rest[..start]);
+        // Real keys are long; short matches are ordinary words like "sk-8"
+        if at_word_start && end >= 20 {
+            out.push_str(REDACTED);
+        } else {
+            out.push_str(&after[..end]);
+        }
+        rest = &after[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// For errors from HTTP clients, which include the request URL and may include tokens.
+pub fn scrub_error(e: impl fmt::Display) -> anyhow::Error {
+    anyhow::anyhow!(scrub(&e.to_string()))
+}
@@ -0,0 +1,85 @@
+use crate::homeserver::{delete_post, publish_post};
+use crate::privacy;
+use anyhow::Result;
+use pubky::{Client, Keypair, PublicKey};
+use pubky_app_specs::{PubkyAppPost, PubkyAppPostKind};
+use pubky_timestamp::Timestamp;
+use std::env;
+use std::time::Duration;
+
+// Homeserver of the Pubky testnet, where the scratch account is signed up.
+const TESTNET_HOMESERVER: &str = "8pinxxgqs41n4aididenw5apqp1urfmzdztr8jt4abrkdn435ewo";
+
+// Startup self-test, with SELF_TEST=true (report and carry on) or SELF_TEST=required
+// (exit when it fails). Only runs with TESTNET=true: it signs up a throwaway account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Off,
+    Report,
+    Required,
+}
+
+pub fn mode() -> Mode {
+    match env::var("SELF_TEST").unwrap_or_default().trim().to_lowercase().as_str() {
+        "true" => Mode::Report,
+        "required" => Mode::Required,
+        _ => Mode::Off,
+    }
+}
+
+pub fn is_testnet() -> bool {
+    env::var("TESTNET").map(|v| v == "true").unwrap_or(false)
+}
+
+// How long the mention may take to be indexed and answered, from SELF_TEST_TIMEOUT_SECS.
+pub fn timeout() -> Duration {
+    Duration::from_secs(env::var("SELF_TEST_TIMEOUT_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(180))
+}
+
+// The posts of a self-test run: the bot's probe post and a scratch account's reply to
+// it mentioning the bot, which the bot must answer through its normal pipeline.
+pub struct Probe {
+    scratch: Client,
+    pub probe_uri: String,
+    pub mention_uri: String,
+}
+
+impl Probe {
+    pub async fn start(client: &Client, keypair: &Keypair) -> Result<Probe> {
+        let probe_uri = publish_post(client, keypair, None, "🧪 Startup self-test, this post will be removed shortly.".to_string(), PubkyAppPostKind::Short).await?;
+        log!("Self-test: published probe post {}", probe_uri);
+
+        let homeserver = env::var("SELF_TEST_HOMESERVER").ok().filter(|h| !h.trim().is_empty()).unwrap_or_else(|| TESTNET_HOMESERVER.to_string());
+        let homeserver = PublicKey::try_from(homeserver.as_str()).map_err(|e| anyhow::anyhow!("Invalid SELF_TEST_HOMESERVER: {}", e))?;
+        let scratch = Client::builder().testnet().build()?;
+        let scratch_keypair = Keypair::random();
+        scratch.signup(&scratch_keypair, &homeserver, None).await?;
+        log!("Self-test: signed up scratch account {}", privacy::user(&scratch_keypair.public_key().to_string()));
+
+        // Written directly: the bot's own helpers would track and back up the file as its own
+        let mention = PubkyAppPost {
+            content: format!("pk:{} self-test: what is Pubky?", keypair.public_key()),
+            kind: PubkyAppPostKind::Short,
+            parent: Some(probe_uri.clone()),
+            embed: None,
+            attachments: None,
+        };
+        let mention_uri = format!("pubky://{}/pub/pubky.app/posts/{}", scratch_keypair.public_key(), Timestamp::now());
+        scratch.put(&mention_uri).body(serde_json::to_vec(&mention)?).send().await?.error_for_status()?;
+        log!("Self-test: scratch account mentioned the bot in {}", mention_uri);
+        Ok(Probe { scratch, probe_uri, mention_uri })
+    }
+
+    // Deletes the test posts, and the bot's reply when there is one. Failures are only
+    // logged, the result of the test stands.
+    pub async fn clean_up(&self, client: &Client, reply_uri: Option<&str>) {
+        if let Err(e) = self.scratch.delete(&self.mention_uri).send().await.and_then(|r| r.error_for_status()) {
+            log!("Self-test: failed to delete {}: {}", self.mention_uri, e);
+        }
+        for uri in reply_uri.into_iter().chain([self.probe_uri.as_str()]) {
+            if let Err(e) = delete_post(client, uri).await {
+                log!("Self-test: failed to delete {}: {}", uri, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use crate::http;
+use crate::llm::openai_url;
+use crate::secrets::Secret;
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::env;
+
+const DEESCALATE_INSTRUCTION: &str = "The user's post is hostile or upset. Stay calm, warm and respectful. Don't mirror their tone, don't argue or lecture, acknowledge their frustration briefly, and keep the reply short and constructive.";
+
+#[derive(Debug, Deserialize)]
+struct ModerationResponse {
+    results: Vec<ModerationResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModerationResult {
+    category_scores: HashMap<String, f32>,
+}
+
+// How the bot should treat a mention given its toxicity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SentimentAction {
+    Engage,
+    Deescalate,
+    NoEngage,
+}
+
+#[derive(Debug)]
+pub struct SentimentDecision {
+    pub action: SentimentAction,
+    pub score: f32,
+    pub category: String,
+}
+
+impl SentimentAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SentimentAction::Engage => "engage",
+            SentimentAction::Deescalate => "deescalate",
+            SentimentAction::NoEngage => "no_engage",
+        }
+    }
+
+    pub fn instruction(&self) -> Option<&'static str> {
+        match self {
+            SentimentAction::Deescalate => Some(DEESCALATE_INSTRUCTION),
+            _ => None,
+        }
+    }
+}
+
+// Enabled with SENTIMENT_POLICY=true.
+pub fn is_enabled() -> bool {
+    env::var("SENTIMENT_POLICY").map(|v| v == "true").unwrap_or(false)
+}
+
+fn threshold(name: &str, default: f32) -> f32 {
+    env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+// Scores the post with OpenAI's moderation endpoint and maps the highest category
+// score onto the configured thresholds.
+pub async fn assess(content: &str) -> Result<SentimentDecision> {
+    let api_key = Secret::from_env("OPENAI_API_KEY").ok_or_else(|| anyhow::anyhow!("OPENAI_API_KEY not found in .env"))?;
+    let response = http::llm()
+        .post(openai_url("moderations"))
+        .header("Authorization", format!("Bearer {}", api_key.expose()))
+        .json(&json!({ "model": "omni-moderation-latest", "input": content }))
+        .send()
+        .await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(anyhow::anyhow!("Moderation request failed: {}", status));
+    }
+    let moderation: ModerationResponse = response.json().await?;
+
+    let (category, score) = moderation
+        .results
+        .first()
+        .and_then(|r| r.category_scores.iter().max_by(|a, b| a.1.total_cmp(b.1)))
+        .map(|(c, s)| (c.clone(), *s))
+        .unwrap_or_default();
+
+    let action = if score >= threshold("TOXICITY_NO_ENGAGE_THRESHOLD", 0.8) {
+        SentimentAction::NoEngage
+    } else if score >= threshold("TOXICITY_DEESCALATE_THRESHOLD", 0.4) {
+        SentimentAction::Deescalate
+    } else {
+        SentimentAction::Engage
+    };
+
+    Ok(SentimentDecision { action, score, category })
+}
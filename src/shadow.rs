@@ -0,0 +1,139 @@
+use crate::analytics::{self, AnalyticsRow};
+use crate::context::PromptVars;
+use crate::correlation;
+use crate::examples::Example;
+use crate::llm::{generate_response, ChatMessage, PromptContext};
+use crate::llm_queue::Priority;
+use crate::privacy;
+use std::collections::BTreeMap;
+use std::env;
+use std::path::PathBuf;
+
+// Shadow evaluation (SHADOW_MODE): replies to mentions are also, or only, generated
+// for the analytics log instead of the network, so a new prompt, model or persona can
+// be compared on real traffic before it goes live.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Off,
+    // Nothing is published; replies go to the analytics log only
+    Only,
+    // Live replies are published as usual and the candidate's reply is logged next to each
+    Alongside,
+}
+
+pub fn mode() -> Mode {
+    match env::var("SHADOW_MODE").unwrap_or_default().as_str() {
+        "only" | "true" => Mode::Only,
+        "alongside" => Mode::Alongside,
+        _ => Mode::Off,
+    }
+}
+
+// Whether replies must stay off the network.
+pub fn is_only() -> bool {
+    mode() == Mode::Only
+}
+
+// What the shadow replies are generated with, in place of the live configuration.
+#[derive(Debug, Clone, Default)]
+pub struct Candidate {
+    pub prompt_file: Option<PathBuf>,
+    // Model name or alias
+    pub model: Option<String>,
+    pub persona: Option<String>,
+}
+
+impl Candidate {
+    // How the candidate is labelled in the analytics log, to group rows by variant.
+    pub fn label(&self) -> String {
+        env::var("SHADOW_LABEL").ok().filter(|l| !l.is_empty()).unwrap_or_else(|| {
+            let parts: Vec<String> = [
+                self.prompt_file.as_ref().map(|p| format!("prompt={}", p.display())),
+                self.model.as_ref().map(|m| format!("model={}", m)),
+                self.persona.as_ref().map(|p| format!("persona={}", p)),
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
+            if parts.is_empty() { "live".to_string() } else { parts.join(" ") }
+        })
+    }
+}
+
+// The candidate configured by SHADOW_SYSTEM_PROMPT_FILE, SHADOW_MODEL and SHADOW_PERSONA;
+// None when none is set, so shadow replies use the live configuration.
+pub fn candidate() -> Option<Candidate> {
+    let var = |name: &str| env::var(name).ok().filter(|v| !v.trim().is_empty());
+    let candidate = Candidate {
+        prompt_file: var("SHADOW_SYSTEM_PROMPT_FILE").map(PathBuf::from),
+        model: var("SHADOW_MODEL"),
+        persona: var("SHADOW_PERSONA"),
+    };
+    (candidate.prompt_file.is_some() || candidate.model.is_some() || candidate.persona.is_some()).then_some(candidate)
+}
+
+// Writes a reply that was not published to the analytics log as `shadow_reply`, with the
+// published reply it shadows when there is one.
+pub fn record(to_user: &str, parent_uri: &str, prompt: &str, content: &str, live_reply: Option<&str>) {
+    let label = candidate().map(|c| c.label()).unwrap_or_else(|| "live".to_string());
+    log!("Shadow reply ({}) to {}: {}", label, privacy::uri(parent_uri), privacy::content(content));
+    let mut row = AnalyticsRow::new("shadow_reply", Some(to_user), Some(parent_uri))
+        .with("candidate", label)
+        .with("prompt", prompt)
+        .with("content", content);
+    if let Some(live) = live_reply {
+        row = row.with("live_reply", live);
+    }
+    analytics::record(row);
+}
+
+// Owned copy of what a reply was generated from, so the candidate's reply can be
+// generated in the background without holding up the live one.
+pub struct ShadowRequest {
+    pub to_user: String,
+    pub parent_uri: String,
+    pub prompt: String,
+    pub prefs: Option<BTreeMap<String, String>>,
+    pub instruction: Option<String>,
+    pub vars: PromptVars,
+    pub history: Vec<ChatMessage>,
+    pub language: Option<String>,
+    pub examples: Vec<Example>,
+    pub max_chars: Option<usize>,
+    pub people: Option<String>,
+    pub live_reply: String,
+}
+
+// Generates the candidate's reply to a mention that was answered live and records both,
+// at digest priority so it never delays a live reply.
+pub fn spawn(request: ShadowRequest) {
+    let Some(candidate) = candidate() else {
+        log!("SHADOW_MODE=alongside needs SHADOW_SYSTEM_PROMPT_FILE, SHADOW_MODEL or SHADOW_PERSONA");
+        return;
+    };
+    let correlation_id = correlation::current();
+    let work = async move {
+        let context = PromptContext {
+            prefs: request.prefs.as_ref(),
+            instruction: request.instruction.as_deref(),
+            vars: Some(&request.vars),
+            history: &request.history,
+            language: request.language.as_deref(),
+            examples: &request.examples,
+            priority: Priority::Digest,
+            candidate: Some(&candidate),
+            max_chars: request.max_chars,
+            people: request.people.as_deref(),
+        };
+        match generate_response(&request.prompt, &context).await {
+            Ok(content) => record(&request.to_user, &request.parent_uri, &request.prompt, &content, Some(&request.live_reply)),
+            Err(e) => log!("Shadow generation failed for {}: {}", privacy::uri(&request.parent_uri), e),
+        }
+    };
+    tokio::spawn(async move {
+        match correlation_id {
+            Some(id) => correlation::scope(id, work).await,
+            None => work.await,
+        }
+    });
+}
@@ -0,0 +1,191 @@
+use crate::analytics;
+use crate::intake;
+use crate::llm_queue;
+use crate::memory::now_millis;
+use crate::replay::{self, Sandbox};
+use crate::timings;
+use anyhow::Result;
+use pubky::Keypair;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Instant;
+
+const QUESTIONS: &[(&str, &[&str])] = &[
+    ("en", &["What is Pubky and how does it work?", "How do I run my own homeserver?", "Can I move my account to another homeserver?"]),
+    ("es", &["¿Qué es Pubky y cómo funciona?", "¿Cómo puedo ejecutar mi propio homeserver?", "¿Para qué sirven las etiquetas?"]),
+    ("pt", &["O que é o Pubky e como funciona?", "Como faço para rodar meu próprio homeserver?", "Para que servem as tags?"]),
+    ("de", &["Was ist Pubky und wie funktioniert es?", "Wie betreibe ich meinen eigenen Homeserver?", "Wofür sind Tags gut?"]),
+    ("fr", &["Qu'est-ce que Pubky et comment ça marche ?", "Comment faire tourner mon propre homeserver ?", "À quoi servent les tags ?"]),
+];
+const FILLER: &str = "Some more context about my setup, what I tried so far and what I expected to happen instead. ";
+// Long posts are padded to about this many characters
+const LONG_POST_CHARS: usize = 2_000;
+const ATTACHMENT_BYTES: usize = 64 * 1024;
+
+// Mix of synthetic mentions to generate.
+struct Mix {
+    mentions: usize,
+    batch: usize,
+    long_percent: u64,
+    attachment_percent: u64,
+    languages: Vec<&'static str>,
+    seed: u64,
+}
+
+impl Mix {
+    fn parse(args: &[String]) -> Result<Mix> {
+        let usage = || anyhow::anyhow!("Usage: simulate [--mentions N] [--batch N] [--long PERCENT] [--attachments PERCENT] [--languages en,es,pt,de,fr] [--seed N]");
+        let mut mix = Mix {
+            mentions: 100,
+            batch: intake::page_size(),
+            long_percent: 20,
+            attachment_percent: 10,
+            languages: QUESTIONS.iter().map(|(lang, _)| *lang).collect(),
+            seed: 1,
+        };
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            let value = args.next().ok_or_else(usage)?;
+            let number = || value.parse::<u64>().map_err(|_| anyhow::anyhow!("{} needs a number", flag));
+            match flag.as_str() {
+                "--mentions" => mix.mentions = number()? as usize,
+                "--batch" => mix.batch = (number()? as usize).max(1),
+                "--long" => mix.long_percent = number()?.min(100),
+                "--attachments" => mix.attachment_percent = number()?.min(100),
+                "--seed" => mix.seed = number()?,
+                "--languages" => {
+                    mix.languages = value
+                        .split(',')
+                        .map(|code| {
+                            QUESTIONS
+                                .iter()
+                                .map(|(lang, _)| *lang)
+                                .find(|lang| *lang == code.trim())
+                                .ok_or_else(|| anyhow::anyhow!("Unsupported language {} (expected en, es, pt, de or fr)", code))
+                        })
+                        .collect::<Result<_>>()?;
+                }
+                _ => return Err(usage()),
+            }
+        }
+        Ok(mix)
+    }
+}
+
+// Small deterministic generator, so a simulation can be rerun with the same traffic.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn percent(&mut self, percent: u64) -> bool {
+        self.next() % 100 < percent
+    }
+}
+
+// `simulate [...]`: pushes a volume of synthetic mentions through the full pipeline
+// against the mock LLM, keeping the configured rate limits, to see how the bot copes
+// before it meets real traffic. Posts vary in language and length and some carry an
+// image or voice note; set MOCK_LLM_LATENCY_MS for realistic provider latency.
+pub async fn run(args: &[String]) -> Result<()> {
+    let mix = Mix::parse(args)?;
+    let mut sandbox = Sandbox::new("simulate", true).await?;
+    let (batches, files) = generate(&mix);
+    replay::serve(files)?;
+    log!(
+        "Simulating {} mention(s) in {} batch(es): {}% long, {}% with attachments, languages {}",
+        mix.mentions,
+        batches.len(),
+        mix.long_percent,
+        mix.attachment_percent,
+        mix.languages.join(",")
+    );
+
+    let started = Instant::now();
+    let (handled, failed) = sandbox.feed(&batches).await;
+    let elapsed = started.elapsed();
+
+    println!();
+    println!(
+        "Handled {} mention(s) in {:.1}s ({:.1}/min), {} failed",
+        handled + failed,
+        elapsed.as_secs_f64(),
+        (handled + failed) as f64 / elapsed.as_secs_f64().max(0.001) * 60.0,
+        failed
+    );
+    let mut outcomes: BTreeMap<String, usize> = BTreeMap::new();
+    for row in analytics::load_rows()? {
+        *outcomes.entry(row.event).or_default() += 1;
+    }
+    println!("Analytics events: {}", outcomes.iter().map(|(event, n)| format!("{} {}", event, n)).collect::<Vec<_>>().join(", "));
+    let queue = llm_queue::queue_stats();
+    println!(
+        "LLM queue: {} request(s), ~{} tokens, {} delayed by the rate limits (total wait {:.1}s)",
+        queue.requests,
+        queue.estimated_tokens,
+        queue.delayed,
+        queue.total_delay_ms as f64 / 1000.0
+    );
+    println!("{:<20} {:>8} {:>10} {:>10}", "stage", "count", "mean", "max");
+    for (stage, histogram) in timings::histograms() {
+        let mean = histogram.sum_ms / histogram.count.max(1);
+        println!("{:<20} {:>8} {:>7} ms {:>7} ms", stage, histogram.count, mean, histogram.max_ms);
+    }
+    Ok(())
+}
+
+// Builds the Nexus responses and the homeserver files (posts, file records, blobs)
+// the synthetic mentions point to.
+fn generate(mix: &Mix) -> (Vec<String>, HashMap<String, Option<Vec<u8>>>) {
+    let mut rng = Rng(mix.seed.max(1));
+    let mut files = HashMap::new();
+    let mut notifications = Vec::with_capacity(mix.mentions);
+    let started_at = now_millis();
+    for i in 0..mix.mentions {
+        let author = Keypair::random().public_key().to_string();
+        let lang = mix.languages[rng.below(mix.languages.len())];
+        let questions = QUESTIONS.iter().find(|(code, _)| *code == lang).map(|(_, q)| *q).unwrap_or_default();
+        let mut content = questions[rng.below(questions.len())].to_string();
+        let long = rng.percent(mix.long_percent);
+        while long && content.len() < LONG_POST_CHARS {
+            content.push(' ');
+            content.push_str(FILLER);
+        }
+
+        let mut attachments = Vec::new();
+        if rng.percent(mix.attachment_percent) {
+            let (name, content_type) = if rng.percent(50) { ("screenshot.png", "image/png") } else { ("voice-note.ogg", "audio/ogg") };
+            let file_uri = format!("pubky://{}/pub/pubky.app/files/SIM{:06}", author, i);
+            let blob_uri = format!("pubky://{}/pub/pubky.app/blobs/SIM{:06}", author, i);
+            let record = json!({ "name": name, "created_at": started_at, "src": blob_uri, "content_type": content_type, "size": ATTACHMENT_BYTES });
+            files.insert(file_uri.clone(), Some(record.to_string().into_bytes()));
+            files.insert(blob_uri, Some(vec![(i % 251) as u8; ATTACHMENT_BYTES]));
+            attachments.push(file_uri);
+        }
+
+        let post_uri = format!("pubky://{}/pub/pubky.app/posts/SIM{:06}", author, i);
+        let post = json!({
+            "content": content,
+            "kind": if long { "long" } else { "short" },
+            "parent": null,
+            "embed": null,
+            "attachments": (!attachments.is_empty()).then_some(attachments),
+        });
+        files.insert(post_uri.clone(), Some(post.to_string().into_bytes()));
+        notifications.push(json!({
+            "timestamp": started_at + i as i64,
+            "body": { "type": "mention", "mentioned_by": author, "post_uri": post_uri },
+        }));
+    }
+    let batches = notifications.chunks(mix.batch).map(|batch| serde_json::Value::from(batch.to_vec()).to_string()).collect();
+    (batches, files)
+}
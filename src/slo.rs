@@ -0,0 +1,173 @@
+use crate::analytics::{self, AnalyticsRow};
+use crate::memory::now_millis;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::VecDeque;
+use std::env;
+use std::sync::Mutex;
+
+// How often the percentiles are checked against the SLOs.
+pub const CHECK_INTERVAL_MILLIS: i64 = 60_000;
+
+// One published reply: model time (None for replies that didn't call the model) and
+// time from the start of handling to publishing.
+struct Sample {
+    at: i64,
+    llm_ms: Option<u64>,
+    reply_ms: u64,
+}
+
+// A latency objective: a percentile of one measure that should stay under a limit.
+struct Objective {
+    name: &'static str,
+    var: &'static str,
+    percentile: f64,
+    llm: bool,
+}
+
+const OBJECTIVES: [Objective; 4] = [
+    Objective { name: "llm_p50", var: "SLO_LLM_P50_MS", percentile: 0.5, llm: true },
+    Objective { name: "llm_p95", var: "SLO_LLM_P95_MS", percentile: 0.95, llm: true },
+    Objective { name: "reply_p50", var: "SLO_REPLY_P50_MS", percentile: 0.5, llm: false },
+    Objective { name: "reply_p95", var: "SLO_REPLY_P95_MS", percentile: 0.95, llm: false },
+];
+
+#[derive(Default)]
+struct State {
+    samples: VecDeque<Sample>,
+    // Objective name -> when its current breach started, and whether it was alerted
+    breaches: Vec<(&'static str, i64, bool)>,
+    last_check: i64,
+}
+
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SloStatus {
+    pub objective: &'static str,
+    pub limit_ms: u64,
+    pub current_ms: Option<u64>,
+    // Unix ms the ongoing breach started, if any
+    pub breached_since: Option<i64>,
+}
+
+fn minutes(var: &str, default: i64) -> i64 {
+    env::var(var).ok().and_then(|v| v.parse::<i64>().ok()).unwrap_or(default).max(1) * 60_000
+}
+
+fn limit(objective: &Objective) -> Option<u64> {
+    env::var(objective.var).ok().and_then(|v| v.parse().ok()).filter(|ms| *ms > 0)
+}
+
+// Whether any SLO_*_MS threshold is set.
+pub fn is_enabled() -> bool {
+    OBJECTIVES.iter().any(|objective| limit(objective).is_some())
+}
+
+// Records a published reply's latencies.
+pub fn record(llm_ms: Option<u64>, reply_ms: u64) {
+    if !is_enabled() {
+        return;
+    }
+    let now = now_millis();
+    let window = minutes("SLO_WINDOW_MINUTES", 15);
+    if let Ok(mut state) = STATE.lock() {
+        let state = state.get_or_insert_with(State::default);
+        state.samples.push_back(Sample { at: now, llm_ms, reply_ms });
+        while state.samples.front().is_some_and(|s| now - s.at > window) {
+            state.samples.pop_front();
+        }
+    }
+}
+
+fn percentile(state: &State, objective: &Objective, now: i64) -> Option<u64> {
+    let window = minutes("SLO_WINDOW_MINUTES", 15);
+    let min_samples = env::var("SLO_MIN_SAMPLES").ok().and_then(|v| v.parse().ok()).unwrap_or(5);
+    let mut values: Vec<u64> = state
+        .samples
+        .iter()
+        .filter(|s| now - s.at <= window)
+        .filter_map(|s| if objective.llm { s.llm_ms } else { Some(s.reply_ms) })
+        .collect();
+    if values.is_empty() || values.len() < min_samples {
+        return None;
+    }
+    values.sort_unstable();
+    let rank = ((values.len() as f64 * objective.percentile).ceil() as usize).clamp(1, values.len());
+    Some(values[rank - 1])
+}
+
+// Compares the percentiles over the last SLO_WINDOW_MINUTES with their thresholds, at
+// most once per CHECK_INTERVAL_MILLIS. Returns alerts for objectives breached for
+// SLO_SUSTAIN_MINUTES in a row, and for alerted ones that recovered.
+pub fn check() -> Vec<String> {
+    let now = now_millis();
+    let sustain = minutes("SLO_SUSTAIN_MINUTES", 10);
+    let Ok(mut state) = STATE.lock() else { return Vec::new() };
+    let state = state.get_or_insert_with(State::default);
+    if now - state.last_check < CHECK_INTERVAL_MILLIS {
+        return Vec::new();
+    }
+    state.last_check = now;
+
+    let mut alerts = Vec::new();
+    for objective in &OBJECTIVES {
+        let Some(limit_ms) = limit(objective) else { continue };
+        let current = percentile(state, objective, now);
+        let position = state.breaches.iter().position(|(name, _, _)| *name == objective.name);
+        match (current.filter(|ms| *ms > limit_ms), position) {
+            (Some(ms), None) => {
+                log!("SLO {} breached: {} ms over the {} ms threshold", objective.name, ms, limit_ms);
+                state.breaches.push((objective.name, now, false));
+            }
+            (Some(ms), Some(i)) => {
+                let (_, since, alerted) = &mut state.breaches[i];
+                if !*alerted && now - *since >= sustain {
+                    *alerted = true;
+                    alerts.push(format!("Latency SLO breached: {} is {} ms (threshold {} ms) for {} min", objective.name, ms, limit_ms, (now - *since) / 60_000));
+                    analytics::record(AnalyticsRow::new("slo_breach", None, None).with("objective", objective.name).with("ms", ms).with("threshold_ms", limit_ms));
+                }
+            }
+            (None, Some(i)) => {
+                let (_, _, alerted) = state.breaches.remove(i);
+                if alerted {
+                    alerts.push(format!("Latency SLO recovered: {} is back under {} ms", objective.name, limit_ms));
+                }
+            }
+            (None, None) => {}
+        }
+    }
+    alerts
+}
+
+// Every configured objective with its current value, for /status.
+pub fn status() -> Vec<SloStatus> {
+    let now = now_millis();
+    let Ok(state) = STATE.lock() else { return Vec::new() };
+    OBJECTIVES
+        .iter()
+        .filter_map(|objective| {
+            let limit_ms = limit(objective)?;
+            Some(SloStatus {
+                objective: objective.name,
+                limit_ms,
+                current_ms: state.as_ref().and_then(|state| percentile(state, objective, now)),
+                breached_since: state.as_ref().and_then(|state| state.breaches.iter().find(|(name, _, _)| *name == objective.name).map(|(_, since, _)| *since)),
+            })
+        })
+        .collect()
+}
+
+// Posts an alert to SLO_ALERT_WEBHOOK as `{"text": ...}` (the shape Slack and most
+// chat webhooks accept), in the background.
+pub fn send_webhook(text: String) {
+    let Some(url) = env::var("SLO_ALERT_WEBHOOK").ok().filter(|u| !u.is_empty()) else { return };
+    tokio::spawn(async move {
+        let sent = reqwest::Client::new().post(&url).json(&json!({ "text": text })).send().await;
+        match sent {
+            Ok(response) if !response.status().is_success() => log!("SLO alert webhook answered {}", response.status()),
+            Err(e) => log!("Failed to send SLO alert webhook: {}", e),
+            Ok(_) => {}
+        }
+    });
+}
@@ -0,0 +1,103 @@
+use crate::budget::{self, BucketSnapshot};
+use crate::channels::{self, ChannelState};
+use crate::llm_queue::{self, WindowSnapshot};
+use crate::memory::now_millis;
+use crate::migrations;
+use crate::watch::{self, Watch};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+// State that otherwise only lives in memory: LLM budgets, the LLM_RPM / LLM_TPM window,
+// channel caps and seen posts, and watched threads. Written periodically and read back at startup, so a restart
+// doesn't reset cooldowns and let a burst through.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Snapshot {
+    // Format version, see migrations::STATE_SNAPSHOT
+    #[serde(default)]
+    schema_version: u32,
+    #[serde(default)]
+    taken_at: i64,
+    #[serde(default)]
+    budgets: BTreeMap<String, BucketSnapshot>,
+    #[serde(default)]
+    llm_window: Option<WindowSnapshot>,
+    #[serde(default)]
+    channels: HashMap<String, ChannelState>,
+    #[serde(default)]
+    watches: Vec<Watch>,
+}
+
+static LAST_WRITE: Mutex<i64> = Mutex::new(0);
+
+fn path() -> String {
+    env::var("STATE_SNAPSHOT").unwrap_or_else(|_| "state-snapshot.json".to_string())
+}
+
+// Time between snapshots, from STATE_SNAPSHOT_SECS (default 60). 0 disables them.
+fn interval_millis() -> i64 {
+    env::var("STATE_SNAPSHOT_SECS").ok().and_then(|v| v.parse::<i64>().ok()).unwrap_or(60).max(0) * 1000
+}
+
+pub fn is_enabled() -> bool {
+    interval_millis() > 0
+}
+
+// Restores the last snapshot at startup. A missing or unreadable one starts empty.
+pub fn restore() {
+    if !is_enabled() {
+        return;
+    }
+    let Some(value) = migrations::upgrade_or_set_aside(Path::new(&path()), &migrations::STATE_SNAPSHOT) else {
+        return;
+    };
+    let snapshot: Snapshot = match serde_json::from_value(value) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            log!("Failed to read the state snapshot: {}", e);
+            return;
+        }
+    };
+    log!(
+        "Restored state snapshot from {}s ago: {} budget(s), {} recent LLM request(s), {} channel(s), {} watched thread(s)",
+        (now_millis() - snapshot.taken_at).max(0) / 1000,
+        snapshot.budgets.len(),
+        snapshot.llm_window.as_ref().map_or(0, |w| w.sent.len()),
+        snapshot.channels.len(),
+        snapshot.watches.len()
+    );
+    budget::restore(&snapshot.budgets);
+    if let Some(window) = &snapshot.llm_window {
+        llm_queue::restore(window);
+    }
+    channels::restore(snapshot.channels);
+    watch::restore(snapshot.watches);
+}
+
+// Writes a snapshot once STATE_SNAPSHOT_SECS have passed since the last one.
+pub fn write_if_due() -> Result<()> {
+    let now = now_millis();
+    {
+        let Ok(mut last_write) = LAST_WRITE.lock() else { return Ok(()) };
+        if !is_enabled() || now - *last_write < interval_millis() {
+            return Ok(());
+        }
+        *last_write = now;
+    }
+    let snapshot = Snapshot {
+        schema_version: migrations::STATE_SNAPSHOT.version(),
+        taken_at: now,
+        budgets: budget::snapshot(),
+        llm_window: llm_queue::snapshot(),
+        channels: channels::snapshot(),
+        watches: watch::snapshot(),
+    };
+    let tmp = format!("{}.tmp", path());
+    fs::write(&tmp, serde_json::to_string(&snapshot)?)?;
+    fs::rename(&tmp, path())?;
+    Ok(())
+}
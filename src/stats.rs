@@ -0,0 +1,67 @@
+use crate::analytics::AnalyticsRow;
+use std::collections::{HashMap, HashSet};
+use std::env;
+
+const DEFAULT_TEMPLATE: &str = "📊 AI Rand stats: I answered {replies} of {mentions} mentions from {users} people. Up for {uptime}. Favorite topics: {topics}.";
+
+// Lowercased #hashtags in a post, used as its topics in the analytics log.
+pub fn hashtags(content: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for word in content.split_whitespace() {
+        let Some(tag) = word.strip_prefix('#') else { continue };
+        let tag: String = tag.chars().take_while(|c| c.is_alphanumeric() || *c == '_' || *c == '-').collect();
+        let tag = tag.to_lowercase();
+        if !tag.is_empty() && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+// Renders STATS_TEMPLATE from the analytics log: mentions received, replies published,
+// distinct users, uptime since the last "startup" row and the most common topics
+// (hashtags in mentions plus tag labels).
+pub fn build_reply(rows: &[AnalyticsRow], now: i64) -> String {
+    let mentions: Vec<&AnalyticsRow> = rows.iter().filter(|r| r.event == "mention").collect();
+    let users: HashSet<&str> = mentions.iter().filter_map(|r| r.user.as_deref()).collect();
+    let replies = rows.iter().filter(|r| r.event == "reply").count();
+    let started = rows.iter().rev().find(|r| r.event == "startup").map(|r| r.timestamp).unwrap_or(now);
+
+    let mut topics: HashMap<&str, usize> = HashMap::new();
+    for row in rows {
+        let labels: Vec<&str> = match row.event.as_str() {
+            "mention" => row.data.get("topics").and_then(|t| t.as_array()).map(|t| t.iter().filter_map(|v| v.as_str()).collect()).unwrap_or_default(),
+            "tag" => row.data.get("label").and_then(|l| l.as_str()).into_iter().collect(),
+            _ => Vec::new(),
+        };
+        for label in labels {
+            *topics.entry(label).or_default() += 1;
+        }
+    }
+    let mut ranked: Vec<(&str, usize)> = topics.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    let topics = if ranked.is_empty() {
+        "none yet".to_string()
+    } else {
+        ranked.iter().take(3).map(|(t, _)| format!("#{}", t)).collect::<Vec<_>>().join(", ")
+    };
+
+    let template = env::var("STATS_TEMPLATE").ok().filter(|t| !t.trim().is_empty()).unwrap_or_else(|| DEFAULT_TEMPLATE.to_string());
+    template
+        .replace("\\n", "\n")
+        .replace("{mentions}", &mentions.len().to_string())
+        .replace("{replies}", &replies.to_string())
+        .replace("{users}", &users.len().to_string())
+        .replace("{uptime}", &format_duration(now - started))
+        .replace("{topics}", &topics)
+}
+
+pub fn format_duration(millis: i64) -> String {
+    let minutes = millis.max(0) / 60_000;
+    let (days, hours, minutes) = (minutes / 1440, minutes / 60 % 24, minutes % 60);
+    match (days, hours) {
+        (0, 0) => format!("{}m", minutes),
+        (0, _) => format!("{}h {}m", hours, minutes),
+        _ => format!("{}d {}h", days, hours),
+    }
+}
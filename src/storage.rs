@@ -0,0 +1,150 @@
+use crate::migrations;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+// Bytes the bot has written to its homeserver, per path, so usage can be reported
+// and compared against the homeserver quota before writes start failing.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StorageState {
+    // Format version, see migrations::STORAGE_STATE
+    #[serde(default)]
+    schema_version: u32,
+    files: HashMap<String, u64>,
+    #[serde(skip)]
+    warned: bool,
+    #[serde(skip)]
+    pending_warning: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageUsage {
+    pub total_bytes: u64,
+    pub posts: usize,
+    pub post_bytes: u64,
+    pub other_bytes: u64,
+    pub quota_bytes: Option<u64>,
+    pub used_percent: Option<f64>,
+}
+
+fn state_path() -> String {
+    env::var("STORAGE_STATE").unwrap_or_else(|_| "storage.json".to_string())
+}
+
+// STORAGE_QUOTA_MB: the homeserver quota; unset means usage is only reported.
+fn quota_bytes() -> Option<u64> {
+    env::var("STORAGE_QUOTA_MB").ok().and_then(|v| v.parse::<u64>().ok()).filter(|mb| *mb > 0).map(|mb| mb * 1024 * 1024)
+}
+
+fn warn_percent() -> f64 {
+    env::var("STORAGE_WARN_PERCENT").ok().and_then(|v| v.parse().ok()).unwrap_or(80.0)
+}
+
+fn state() -> &'static Mutex<StorageState> {
+    static STATE: OnceLock<Mutex<StorageState>> = OnceLock::new();
+    STATE.get_or_init(|| {
+        let mut state: StorageState = migrations::upgrade_or_set_aside(Path::new(&state_path()), &migrations::STORAGE_STATE)
+            .and_then(|value| serde_json::from_value(value).map_err(|e| log!("Failed to read the storage tracking state: {}", e)).ok())
+            .unwrap_or_default();
+        state.schema_version = migrations::STORAGE_STATE.version();
+        Mutex::new(state)
+    })
+}
+
+pub fn record_write(uri: &str, bytes: u64) {
+    update(|files| {
+        files.insert(uri.to_string(), bytes);
+    });
+}
+
+pub fn record_delete(uri: &str) {
+    update(|files| {
+        files.remove(uri);
+    });
+}
+
+// Every file the bot is known to have written.
+pub fn tracked_uris() -> Vec<String> {
+    state().lock().map(|state| state.files.keys().cloned().collect()).unwrap_or_default()
+}
+
+// Replaces the tracked files, e.g. after rescanning the homeserver.
+pub fn replace_all(scanned: HashMap<String, u64>) {
+    update(|files| *files = scanned);
+}
+
+fn update(change: impl FnOnce(&mut HashMap<String, u64>)) {
+    let Ok(mut state) = state().lock() else { return };
+    change(&mut state.files);
+
+    let usage = compute(&state.files);
+    match usage.used_percent {
+        Some(percent) if percent >= warn_percent() && !state.warned => {
+            let warning = format!(
+                "Homeserver storage at {:.1}% of quota ({} of {} bytes)",
+                percent,
+                usage.total_bytes,
+                usage.quota_bytes.unwrap_or_default()
+            );
+            log!("Warning: {}", warning);
+            state.warned = true;
+            state.pending_warning = Some(warning);
+        }
+        Some(percent) if percent < warn_percent() => state.warned = false,
+        _ => {}
+    }
+
+    // Usage tracking must never break publishing, so failures are only logged
+    let result = serde_json::to_string(&*state).map_err(anyhow::Error::from).and_then(|json| {
+        let tmp = format!("{}.tmp", state_path());
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, state_path())?;
+        Ok(())
+    });
+    if let Err(e) = result {
+        log!("Failed to save storage usage: {}", e);
+    }
+}
+
+fn compute(files: &HashMap<String, u64>) -> StorageUsage {
+    let (posts, post_bytes) = files
+        .iter()
+        .filter(|(uri, _)| uri.contains("/pub/pubky.app/posts/"))
+        .fold((0, 0), |(count, bytes), (_, size)| (count + 1, bytes + size));
+    let total_bytes: u64 = files.values().sum();
+    let quota_bytes = quota_bytes();
+    StorageUsage {
+        total_bytes,
+        posts,
+        post_bytes,
+        other_bytes: total_bytes - post_bytes,
+        quota_bytes,
+        used_percent: quota_bytes.map(|quota| total_bytes as f64 * 100.0 / quota as f64),
+    }
+}
+
+pub fn usage() -> StorageUsage {
+    match state().lock() {
+        Ok(state) => compute(&state.files),
+        Err(_) => compute(&HashMap::new()),
+    }
+}
+
+// Returns the quota warning raised since the last call, so the bot can forward it once.
+pub fn take_warning() -> Option<String> {
+    state().lock().ok().and_then(|mut state| state.pending_warning.take())
+}
+
+pub fn format_usage(usage: &StorageUsage) -> String {
+    let mut text = format!(
+        "{} bytes written ({} posts: {} bytes, other files: {} bytes)",
+        usage.total_bytes, usage.posts, usage.post_bytes, usage.other_bytes
+    );
+    if let (Some(quota), Some(percent)) = (usage.quota_bytes, usage.used_percent) {
+        text.push_str(&format!(", {:.1}% of the {} byte quota", percent, quota));
+    }
+    text
+}
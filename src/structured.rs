@@ -0,0 +1,27 @@
+use crate::error::{BotError, BotResult};
+use crate::llm::{send_chat, ChatRequest};
+use crate::llm_queue::{self, Priority};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+// Runs a completion constrained to `schema` (OpenAI structured outputs) and parses the
+// answer into `T`, so callers act on validated data instead of scraping free text.
+// `schema` must follow the strict-mode rules: every property required, no extra ones.
+pub async fn complete<T: DeserializeOwned>(request: &ChatRequest, name: &str, schema: Value, priority: Priority) -> BotResult<T> {
+    let mut body = serde_json::to_value(request).map_err(BotError::llm)?;
+    body["response_format"] = json!({
+        "type": "json_schema",
+        "json_schema": { "name": name, "strict": true, "schema": schema },
+    });
+    let prompt_chars = request.messages.iter().map(|m| m.content.len()).sum();
+    let response = send_chat(&body, priority, llm_queue::estimate_tokens(prompt_chars, request.max_tokens)).await?;
+
+    let message = &response["choices"][0]["message"];
+    if let Some(refusal) = message["refusal"].as_str() {
+        return Err(BotError::Llm(format!("Model refused structured output: {}", refusal)));
+    }
+    let content = message["content"]
+        .as_str()
+        .ok_or_else(|| BotError::Llm("OpenAI response contained no choices".to_string()))?;
+    serde_json::from_str(content).map_err(|e| BotError::Validation(format!("Answer doesn't match the {} schema: {}", name, e)))
+}
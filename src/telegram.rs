@@ -0,0 +1,142 @@
+use crate::approval::OperatorCommand;
+use crate::secrets::{self, Secret};
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::json;
+use std::env;
+use std::sync::Arc;
+use tokio::sync::mpsc::UnboundedSender;
+
+// Forwards bot activity to an operator's Telegram chat and listens for operator commands.
+// Enabled by setting TELEGRAM_BOT_TOKEN and TELEGRAM_CHAT_ID.
+pub struct TelegramBridge {
+    http: reqwest::Client,
+    token: Secret,
+    chat_id: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdatesResponse {
+    ok: bool,
+    #[serde(default)]
+    result: Vec<Update>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Update {
+    update_id: i64,
+    #[serde(default)]
+    message: Option<TelegramMessage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TelegramMessage {
+    chat: Chat,
+    #[serde(default)]
+    text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Chat {
+    id: i64,
+}
+
+impl TelegramBridge {
+    pub fn from_env() -> Result<Option<TelegramBridge>> {
+        let Some(token) = Secret::from_env("TELEGRAM_BOT_TOKEN") else {
+            return Ok(None);
+        };
+        let chat_id = env::var("TELEGRAM_CHAT_ID")
+            .map_err(|_| anyhow::anyhow!("TELEGRAM_CHAT_ID not found in .env"))?
+            .parse::<i64>()
+            .map_err(|_| anyhow::anyhow!("TELEGRAM_CHAT_ID must be a numeric chat id"))?;
+        log!("Telegram bridge enabled for chat {}", chat_id);
+        Ok(Some(TelegramBridge { http: reqwest::Client::new(), token, chat_id }))
+    }
+
+    fn url(&self, method: &str) -> String {
+        format!("https://api.telegram.org/bot{}/{}", self.token.expose(), method)
+    }
+
+    pub async fn send(&self, text: &str) -> Result<()> {
+        // Telegram rejects messages over 4096 characters
+        let text: String = text.chars().take(4000).collect();
+        let response = self.http
+            .post(self.url("sendMessage"))
+            .json(&json!({ "chat_id": self.chat_id, "text": text }))
+            .send()
+            .await
+            .map_err(secrets::scrub_error)?;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("Telegram sendMessage failed: {}", response.status()));
+        }
+        Ok(())
+    }
+
+    // Sends a message in the background so the bot never waits on Telegram.
+    pub fn notify(self: &Arc<Self>, text: String) {
+        let bridge = Arc::clone(self);
+        tokio::spawn(async move {
+            if let Err(e) = bridge.send(&text).await {
+                log!("Failed to forward to Telegram: {}", e);
+            }
+        });
+    }
+
+    // Long-polls Telegram for `/approve <id>` and `/deny <id>` messages from the operator chat.
+    pub fn spawn_listener(self: &Arc<Self>, commands: UnboundedSender<OperatorCommand>) {
+        let bridge = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut offset = 0;
+            loop {
+                match bridge.poll_updates(offset).await {
+                    Ok(updates) => {
+                        for update in updates {
+                            offset = update.update_id + 1;
+                            let Some(message) = update.message else { continue };
+                            if message.chat.id != bridge.chat_id {
+                                continue;
+                            }
+                            if let Some(command) = message.text.as_deref().and_then(parse_operator_command) {
+                                if commands.send(command).is_err() {
+                                    return;
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        log!("Telegram polling failed: {}", e);
+                        tokio::time::sleep(tokio::time::Duration::from_secs(10)).await;
+                    }
+                }
+            }
+        });
+    }
+
+    async fn poll_updates(&self, offset: i64) -> Result<Vec<Update>> {
+        let response = self.http
+            .get(self.url("getUpdates"))
+            .query(&[("offset", offset.to_string()), ("timeout", "30".to_string())])
+            .timeout(std::time::Duration::from_secs(40))
+            .send()
+            .await
+            .map_err(secrets::scrub_error)?;
+        let updates: UpdatesResponse = response.json().await.map_err(secrets::scrub_error)?;
+        if !updates.ok {
+            return Err(anyhow::anyhow!("Telegram getUpdates returned ok=false"));
+        }
+        Ok(updates.result)
+    }
+}
+
+fn parse_operator_command(text: &str) -> Option<OperatorCommand> {
+    let mut words = text.split_whitespace();
+    let command = words.next()?;
+    let id = words.next()?.to_string();
+    // Commands may arrive as /approve@BotName in group chats
+    match command.split('@').next()? {
+        "/approve" => Some(OperatorCommand::Approve(id)),
+        "/deny" => Some(OperatorCommand::Deny(id)),
+        _ => None,
+    }
+}
@@ -0,0 +1,78 @@
+use crate::homeserver::fetch_post;
+use crate::llm::ChatMessage;
+use anyhow::Result;
+use pubky::{Client, Keypair};
+use std::env;
+
+// How far up a thread is walked when counting the bot's replies.
+const MAX_THREAD_WALK: usize = 50;
+
+fn max_depth() -> usize {
+    env::var("THREAD_CONTEXT_DEPTH").ok().and_then(|v| v.parse().ok()).unwrap_or(6)
+}
+
+// Earlier turns of the conversation `post_uri` belongs to, oldest first: the bot's own
+// replies as assistant messages and everyone else's posts as user messages. Walks up
+// at most THREAD_CONTEXT_DEPTH parents (0 disables) and returns nothing when the bot
+// hasn't taken part in the thread yet.
+pub async fn thread_history(client: &Client, keypair: &Keypair, post_uri: &str) -> Result<Vec<ChatMessage>> {
+    let own_prefix = format!("pubky://{}/", keypair.public_key());
+    let mut history = Vec::new();
+    let mut bot_replied = false;
+
+    let mut next = fetch_post(client, post_uri).await?.and_then(|p| p.parent);
+    while let Some(uri) = next.take() {
+        if history.len() >= max_depth() {
+            break;
+        }
+        let Some(post) = fetch_post(client, &uri).await? else {
+            break;
+        };
+        let own = uri.starts_with(&own_prefix);
+        bot_replied |= own;
+        history.push(ChatMessage {
+            role: if own { "assistant" } else { "user" }.to_string(),
+            content: post.content,
+        });
+        next = post.parent;
+    }
+
+    if !bot_replied {
+        return Ok(Vec::new());
+    }
+    history.reverse();
+    Ok(history)
+}
+
+// Number of the bot's own posts among the ancestors of `post_uri`.
+pub async fn bot_replies_in_thread(client: &Client, keypair: &Keypair, post_uri: &str) -> Result<usize> {
+    let own_prefix = format!("pubky://{}/", keypair.public_key());
+    let mut count = 0;
+    let mut next = fetch_post(client, post_uri).await?.and_then(|p| p.parent);
+    for _ in 0..MAX_THREAD_WALK {
+        let Some(uri) = next.take() else { break };
+        if uri.starts_with(&own_prefix) {
+            count += 1;
+        }
+        next = fetch_post(client, &uri).await?.and_then(|p| p.parent);
+    }
+    Ok(count)
+}
+
+// `post_uri` and the posts above it, nearest first, as (uri, content).
+pub async fn ancestors(client: &Client, post_uri: &str) -> Result<Vec<(String, String)>> {
+    let mut posts = Vec::new();
+    let mut next = Some(post_uri.to_string());
+    for _ in 0..MAX_THREAD_WALK {
+        let Some(uri) = next.take() else { break };
+        let Some(post) = fetch_post(client, &uri).await? else { break };
+        next = post.parent;
+        posts.push((uri, post.content));
+    }
+    Ok(posts)
+}
+
+// URI of the post that starts the thread `post_uri` is in, which identifies the thread.
+pub async fn root(client: &Client, post_uri: &str) -> Result<String> {
+    Ok(ancestors(client, post_uri).await?.pop().map_or_else(|| post_uri.to_string(), |(uri, _)| uri))
+}
@@ -0,0 +1,139 @@
+use crate::analytics::{self, AnalyticsRow};
+use crate::memory::now_millis;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::env;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Highest throttle level: polling 16x slower and a 16th of the LLM_RPM / LLM_TPM budget.
+const MAX_LEVEL: u32 = 4;
+// Model calls the error rate and latency are measured over.
+const WINDOW: usize = 20;
+// Fewer calls than this in the window say nothing either way.
+const MIN_SAMPLES: usize = 5;
+
+// Closed-loop throttling (AUTO_THROTTLE=true): when model calls fail or slow down past
+// the thresholds, the level goes up one step per evaluation, slowing down Nexus polling
+// and the LLM queue; once calls are healthy again it comes back down one step at a time.
+struct Throttle {
+    level: u32,
+    // (finished at, latency ms, succeeded) of the latest model calls
+    calls: VecDeque<(i64, u64, bool)>,
+    evaluated_at: i64,
+}
+
+static THROTTLE: Mutex<Throttle> = Mutex::new(Throttle { level: 0, calls: VecDeque::new(), evaluated_at: 0 });
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ThrottleStats {
+    pub enabled: bool,
+    pub level: u32,
+    pub factor: u32,
+    pub error_rate: f64,
+    pub avg_latency_ms: u64,
+}
+
+pub fn is_enabled() -> bool {
+    env::var("AUTO_THROTTLE").map(|v| v == "true").unwrap_or(false)
+}
+
+// Share of failed model calls above which the bot throttles, from THROTTLE_ERROR_RATE.
+fn max_error_rate() -> f64 {
+    env::var("THROTTLE_ERROR_RATE").ok().and_then(|v| v.parse().ok()).unwrap_or(0.2)
+}
+
+// Average model response time above which the bot throttles, from THROTTLE_LATENCY_MS.
+fn max_latency_ms() -> u64 {
+    env::var("THROTTLE_LATENCY_MS").ok().and_then(|v| v.parse().ok()).unwrap_or(10_000)
+}
+
+// Time between level changes, from THROTTLE_STEP_SECS, so each step has time to show
+// its effect before the next.
+fn step_millis() -> i64 {
+    env::var("THROTTLE_STEP_SECS").ok().and_then(|v| v.parse::<i64>().ok()).unwrap_or(60).max(1) * 1000
+}
+
+fn measure(calls: &VecDeque<(i64, u64, bool)>) -> (f64, u64) {
+    if calls.is_empty() {
+        return (0.0, 0);
+    }
+    let failed = calls.iter().filter(|(_, _, ok)| !ok).count();
+    let latency = calls.iter().map(|(_, ms, _)| ms).sum::<u64>() / calls.len() as u64;
+    (failed as f64 / calls.len() as f64, latency)
+}
+
+// Records the outcome of a model call and moves the level when a step is due.
+pub fn record(latency: Duration, succeeded: bool) {
+    if !is_enabled() {
+        return;
+    }
+    let now = now_millis();
+    let Ok(mut throttle) = THROTTLE.lock() else { return };
+    throttle.calls.push_back((now, latency.as_millis() as u64, succeeded));
+    while throttle.calls.len() > WINDOW {
+        throttle.calls.pop_front();
+    }
+    if now - throttle.evaluated_at < step_millis() {
+        return;
+    }
+    // Calls older than a few steps no longer describe the provider
+    throttle.calls.retain(|(at, _, _)| now - at < 5 * step_millis());
+    if throttle.calls.len() < MIN_SAMPLES {
+        return;
+    }
+    throttle.evaluated_at = now;
+    let (error_rate, latency_ms) = measure(&throttle.calls);
+    let unhealthy = error_rate > max_error_rate() || latency_ms > max_latency_ms();
+    let level = if unhealthy { (throttle.level + 1).min(MAX_LEVEL) } else { throttle.level.saturating_sub(1) };
+    if level == throttle.level {
+        return;
+    }
+    let direction = if level > throttle.level { "up" } else { "down" };
+    throttle.level = level;
+    drop(throttle);
+    log!("Auto-throttle {} to level {} (error rate {:.0}%, average latency {}ms)", direction, level, error_rate * 100.0, latency_ms);
+    analytics::record(
+        AnalyticsRow::new("throttle", None, None)
+            .with("level", level)
+            .with("error_rate", error_rate)
+            .with("avg_latency_ms", latency_ms),
+    );
+}
+
+fn factor() -> u32 {
+    THROTTLE.lock().map(|t| 1 << t.level).unwrap_or(1)
+}
+
+// A poll interval stretched by the current level.
+pub fn interval(base: Duration) -> Duration {
+    base * factor()
+}
+
+// Idle rounds in a row past which polling stops slowing down further (8x).
+const MAX_IDLE_STEPS: u32 = 3;
+
+// A poll interval stretched by the current level and, with AUTO_THROTTLE on, doubled for
+// each round in a row that found nothing to do, so a quiet bot polls Nexus less often.
+pub fn idle_interval(base: Duration, idle_rounds: u32) -> Duration {
+    if !is_enabled() {
+        return interval(base);
+    }
+    interval(base) * (1 << idle_rounds.min(MAX_IDLE_STEPS))
+}
+
+// A per-minute limit divided by the current level; 0 (no limit) stays unlimited.
+pub fn limit(base: usize) -> usize {
+    if base == 0 {
+        return 0;
+    }
+    (base / factor() as usize).max(1)
+}
+
+pub fn stats() -> ThrottleStats {
+    let Ok(throttle) = THROTTLE.lock() else {
+        return ThrottleStats { enabled: is_enabled(), level: 0, factor: 1, error_rate: 0.0, avg_latency_ms: 0 };
+    };
+    let (error_rate, avg_latency_ms) = measure(&throttle.calls);
+    ThrottleStats { enabled: is_enabled(), level: throttle.level, factor: 1 << throttle.level, error_rate, avg_latency_ms }
+}
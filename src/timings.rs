@@ -0,0 +1,87 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Upper bounds (ms) of the histogram buckets; slower observations go in a final bucket
+const BUCKETS_MS: [u64; 10] = [10, 25, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000];
+
+// Latency distribution of one pipeline stage since startup.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Histogram {
+    pub count: u64,
+    pub sum_ms: u64,
+    pub max_ms: u64,
+    // Observations per bucket: "<=10", ..., "<=10000", ">10000"
+    pub buckets: BTreeMap<String, u64>,
+}
+
+impl Histogram {
+    fn observe(&mut self, ms: u64) {
+        self.count += 1;
+        self.sum_ms += ms;
+        self.max_ms = self.max_ms.max(ms);
+        let bucket = match BUCKETS_MS.iter().find(|bound| ms <= **bound) {
+            Some(bound) => format!("<={}", bound),
+            None => format!(">{}", BUCKETS_MS[BUCKETS_MS.len() - 1]),
+        };
+        *self.buckets.entry(bucket).or_default() += 1;
+    }
+}
+
+static HISTOGRAMS: Mutex<BTreeMap<&'static str, Histogram>> = Mutex::new(BTreeMap::new());
+
+// Adds one measurement of `stage` to its histogram.
+pub fn observe(stage: &'static str, elapsed: Duration) {
+    if let Ok(mut histograms) = HISTOGRAMS.lock() {
+        histograms.entry(stage).or_default().observe(elapsed.as_millis() as u64);
+    }
+}
+
+// Every stage's histogram, for the admin API's /status.
+pub fn histograms() -> BTreeMap<&'static str, Histogram> {
+    HISTOGRAMS.lock().map(|h| h.clone()).unwrap_or_default()
+}
+
+// Stage timings of the mention or tag being handled, attached to its reply's
+// analytics row. Each stage also feeds the global histograms.
+#[derive(Debug, Default)]
+pub struct StageTimings {
+    stages: Vec<(&'static str, u64)>,
+    started: Option<Instant>,
+}
+
+impl StageTimings {
+    pub fn reset(&mut self) {
+        self.stages.clear();
+        self.started = Some(Instant::now());
+    }
+
+    // Time since the last reset, i.e. since handling of the current mention began.
+    pub fn elapsed_ms(&self) -> u64 {
+        self.started.map(|started| started.elapsed().as_millis() as u64).unwrap_or_default()
+    }
+
+    // Total time spent in `stage`, if it ran.
+    pub fn stage_ms(&self, stage: &str) -> Option<u64> {
+        let times: Vec<u64> = self.stages.iter().filter(|(name, _)| *name == stage).map(|(_, ms)| *ms).collect();
+        (!times.is_empty()).then(|| times.iter().sum())
+    }
+
+    // Records `stage` as having run from `started` until now.
+    pub fn finish(&mut self, stage: &'static str, started: Instant) {
+        let elapsed = started.elapsed();
+        observe(stage, elapsed);
+        self.stages.push((stage, elapsed.as_millis() as u64));
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut map = Map::new();
+        for (stage, ms) in &self.stages {
+            let total = map.get(*stage).and_then(Value::as_u64).unwrap_or(0) + ms;
+            map.insert(stage.to_string(), total.into());
+        }
+        Value::Object(map)
+    }
+}
@@ -0,0 +1,155 @@
+use crate::calc;
+use crate::error::{BotError, BotResult};
+use crate::llm::{chat_completion, send_chat, ChatRequest};
+use crate::llm_queue::{self, Priority};
+use crate::privacy;
+use crate::search;
+use crate::wikipedia;
+use serde_json::{json, Value};
+use std::env;
+
+// Tool results are trimmed so a chatty page can't blow up the prompt
+const MAX_TOOL_RESULT_CHARS: usize = 4000;
+
+// Tools the model may call while writing a reply, as (name, description, JSON schema
+// of the arguments). Only configured tools are offered.
+fn available_tools() -> Vec<(&'static str, &'static str, Value)> {
+    let mut tools = Vec::new();
+    if search::provider().is_some() {
+        tools.push((
+            "web_search",
+            "Search the web for current information, news or facts you are unsure about. Returns titles, URLs and snippets; cite the URLs you rely on in your reply.",
+            json!({
+                "type": "object",
+                "properties": { "query": { "type": "string", "description": "Search query" } },
+                "required": ["query"],
+            }),
+        ));
+    }
+    if wikipedia::is_enabled() {
+        tools.push((
+            "wikipedia",
+            "Look up the Wikipedia summary of a person, place, organization or concept. Cheaper than web search for well-known entities; cite the article URL if you use it.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string", "description": "Name of the entity or topic" },
+                    "language": { "type": "string", "description": "Wikipedia language code, e.g. en or pt" },
+                },
+                "required": ["query"],
+            }),
+        ));
+    }
+    if calc::is_enabled() {
+        tools.push((
+            "calculate",
+            "Evaluate an arithmetic expression exactly instead of doing math in your head. Supports + - * / % ^, parentheses, pi, e and sqrt, abs, ln, log, log2, exp, sin, cos, tan, floor, ceil, round.",
+            json!({
+                "type": "object",
+                "properties": { "expression": { "type": "string", "description": "Expression, e.g. (2^10 - 24) / 5" } },
+                "required": ["expression"],
+            }),
+        ));
+    }
+    tools
+}
+
+// How many tool calls one reply may make, from TOOL_CALL_BUDGET.
+fn call_budget() -> usize {
+    env::var("TOOL_CALL_BUDGET").ok().and_then(|v| v.parse().ok()).unwrap_or(3)
+}
+
+// Whether replies are generated with tools at all.
+pub fn is_enabled() -> bool {
+    !available_tools().is_empty() && call_budget() > 0
+}
+
+// Runs the completion with the configured tools, executing the model's tool calls
+// until it answers or the per-reply budget is spent. Without tools this is a plain
+// chat completion.
+pub async fn complete(request: &ChatRequest, priority: Priority) -> BotResult<String> {
+    let tools = available_tools();
+    let budget = call_budget();
+    if tools.is_empty() || budget == 0 {
+        return chat_completion(request, priority).await;
+    }
+    let tool_specs: Vec<Value> = tools
+        .iter()
+        .map(|(name, description, parameters)| {
+            json!({ "type": "function", "function": { "name": name, "description": description, "parameters": parameters } })
+        })
+        .collect();
+
+    let mut messages: Vec<Value> = request.messages.iter().map(|m| json!({ "role": m.role, "content": m.content })).collect();
+    let mut calls = 0;
+    loop {
+        let mut body = json!({
+            "model": request.model,
+            "messages": messages,
+            "temperature": request.temperature,
+            "max_tokens": request.max_tokens,
+            "tools": tool_specs,
+        });
+        // Once the budget is spent the model has to answer with what it has
+        if calls >= budget {
+            body["tool_choice"] = json!("none");
+        }
+        let prompt_chars = messages.iter().map(|m| m.to_string().len()).sum();
+        let response = send_chat(&body, priority, llm_queue::estimate_tokens(prompt_chars, request.max_tokens)).await?;
+        let message = response["choices"][0]["message"].clone();
+        let tool_calls = message["tool_calls"].as_array().cloned().unwrap_or_default();
+        if tool_calls.is_empty() {
+            return message["content"]
+                .as_str()
+                .map(String::from)
+                .ok_or_else(|| BotError::Llm("OpenAI response contained no reply".to_string()));
+        }
+
+        messages.push(message);
+        for call in tool_calls {
+            calls += 1;
+            let name = call["function"]["name"].as_str().unwrap_or_default();
+            let arguments: Value = serde_json::from_str(call["function"]["arguments"].as_str().unwrap_or("{}")).unwrap_or_default();
+            let result = if calls > budget {
+                "Tool call budget for this reply is used up; answer with what you have.".to_string()
+            } else {
+                log!("Model called tool {} with {}", name, privacy::content(&arguments.to_string()));
+                run_tool(name, &arguments).await.unwrap_or_else(|e| format!("Error: {}", e))
+            };
+            messages.push(json!({
+                "role": "tool",
+                "tool_call_id": call["id"],
+                "content": result.chars().take(MAX_TOOL_RESULT_CHARS).collect::<String>(),
+            }));
+        }
+    }
+}
+
+async fn run_tool(name: &str, arguments: &Value) -> BotResult<String> {
+    match name {
+        "web_search" => {
+            let query = arguments["query"].as_str().ok_or_else(|| BotError::Tool("web_search needs a query".to_string()))?;
+            let results = search::search(query).await?;
+            if results.is_empty() {
+                return Ok("No results.".to_string());
+            }
+            Ok(results
+                .iter()
+                .map(|r| format!("{}\n{}\n{}", r.title, r.url, r.snippet))
+                .collect::<Vec<_>>()
+                .join("\n\n"))
+        }
+        "wikipedia" => {
+            let query = arguments["query"].as_str().ok_or_else(|| BotError::Tool("wikipedia needs a query".to_string()))?;
+            Ok(match wikipedia::summary(query, arguments["language"].as_str()).await? {
+                Some(summary) => format!("{}\n{}\n{}", summary.title, summary.url, summary.extract),
+                None => "No matching article.".to_string(),
+            })
+        }
+        "calculate" => {
+            let expression = arguments["expression"].as_str().ok_or_else(|| BotError::Tool("calculate needs an expression".to_string()))?;
+            calc::evaluate(expression)
+        }
+        _ => Err(BotError::Tool(format!("Unknown tool {}", name))),
+    }
+}
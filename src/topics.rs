@@ -0,0 +1,112 @@
+use crate::llm::{ChatMessage, ChatRequest};
+use crate::llm_queue::Priority;
+use crate::models;
+use crate::structured;
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::json;
+use std::env;
+
+// Built-in restricted topics: (name, description for the classifier, canned reply).
+const BUILTIN_TOPICS: [(&str, &str, &str); 4] = [
+    (
+        "financial_advice",
+        "requests for personal investment, trading or price predictions, or advice on what to buy or sell",
+        "I can't give financial or investment advice. For decisions about your money, please talk to a licensed financial professional. Happy to explain how Pubky or other technology works, though!",
+    ),
+    (
+        "medical",
+        "requests for diagnosis, treatment, medication or dosage advice about a health condition",
+        "I'm not able to give medical advice. Please reach out to a doctor or another qualified health professional about this. Take care!",
+    ),
+    (
+        "legal",
+        "requests for legal advice about a specific personal situation",
+        "I can't give legal advice for specific situations. A qualified lawyer in your jurisdiction is the right person to ask.",
+    ),
+    (
+        "self_harm",
+        "mentions of self-harm or suicide",
+        "I'm really sorry you're going through this. Please reach out to someone you trust or a local crisis line right away; you don't have to face this alone.",
+    ),
+];
+
+const DEFAULT_REFUSAL: &str = "Sorry, that's a topic I don't discuss here. Feel free to ask me about something else!";
+
+#[derive(Debug, Clone)]
+pub struct RestrictedTopic {
+    pub name: String,
+    description: String,
+    pub response: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Classification {
+    topic: String,
+}
+
+// Reads RESTRICTED_TOPICS, e.g. `financial_advice;medical;politics=partisan political opinions`.
+// Built-in names need no description; custom topics use `name=description` and can set
+// their canned reply with TOPIC_RESPONSE_<NAME>.
+pub fn restricted_topics() -> Vec<RestrictedTopic> {
+    let config = env::var("RESTRICTED_TOPICS").unwrap_or_default();
+    config
+        .split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .filter_map(|entry| {
+            let (name, description) = match entry.split_once('=') {
+                Some((name, description)) => (name.trim(), Some(description.trim())),
+                None => (entry, None),
+            };
+            let builtin = BUILTIN_TOPICS.iter().find(|(n, _, _)| *n == name);
+            let description = description.or(builtin.map(|(_, d, _)| *d));
+            let Some(description) = description else {
+                log!("Ignoring restricted topic \"{}\": it needs a description (name=description)", name);
+                return None;
+            };
+            let response = env::var(format!("TOPIC_RESPONSE_{}", name.to_uppercase()))
+                .ok()
+                .or(builtin.map(|(_, _, r)| r.to_string()))
+                .unwrap_or_else(|| DEFAULT_REFUSAL.to_string());
+            Some(RestrictedTopic { name: name.to_string(), description: description.to_string(), response })
+        })
+        .collect()
+}
+
+// Asks a cheap, deterministic model call whether the post falls into one of the
+// restricted topics. This runs before generation and ignores the main system prompt.
+pub async fn classify(content: &str, topics: &[RestrictedTopic]) -> Result<Option<RestrictedTopic>> {
+    if topics.is_empty() {
+        return Ok(None);
+    }
+    let list = topics.iter().map(|t| format!("- {}: {}", t.name, t.description)).collect::<Vec<_>>().join("\n");
+    let request = ChatRequest {
+        model: models::resolve("default"),
+        messages: vec![
+            ChatMessage {
+                role: "system".to_string(),
+                content: format!(
+                    "You are a content classifier. Decide whether the user's post mainly asks about one of these restricted topics:\n{}\n\nAnswer with the topic's name, or \"none\". General or educational questions that don't ask for personal advice are \"none\".",
+                    list
+                ),
+            },
+            ChatMessage {
+                role: "user".to_string(),
+                content: content.to_string(),
+            },
+        ],
+        temperature: 0.0,
+        max_tokens: 20,
+    };
+    let mut names: Vec<&str> = topics.iter().map(|t| t.name.as_str()).collect();
+    names.push("none");
+    let schema = json!({
+        "type": "object",
+        "properties": { "topic": { "type": "string", "enum": names } },
+        "required": ["topic"],
+        "additionalProperties": false,
+    });
+    let classification: Classification = structured::complete(&request, "classification", schema, Priority::Mention).await?;
+    Ok(topics.iter().find(|t| t.name == classification.topic).cloned())
+}
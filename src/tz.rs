@@ -0,0 +1,365 @@
+use crate::cron::{days_from_civil, weekday};
+use crate::error::{BotError, BotResult};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+// A time zone: a fixed offset (`UTC`, `UTC+2`, `+05:30`) or an IANA name
+// (`Europe/Lisbon`) read from the system's zoneinfo database (TZIF_DIR, default
+// /usr/share/zoneinfo), so daylight saving time follows the zone's real rules.
+#[derive(Debug, Clone)]
+pub enum Zone {
+    Fixed(i64),
+    Rules {
+        // (unix seconds, UTC offset in seconds from then on), in order
+        transitions: Vec<(i64, i64)>,
+        initial: i64,
+        // Rule for instants after the last transition
+        rule: Option<PosixRule>,
+    },
+}
+
+// A POSIX TZ string such as `WET0WEST,M3.5.0/1,M10.5.0`, as found at the end of
+// version 2+ zoneinfo files.
+#[derive(Debug, Clone)]
+pub struct PosixRule {
+    std_offset: i64,
+    dst: Option<(i64, DateRule, DateRule)>,
+}
+
+// `Mm.w.d/time`: day `d` (0 = Sunday) of week `w` (5 = last) of month `m`, at `time`
+// seconds of local time.
+#[derive(Debug, Clone, Copy)]
+struct DateRule {
+    month: u32,
+    week: u32,
+    weekday: u32,
+    time: i64,
+}
+
+impl Zone {
+    pub fn parse(name: &str) -> BotResult<Zone> {
+        let name = name.trim();
+        if let Some(offset) = fixed_offset(name) {
+            return Ok(Zone::Fixed(offset));
+        }
+        let dir = PathBuf::from(env::var("TZIF_DIR").unwrap_or_else(|_| "/usr/share/zoneinfo".to_string()));
+        if name.contains("..") {
+            return Err(BotError::Validation(format!("Invalid time zone {}", name)));
+        }
+        let data = fs::read(dir.join(name)).map_err(|e| BotError::Validation(format!("Unknown time zone {}: {}", name, e)))?;
+        parse_tzif(&data).ok_or_else(|| BotError::Validation(format!("Unreadable zoneinfo file for {}", name)))
+    }
+
+    // UTC offset in seconds at a unix time.
+    pub fn offset_at(&self, utc: i64) -> i64 {
+        match self {
+            Zone::Fixed(offset) => *offset,
+            Zone::Rules { transitions, initial, rule } => {
+                if let (Some(rule), Some(last)) = (rule, transitions.last()) {
+                    if utc >= last.0 {
+                        return rule.offset_at(utc);
+                    }
+                }
+                if transitions.is_empty() {
+                    if let Some(rule) = rule {
+                        return rule.offset_at(utc);
+                    }
+                }
+                match transitions.partition_point(|(at, _)| *at <= utc) {
+                    0 => *initial,
+                    i => transitions[i - 1].1,
+                }
+            }
+        }
+    }
+
+    // The unix time a local wall-clock time (as seconds since 1970-01-01 local) falls
+    // on: the earlier one when clocks go back, None when it's skipped by clocks going
+    // forward.
+    pub fn to_utc(&self, local: i64) -> Option<i64> {
+        let mut candidates: Vec<i64> = [self.offset_at(local - 86_400), self.offset_at(local), self.offset_at(local + 86_400)]
+            .into_iter()
+            .map(|offset| local - offset)
+            .filter(|utc| self.offset_at(*utc) == local - utc)
+            .collect();
+        candidates.sort_unstable();
+        candidates.first().copied()
+    }
+}
+
+// `UTC`, `GMT`, `Z`, `UTC+2`, `UTC-03:30`, `+05:30`.
+fn fixed_offset(name: &str) -> Option<i64> {
+    let rest = ["UTC", "GMT"].iter().find_map(|prefix| name.strip_prefix(prefix)).unwrap_or(name);
+    if rest.is_empty() || rest == "Z" {
+        return Some(0);
+    }
+    let sign = match rest.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let (hours, minutes) = rest[1..].split_once(':').unwrap_or((&rest[1..], "0"));
+    let hours: i64 = hours.parse().ok().filter(|h| *h <= 14)?;
+    let minutes: i64 = minutes.parse().ok().filter(|m| *m < 60)?;
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+fn parse_tzif(data: &[u8]) -> Option<Zone> {
+    fn counts(data: &[u8], at: usize) -> Option<[usize; 6]> {
+        if data.get(at..at + 4)? != b"TZif" {
+            return None;
+        }
+        let mut counts = [0usize; 6];
+        for (i, count) in counts.iter_mut().enumerate() {
+            let start = at + 20 + i * 4;
+            *count = u32::from_be_bytes(data.get(start..start + 4)?.try_into().ok()?) as usize;
+        }
+        Some(counts)
+    }
+    // isutcnt, isstdcnt, leapcnt, timecnt, typecnt, charcnt
+    let block_len = |c: [usize; 6], time_size: usize| c[3] * time_size + c[3] + c[4] * 6 + c[5] + c[2] * (time_size + 4) + c[1] + c[0];
+
+    let first = counts(data, 0)?;
+    let version = *data.get(4)?;
+    let (header, time_size) = if version >= b'2' { (44 + block_len(first, 4), 8) } else { (0, 4) };
+    let c = if time_size == 8 { counts(data, header)? } else { first };
+    let (timecnt, typecnt) = (c[3], c[4]);
+    let mut at = header + 44;
+    let mut times = Vec::with_capacity(timecnt);
+    for _ in 0..timecnt {
+        let bytes = data.get(at..at + time_size)?;
+        times.push(if time_size == 8 { i64::from_be_bytes(bytes.try_into().ok()?) } else { i32::from_be_bytes(bytes.try_into().ok()?) as i64 });
+        at += time_size;
+    }
+    let indices = data.get(at..at + timecnt)?.to_vec();
+    at += timecnt;
+    let mut offsets = Vec::with_capacity(typecnt);
+    for _ in 0..typecnt {
+        offsets.push(i32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?) as i64);
+        at += 6;
+    }
+    let transitions = times.into_iter().zip(indices).map(|(time, i)| Some((time, *offsets.get(i as usize)?))).collect::<Option<Vec<_>>>()?;
+    let rule = if time_size == 8 {
+        let footer = data.get(header + 44 + block_len(c, 8)..)?;
+        std::str::from_utf8(footer).ok().and_then(|f| PosixRule::parse(f.trim_matches('\n')))
+    } else {
+        None
+    };
+    Some(Zone::Rules { transitions, initial: *offsets.first()?, rule })
+}
+
+impl PosixRule {
+    fn parse(spec: &str) -> Option<PosixRule> {
+        let mut rest = spec;
+        skip_name(&mut rest)?;
+        let std_offset = -parse_offset(&mut rest)?;
+        if rest.is_empty() {
+            return Some(PosixRule { std_offset, dst: None });
+        }
+        skip_name(&mut rest)?;
+        let dst_offset = if rest.starts_with(',') { std_offset + 3600 } else { -parse_offset(&mut rest)? };
+        let (start, end) = rest.strip_prefix(',')?.split_once(',')?;
+        Some(PosixRule { std_offset, dst: Some((dst_offset, DateRule::parse(start)?, DateRule::parse(end)?)) })
+    }
+
+    fn offset_at(&self, utc: i64) -> i64 {
+        let Some((dst_offset, start, end)) = self.dst else { return self.std_offset };
+        let year = crate::cron::civil_from_days((utc + self.std_offset).div_euclid(86_400)).0;
+        // Transitions happen at local time: the start in standard time, the end in DST
+        let starts = start.local_seconds(year) - self.std_offset;
+        let ends = end.local_seconds(year) - dst_offset;
+        let in_dst = if starts < ends { utc >= starts && utc < ends } else { !(utc >= ends && utc < starts) };
+        if in_dst {
+            dst_offset
+        } else {
+            self.std_offset
+        }
+    }
+}
+
+// Skips a zone abbreviation: letters, or anything within `<...>`.
+fn skip_name(rest: &mut &str) -> Option<()> {
+    if let Some(quoted) = rest.strip_prefix('<') {
+        *rest = &quoted[quoted.find('>')? + 1..];
+    } else {
+        let len = rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len());
+        if len < 3 {
+            return None;
+        }
+        *rest = &rest[len..];
+    }
+    Some(())
+}
+
+// `[+-]hh[:mm[:ss]]` in seconds, as written (POSIX offsets are west-positive).
+fn parse_offset(rest: &mut &str) -> Option<i64> {
+    let len = rest.find(|c: char| !(c.is_ascii_digit() || c == ':' || c == '+' || c == '-')).unwrap_or(rest.len());
+    let value = parse_hms(&rest[..len])?;
+    *rest = &rest[len..];
+    Some(value)
+}
+
+fn parse_hms(text: &str) -> Option<i64> {
+    let (sign, digits) = match text.strip_prefix('-') {
+        Some(digits) => (-1, digits),
+        None => (1, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let mut parts = digits.split(':');
+    let hours: i64 = parts.next()?.parse().ok()?;
+    let minutes: i64 = parts.next().map(|m| m.parse().ok()).unwrap_or(Some(0))?;
+    let seconds: i64 = parts.next().map(|s| s.parse().ok()).unwrap_or(Some(0))?;
+    Some(sign * (hours * 3600 + minutes * 60 + seconds))
+}
+
+impl DateRule {
+    // Only the `M` form is used by current zoneinfo files.
+    fn parse(spec: &str) -> Option<DateRule> {
+        let (date, time) = match spec.split_once('/') {
+            Some((date, time)) => (date, parse_hms(time)?),
+            None => (spec, 7200),
+        };
+        let mut fields = date.strip_prefix('M')?.split('.').map(|f| f.parse::<u32>().ok());
+        let (month, week, weekday) = (fields.next()??, fields.next()??, fields.next()??);
+        ((1..=12).contains(&month) && (1..=5).contains(&week) && weekday <= 6).then_some(DateRule { month, week, weekday, time })
+    }
+
+    // Seconds since 1970-01-01 local time at which the rule fires in `year`.
+    fn local_seconds(&self, year: i64) -> i64 {
+        let first = days_from_civil(year, self.month, 1);
+        let mut day = first + ((self.weekday + 7 - weekday(first)) % 7) as i64 + (self.week as i64 - 1) * 7;
+        let next_month = if self.month == 12 { days_from_civil(year + 1, 1, 1) } else { days_from_civil(year, self.month + 1, 1) };
+        while day >= next_month {
+            day -= 7;
+        }
+        day * 86_400 + self.time
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cron::days_from_civil;
+
+    const LISBON: &str = "WET0WEST,M3.5.0/1,M10.5.0";
+
+    fn lisbon() -> Zone {
+        Zone::Rules { transitions: Vec::new(), initial: 0, rule: PosixRule::parse(LISBON) }
+    }
+
+    // Seconds since 1970-01-01 of a date and time, read as UTC or as local time.
+    fn at(year: i64, month: u32, day: u32, hour: i64, minute: i64) -> i64 {
+        days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60
+    }
+
+    // A version 2 zoneinfo file with one transition and `footer` after the data.
+    fn tzif(footer: &[u8]) -> Vec<u8> {
+        let header = |timecnt: u32, typecnt: u32, charcnt: u32| {
+            let mut h = b"TZif2".to_vec();
+            h.extend_from_slice(&[0; 15]);
+            for count in [0, 0, 0, timecnt, typecnt, charcnt] {
+                h.extend_from_slice(&u32::to_be_bytes(count));
+            }
+            h
+        };
+        let types = |data: &mut Vec<u8>| {
+            for (offset, dst, abbr) in [(0i32, 0u8, 0u8), (3600, 1, 4)] {
+                data.extend_from_slice(&offset.to_be_bytes());
+                data.extend_from_slice(&[dst, abbr]);
+            }
+            data.extend_from_slice(b"WET\0WEST\0");
+        };
+        let mut data = header(1, 2, 9);
+        data.extend_from_slice(&(at(2000, 3, 26, 1, 0) as i32).to_be_bytes());
+        data.push(1);
+        types(&mut data);
+        data.extend(header(1, 2, 9));
+        data.extend_from_slice(&at(2000, 3, 26, 1, 0).to_be_bytes());
+        data.push(1);
+        types(&mut data);
+        data.extend_from_slice(footer);
+        data
+    }
+
+    #[test]
+    fn fixed_offsets() {
+        assert_eq!(fixed_offset("UTC"), Some(0));
+        assert_eq!(fixed_offset("UTC+2"), Some(7200));
+        assert_eq!(fixed_offset("GMT-03:30"), Some(-12_600));
+        assert_eq!(fixed_offset("+05:30"), Some(19_800));
+        assert_eq!(fixed_offset("UTC+15"), None);
+        assert_eq!(fixed_offset("Europe/Lisbon"), None);
+    }
+
+    #[test]
+    fn lisbon_switches_at_one_utc() {
+        let zone = lisbon();
+        // Last Sunday of March 2026 is the 29th, of October the 25th
+        assert_eq!(zone.offset_at(at(2026, 3, 29, 0, 59)), 0);
+        assert_eq!(zone.offset_at(at(2026, 3, 29, 1, 0)), 3600);
+        assert_eq!(zone.offset_at(at(2026, 7, 1, 12, 0)), 3600);
+        assert_eq!(zone.offset_at(at(2026, 10, 25, 0, 59)), 3600);
+        assert_eq!(zone.offset_at(at(2026, 10, 25, 1, 0)), 0);
+        assert_eq!(zone.offset_at(at(2026, 12, 1, 12, 0)), 0);
+    }
+
+    #[test]
+    fn week_five_is_the_last_weekday_of_the_month() {
+        let rule = DateRule::parse("M10.5.0").unwrap();
+        assert_eq!(rule.time, 7200);
+        // October 2026 has four Sundays, the last on the 25th
+        assert_eq!(rule.local_seconds(2026), at(2026, 10, 25, 2, 0));
+        // October 2023 has five, the last on the 29th
+        assert_eq!(rule.local_seconds(2023), at(2023, 10, 29, 2, 0));
+        assert_eq!(DateRule::parse("M3.5.0/1").unwrap().local_seconds(2026), at(2026, 3, 29, 1, 0));
+        assert!(DateRule::parse("M13.1.0").is_none());
+        assert!(DateRule::parse("J60").is_none());
+    }
+
+    #[test]
+    fn skipped_hour_has_no_utc_time() {
+        let zone = lisbon();
+        assert_eq!(zone.to_utc(at(2026, 3, 29, 0, 59)), Some(at(2026, 3, 29, 0, 59)));
+        assert_eq!(zone.to_utc(at(2026, 3, 29, 1, 30)), None);
+        assert_eq!(zone.to_utc(at(2026, 3, 29, 2, 0)), Some(at(2026, 3, 29, 1, 0)));
+    }
+
+    #[test]
+    fn repeated_hour_takes_the_earlier_time() {
+        let zone = lisbon();
+        // 01:30 happens first in summer time (00:30 UTC), then in winter time (01:30 UTC)
+        assert_eq!(zone.to_utc(at(2026, 10, 25, 1, 30)), Some(at(2026, 10, 25, 0, 30)));
+        assert_eq!(zone.to_utc(at(2026, 10, 25, 2, 30)), Some(at(2026, 10, 25, 2, 30)));
+        assert_eq!(zone.to_utc(at(2026, 10, 25, 0, 30)), Some(at(2026, 10, 24, 23, 30)));
+    }
+
+    #[test]
+    fn southern_hemisphere_rule_wraps_the_year() {
+        // Chile: DST from September to April
+        let zone = Zone::Rules { transitions: Vec::new(), initial: 0, rule: PosixRule::parse("<-04>4<-03>,M9.1.6/24,M4.1.6/24") };
+        assert_eq!(zone.offset_at(at(2026, 1, 15, 12, 0)), -3 * 3600);
+        assert_eq!(zone.offset_at(at(2026, 6, 15, 12, 0)), -4 * 3600);
+        assert_eq!(zone.offset_at(at(2026, 12, 15, 12, 0)), -3 * 3600);
+    }
+
+    #[test]
+    fn parses_zoneinfo_with_footer_rule() {
+        let zone = parse_tzif(&tzif(format!("\n{}\n", LISBON).as_bytes())).unwrap();
+        // Before the only transition, then the footer rule after it
+        assert_eq!(zone.offset_at(at(1999, 1, 1, 0, 0)), 0);
+        assert_eq!(zone.offset_at(at(2000, 6, 1, 0, 0)), 3600);
+        assert_eq!(zone.offset_at(at(2030, 1, 1, 0, 0)), 0);
+        assert_eq!(zone.offset_at(at(2030, 7, 1, 0, 0)), 3600);
+    }
+
+    #[test]
+    fn truncated_zoneinfo_is_rejected() {
+        let data = tzif(format!("\n{}\n", LISBON).as_bytes());
+        let end = data.len() - LISBON.len() - 2;
+        for len in [0, 10, 44, 60, end - 1] {
+            assert!(parse_tzif(&data[..len]).is_none(), "{} bytes", len);
+        }
+        assert!(parse_tzif(&data[..end]).is_some());
+        assert!(parse_tzif(b"not a zoneinfo file at all, but long enough to have a header").is_none());
+    }
+}
@@ -0,0 +1,131 @@
+use crate::error::{BotError, BotResult};
+use crate::http;
+use crate::memory::now_millis;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::env;
+use std::sync::Mutex;
+
+// A reply the bot published, watched for follow-ups from the user it answered.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Watch {
+    reply_uri: String,
+    user: String,
+    since: i64,
+    until: i64,
+    // Follow-ups already handed out
+    seen: HashSet<String>,
+}
+
+static WATCHES: Mutex<Vec<Watch>> = Mutex::new(Vec::new());
+static LAST_POLL: Mutex<i64> = Mutex::new(0);
+
+// How long a thread is watched after the bot answers in it, from THREAD_WATCH_MINUTES.
+// 0 (the default) disables watching.
+fn window_millis() -> i64 {
+    env::var("THREAD_WATCH_MINUTES").ok().and_then(|v| v.parse::<i64>().ok()).unwrap_or(0).max(0) * 60_000
+}
+
+// Time between checks of the watched threads, from THREAD_WATCH_INTERVAL_SECS.
+fn interval_millis() -> i64 {
+    env::var("THREAD_WATCH_INTERVAL_SECS").ok().and_then(|v| v.parse::<i64>().ok()).unwrap_or(15).max(1) * 1000
+}
+
+pub fn is_enabled() -> bool {
+    window_millis() > 0
+}
+
+// Starts watching `reply_uri` for follow-ups from `user`.
+pub fn start(reply_uri: &str, user: &str) {
+    if !is_enabled() {
+        return;
+    }
+    let now = now_millis();
+    if let Ok(mut watches) = WATCHES.lock() {
+        watches.push(Watch { reply_uri: reply_uri.to_string(), user: user.to_string(), since: now, until: now + window_millis(), seen: HashSet::new() });
+    }
+}
+
+// The live watches, for the state snapshot.
+pub fn snapshot() -> Vec<Watch> {
+    let now = now_millis();
+    WATCHES.lock().map(|watches| watches.iter().filter(|w| w.until > now).cloned().collect()).unwrap_or_default()
+}
+
+// Resumes the watches of a snapshot whose window hasn't ended.
+pub fn restore(saved: Vec<Watch>) {
+    let now = now_millis();
+    if let Ok(mut watches) = WATCHES.lock() {
+        watches.extend(saved.into_iter().filter(|w| w.until > now));
+    }
+}
+
+// Stops watching the replies to `user` and returns how many there were.
+pub fn forget_user(user: &str) -> usize {
+    let Ok(mut watches) = WATCHES.lock() else { return 0 };
+    let before = watches.len();
+    watches.retain(|w| w.user != user);
+    before - watches.len()
+}
+
+// Number of replies currently watched, for /status.
+pub fn active() -> usize {
+    let now = now_millis();
+    WATCHES.lock().map(|watches| watches.iter().filter(|w| w.until > now).count()).unwrap_or(0)
+}
+
+// New replies to the watched replies from the users they answered, as (author, post
+// URI). Asks Nexus for the replies of each watched post at most every
+// THREAD_WATCH_INTERVAL_SECS, so a follow-up that doesn't mention the bot is still
+// answered, and one that does is answered before its notification arrives.
+pub async fn poll() -> BotResult<Vec<(String, String)>> {
+    let now = now_millis();
+    {
+        let Ok(mut last_poll) = LAST_POLL.lock() else { return Ok(Vec::new()) };
+        if now - *last_poll < interval_millis() {
+            return Ok(Vec::new());
+        }
+        *last_poll = now;
+    }
+    let targets: Vec<(String, String, i64)> = match WATCHES.lock() {
+        Ok(mut watches) => {
+            watches.retain(|watch| watch.until > now);
+            watches.iter().map(|w| (w.reply_uri.clone(), w.user.clone(), w.since)).collect()
+        }
+        Err(_) => return Ok(Vec::new()),
+    };
+    if targets.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let nexus_url = env::var("NEXT_PUBLIC_NEXUS").map_err(|_| BotError::Validation("NEXT_PUBLIC_NEXUS not found in .env".to_string()))?;
+    let http = http::nexus();
+    let mut follow_ups = Vec::new();
+    for (reply_uri, user, since) in targets {
+        let Some((author_id, post_id)) = reply_uri.strip_prefix("pubky://").and_then(|rest| rest.split_once("/pub/pubky.app/posts/")) else {
+            continue;
+        };
+        let url = format!("{}/v0/stream/posts?source=post_replies&author_id={}&post_id={}&limit=20", nexus_url, author_id, post_id);
+        let response = http.get(&url).send().await.map_err(BotError::nexus)?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            continue;
+        }
+        let posts: Vec<Value> = response.error_for_status().map_err(BotError::nexus)?.json().await.map_err(BotError::nexus)?;
+        let new: Vec<String> = posts
+            .iter()
+            .map(|post| &post["details"])
+            .filter(|details| details["author"].as_str() == Some(user.as_str()) && details["indexed_at"].as_i64().unwrap_or(0) >= since)
+            .filter_map(|details| details["uri"].as_str().map(String::from))
+            .collect();
+        if let Ok(mut watches) = WATCHES.lock() {
+            let Some(watch) = watches.iter_mut().find(|w| w.reply_uri == reply_uri) else { continue };
+            for uri in new {
+                if watch.seen.insert(uri.clone()) {
+                    follow_ups.push((user.clone(), uri));
+                }
+            }
+        }
+    }
+    Ok(follow_ups)
+}
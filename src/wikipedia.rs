@@ -0,0 +1,101 @@
+use crate::error::{BotError, BotResult};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+const USER_AGENT: &str = "ai-rand (Pubky bot; https://github.com/MiguelMedeiros/ai-rand)";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// Summaries rarely change, so repeated questions about the same entity are answered
+// from memory instead of hitting the API again
+const CACHE_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+const CACHE_SIZE: usize = 200;
+
+#[derive(Debug, Clone)]
+pub struct Summary {
+    pub title: String,
+    pub extract: String,
+    pub url: String,
+}
+
+// "language:query" -> when it was fetched and the result
+type SummaryCache = HashMap<String, (Instant, Option<Summary>)>;
+
+static CACHE: OnceLock<Mutex<SummaryCache>> = OnceLock::new();
+
+// Wikipedia lookup tool, enabled with WIKIPEDIA=true.
+pub fn is_enabled() -> bool {
+    env::var("WIKIPEDIA").map(|v| v == "true").unwrap_or(false)
+}
+
+fn default_language() -> String {
+    env::var("WIKIPEDIA_LANG").unwrap_or_else(|_| "en".to_string())
+}
+
+// Finds the article best matching `query` and returns its lead summary, or None when
+// nothing matches. `language` is a Wikipedia language code; the default is WIKIPEDIA_LANG.
+pub async fn summary(query: &str, language: Option<&str>) -> BotResult<Option<Summary>> {
+    let language = language
+        .filter(|l| !l.is_empty() && l.len() <= 12 && l.chars().all(|c| c.is_ascii_alphabetic() || c == '-'))
+        .map(String::from)
+        .unwrap_or_else(default_language);
+    let key = format!("{}:{}", language, query.to_lowercase());
+    let cache = CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Some((at, cached)) = cache.lock().unwrap().get(&key) {
+        if at.elapsed() < CACHE_TTL {
+            return Ok(cached.clone());
+        }
+    }
+
+    let result = fetch_summary(query, &language).await?;
+    let mut cache = cache.lock().unwrap();
+    if cache.len() >= CACHE_SIZE {
+        cache.retain(|_, (at, _)| at.elapsed() < CACHE_TTL);
+        if cache.len() >= CACHE_SIZE {
+            cache.clear();
+        }
+    }
+    cache.insert(key, (Instant::now(), result.clone()));
+    Ok(result)
+}
+
+async fn fetch_summary(query: &str, language: &str) -> BotResult<Option<Summary>> {
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .user_agent(USER_AGENT)
+        .build()
+        .map_err(BotError::tool)?;
+    let base = format!("https://{}.wikipedia.org", language);
+
+    let search: Value = client
+        .get(format!("{}/w/rest.php/v1/search/title", base))
+        .query(&[("q", query), ("limit", "1")])
+        .send()
+        .await
+        .map_err(BotError::tool)?
+        .json()
+        .await
+        .map_err(BotError::tool)?;
+    let Some(key) = search["pages"][0]["key"].as_str() else {
+        return Ok(None);
+    };
+
+    let response = client
+        .get(format!("{}/api/rest_v1/page/summary/{}", base, key))
+        .send()
+        .await
+        .map_err(BotError::tool)?;
+    if !response.status().is_success() {
+        return Err(BotError::Tool(format!("Wikipedia summary for {} failed: {}", key, response.status())));
+    }
+    let page: Value = response.json().await.map_err(BotError::tool)?;
+    Ok(Some(Summary {
+        title: page["title"].as_str().unwrap_or(key).to_string(),
+        extract: page["extract"].as_str().unwrap_or_default().to_string(),
+        url: page["content_urls"]["desktop"]["page"]
+            .as_str()
+            .map(String::from)
+            .unwrap_or_else(|| format!("{}/wiki/{}", base, key)),
+    }))
+}
@@ -0,0 +1,93 @@
+use crate::homeserver::{delete_file, put_file};
+use crate::privacy;
+use anyhow::Result;
+use pubky::Client;
+use std::env;
+use tokio::task::JoinSet;
+
+// One file written on the bot's homeserver as part of a batch.
+pub struct Write {
+    pub uri: String,
+    pub body: Vec<u8>,
+}
+
+// Related homeserver writes for one interaction (the reply's posts, its provenance
+// record, ...) executed together. Steps run in order, so a post is never written before
+// its parent; the writes within a step go out concurrently, up to
+// HOMESERVER_WRITE_CONCURRENCY at a time (default 4). When a write fails, everything
+// the batch already wrote is deleted again, newest first, so a retry starts from a
+// clean slate instead of finding half an interaction. The reaction tag and last_read are
+// left out: the tag goes out before generation, may stand in for the reply and is
+// rewritten in place on a retry (its path comes from the post and label), and
+// last_read is the cursor moved once the whole notification batch is handled.
+#[derive(Default)]
+pub struct WriteBatch {
+    steps: Vec<Vec<Write>>,
+}
+
+fn concurrency() -> usize {
+    env::var("HOMESERVER_WRITE_CONCURRENCY").ok().and_then(|v| v.parse().ok()).unwrap_or(4).max(1)
+}
+
+impl WriteBatch {
+    pub fn new() -> WriteBatch {
+        WriteBatch::default()
+    }
+
+    // Adds a step, run once every earlier step has been written.
+    pub fn step(&mut self, writes: Vec<Write>) {
+        if !writes.is_empty() {
+            self.steps.push(writes);
+        }
+    }
+
+    pub async fn execute(self, client: &Client) -> Result<()> {
+        let mut written: Vec<String> = Vec::new();
+        for step in self.steps {
+            let mut pending = step.into_iter();
+            let mut running = JoinSet::new();
+            let mut failure = None;
+            loop {
+                while running.len() < concurrency() {
+                    let Some(write) = pending.next() else { break };
+                    let client = client.clone();
+                    running.spawn(async move {
+                        let result = put_file(&client, &write.uri, write.body).await;
+                        (write.uri, result)
+                    });
+                }
+                let Some(joined) = running.join_next().await else { break };
+                match joined {
+                    Ok((uri, Ok(()))) => written.push(uri),
+                    Ok((_, Err(e))) => {
+                        failure.get_or_insert(e.into());
+                    }
+                    Err(e) => {
+                        failure.get_or_insert_with(|| anyhow::anyhow!("Homeserver write task failed: {}", e));
+                    }
+                }
+                // Nothing more is started once a write failed; the running ones finish
+                if failure.is_some() {
+                    pending.by_ref().for_each(drop);
+                }
+            }
+            if let Some(e) = failure {
+                roll_back(client, &written).await;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn roll_back(client: &Client, written: &[String]) {
+    if written.is_empty() {
+        return;
+    }
+    log!("Rolling back {} homeserver write(s) of a failed batch", written.len());
+    for uri in written.iter().rev() {
+        if let Err(e) = delete_file(client, uri).await {
+            log!("Failed to roll back {}: {}", privacy::uri(uri), e);
+        }
+    }
+}